@@ -0,0 +1,291 @@
+#!/usr/bin/env python3
+"""Builds default.sf2, the General MIDI SoundFont bundled with the app.
+
+Every sound is synthesized here, there is no recorded sample in it, so the file can be
+shipped and changed freely. It is small and plain on purpose: a single-cycle waveform per
+GM family (16 instruments for the 128 programs) and a noise/sine drum kit on bank 128.
+Run it from this folder after changing anything: python3 make_default.py
+"""
+
+import math
+import random
+import struct
+
+# loops are one cycle of PERIOD samples at RATE, which is A2 (110 Hz, key 45)
+PERIOD = 200
+RATE = 22000
+LOOP_ROOT = 45
+# drums are one-shots recorded at this rate
+DRUM_RATE = 22050
+
+# SF2 generator numbers
+KEY_RANGE = 43
+INSTRUMENT = 41
+SAMPLE_ID = 53
+SAMPLE_MODES = 54
+SCALE_TUNING = 56
+EXCLUSIVE_CLASS = 57
+OVERRIDING_ROOT_KEY = 58
+ATTACK = 34
+DECAY = 36
+SUSTAIN = 37
+RELEASE = 38
+
+
+def timecents(secs):
+    return round(1200 * math.log2(secs)) & 0xFFFF
+
+
+def centibels(db):
+    return round(db * 10)
+
+
+def to_pcm(values, peak=0.5):
+    top = max(abs(v) for v in values) or 1.0
+    return [round(v / top * peak * 32767) for v in values]
+
+
+def harmonics(weights):
+    """One cycle of sum(weight * sin(n * x))"""
+    return [
+        sum(w * math.sin(2 * math.pi * n * i / PERIOD) for n, w in weights)
+        for i in range(PERIOD)
+    ]
+
+
+def waveform(partials, limit):
+    return to_pcm(harmonics([(n, w) for n, w in partials if n <= limit]))
+
+
+# (name, partials); the bright copy plays below C5, the dull one above so high notes don't alias
+WAVES = [
+    ("Piano", [(n, 1 / n**1.5) for n in range(1, 25)]),
+    ("Organ", [(1, 1), (2, 0.8), (3, 0.6), (4, 0.5), (6, 0.3), (8, 0.3), (10, 0.1), (16, 0.1)]),
+    ("Flute", [(1, 1), (2, 0.15), (3, 0.05)]),
+    ("Saw", [(n, 1 / n) for n in range(1, 25)]),
+    ("Square", [(n, 1 / n) for n in range(1, 25, 2)]),
+    ("Triangle", [(n, (-1) ** (n // 2) / n**2) for n in range(1, 25, 2)]),
+]
+WAVE_SPLIT = 71
+
+# one instrument per GM family: (name, wave, attack, decay, sustain dB, release)
+FAMILIES = [
+    ("Piano", "Piano", 0.002, 2.5, 30, 0.4),
+    ("Chromatic Perc", "Flute", 0.001, 0.8, 60, 0.5),
+    ("Organ", "Organ", 0.01, 1.0, 0, 0.1),
+    ("Guitar", "Piano", 0.002, 1.8, 40, 0.3),
+    ("Bass", "Triangle", 0.005, 1.2, 12, 0.1),
+    ("Strings", "Saw", 0.15, 1.0, 3, 0.4),
+    ("Ensemble", "Saw", 0.25, 1.0, 3, 0.6),
+    ("Brass", "Saw", 0.05, 0.6, 4, 0.2),
+    ("Reed", "Square", 0.03, 0.6, 3, 0.15),
+    ("Pipe", "Flute", 0.05, 0.6, 2, 0.2),
+    ("Synth Lead", "Square", 0.005, 0.5, 2, 0.1),
+    ("Synth Pad", "Saw", 0.6, 2.0, 4, 1.2),
+    ("Synth FX", "Triangle", 0.3, 3.0, 6, 1.5),
+    ("Ethnic", "Piano", 0.002, 1.5, 36, 0.3),
+    ("Percussive", "Flute", 0.001, 0.4, 80, 0.2),
+    ("Sound FX", "Saw", 0.1, 2.0, 12, 0.8),
+]
+
+
+def drum(secs, fn):
+    return to_pcm([fn(i / DRUM_RATE) for i in range(int(secs * DRUM_RATE))], 0.7)
+
+
+def drum_samples():
+    rng = random.Random(1)
+    noise = [rng.uniform(-1, 1) for _ in range(DRUM_RATE)]
+
+    def hiss(t, decay, bright=True):
+        i = int(t * DRUM_RATE)
+        n = noise[i] - noise[i - 1] if bright else noise[i]
+        return n * math.exp(-t / decay)
+
+    def sweep(start, end, decay):
+        def fn(t):
+            # the pitch falls from start to end, this is its phase
+            phase = end * t + (start - end) * (1 - math.exp(-t * 30)) / 30
+            return math.sin(2 * math.pi * phase) * math.exp(-t / decay)
+
+        return fn
+
+    return {
+        "Kick": drum(0.4, sweep(150, 50, 0.12)),
+        "Snare": drum(
+            0.3,
+            lambda t: 0.6 * hiss(t, 0.07, False)
+            + 0.5 * math.sin(2 * math.pi * 190 * t) * math.exp(-t / 0.05),
+        ),
+        "Tom": drum(0.5, sweep(220, 150, 0.15)),
+        "Hat": drum(0.08, lambda t: hiss(t, 0.02)),
+        "Open Hat": drum(0.5, lambda t: hiss(t, 0.15)),
+        "Cymbal": drum(0.99, lambda t: hiss(t, 0.4)),
+        "Click": drum(0.05, lambda t: hiss(t, 0.008, False)),
+    }
+
+
+# (first key, last key, sample, generators), keys follow the GM percussion map
+DRUM_ZONES = [
+    (27, 34, "Click", []),
+    (35, 36, "Kick", []),
+    (37, 37, "Click", []),
+    (38, 40, "Snare", []),
+    (41, 41, "Tom", [(OVERRIDING_ROOT_KEY, 45), (SCALE_TUNING, 50)]),
+    (42, 42, "Hat", [(EXCLUSIVE_CLASS, 1)]),
+    (43, 43, "Tom", [(OVERRIDING_ROOT_KEY, 45), (SCALE_TUNING, 50)]),
+    (44, 44, "Hat", [(EXCLUSIVE_CLASS, 1)]),
+    (45, 45, "Tom", [(OVERRIDING_ROOT_KEY, 45), (SCALE_TUNING, 50)]),
+    (46, 46, "Open Hat", [(EXCLUSIVE_CLASS, 1)]),
+    (47, 48, "Tom", [(OVERRIDING_ROOT_KEY, 45), (SCALE_TUNING, 50)]),
+    (49, 49, "Cymbal", []),
+    (50, 50, "Tom", [(OVERRIDING_ROOT_KEY, 45), (SCALE_TUNING, 50)]),
+    (51, 59, "Cymbal", []),
+    (60, 87, "Click", [(OVERRIDING_ROOT_KEY, 60), (SCALE_TUNING, 30)]),
+]
+
+
+def chunk(tag, data):
+    pad = b"\0" if len(data) % 2 else b""
+    return tag + struct.pack("<I", len(data)) + data + pad
+
+
+def riff_list(kind, chunks):
+    return chunk(b"LIST", kind + b"".join(chunks))
+
+
+def name20(name):
+    return name.encode("ascii")[:19].ljust(20, b"\0")
+
+
+def zstr(text):
+    data = text.encode("ascii") + b"\0"
+    return data + (b"\0" if len(data) % 2 else b"")
+
+
+def build():
+    # samples: (name, pcm, rate, loop start, loop end, root key)
+    samples = []
+    sample_ids = {}
+
+    def add_sample(name, pcm, rate, loop, root):
+        sample_ids[name] = len(samples)
+        samples.append((name, pcm, rate, loop, root))
+
+    for name, partials in WAVES:
+        for suffix, limit in (("", 24), (" Hi", 6)):
+            cycle = waveform(partials, limit)
+            # four cycles, the loop is the middle two
+            add_sample(name + suffix, cycle * 4, RATE, (PERIOD, PERIOD * 3), LOOP_ROOT)
+    for name, pcm in drum_samples().items():
+        add_sample(name, pcm, DRUM_RATE, (8, len(pcm) - 8), 60)
+
+    # instruments: (name, [zone generators])
+    instruments = []
+    for name, wave, attack, decay, sustain, release in FAMILIES:
+        env = [
+            (ATTACK, timecents(attack)),
+            (DECAY, timecents(decay)),
+            (SUSTAIN, centibels(sustain)),
+            (RELEASE, timecents(release)),
+        ]
+        zones = []
+        for (lo, hi), suffix in (((0, WAVE_SPLIT), ""), ((WAVE_SPLIT + 1, 127), " Hi")):
+            zones.append(
+                [(KEY_RANGE, lo | hi << 8)]
+                + env
+                + [(SAMPLE_MODES, 1), (SAMPLE_ID, sample_ids[wave + suffix])]
+            )
+        instruments.append((name, zones))
+    drum_zones = [
+        [(KEY_RANGE, lo | hi << 8)]
+        + gens
+        + [(RELEASE, timecents(0.3)), (SAMPLE_ID, sample_ids[sample])]
+        for lo, hi, sample, gens in DRUM_ZONES
+    ]
+    drum_instrument = len(instruments)
+    instruments.append(("Standard Kit", drum_zones))
+
+    # presets: (name, program, bank, instrument)
+    presets = [
+        ("%s %d" % (FAMILIES[p // 8][0], p % 8 + 1), p, 0, p // 8) for p in range(128)
+    ]
+    presets.append(("Standard Kit", 0, 128, drum_instrument))
+
+    smpl = b""
+    shdr = b""
+    offset = 0
+    for name, pcm, rate, (loop_start, loop_end), root in samples:
+        smpl += struct.pack("<%dh" % len(pcm), *pcm)
+        # the format wants 46 zero samples after each sample
+        smpl += b"\0" * 92
+        shdr += name20(name) + struct.pack(
+            "<IIIIIBbHH",
+            offset,
+            offset + len(pcm),
+            offset + loop_start,
+            offset + loop_end,
+            rate,
+            root,
+            0,
+            0,
+            1,
+        )
+        offset += len(pcm) + 46
+    shdr += name20("EOS") + b"\0" * 26
+
+    inst = ibag = igen = b""
+    bag = gen = 0
+    for name, zones in instruments:
+        inst += name20(name) + struct.pack("<H", bag)
+        for zone in zones:
+            ibag += struct.pack("<HH", gen, 0)
+            for op, amount in zone:
+                igen += struct.pack("<HH", op, amount & 0xFFFF)
+            gen += len(zone)
+            bag += 1
+    inst += name20("EOI") + struct.pack("<H", bag)
+    ibag += struct.pack("<HH", gen, 0)
+    igen += struct.pack("<HH", 0, 0)
+
+    phdr = pbag = pgen = b""
+    for index, (name, program, bank, instrument) in enumerate(presets):
+        phdr += name20(name) + struct.pack("<HHHIII", program, bank, index, 0, 0, 0)
+        pbag += struct.pack("<HH", index, 0)
+        pgen += struct.pack("<HH", INSTRUMENT, instrument)
+    phdr += name20("EOP") + struct.pack("<HHHIII", 0, 0, len(presets), 0, 0, 0)
+    pbag += struct.pack("<HH", len(presets), 0)
+    pgen += struct.pack("<HH", 0, 0)
+
+    info = riff_list(
+        b"INFO",
+        [
+            chunk(b"ifil", struct.pack("<HH", 2, 1)),
+            chunk(b"isng", zstr("EMU8000")),
+            chunk(b"INAM", zstr("my-ktv default")),
+            chunk(b"ICMT", zstr("Generated by make_default.py, no recorded samples")),
+        ],
+    )
+    sdta = riff_list(b"sdta", [chunk(b"smpl", smpl)])
+    pdta = riff_list(
+        b"pdta",
+        [
+            chunk(b"phdr", phdr),
+            chunk(b"pbag", pbag),
+            chunk(b"pmod", b"\0" * 10),
+            chunk(b"pgen", pgen),
+            chunk(b"inst", inst),
+            chunk(b"ibag", ibag),
+            chunk(b"imod", b"\0" * 10),
+            chunk(b"igen", igen),
+            chunk(b"shdr", shdr),
+        ],
+    )
+    return chunk(b"RIFF", b"sfbk" + info + sdta + pdta)
+
+
+if __name__ == "__main__":
+    data = build()
+    with open("default.sf2", "wb") as f:
+        f.write(data)
+    print("default.sf2: %d bytes" % len(data))
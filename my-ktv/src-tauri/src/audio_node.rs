@@ -4,20 +4,40 @@
  * @ Date:      20260121
  */
 
+pub mod backend;
+pub mod channel_map;
+pub mod clock;
 pub mod fake_audio_wave_src;
+pub mod fan_out;
 pub mod file_src;
+pub mod gain;
+pub mod graph;
+pub mod limiter;
 pub mod mic_src;
+pub mod midi_src;
 pub mod mixer;
 mod node_const;
+pub mod offline;
 pub mod speaker_dest;
 mod utils;
+pub mod virtual_backend;
+pub mod wav_file_dest;
 
-use crate::audio_node::fake_audio_wave_src::FakeAudioWaveSRC;
+use crate::audio_node::clock::RenderClock;
+use crate::audio_node::fake_audio_wave_src::{FakeAudioWaveSRC, FAKE_AUDIO_CHANNELS};
+use crate::audio_node::fan_out::FanOut;
 use crate::audio_node::file_src::FileSrc;
+use crate::audio_node::gain::GainNode;
+use crate::audio_node::graph::NodeId;
 use crate::audio_node::mic_src::MicSrc;
+use crate::audio_node::midi_src::MidiSrc;
 use crate::audio_node::mixer::Mixer;
+use crate::audio_node::offline::RenderStep;
 use crate::audio_node::speaker_dest::SpeakerDest;
-use rtrb::Producer;
+use crate::audio_node::wav_file_dest::WavFileDest;
+use std::sync::Arc;
+
+pub use crate::audio_node::utils::{IOStreamConfig, ResampleQuality};
 
 #[derive(Clone, Copy, Debug)]
 pub enum AudioNodeType {
@@ -30,15 +50,19 @@ pub enum AudioNodeType {
 pub enum AudioNodeState {
     INITIALIZED,
     RUNNING,
+    PAUSED,
     STOPPED,
 }
 
 pub enum AudioNodeEnum {
     FakeAudioWaveSRC(FakeAudioWaveSRC),
     FileSrc(FileSrc),
+    MidiSrc(MidiSrc),
     SpeakerDest(SpeakerDest),
     MicSrc(MicSrc),
     Mixer(Mixer),
+    WavFileDest(WavFileDest),
+    GainNode(GainNode),
 }
 
 pub trait AudioNode {
@@ -58,9 +82,12 @@ impl AudioNode for AudioNodeEnum {
         match self {
             AudioNodeEnum::FakeAudioWaveSRC(node) => node.start(),
             AudioNodeEnum::FileSrc(node) => node.start(),
+            AudioNodeEnum::MidiSrc(node) => node.start(),
             AudioNodeEnum::SpeakerDest(node) => node.start(),
             AudioNodeEnum::MicSrc(node) => node.start(),
             AudioNodeEnum::Mixer(node) => node.start(),
+            AudioNodeEnum::WavFileDest(node) => node.start(),
+            AudioNodeEnum::GainNode(node) => node.start(),
         }
     }
 
@@ -68,9 +95,12 @@ impl AudioNode for AudioNodeEnum {
         match self {
             AudioNodeEnum::FakeAudioWaveSRC(node) => node.stop(),
             AudioNodeEnum::FileSrc(node) => node.stop(),
+            AudioNodeEnum::MidiSrc(node) => node.stop(),
             AudioNodeEnum::SpeakerDest(node) => node.stop(),
             AudioNodeEnum::MicSrc(node) => node.stop(),
             AudioNodeEnum::Mixer(node) => node.stop(),
+            AudioNodeEnum::WavFileDest(node) => node.stop(),
+            AudioNodeEnum::GainNode(node) => node.stop(),
         }
     }
 
@@ -78,9 +108,12 @@ impl AudioNode for AudioNodeEnum {
         match self {
             AudioNodeEnum::FakeAudioWaveSRC(node) => node.get_type(),
             AudioNodeEnum::FileSrc(node) => node.get_type(),
+            AudioNodeEnum::MidiSrc(node) => node.get_type(),
             AudioNodeEnum::SpeakerDest(node) => node.get_type(),
             AudioNodeEnum::MicSrc(node) => node.get_type(),
             AudioNodeEnum::Mixer(node) => node.get_type(),
+            AudioNodeEnum::WavFileDest(node) => node.get_type(),
+            AudioNodeEnum::GainNode(node) => node.get_type(),
         }
     }
 
@@ -88,84 +121,205 @@ impl AudioNode for AudioNodeEnum {
         match self {
             AudioNodeEnum::FakeAudioWaveSRC(node) => node.get_state(),
             AudioNodeEnum::FileSrc(node) => node.get_state(),
+            AudioNodeEnum::MidiSrc(node) => node.get_state(),
             AudioNodeEnum::SpeakerDest(node) => node.get_state(),
             AudioNodeEnum::MicSrc(node) => node.get_state(),
             AudioNodeEnum::Mixer(node) => node.get_state(),
+            AudioNodeEnum::WavFileDest(node) => node.get_state(),
+            AudioNodeEnum::GainNode(node) => node.get_state(),
         }
     }
 }
 
-pub fn connect(source: &mut AudioNodeEnum, dest: &mut AudioNodeEnum) -> Result<(), String> {
-    match (source, dest) {
-        (AudioNodeEnum::FakeAudioWaveSRC(src_inner), AudioNodeEnum::SpeakerDest(dest_inner)) => {
-            transfer_producer(
-                &mut src_inner.audio_producer,
-                &mut dest_inner.audio_producer,
-            )
+impl AudioNodeEnum {
+    /// Output side of nodes that can feed others, `None` for destinations
+    fn output_mut(&mut self) -> Option<&mut Option<FanOut>> {
+        match self {
+            AudioNodeEnum::FakeAudioWaveSRC(node) => Some(&mut node.audio_producer),
+            AudioNodeEnum::FileSrc(node) => Some(&mut node.audio_producer),
+            AudioNodeEnum::MidiSrc(node) => Some(&mut node.player_mut().audio_producer),
+            AudioNodeEnum::MicSrc(node) => Some(&mut node.audio_producer),
+            AudioNodeEnum::Mixer(node) => Some(&mut node.audio_producer),
+            AudioNodeEnum::GainNode(node) => Some(&mut node.audio_producer),
+            AudioNodeEnum::SpeakerDest(_) | AudioNodeEnum::WavFileDest(_) => None,
         }
+    }
 
-        (AudioNodeEnum::MicSrc(src_inner), AudioNodeEnum::SpeakerDest(dest_inner)) => {
-            src_inner.input_producer_config = Option::from(dest_inner.config.clone());
-            transfer_producer(
-                &mut src_inner.audio_producer,
-                &mut dest_inner.audio_producer,
-            )
+    /// Whether the output ring buffers are back in the struct (not owned by a thread or stream)
+    pub fn can_rewire(&self) -> bool {
+        match self {
+            AudioNodeEnum::FakeAudioWaveSRC(node) => node.audio_producer.is_some(),
+            AudioNodeEnum::FileSrc(node) => node.audio_producer.is_some(),
+            AudioNodeEnum::MidiSrc(node) => node.player().audio_producer.is_some(),
+            AudioNodeEnum::MicSrc(node) => node.audio_producer.is_some(),
+            AudioNodeEnum::Mixer(node) => node.audio_producer.is_some(),
+            AudioNodeEnum::GainNode(node) => node.audio_producer.is_some(),
+            AudioNodeEnum::SpeakerDest(_) | AudioNodeEnum::WavFileDest(_) => true,
         }
+    }
 
-        (AudioNodeEnum::FileSrc(src_inner), AudioNodeEnum::SpeakerDest(dest_inner)) => {
-            transfer_producer(
-                &mut src_inner.audio_producer,
-                &mut dest_inner.audio_producer,
-            )
+    /// Interleaved channels this node writes, `None` until it is configured
+    pub fn output_channels(&self) -> Option<usize> {
+        match self {
+            AudioNodeEnum::FakeAudioWaveSRC(_) => Some(FAKE_AUDIO_CHANNELS),
+            AudioNodeEnum::FileSrc(node) => node.channels(),
+            AudioNodeEnum::MidiSrc(node) => node.player().channels(),
+            AudioNodeEnum::MicSrc(node) => node
+                .input_producer_config
+                .as_ref()
+                .map(|c| c.stream_config.channels as usize),
+            AudioNodeEnum::Mixer(node) => Some(node.channels()),
+            AudioNodeEnum::GainNode(node) => Some(node.channels()),
+            AudioNodeEnum::SpeakerDest(_) | AudioNodeEnum::WavFileDest(_) => None,
         }
+    }
 
-        // Mixer -> Destination connections
-        (AudioNodeEnum::Mixer(mixer_inner), AudioNodeEnum::SpeakerDest(dest_inner)) => {
-            transfer_producer(
-                &mut mixer_inner.audio_producer,
-                &mut dest_inner.audio_producer,
-            )
+    /// Interleaved channels this node reads, `None` when it follows whatever is linked
+    pub fn input_channels(&self) -> Option<usize> {
+        match self {
+            AudioNodeEnum::SpeakerDest(node) => Some(node.config.stream_config.channels as usize),
+            AudioNodeEnum::WavFileDest(node) => Some(node.channels()),
+            AudioNodeEnum::Mixer(node) => Some(node.channels()),
+            _ => None,
         }
+    }
 
-        // Source -> Mixer connections (using input slot)
-        (AudioNodeEnum::FileSrc(src_inner), AudioNodeEnum::Mixer(mixer_inner)) => {
-            get_producer_from_mixer(&mut src_inner.audio_producer, mixer_inner)
+    /// Frames a sample spends in this node before it is passed on, device included for
+    /// speakers. Sources report their own queue instead.
+    pub fn latency_frames(&self) -> usize {
+        match self {
+            AudioNodeEnum::Mixer(node) => node.latency_frames(),
+            AudioNodeEnum::GainNode(node) => node.latency_frames(),
+            AudioNodeEnum::SpeakerDest(node) => node.latency_frames(),
+            _ => 0,
         }
+    }
+
+    /// Nodes without their own thread, they only run when a speaker clock ticks them
+    pub fn is_clocked(&self) -> bool {
+        matches!(
+            self,
+            AudioNodeEnum::FakeAudioWaveSRC(_)
+                | AudioNodeEnum::Mixer(_)
+                | AudioNodeEnum::GainNode(_)
+        )
+    }
 
-        (AudioNodeEnum::MicSrc(src_inner), AudioNodeEnum::Mixer(mixer_inner)) => {
-            get_producer_from_mixer(&mut src_inner.audio_producer, mixer_inner)
+    pub(crate) fn attach_clock(&mut self, id: NodeId, clock: RenderClock) {
+        match self {
+            AudioNodeEnum::FakeAudioWaveSRC(node) => node.attach_clock(id, clock),
+            AudioNodeEnum::Mixer(node) => node.attach_clock(id, clock),
+            AudioNodeEnum::GainNode(node) => node.attach_clock(id, clock),
+            _ => {}
         }
+    }
 
-        (AudioNodeEnum::FakeAudioWaveSRC(src_inner), AudioNodeEnum::Mixer(mixer_inner)) => {
-            get_producer_from_mixer(&mut src_inner.audio_producer, mixer_inner)
+    /// Clock of a destination that drives its upstream nodes
+    pub(crate) fn clock(&self) -> Option<RenderClock> {
+        match self {
+            AudioNodeEnum::SpeakerDest(node) => Some(node.clock()),
+            _ => None,
         }
+    }
 
-        _ => Err("no supported connection".to_string()),
+    pub(crate) fn begin_offline(&mut self) -> Result<(), String> {
+        match self {
+            AudioNodeEnum::FileSrc(node) => node.begin_offline(),
+            AudioNodeEnum::MidiSrc(node) => node.player_mut().begin_offline(),
+            AudioNodeEnum::Mixer(node) => node.begin_offline(),
+            AudioNodeEnum::WavFileDest(node) => node.begin_offline(),
+            AudioNodeEnum::GainNode(node) => node.begin_offline(),
+            _ => Err(format!("{:?} can only run in realtime", self.get_type())),
+        }
+    }
+
+    pub(crate) fn step_offline(&mut self) -> RenderStep {
+        match self {
+            AudioNodeEnum::FileSrc(node) => node.step_offline(),
+            AudioNodeEnum::MidiSrc(node) => node.player_mut().step_offline(),
+            AudioNodeEnum::Mixer(node) => node.step_offline(),
+            AudioNodeEnum::WavFileDest(node) => node.step_offline(),
+            AudioNodeEnum::GainNode(node) => node.step_offline(),
+            _ => RenderStep::Idle,
+        }
     }
 }
 
-fn get_producer_from_mixer(
-    src_producer_slot: &mut Option<Producer<f32>>,
-    mixer: &mut Mixer,
+/// Hand a ring buffer from `dest` to `source`, edges are validated by `AudioGraph`
+pub(crate) fn link(
+    source: &mut AudioNodeEnum,
+    dest: &mut AudioNodeEnum,
+    source_id: NodeId,
+    dest_id: NodeId,
 ) -> Result<(), String> {
-    // Get a new input producer from the mixer
-    let mixer_input_producer = mixer.add_input();
-
-    // Transfer it to the source node
-    *src_producer_slot = Some(mixer_input_producer);
+    let (producer, signal) = match dest {
+        AudioNodeEnum::SpeakerDest(dest_inner) => {
+            if let AudioNodeEnum::MicSrc(src_inner) = source {
+                src_inner.input_producer_config = Option::from(dest_inner.config.clone());
+            }
+            let producer = dest_inner.audio_producer.take().ok_or(
+                "No producer available in destination (maybe already connected?)".to_string(),
+            )?;
+            (producer, Arc::clone(&dest_inner.input_signal))
+        }
+        AudioNodeEnum::WavFileDest(dest_inner) => {
+            let producer = dest_inner.audio_producer.take().ok_or(
+                "No producer available in destination (maybe already connected?)".to_string(),
+            )?;
+            (producer, Arc::clone(&dest_inner.input_signal))
+        }
+        AudioNodeEnum::Mixer(mixer_inner) => mixer_inner.add_input(source_id),
+        AudioNodeEnum::GainNode(gain_inner) => {
+            if let Some(channels) = source.output_channels() {
+                gain_inner.set_channels(channels);
+            }
+            let producer = gain_inner
+                .input_producer
+                .take()
+                .ok_or("Gain node already has an input".to_string())?;
+            (producer, Arc::clone(&gain_inner.input_signal))
+        }
+        _ => return Err("no supported connection".to_string()),
+    };
 
-    Ok(())
+    match source.output_mut() {
+        Some(Some(output)) => {
+            output.add(dest_id, producer, signal);
+            Ok(())
+        }
+        Some(None) => Err("source output is owned by a running node".to_string()),
+        None => Err("no supported connection".to_string()),
+    }
 }
 
-fn transfer_producer(
-    src_producer_slot: &mut Option<Producer<f32>>,
-    dest_producer_slot: &mut Option<Producer<f32>>,
+/// Reverse of `link`, the ring buffer goes back to where it came from
+pub(crate) fn unlink(
+    source: &mut AudioNodeEnum,
+    dest: &mut AudioNodeEnum,
+    source_id: NodeId,
+    dest_id: NodeId,
 ) -> Result<(), String> {
-    match dest_producer_slot.take() {
-        Some(producer) => {
-            *src_producer_slot = Some(producer);
-            Ok(())
+    let producer = match source.output_mut() {
+        Some(Some(output)) => output.remove(dest_id),
+        Some(None) => return Err("source output is owned by a running node".to_string()),
+        None => return Err("no supported connection".to_string()),
+    };
+    let producer = producer.ok_or("source is not linked to destination".to_string())?;
+
+    match dest {
+        AudioNodeEnum::SpeakerDest(dest_inner) => {
+            dest_inner.audio_producer = Some(producer);
         }
-        None => Err("No producer available in destination (maybe already connected?)".to_string()),
+        AudioNodeEnum::WavFileDest(dest_inner) => {
+            dest_inner.audio_producer = Some(producer);
+        }
+        AudioNodeEnum::Mixer(mixer_inner) => {
+            mixer_inner.remove_input(source_id);
+        }
+        AudioNodeEnum::GainNode(gain_inner) => {
+            gain_inner.input_producer = Some(producer);
+        }
+        _ => return Err("no supported connection".to_string()),
     }
+    Ok(())
 }
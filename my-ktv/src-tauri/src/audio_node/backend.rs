@@ -0,0 +1,225 @@
+/***
+ * @ Mod:       backend
+ * @ Author:    Leon Lin
+ * @ Date:      20261017
+ */
+
+use crate::audio_node::utils::{
+    generate_input_resolve_config, generate_output_resolve_config, IOStreamConfig,
+};
+use crate::audio_node::virtual_backend::VirtualBackend;
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, SampleFormat, SizedSample, StreamError};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+pub type OutputCallback = Box<dyn FnMut(&mut [f32]) + Send>;
+pub type InputCallback = Box<dyn FnMut(&[f32]) + Send>;
+
+/// Env var to pick the backend on headless hosts: `cpal` (default) or `virtual`
+pub const AUDIO_BACKEND_ENV: &str = "MY_KTV_AUDIO_BACKEND";
+
+/// Handle of a running device stream, dropping it closes the stream
+pub trait AudioStream: Send {
+    fn play(&self) -> Result<(), String>;
+    fn pause(&self) -> Result<(), String>;
+    /// Frames between the callback handing over a sample and the device playing it
+    fn latency_frames(&self) -> usize {
+        0
+    }
+}
+
+/// Where `SpeakerDest` and `MicSrc` get their devices from.
+/// Callbacks always see interleaved f32, sample format conversion is the backend's job.
+pub trait AudioBackend: Send + Sync {
+    fn name(&self) -> String;
+    fn output_config(&self) -> Result<IOStreamConfig, String>;
+    fn input_config(&self) -> Result<IOStreamConfig, String>;
+    fn build_output_stream(
+        &self,
+        config: &IOStreamConfig,
+        callback: OutputCallback,
+    ) -> Result<Box<dyn AudioStream>, String>;
+    fn build_input_stream(
+        &self,
+        config: &IOStreamConfig,
+        callback: InputCallback,
+    ) -> Result<Box<dyn AudioStream>, String>;
+}
+
+/// Backend chosen by `MY_KTV_AUDIO_BACKEND`. The virtual device is only used when asked for,
+/// a host without an output device is an error for the UI to show.
+pub fn default_backend() -> Result<Arc<dyn AudioBackend>, String> {
+    if std::env::var(AUDIO_BACKEND_ENV).is_ok_and(|v| v == "virtual") {
+        println!("[HAL] Using virtual audio backend");
+        return Ok(Arc::new(VirtualBackend::new()));
+    }
+    if cpal::default_host().default_output_device().is_none() {
+        return Err(format!(
+            "No output device, set {}=virtual to run without one",
+            AUDIO_BACKEND_ENV
+        ));
+    }
+    Ok(Arc::new(CpalBackend))
+}
+
+/// Real sound card through the cpal default host
+pub struct CpalBackend;
+
+impl CpalBackend {
+    fn output_device(&self) -> Result<cpal::Device, String> {
+        let host = cpal::default_host();
+        println!("[HAL] Audio Host: {:?}", host.id());
+        // 獲取輸出設備 (DAC)
+        let device = host
+            .default_output_device()
+            .ok_or("no output device available".to_string())?;
+        println!(
+            "[HAL] Output Device: {:?}",
+            device.description().map(|d| d.name().to_string())
+        );
+        Ok(device)
+    }
+
+    fn input_device(&self) -> Result<cpal::Device, String> {
+        // 獲取預設輸入設備 (麥克風)
+        let device = cpal::default_host()
+            .default_input_device()
+            .ok_or("no input device available".to_string())?;
+        println!(
+            "[HAL] Input Device: {:?}",
+            device.description().map(|d| d.name().to_string())
+        );
+        Ok(device)
+    }
+}
+
+impl AudioBackend for CpalBackend {
+    fn name(&self) -> String {
+        "cpal".to_string()
+    }
+
+    fn output_config(&self) -> Result<IOStreamConfig, String> {
+        let mut resolve_config_fn = generate_output_resolve_config("Speaker".to_string());
+        resolve_config_fn(&self.output_device()?)
+    }
+
+    fn input_config(&self) -> Result<IOStreamConfig, String> {
+        let mut resolve_config_fn = generate_input_resolve_config("Mic".to_string());
+        resolve_config_fn(&self.input_device()?)
+    }
+
+    fn build_output_stream(
+        &self,
+        config: &IOStreamConfig,
+        callback: OutputCallback,
+    ) -> Result<Box<dyn AudioStream>, String> {
+        let device = self.output_device()?;
+        let latency = Arc::new(AtomicUsize::new(0));
+        let stream = match config.sample_format {
+            SampleFormat::F32 => build_output::<f32>(&device, config, callback, latency.clone()),
+            SampleFormat::I32 => build_output::<i32>(&device, config, callback, latency.clone()),
+            SampleFormat::I16 => build_output::<i16>(&device, config, callback, latency.clone()),
+            SampleFormat::U8 => build_output::<u8>(&device, config, callback, latency.clone()),
+            _ => return Err("Unsupported format".to_string()),
+        }
+        .map_err(|e| format!("output stream created error: {}", e))?;
+        Ok(Box::new(CpalStream { stream, latency }))
+    }
+
+    fn build_input_stream(
+        &self,
+        config: &IOStreamConfig,
+        callback: InputCallback,
+    ) -> Result<Box<dyn AudioStream>, String> {
+        let device = self.input_device()?;
+        let stream = match config.sample_format {
+            SampleFormat::F32 => build_input::<f32>(&device, config, callback),
+            SampleFormat::I32 => build_input::<i32>(&device, config, callback),
+            SampleFormat::I16 => build_input::<i16>(&device, config, callback),
+            SampleFormat::U8 => build_input::<u8>(&device, config, callback),
+            _ => return Err("Unsupported format".to_string()),
+        }
+        .map_err(|e| format!("failed to build input stream: {}", e))?;
+        Ok(Box::new(CpalStream {
+            stream,
+            latency: Arc::new(AtomicUsize::new(0)),
+        }))
+    }
+}
+
+struct CpalStream {
+    stream: cpal::Stream,
+    // measured by the output callback from the device timestamps, 0 for inputs
+    latency: Arc<AtomicUsize>,
+}
+
+impl AudioStream for CpalStream {
+    fn play(&self) -> Result<(), String> {
+        self.stream.play().map_err(|e| e.to_string())
+    }
+
+    fn pause(&self) -> Result<(), String> {
+        self.stream.pause().map_err(|e| e.to_string())
+    }
+
+    fn latency_frames(&self) -> usize {
+        self.latency.load(Ordering::Relaxed)
+    }
+}
+
+// scratch buffer is sized up front, device buffers are far smaller than this
+const CONVERT_SCRATCH_CAPACITY: usize = 16384;
+
+fn build_output<T>(
+    device: &cpal::Device,
+    config: &IOStreamConfig,
+    mut callback: OutputCallback,
+    latency: Arc<AtomicUsize>,
+) -> Result<cpal::Stream, cpal::BuildStreamError>
+where
+    T: SizedSample + FromSample<f32>,
+{
+    let mut scratch: Vec<f32> = Vec::with_capacity(CONVERT_SCRATCH_CAPACITY);
+    let sample_rate = config.stream_config.sample_rate as f64;
+    device.build_output_stream(
+        &config.stream_config,
+        move |data: &mut [T], info: &cpal::OutputCallbackInfo| {
+            // how far ahead of the DAC this callback writes
+            let timestamp = info.timestamp();
+            if let Some(ahead) = timestamp.playback.duration_since(&timestamp.callback) {
+                let frames = (ahead.as_secs_f64() * sample_rate) as usize;
+                latency.store(frames, Ordering::Relaxed);
+            }
+            scratch.resize(data.len(), 0.0);
+            callback(&mut scratch);
+            for (dest, &src) in data.iter_mut().zip(scratch.iter()) {
+                *dest = T::from_sample(src);
+            }
+        },
+        |err: StreamError| eprintln!("[HAL] Output Stream Error: {}", err),
+        None, // Timeout: blocking negotiation
+    )
+}
+
+fn build_input<T>(
+    device: &cpal::Device,
+    config: &IOStreamConfig,
+    mut callback: InputCallback,
+) -> Result<cpal::Stream, cpal::BuildStreamError>
+where
+    T: SizedSample,
+    f32: FromSample<T>,
+{
+    let mut scratch: Vec<f32> = Vec::with_capacity(CONVERT_SCRATCH_CAPACITY);
+    device.build_input_stream(
+        &config.stream_config,
+        move |data: &[T], _: &cpal::InputCallbackInfo| {
+            scratch.clear();
+            scratch.extend(data.iter().map(|s| s.to_sample::<f32>()));
+            callback(&scratch);
+        },
+        |err: StreamError| eprintln!("[HAL] Input Stream Error: {}", err),
+        None,
+    )
+}
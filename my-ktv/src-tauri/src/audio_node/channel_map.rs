@@ -0,0 +1,140 @@
+/***
+ * @ Mod:       channel_map
+ * @ Author:    Leon Lin
+ * @ Date:      20261017
+ */
+
+use std::f32::consts::FRAC_1_SQRT_2;
+
+/// Gain from every source channel to every target channel, `rows[target][source]`.
+/// Source channels are expected in WAVE order: L R C LFE Ls Rs (Lb Rb).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelMatrix {
+    rows: Vec<Vec<f32>>,
+}
+
+impl ChannelMatrix {
+    /// Custom matrix, one row per target channel, every row as long as the source has channels
+    pub fn new(rows: Vec<Vec<f32>>) -> Result<Self, String> {
+        let inputs = rows.first().map(|r| r.len()).unwrap_or(0);
+        if inputs == 0 {
+            return Err("channel matrix needs at least one source and one target".to_string());
+        }
+        if rows.iter().any(|r| r.len() != inputs) {
+            return Err("every channel matrix row needs the same length".to_string());
+        }
+        Ok(Self { rows })
+    }
+
+    /// Standard mapping: same layout passes through, mono is copied to L and R, anything
+    /// wider is folded down with ITU-R BS.775 coefficients (centre and surrounds at -3dB, LFE
+    /// dropped)
+    pub fn standard(src_channels: usize, target_channels: usize) -> Self {
+        let src = src_channels.max(1);
+        let target = target_channels.max(1);
+        let rows = if src == target {
+            identity(target, src)
+        } else if src == 1 {
+            // 單聲道：前兩個聲道都放一樣的，其他留空
+            (0..target)
+                .map(|t| vec![if t < 2 { 1.0 } else { 0.0 }])
+                .collect()
+        } else if target == 1 {
+            let stereo = to_stereo(src);
+            vec![stereo[0]
+                .iter()
+                .zip(stereo[1].iter())
+                .map(|(l, r)| (l + r) * 0.5)
+                .collect()]
+        } else if target == 2 {
+            to_stereo(src)
+        } else {
+            // 目標聲道比較多或是不認得的排列：對應得到的直接放，其他留空
+            identity(target, src)
+        };
+        Self { rows }
+    }
+
+    pub fn inputs(&self) -> usize {
+        self.rows[0].len()
+    }
+
+    pub fn outputs(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn gain(&self, target: usize, source: usize) -> f32 {
+        self.rows[target][source]
+    }
+
+    /// Map one frame, `input` has `inputs()` samples and `output` has `outputs()`
+    pub fn apply(&self, input: &[f32], output: &mut [f32]) {
+        for (out, row) in output.iter_mut().zip(self.rows.iter()) {
+            *out = row.iter().zip(input.iter()).map(|(g, s)| g * s).sum();
+        }
+    }
+}
+
+fn identity(target: usize, src: usize) -> Vec<Vec<f32>> {
+    (0..target)
+        .map(|t| (0..src).map(|s| if s == t { 1.0 } else { 0.0 }).collect())
+        .collect()
+}
+
+/// Fold `src` (> 2) WAVE ordered channels into L and R
+fn to_stereo(src: usize) -> Vec<Vec<f32>> {
+    if src == 2 {
+        return identity(2, 2);
+    }
+    let mut left = vec![0.0; src];
+    let mut right = vec![0.0; src];
+    left[0] = 1.0;
+    right[1] = 1.0;
+    // 依聲道數決定 L R 之後是什麼
+    let layout: &[Speaker] = match src {
+        3 => &[Speaker::Center],
+        4 => &[Speaker::SurroundLeft, Speaker::SurroundRight],
+        5 => &[
+            Speaker::Center,
+            Speaker::SurroundLeft,
+            Speaker::SurroundRight,
+        ],
+        6 => &[
+            Speaker::Center,
+            Speaker::Lfe,
+            Speaker::SurroundLeft,
+            Speaker::SurroundRight,
+        ],
+        _ => &[
+            Speaker::Center,
+            Speaker::Lfe,
+            Speaker::SurroundLeft,
+            Speaker::SurroundRight,
+            Speaker::SurroundLeft,
+            Speaker::SurroundRight,
+        ],
+    };
+    for (offset, speaker) in layout.iter().enumerate() {
+        let ch = offset + 2;
+        if ch >= src {
+            break;
+        }
+        match speaker {
+            Speaker::Center => {
+                left[ch] = FRAC_1_SQRT_2;
+                right[ch] = FRAC_1_SQRT_2;
+            }
+            Speaker::SurroundLeft => left[ch] = FRAC_1_SQRT_2,
+            Speaker::SurroundRight => right[ch] = FRAC_1_SQRT_2,
+            Speaker::Lfe => {}
+        }
+    }
+    vec![left, right]
+}
+
+enum Speaker {
+    Center,
+    Lfe,
+    SurroundLeft,
+    SurroundRight,
+}
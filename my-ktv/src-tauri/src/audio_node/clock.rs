@@ -0,0 +1,149 @@
+/***
+ * @ Mod:       clock
+ * @ Author:    Leon Lin
+ * @ Date:      20261017
+ */
+
+use crate::audio_node::fake_audio_wave_src::WaveCore;
+use crate::audio_node::gain::GainCore;
+use crate::audio_node::graph::NodeId;
+use crate::audio_node::mixer::MixerCore;
+use crate::audio_node::node_const::CLOCK_LIST_CAPACITY;
+use rtrb::{Consumer, Producer, RingBuffer};
+use std::sync::{Arc, Mutex};
+
+/// Realtime part of a node while it is driven by a clock
+pub(crate) enum ClockJob {
+    Mixer(MixerCore),
+    Gain(GainCore),
+    Wave(WaveCore),
+}
+
+impl ClockJob {
+    fn process(&mut self, samples: usize) {
+        match self {
+            ClockJob::Mixer(core) => core.process(samples),
+            ClockJob::Gain(core) => core.process(samples),
+            ClockJob::Wave(core) => core.process(samples),
+        }
+    }
+}
+
+// One job on the clock. The control side only locks it to take the job off the clock, so the
+// callback's `try_lock` can only miss a job that is leaving anyway.
+type JobSlot = Arc<Mutex<Option<ClockJob>>>;
+type JobList = Vec<(NodeId, JobSlot)>;
+
+// The newest list the callback hasn't picked up yet. A later edit replaces it, every list is
+// the whole state so nothing is lost; the control side only locks it for the swap.
+type PendingList = Arc<Mutex<Option<JobList>>>;
+
+// the list as the graph knows it, the hand-over slot and the lists coming back from the callback
+struct ClockControl {
+    jobs: JobList,
+    pending: PendingList,
+    retired: Consumer<JobList>,
+}
+
+/// Pull scheduler owned by a device callback.
+/// Every tick runs the registered nodes upstream first, each produces exactly the samples the
+/// callback asked for, so nothing spins and the buffers in between stay (almost) empty.
+/// The callback never shares the job list: every edit builds a new one and leaves it in a
+/// single slot, the callback swaps in whatever is there at the next tick and hands the old one
+/// back to be freed. Clones are control side handles.
+#[derive(Clone)]
+pub struct RenderClock {
+    control: Arc<Mutex<ClockControl>>,
+}
+
+/// The callback side of a `RenderClock`
+pub struct ClockRunner {
+    jobs: JobList,
+    pending: PendingList,
+    retired: Producer<JobList>,
+}
+
+impl RenderClock {
+    pub fn new() -> (Self, ClockRunner) {
+        let pending: PendingList = Arc::new(Mutex::new(None));
+        let (retired_producer, retired) = RingBuffer::new(CLOCK_LIST_CAPACITY);
+        let clock = Self {
+            control: Arc::new(Mutex::new(ClockControl {
+                jobs: Vec::new(),
+                pending: Arc::clone(&pending),
+                retired,
+            })),
+        };
+        let runner = ClockRunner {
+            jobs: Vec::new(),
+            pending,
+            retired: retired_producer,
+        };
+        (clock, runner)
+    }
+
+    pub(crate) fn insert(&self, id: NodeId, job: ClockJob) {
+        let mut control = self.control.lock().unwrap();
+        control.jobs.push((id, Arc::new(Mutex::new(Some(job)))));
+        control.publish();
+    }
+
+    /// Take a job off the clock. Waits at most for the tick that is running it right now.
+    pub(crate) fn remove(&self, id: NodeId) -> Option<ClockJob> {
+        let mut control = self.control.lock().unwrap();
+        let index = control.jobs.iter().position(|(job_id, _)| *job_id == id)?;
+        let (_, slot) = control.jobs.remove(index);
+        control.publish();
+        // the callback may still hold the old list, it finds the slot empty from now on
+        let job = slot.lock().unwrap().take();
+        job
+    }
+
+    /// Run jobs in the order of `order` (upstream first), unknown ids go last
+    pub(crate) fn set_order(&self, order: &[NodeId]) {
+        let mut control = self.control.lock().unwrap();
+        control
+            .jobs
+            .sort_by_key(|(id, _)| order.iter().position(|o| o == id).unwrap_or(usize::MAX));
+        control.publish();
+    }
+
+    pub fn job_count(&self) -> usize {
+        self.control.lock().unwrap().jobs.len()
+    }
+}
+
+impl ClockControl {
+    /// Hand the callback a copy of the list, and free the lists it is done with. A list it
+    /// hasn't picked up yet is replaced and freed here.
+    fn publish(&mut self) {
+        while self.retired.pop().is_ok() {}
+        let list = self.jobs.clone();
+        let stale = self.pending.lock().unwrap().replace(list);
+        drop(stale);
+    }
+}
+
+impl ClockRunner {
+    /// Let every job produce `samples` samples. Never blocks, never skips and never frees: a
+    /// list edited meanwhile is picked up at the next tick, or the one after if the control
+    /// side is swapping it right now or hasn't taken the old ones back yet.
+    pub(crate) fn tick(&mut self, samples: usize) {
+        if !self.retired.is_full() {
+            if let Ok(mut pending) = self.pending.try_lock() {
+                if let Some(list) = pending.take() {
+                    let old = std::mem::replace(&mut self.jobs, list);
+                    // room was checked above, the control side frees it
+                    let _ = self.retired.push(old);
+                }
+            }
+        }
+        for (_, slot) in self.jobs.iter() {
+            if let Ok(mut job) = slot.try_lock() {
+                if let Some(job) = job.as_mut() {
+                    job.process(samples);
+                }
+            }
+        }
+    }
+}
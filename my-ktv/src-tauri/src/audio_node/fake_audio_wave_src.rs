@@ -1,17 +1,23 @@
+use crate::audio_node::clock::{ClockJob, RenderClock};
+use crate::audio_node::fan_out::FanOut;
+use crate::audio_node::graph::NodeId;
 use crate::audio_node::node_const::{MOCK_AUDIO_SAMPLE_HZ, MOCK_AUDIO_SAMPLE_RATE};
 use crate::audio_node::{AudioNode, AudioNodeState, AudioNodeType};
-use rtrb::Producer;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
-use std::thread;
-use std::thread::JoinHandle;
+
+// the test tone is always interleaved stereo
+pub const FAKE_AUDIO_CHANNELS: usize = 2;
 
 pub struct FakeAudioWaveSRC {
     pub state: AudioNodeState,
     phase: f32,
-    pub audio_producer: Option<Producer<f32>>,
-    keep_running: Arc<AtomicBool>,
-    producer_handler: Option<JoinHandle<(Producer<f32>, f32)>>,
+    pub audio_producer: Option<FanOut>,
+    clock: Option<(NodeId, RenderClock)>,
+}
+
+impl FakeAudioWaveSRC {
+    pub(crate) fn attach_clock(&mut self, id: NodeId, clock: RenderClock) {
+        self.clock = Some((id, clock));
+    }
 }
 
 impl AudioNode for FakeAudioWaveSRC {
@@ -19,58 +25,48 @@ impl AudioNode for FakeAudioWaveSRC {
         Self {
             state: AudioNodeState::INITIALIZED,
             phase: 0.0,
-            audio_producer: None,
-            keep_running: Arc::new(AtomicBool::new(false)),
-            producer_handler: None,
+            audio_producer: Some(FanOut::new()),
+            clock: None,
         }
     }
     fn start(&mut self) {
-        let sleep_ms = 10;
-        let mut producer = match self.audio_producer.take() {
+        let producer = match self.audio_producer.take() {
             Some(p) => p,
             None => panic!("FakeAudioWaveSRC: cannot start audio node"),
         };
-
-        let mut phase = self.phase;
-        let keep_running = Arc::clone(&self.keep_running);
-        keep_running.store(true, Ordering::Relaxed);
-
-        self.producer_handler = Some(thread::spawn(move || {
-            println!("Producer Thread Started");
-
-            while keep_running.load(Ordering::Relaxed) {
-                match generate_sine_wave(&mut producer, phase) {
-                    Ok(new_phase) => {
-                        phase = new_phase;
-                    }
-                    Err(e) => {
-                        println!("{}", e);
-                        break;
-                    }
-                }
-                // 避免空轉佔用 CPU，當 Buffer 滿時休息一下
-                thread::sleep(std::time::Duration::from_millis(sleep_ms));
+        let (id, clock) = match self.clock.as_ref() {
+            Some(c) => c,
+            None => {
+                panic!("FakeAudioWaveSRC: cannot start - no clock, start it through AudioGraph")
             }
+        };
 
-            println!("Producer Thread Stopped");
-            (producer, phase)
-        }));
+        // 不再自己開 thread，由下游的 clock 要多少就產生多少
+        clock.insert(
+            *id,
+            ClockJob::Wave(WaveCore {
+                producer,
+                phase: self.phase,
+            }),
+        );
+        println!("Producer attached to clock");
 
         self.state = AudioNodeState::RUNNING;
     }
 
     fn stop(&mut self) {
-        self.keep_running.store(false, Ordering::Relaxed);
-
-        if let Some(handle) = self.producer_handler.take() {
-            if let Ok((producer, last_phase)) = handle.join() {
+        match self
+            .clock
+            .as_ref()
+            .and_then(|(id, clock)| clock.remove(*id))
+        {
+            Some(ClockJob::Wave(core)) => {
                 // store back state
-                self.audio_producer = Some(producer);
-                self.phase = last_phase;
+                self.audio_producer = Some(core.producer);
+                self.phase = core.phase;
                 println!("Producer and Phase returned to struct");
             }
-        } else {
-            panic!("FakeAudioWaveSRC: cannot stop audio node");
+            _ => panic!("FakeAudioWaveSRC: cannot stop audio node"),
         }
 
         self.state = AudioNodeState::STOPPED;
@@ -79,23 +75,40 @@ impl AudioNode for FakeAudioWaveSRC {
         AudioNodeType::SOURCE
     }
     fn get_state(&self) -> AudioNodeState {
-        self.state.clone()
+        self.state
     }
 }
 
-fn generate_sine_wave(producer: &mut Producer<f32>, phase: f32) -> Result<f32, String> {
+/// What the sine source needs on the clock
+pub(crate) struct WaveCore {
+    producer: FanOut,
+    phase: f32,
+}
+
+impl WaveCore {
+    pub(crate) fn process(&mut self, samples: usize) {
+        match generate_sine_wave(&mut self.producer, self.phase, samples) {
+            Ok(new_phase) => self.phase = new_phase,
+            Err(e) => println!("{}", e),
+        }
+    }
+}
+
+fn generate_sine_wave(producer: &mut FanOut, phase: f32, samples: usize) -> Result<f32, String> {
     let sample_rate = MOCK_AUDIO_SAMPLE_RATE;
     let frequency = MOCK_AUDIO_SAMPLE_HZ;
     let mut cur_phase = phase;
 
-    while producer.slots() >= 2 {
-        for _ in 0..2 {
-            let val = (cur_phase * 2.0 * std::f32::consts::PI).sin() * 0.1;
-            if producer.push(val).is_err() {
-                return Err("FakeAudioWaveSRC: push error".into());
-            }
+    let frames = samples.min(producer.slots()) / FAKE_AUDIO_CHANNELS;
+    let mut frame = [0.0; FAKE_AUDIO_CHANNELS];
+    for _ in 0..frames {
+        for val in frame.iter_mut() {
+            *val = (cur_phase * 2.0 * std::f32::consts::PI).sin() * 0.1;
             cur_phase = (cur_phase + frequency / sample_rate) % 1.0;
         }
+        if !producer.push_frame(&frame) {
+            return Err("FakeAudioWaveSRC: push error".into());
+        }
     }
     Ok(cur_phase)
 }
@@ -0,0 +1,232 @@
+/***
+ * @ Mod:       fan_out
+ * @ Author:    Leon Lin
+ * @ Date:      20261017
+ */
+
+use crate::audio_node::graph::NodeId;
+use rtrb::{Consumer, Producer};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, Thread};
+
+/// Buffer-level wake up between the two ends of one ring buffer.
+/// The producer thread parks when the ring is full, the consumer unparks it once the ring
+/// drained below half, so nobody has to poll.
+/// It also carries flush requests: the producer asks, the consumer throws away what is queued
+/// and answers, the producer writes nothing new until then.
+#[derive(Default)]
+pub struct RoomSignal {
+    waiting: AtomicBool,
+    thread: Mutex<Option<Thread>>,
+    flush: AtomicBool,
+    // samples the consumer threw away answering the last flush
+    discarded: AtomicUsize,
+    // running totals of both ends, their difference is what is still queued
+    written: AtomicU64,
+    read: AtomicU64,
+    // `written` when the producer last marked a point in its stream
+    mark: AtomicU64,
+}
+
+impl RoomSignal {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    /// Called by the consumer after reading `samples`, never blocks (safe on the audio thread)
+    pub fn consumed(&self, consumer: &Consumer<f32>, samples: usize) {
+        self.read.fetch_add(samples as u64, Ordering::Relaxed);
+        if consumer.slots() > consumer.buffer().capacity() / 2 {
+            return;
+        }
+        self.wake();
+    }
+
+    /// Called by the consumer before reading, drops everything queued when the producer asked
+    /// for a flush. Never blocks (safe on the audio thread)
+    pub fn discard_flushed(&self, consumer: &mut Consumer<f32>) {
+        if !self.flush.load(Ordering::Acquire) {
+            return;
+        }
+        let stale = consumer.slots();
+        if let Ok(chunk) = consumer.read_chunk(stale) {
+            chunk.commit_all();
+        }
+        self.discarded.store(stale, Ordering::Relaxed);
+        self.read.fetch_add(stale as u64, Ordering::Relaxed);
+        self.flush.store(false, Ordering::Release);
+        self.wake();
+    }
+
+    /// Samples written but not read yet
+    pub fn queued(&self) -> usize {
+        let read = self.read.load(Ordering::Relaxed);
+        self.written.load(Ordering::Relaxed).saturating_sub(read) as usize
+    }
+
+    /// Samples the consumer read past the producer's last `FanOut::mark`, 0 while it still
+    /// reads (or drops) what came before
+    pub fn read_since_mark(&self) -> usize {
+        let mark = self.mark.load(Ordering::Relaxed);
+        self.read.load(Ordering::Relaxed).saturating_sub(mark) as usize
+    }
+
+    fn wake(&self) {
+        if self.waiting.swap(false, Ordering::AcqRel) {
+            // producer only holds the lock while registering, it re-checks the ring after that
+            if let Ok(thread) = self.thread.try_lock() {
+                if let Some(thread) = thread.as_ref() {
+                    thread.unpark();
+                }
+            }
+        }
+    }
+
+    /// Register the current thread to be woken, check for room again before parking
+    fn prepare_wait(&self) {
+        *self.thread.lock().unwrap() = Some(thread::current());
+        self.waiting.store(true, Ordering::Release);
+    }
+
+    fn request_flush(&self) {
+        self.discarded.store(0, Ordering::Relaxed);
+        self.flush.store(true, Ordering::Release);
+    }
+
+    pub fn flush_pending(&self) -> bool {
+        self.flush.load(Ordering::Acquire)
+    }
+}
+
+/// Output side of a node, one ring buffer producer per downstream node.
+/// Every sample is written to all links, so a source can feed several consumers.
+#[derive(Default)]
+pub struct FanOut {
+    links: Vec<(NodeId, Producer<f32>, Arc<RoomSignal>)>,
+    // samples written since creation, the same count went to every link
+    written: u64,
+}
+
+impl FanOut {
+    pub fn new() -> Self {
+        Self {
+            links: Vec::new(),
+            written: 0,
+        }
+    }
+
+    /// Link to `dest`, `signal` is shared with the consumer end of `producer`
+    pub fn add(&mut self, dest: NodeId, producer: Producer<f32>, signal: Arc<RoomSignal>) {
+        self.prune_abandoned();
+        self.links.push((dest, producer, signal));
+    }
+
+    pub fn remove(&mut self, dest: NodeId) -> Option<Producer<f32>> {
+        let index = self.links.iter().position(|(id, _, _)| *id == dest)?;
+        Some(self.links.remove(index).1)
+    }
+
+    pub fn contains(&self, dest: NodeId) -> bool {
+        self.links.iter().any(|(id, _, _)| *id == dest)
+    }
+
+    pub fn len(&self) -> usize {
+        self.links.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.links.is_empty()
+    }
+
+    /// Free slots of the slowest live consumer, all links advance together
+    pub fn slots(&self) -> usize {
+        self.links
+            .iter()
+            .filter(|(_, p, _)| !p.is_abandoned())
+            .map(|(_, p, _)| p.slots())
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Write one interleaved frame to every link, or nothing at all when any live consumer
+    /// lacks room for the whole frame, so a consumer never sees half a frame
+    pub fn push_frame(&mut self, frame: &[f32]) -> bool {
+        if self.slots() < frame.len() {
+            return false;
+        }
+        for (_, producer, signal) in self.links.iter_mut() {
+            if producer.is_abandoned() {
+                continue;
+            }
+            for sample in frame {
+                // room was checked above, this can't fail
+                let _ = producer.push(*sample);
+            }
+            signal
+                .written
+                .fetch_add(frame.len() as u64, Ordering::Relaxed);
+        }
+        self.written += frame.len() as u64;
+        true
+    }
+
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    /// Ask every consumer to drop what is queued. Write nothing until `flush_pending` is false,
+    /// or the new samples are thrown away with the stale ones.
+    pub fn request_flush(&self) {
+        for (_, _, signal) in self.live_links() {
+            signal.request_flush();
+        }
+    }
+
+    /// Some live consumer has not answered `request_flush` yet
+    pub fn flush_pending(&self) -> bool {
+        self.live_links()
+            .any(|(_, _, signal)| signal.flush_pending())
+    }
+
+    /// Samples thrown away by the consumer that had the most queued, valid once the flush was
+    /// answered
+    pub fn discarded(&self) -> usize {
+        self.live_links()
+            .map(|(_, _, signal)| signal.discarded.load(Ordering::Relaxed))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Remember the current write position on every link, see `RoomSignal::read_since_mark`
+    pub fn mark(&self) {
+        for (_, _, signal) in self.live_links() {
+            let written = signal.written.load(Ordering::Relaxed);
+            signal.mark.store(written, Ordering::Relaxed);
+        }
+    }
+
+    /// Signals of the live links, stay valid while the output is owned by a thread
+    pub fn signals(&self) -> Vec<Arc<RoomSignal>> {
+        self.live_links()
+            .map(|(_, _, signal)| Arc::clone(signal))
+            .collect()
+    }
+
+    fn live_links(&self) -> impl Iterator<Item = &(NodeId, Producer<f32>, Arc<RoomSignal>)> {
+        self.links.iter().filter(|(_, p, _)| !p.is_abandoned())
+    }
+
+    /// Ask every consumer to wake the current thread once it made room.
+    /// Check for room again afterwards, then `thread::park`.
+    pub fn prepare_wait(&self) {
+        for (_, _, signal) in self.links.iter() {
+            signal.prepare_wait();
+        }
+    }
+
+    /// Drop links whose consumer was removed together with its node
+    fn prune_abandoned(&mut self) {
+        self.links.retain(|(_, p, _)| !p.is_abandoned());
+    }
+}
@@ -4,31 +4,58 @@
  * @ Date:      20260127
  */
 
+use crate::audio_node::channel_map::ChannelMatrix;
+use crate::audio_node::fan_out::{FanOut, RoomSignal};
 use crate::audio_node::node_const::{
-    RESAMPLE_BUFFER_CAPACITY, RESAMPLE_INNER_CACHE_BUFFER_CAPACITY,
+    RESAMPLE_BUFFER_CAPACITY, RESAMPLE_INNER_CACHE_BUFFER_CAPACITY, SEEK_CATCH_UP_SAMPLES,
+    SOURCE_PARK_TIMEOUT_MS,
 };
-use crate::audio_node::utils::ResamplingHandler;
+use crate::audio_node::offline::RenderStep;
+use crate::audio_node::utils::{ResampleQuality, ResamplingHandler};
 use crate::audio_node::{AudioNode, AudioNodeState, AudioNodeType};
 use cpal::{BufferSize, ChannelCount, StreamConfig};
-use rodio::Source;
-use rtrb::{Producer, RingBuffer};
+use rodio::{Decoder, Source};
+use rtrb::RingBuffer;
 use std::fs::File;
 use std::io::BufReader;
-use std::path::PathBuf;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
 use std::thread;
 use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// Interleaved samples the producer thread pulls, a decoded file or anything else that plays
+pub type SampleSource = Box<dyn Source<Item = i16> + Send>;
+/// Opens `file_path` as a `SampleSource`, called on start and again when a seek has to start
+/// over from the top
+pub type SourceOpener = Arc<dyn Fn(&Path) -> Result<SampleSource, String> + Send + Sync>;
 
 pub struct FileSrc {
     pub state: AudioNodeState,
-    pub audio_producer: Option<Producer<f32>>,
+    pub audio_producer: Option<FanOut>,
     keep_running: Arc<AtomicBool>,
-    producer_handler: Option<JoinHandle<Producer<f32>>>,
+    // set by the producer thread when the decoder ran out, it exits right after
+    ended: Arc<AtomicBool>,
+    producer_handler: Option<JoinHandle<Box<FileReader>>>,
     file_path: Option<PathBuf>,
     producer_sample_rate: Option<u32>,
     producer_channels: Option<usize>,
-    sleep_ms: u64,
+    // None means the standard mapping for whatever the file turns out to have
+    channel_matrix: Option<ChannelMatrix>,
+    resample_quality: ResampleQuality,
+    // where the next start begins, set by seeking a node that is not playing
+    start_position: Duration,
+    // decoder kept between pause and resume
+    paused_reader: Option<Box<FileReader>>,
+    offline_reader: Option<Box<FileReader>>,
+    // output frame at the producer's last mark on its links, written by the producer thread
+    origin: Arc<AtomicU64>,
+    // rings the producer thread writes to, whatever their consumer read is gone downstream
+    output_signals: Vec<Arc<RoomSignal>>,
+    paused_frame: u64,
+    duration: Option<Duration>,
+    opener: SourceOpener,
 }
 
 impl FileSrc {
@@ -37,138 +64,308 @@ impl FileSrc {
         self.producer_sample_rate = Some(sample_rate);
         self.producer_channels = Some(channels);
     }
-}
 
-impl AudioNode for FileSrc {
-    fn init() -> Self {
-        // Default initialization
-        Self {
-            state: AudioNodeState::INITIALIZED,
-            audio_producer: None,
-            keep_running: Arc::new(AtomicBool::new(false)),
-            producer_handler: None,
-            file_path: None,
-            producer_sample_rate: None,
-            producer_channels: None,
-            sleep_ms: 10,
+    /// Interleaved channels written downstream, `None` until configured
+    pub fn channels(&self) -> Option<usize> {
+        self.producer_channels
+    }
+
+    /// Custom source to target channel mapping, checked against the file when it is opened
+    pub fn set_channel_matrix(&mut self, matrix: Option<ChannelMatrix>) {
+        self.channel_matrix = matrix;
+    }
+
+    /// What the file is read with, rodio's decoder unless a node plays something else through
+    /// this one. Takes effect on next start.
+    pub fn set_opener(&mut self, opener: SourceOpener) {
+        self.opener = opener;
+    }
+
+    /// Resampler used when the file rate differs from the target, takes effect on next start
+    pub fn set_resample_quality(&mut self, quality: ResampleQuality) {
+        self.resample_quality = quality;
+    }
+
+    /// Length of the file, `None` before it is opened or when the format does not say
+    pub fn duration(&self) -> Option<Duration> {
+        self.duration
+    }
+
+    /// What the nodes downstream took so far, what still waits in the rings this node writes
+    /// to doesn't count. Take `AudioGraph::output_latency_frames` off for what is audible.
+    pub fn position(&self) -> Duration {
+        let frame = match self.state {
+            AudioNodeState::RUNNING => self.consumed_frame(),
+            AudioNodeState::PAUSED => self.paused_frame,
+            _ => return self.start_position,
+        };
+        self.frames_to_duration(frame)
+    }
+
+    /// Slowest consumer decides, a fast one may have read ahead
+    fn consumed_frame(&self) -> u64 {
+        let read = self
+            .output_signals
+            .iter()
+            .map(|s| s.read_since_mark())
+            .min()
+            .unwrap_or(0);
+        let channels = self.producer_channels.unwrap_or(1).max(1);
+        self.origin.load(Ordering::Relaxed) + (read / channels) as u64
+    }
+
+    /// The decoder reached the end of the file and the nodes downstream took all of it. The node
+    /// still counts as running, stop or remove it (or seek to play again).
+    pub fn is_finished(&self) -> bool {
+        matches!(self.state, AudioNodeState::RUNNING)
+            && self.ended.load(Ordering::Relaxed)
+            && self.output_signals.iter().all(|s| s.queued() == 0)
+    }
+
+    fn frames_to_duration(&self, frames: u64) -> Duration {
+        let rate = self.producer_sample_rate.unwrap_or(1).max(1);
+        Duration::from_secs_f64(frames as f64 / rate as f64)
+    }
+
+    /// Hold the decoder where it is, whatever is still buffered downstream is dropped and
+    /// played again after `resume`
+    pub fn pause(&mut self) -> Result<(), String> {
+        if !matches!(self.state, AudioNodeState::RUNNING) {
+            return Err("FileSrc: not playing".to_string());
         }
+        let mut reader = self.join_reader()?;
+        // what is queued now gets dropped, the position stays on what was taken
+        self.paused_frame = self.consumed_frame();
+        reader.pause();
+        self.paused_reader = Some(reader);
+        self.state = AudioNodeState::PAUSED;
+        println!("[FileSrc] Paused");
+        Ok(())
     }
 
-    fn start(&mut self) {
-        let sleep_ms = self.sleep_ms;
+    pub fn resume(&mut self) -> Result<(), String> {
+        let reader = match self.paused_reader.take() {
+            Some(r) => r,
+            None => return Err("FileSrc: not paused".to_string()),
+        };
+        self.spawn(reader);
+        println!("[FileSrc] Resumed");
+        Ok(())
+    }
+
+    /// Jump to `position`. Playing and paused nodes flush the resampler and every ring buffer
+    /// they feed, a node that is not started yet begins there on the next start. A source
+    /// that can't seek is decoded up to `position` on the producer thread, the position counts
+    /// from there right away and nothing plays until it got there.
+    pub fn seek(&mut self, position: Duration) -> Result<(), String> {
+        match self.state {
+            AudioNodeState::RUNNING => {
+                let mut reader = self.join_reader()?;
+                reader.seek(position);
+                self.spawn(reader);
+            }
+            AudioNodeState::PAUSED => {
+                let reader = self
+                    .paused_reader
+                    .as_mut()
+                    .ok_or("FileSrc: paused without a decoder".to_string())?;
+                reader.seek(position);
+                self.paused_frame = reader.origin.load(Ordering::Relaxed);
+            }
+            _ => self.start_position = position,
+        }
+        println!("[FileSrc] Seek to {:?}", position);
+        Ok(())
+    }
+
+    /// Take everything `start` needs, shared by live and offline mode
+    fn take_start_config(&mut self) -> (FanOut, PathBuf, u32, usize) {
         let producer = match self.audio_producer.take() {
             Some(p) => p,
             None => panic!("FileSrc: cannot start audio node - no producer"),
         };
-        let file_path = match self.file_path.take() {
+        // config stays so a stopped node can start again, the graph needs the channels too
+        let file_path = match self.file_path.clone() {
             Some(p) => p,
             None => panic!("FileSrc: cannot start audio node - no file path"),
         };
-        let target_sample_rate = match self.producer_sample_rate.take() {
+        let target_sample_rate = match self.producer_sample_rate {
             Some(p) => p,
-            None => panic!("FileSrc: cannot start audio node - no file path"),
+            None => panic!("FileSrc: cannot start audio node - no sample rate"),
         };
-        let target_channels = match self.producer_channels.take() {
+        let target_channels = match self.producer_channels {
             Some(p) => p,
-            None => panic!("FileSrc: cannot start audio node - no file path"),
+            None => panic!("FileSrc: cannot start audio node - no channels"),
+        };
+        (producer, file_path, target_sample_rate, target_channels)
+    }
+
+    /// Open the file and put the decoder at `start_position`, the producer comes back on error
+    fn open_reader(&mut self) -> Result<Box<FileReader>, String> {
+        let (producer, file_path, target_sample_rate, target_channels) = self.take_start_config();
+        println!("[FileSrc] Loading file: {:?}", file_path);
+        let source = match (self.opener)(&file_path) {
+            Ok(s) => s,
+            Err(e) => {
+                self.audio_producer = Some(producer);
+                return Err(e);
+            }
         };
+        self.duration = source.total_duration();
+        let target = StreamConfig {
+            channels: target_channels as ChannelCount,
+            sample_rate: target_sample_rate,
+            buffer_size: BufferSize::Default,
+        };
+        let mut reader = Box::new(FileReader::new(
+            source,
+            file_path,
+            Arc::clone(&self.opener),
+            producer,
+            target,
+            self.channel_matrix.clone(),
+            self.resample_quality,
+        ));
+        self.origin = Arc::clone(&reader.origin);
+        let position = std::mem::take(&mut self.start_position);
+        if !position.is_zero() {
+            reader.reposition(position);
+        }
+        Ok(reader)
+    }
 
+    fn spawn(&mut self, mut reader: Box<FileReader>) {
+        self.output_signals = reader.resampler.producer.signals();
         let keep_running = Arc::clone(&self.keep_running);
         keep_running.store(true, Ordering::Relaxed);
+        let ended = Arc::clone(&self.ended);
+        ended.store(false, Ordering::Relaxed);
 
         self.producer_handler = Some(thread::spawn(move || {
             println!("[FileSrc] Producer Thread Started");
-            println!("[FileSrc] Loading file: {:?}", file_path);
-
-            // Open the file and decode
-            let file = match File::open(&file_path) {
-                Ok(f) => f,
-                Err(e) => {
-                    eprintln!("[FileSrc] Failed to open file: {}", e);
-                    return producer;
-                }
-            };
-
-            let mut source = match rodio::Decoder::new(BufReader::new(file)) {
-                Ok(s) => s,
-                Err(e) => {
-                    eprintln!("[FileSrc] Failed to decode file: {}", e);
-                    return producer;
-                }
-            };
-
-            let source_sample_rate = source.sample_rate();
-            let source_channels = source.channels() as usize;
-            println!(
-                "[FileSrc] Source: {}Hz, {} channels",
-                source_sample_rate, source_channels
-            );
-            println!(
-                "[FileSrc] Target: {}Hz, {} channels",
-                target_sample_rate, target_channels
-            );
-
-            let src_cfg = StreamConfig {
-                channels: source_channels as ChannelCount,
-                sample_rate: source_sample_rate,
-                buffer_size: BufferSize::Default,
-            };
-            let trg_cfg = StreamConfig {
-                channels: target_channels as ChannelCount,
-                sample_rate: target_sample_rate,
-                buffer_size: BufferSize::Default,
-            };
-            let chunk_size = RESAMPLE_BUFFER_CAPACITY * source_channels;
-            let (resample_producer, resample_consumer) =
-                RingBuffer::<f32>::new(RESAMPLE_INNER_CACHE_BUFFER_CAPACITY);
-            let mut resampler = ResamplingHandler::new(
-                producer,
-                src_cfg,
-                trg_cfg,
-                resample_producer,
-                resample_consumer,
-                chunk_size,
-            );
-            let mut data_buffer = vec![0; chunk_size];
-            // Convert samples to f32 and handle resampling if needed
-            let mut is_end = false;
             while keep_running.load(Ordering::Relaxed) {
-                if is_end {
+                if reader.is_end() {
+                    println!("[FileSrc] End of file");
+                    ended.store(true, Ordering::Relaxed);
                     break;
                 }
-                while !resampler.check_must_no_loss_data(chunk_size) {
-                    thread::sleep(std::time::Duration::from_millis(sleep_ms));
+                if reader.catching_up() {
+                    continue;
                 }
-                for cur_chunk_size in 0..chunk_size {
-                    let sample_option = source.next();
-                    if let Some(sample) = sample_option {
-                        data_buffer[cur_chunk_size] = sample;
-                    } else {
-                        is_end = true;
-                        data_buffer[cur_chunk_size] = 0;
+                // after a seek or pause nothing new goes out before the stale audio is gone,
+                // then sleep until the consumers drained the ring. The timeout only covers a
+                // consumer that went away without waking us
+                if reader.flushing() || !reader.has_room() {
+                    reader.prepare_wait();
+                    if reader.flushing() || !reader.has_room() {
+                        thread::park_timeout(Duration::from_millis(SOURCE_PARK_TIMEOUT_MS));
                     }
+                    continue;
                 }
-                resampler.process_packet(&data_buffer);
+                reader.decode_chunk();
             }
-            resampler.producer
+            reader
         }));
 
         self.state = AudioNodeState::RUNNING;
     }
 
-    fn stop(&mut self) {
+    /// Stop the producer thread and get the decoder back
+    fn join_reader(&mut self) -> Result<Box<FileReader>, String> {
         self.keep_running.store(false, Ordering::Relaxed);
+        let handle = self
+            .producer_handler
+            .take()
+            .ok_or("FileSrc: no producer thread".to_string())?;
+        handle.thread().unpark();
+        handle
+            .join()
+            .map_err(|_| "FileSrc: producer thread panicked".to_string())
+    }
 
-        if let Some(handle) = self.producer_handler.take() {
-            if let Ok(producer) = handle.join() {
-                self.audio_producer = Some(producer);
-                println!("[FileSrc] Producer returned to struct");
+    pub(crate) fn begin_offline(&mut self) -> Result<(), String> {
+        self.offline_reader = Some(self.open_reader()?);
+        self.state = AudioNodeState::RUNNING;
+        Ok(())
+    }
+
+    pub(crate) fn step_offline(&mut self) -> RenderStep {
+        let reader = match self.offline_reader.as_mut() {
+            Some(r) => r,
+            None => return RenderStep::Finished,
+        };
+        if reader.is_end() {
+            // dropping the producer tells downstream nothing more is coming
+            self.offline_reader = None;
+            self.state = AudioNodeState::STOPPED;
+            return RenderStep::Finished;
+        }
+        if reader.catching_up() {
+            return RenderStep::Progress;
+        }
+        if !reader.has_room() {
+            return RenderStep::Idle;
+        }
+        reader.decode_chunk();
+        RenderStep::Progress
+    }
+}
+
+impl AudioNode for FileSrc {
+    fn init() -> Self {
+        // Default initialization
+        Self {
+            state: AudioNodeState::INITIALIZED,
+            audio_producer: Some(FanOut::new()),
+            keep_running: Arc::new(AtomicBool::new(false)),
+            ended: Arc::new(AtomicBool::new(false)),
+            producer_handler: None,
+            file_path: None,
+            producer_sample_rate: None,
+            producer_channels: None,
+            channel_matrix: None,
+            resample_quality: ResampleQuality::default(),
+            start_position: Duration::ZERO,
+            paused_reader: None,
+            offline_reader: None,
+            origin: Arc::new(AtomicU64::new(0)),
+            output_signals: Vec::new(),
+            paused_frame: 0,
+            duration: None,
+            opener: Arc::new(open_decoder),
+        }
+    }
+
+    fn start(&mut self) {
+        if matches!(self.state, AudioNodeState::PAUSED) {
+            // start on a paused node is a resume, it keeps the position
+            let _ = self.resume();
+            return;
+        }
+        match self.open_reader() {
+            Ok(reader) => self.spawn(reader),
+            Err(e) => {
+                eprintln!("[FileSrc] {}", e);
+                self.state = AudioNodeState::STOPPED;
             }
-        } else {
-            // Thread already finished or was never started - this is fine
-            println!(
-                "[FileSrc] Stop called but no active thread (already finished or never started)"
-            );
+        }
+    }
+
+    fn stop(&mut self) {
+        let reader = match self.join_reader() {
+            Ok(reader) => Some(reader),
+            Err(_) => {
+                // Thread already finished or was never started - this is fine
+                println!("[FileSrc] Stop called but no active thread (already finished or never started)");
+                self.paused_reader.take()
+            }
+        };
+        if let Some(reader) = reader {
+            let producer = reader.into_producer();
+            // the next start begins at the top, nothing of this run may play after it
+            producer.request_flush();
+            self.audio_producer = Some(producer);
+            println!("[FileSrc] Producer returned to struct");
         }
 
         self.state = AudioNodeState::STOPPED;
@@ -182,3 +379,226 @@ impl AudioNode for FileSrc {
         self.state
     }
 }
+
+fn open_decoder(file_path: &Path) -> Result<SampleSource, String> {
+    // Open the file and decode
+    let file = File::open(file_path).map_err(|e| format!("Failed to open file: {}", e))?;
+    let decoder =
+        Decoder::new(BufReader::new(file)).map_err(|e| format!("Failed to decode file: {}", e))?;
+    Ok(Box::new(decoder))
+}
+
+/// Source plus resampler, pushes one chunk per call into the node output
+struct FileReader {
+    source: SampleSource,
+    file_path: PathBuf,
+    opener: SourceOpener,
+    // `origin_frame` shared with the node
+    origin: Arc<AtomicU64>,
+    resampler: ResamplingHandler,
+    data_buffer: Vec<i16>,
+    chunk_size: usize,
+    is_end: bool,
+    target_sample_rate: u32,
+    target_channels: usize,
+    // output frame that `origin_written` samples of the producer stand for
+    origin_frame: u64,
+    origin_written: u64,
+    // waiting for the consumers to answer a flush, `rewind` goes back over what they dropped
+    flushing: bool,
+    rewind: bool,
+    // seek target of a source that couldn't jump there, it is reopened and `skip` samples
+    // from the top are thrown away
+    catch_up: Option<Duration>,
+    skip: u64,
+}
+
+impl FileReader {
+    fn new(
+        source: SampleSource,
+        file_path: PathBuf,
+        opener: SourceOpener,
+        producer: FanOut,
+        trg_cfg: StreamConfig,
+        channel_matrix: Option<ChannelMatrix>,
+        resample_quality: ResampleQuality,
+    ) -> Self {
+        let target_sample_rate = trg_cfg.sample_rate;
+        let target_channels = trg_cfg.channels as usize;
+        let source_sample_rate = source.sample_rate();
+        let source_channels = source.channels() as usize;
+        println!(
+            "[FileSrc] Source: {}Hz, {} channels",
+            source_sample_rate, source_channels
+        );
+        println!(
+            "[FileSrc] Target: {}Hz, {} channels",
+            target_sample_rate, target_channels
+        );
+
+        let src_cfg = StreamConfig {
+            channels: source_channels as ChannelCount,
+            sample_rate: source_sample_rate,
+            buffer_size: BufferSize::Default,
+        };
+        let chunk_size = RESAMPLE_BUFFER_CAPACITY * source_channels;
+        // resampler takes `chunk_size` frames per round, the cache has to hold two rounds even
+        // for files with many channels
+        let cache_capacity =
+            RESAMPLE_INNER_CACHE_BUFFER_CAPACITY.max(chunk_size * source_channels * 2);
+        let (resample_producer, resample_consumer) = RingBuffer::<f32>::new(cache_capacity);
+        let mut resampler = ResamplingHandler::new(
+            producer,
+            src_cfg,
+            trg_cfg,
+            resample_producer,
+            resample_consumer,
+            chunk_size,
+            resample_quality,
+        );
+        if let Some(matrix) = channel_matrix {
+            if let Err(e) = resampler.set_channel_matrix(matrix) {
+                println!("[FileSrc] {}, using the standard mapping", e);
+            }
+        }
+
+        // a stopped run may have asked for a flush the consumers did not answer yet
+        let flushing = resampler.producer.flush_pending();
+        let origin_written = resampler.producer.written();
+        resampler.producer.mark();
+        Self {
+            source,
+            file_path,
+            opener,
+            origin: Arc::new(AtomicU64::new(0)),
+            resampler,
+            data_buffer: vec![0; chunk_size],
+            chunk_size,
+            is_end: false,
+            target_sample_rate,
+            target_channels,
+            origin_frame: 0,
+            origin_written,
+            flushing,
+            rewind: false,
+            catch_up: None,
+            skip: 0,
+        }
+    }
+
+    /// Output frames handed downstream since the top of the file
+    fn position_frames(&self) -> u64 {
+        let written = self.resampler.producer.written() - self.origin_written;
+        self.origin_frame + written / self.target_channels as u64
+    }
+
+    fn pause(&mut self) {
+        self.resampler.producer.request_flush();
+        self.flushing = true;
+        self.rewind = true;
+    }
+
+    fn seek(&mut self, position: Duration) {
+        self.resampler.producer.request_flush();
+        self.flushing = true;
+        self.rewind = false;
+        self.reposition(position);
+    }
+
+    /// Still waiting for a flush to be answered, once it is a pause goes back over the samples
+    /// that were thrown away
+    fn flushing(&mut self) -> bool {
+        if !self.flushing {
+            return false;
+        }
+        if self.resampler.producer.flush_pending() {
+            return true;
+        }
+        self.flushing = false;
+        if std::mem::take(&mut self.rewind) {
+            let dropped = (self.resampler.producer.discarded() / self.target_channels) as u64;
+            let frame = self.position_frames().saturating_sub(dropped);
+            self.reposition(Duration::from_secs_f64(
+                frame as f64 / self.target_sample_rate as f64,
+            ));
+        }
+        false
+    }
+
+    /// Put the decoder at `position` and restart the resampler, nothing is flushed. A decoder
+    /// that can't seek gets there through `catching_up`.
+    fn reposition(&mut self, position: Duration) {
+        self.catch_up = None;
+        self.skip = 0;
+        if let Err(e) = self.source.try_seek(position) {
+            println!("[FileSrc] {}, decoding up to {:?}", e, position);
+            self.catch_up = Some(position);
+        }
+        self.resampler.reset();
+        self.is_end = false;
+        self.origin_frame = (position.as_secs_f64() * self.target_sample_rate as f64) as u64;
+        self.origin_written = self.resampler.producer.written();
+        self.resampler.producer.mark();
+        self.origin.store(self.origin_frame, Ordering::Relaxed);
+    }
+
+    /// Decode another slice of the way to a seek target the decoder couldn't jump to, false
+    /// once it is there. Nothing goes out before that.
+    fn catching_up(&mut self) -> bool {
+        if let Some(position) = self.catch_up.take() {
+            match (self.opener)(&self.file_path) {
+                Ok(source) => self.source = source,
+                Err(e) => {
+                    eprintln!("[FileSrc] {}", e);
+                    self.is_end = true;
+                    return false;
+                }
+            }
+            let frames = (position.as_secs_f64() * self.source.sample_rate() as f64) as u64;
+            self.skip = frames * self.source.channels() as u64;
+        }
+        if self.skip == 0 {
+            return false;
+        }
+        let samples = self.skip.min(SEEK_CATCH_UP_SAMPLES);
+        for _ in 0..samples {
+            if self.source.next().is_none() {
+                // past the end, the next chunk finds out
+                self.skip = 0;
+                return false;
+            }
+        }
+        self.skip -= samples;
+        true
+    }
+
+    fn is_end(&self) -> bool {
+        self.is_end
+    }
+
+    fn has_room(&mut self) -> bool {
+        self.resampler.check_must_no_loss_data(self.chunk_size)
+    }
+
+    fn prepare_wait(&self) {
+        self.resampler.producer.prepare_wait();
+    }
+
+    // Convert samples to f32 and handle resampling if needed
+    fn decode_chunk(&mut self) {
+        for slot in self.data_buffer.iter_mut() {
+            let sample_option = self.source.next();
+            if let Some(sample) = sample_option {
+                *slot = sample;
+            } else {
+                self.is_end = true;
+                *slot = 0;
+            }
+        }
+        self.resampler.process_packet(&self.data_buffer);
+    }
+
+    fn into_producer(self) -> FanOut {
+        self.resampler.producer
+    }
+}
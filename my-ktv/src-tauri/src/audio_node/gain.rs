@@ -0,0 +1,337 @@
+/***
+ * @ Mod:       gain
+ * @ Author:    Leon Lin
+ * @ Date:      20261017
+ */
+
+use crate::audio_node::clock::{ClockJob, RenderClock};
+use crate::audio_node::fan_out::{FanOut, RoomSignal};
+use crate::audio_node::graph::NodeId;
+use crate::audio_node::node_const::{
+    GAIN_SMOOTHING_COEFF, PULL_RING_BUFFER_CAPACITY, PUSH_RING_BUFFER_CAPACITY,
+};
+use crate::audio_node::offline::RenderStep;
+use crate::audio_node::{AudioNode, AudioNodeState, AudioNodeType};
+use rtrb::{Consumer, Producer, RingBuffer};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+
+// below this the gain is treated as silence
+const MIN_GAIN_DB: f32 = -96.0;
+
+pub fn db_to_linear(db: f32) -> f32 {
+    if db <= MIN_GAIN_DB {
+        0.0
+    } else {
+        10f32.powf(db / 20.0)
+    }
+}
+
+pub fn linear_to_db(linear: f32) -> f32 {
+    if linear <= 0.0 {
+        MIN_GAIN_DB
+    } else {
+        (20.0 * linear.log10()).max(MIN_GAIN_DB)
+    }
+}
+
+struct GainShared {
+    gain_bits: AtomicU32,
+    muted: AtomicBool,
+}
+
+/// Thread safe handle to a gain setting, the UI thread writes and the audio thread reads
+#[derive(Clone)]
+pub struct GainControl {
+    inner: Arc<GainShared>,
+}
+
+impl GainControl {
+    pub fn new(linear: f32) -> Self {
+        Self {
+            inner: Arc::new(GainShared {
+                gain_bits: AtomicU32::new(linear.max(0.0).to_bits()),
+                muted: AtomicBool::new(false),
+            }),
+        }
+    }
+
+    pub fn set_gain(&self, linear: f32) {
+        self.inner
+            .gain_bits
+            .store(linear.max(0.0).to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn set_gain_db(&self, db: f32) {
+        self.set_gain(db_to_linear(db));
+    }
+
+    pub fn gain(&self) -> f32 {
+        f32::from_bits(self.inner.gain_bits.load(Ordering::Relaxed))
+    }
+
+    pub fn gain_db(&self) -> f32 {
+        linear_to_db(self.gain())
+    }
+
+    pub fn set_muted(&self, muted: bool) {
+        self.inner.muted.store(muted, Ordering::Relaxed);
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.inner.muted.load(Ordering::Relaxed)
+    }
+
+    /// Gain the audio thread should be heading to
+    pub fn target(&self) -> f32 {
+        if self.is_muted() {
+            0.0
+        } else {
+            self.gain()
+        }
+    }
+}
+
+impl Default for GainControl {
+    fn default() -> Self {
+        Self::new(1.0)
+    }
+}
+
+/// One-pole smoothing towards the target, avoids zipper noise and clicks on jumps
+#[derive(Clone, Copy, Debug)]
+pub struct GainRamp {
+    current: f32,
+}
+
+impl GainRamp {
+    pub fn new(current: f32) -> Self {
+        Self { current }
+    }
+
+    pub fn next(&mut self, target: f32) -> f32 {
+        let diff = target - self.current;
+        if diff.abs() < 1e-6 {
+            self.current = target;
+        } else {
+            self.current += diff * GAIN_SMOOTHING_COEFF;
+        }
+        self.current
+    }
+
+    pub fn current(&self) -> f32 {
+        self.current
+    }
+}
+
+/// Single input, single output level control
+pub struct GainNode {
+    pub state: AudioNodeState,
+    pub audio_producer: Option<FanOut>,
+    pub input_producer: Option<Producer<f32>>,
+    pub(crate) input_signal: Arc<RoomSignal>,
+    input_consumer: Option<Consumer<f32>>,
+    control: GainControl,
+    ramp: GainRamp,
+    // follows whatever is linked to the input
+    channels: usize,
+    clock: Option<(NodeId, RenderClock)>,
+}
+
+impl GainNode {
+    /// Gain that can follow any source, the input ring is as big as a mixer input
+    pub fn new(control: GainControl) -> Self {
+        Self::with_capacity(control, PUSH_RING_BUFFER_CAPACITY)
+    }
+
+    /// Gain fed by a mixer in small blocks, the input ring is sized like the speaker's so a
+    /// change is heard right away
+    pub fn low_latency(control: GainControl) -> Self {
+        Self::with_capacity(control, PULL_RING_BUFFER_CAPACITY)
+    }
+
+    fn with_capacity(control: GainControl, capacity: usize) -> Self {
+        let (producer, consumer) = RingBuffer::<f32>::new(capacity);
+        let ramp = GainRamp::new(control.target());
+
+        Self {
+            state: AudioNodeState::INITIALIZED,
+            audio_producer: Some(FanOut::new()),
+            input_producer: Some(producer),
+            input_signal: RoomSignal::new(),
+            input_consumer: Some(consumer),
+            control,
+            ramp,
+            channels: 2,
+            clock: None,
+        }
+    }
+
+    /// Set the channel count up front, for a gain wired to its output before anything feeds it
+    pub fn with_channels(mut self, channels: usize) -> Self {
+        self.set_channels(channels);
+        self
+    }
+
+    pub fn control(&self) -> GainControl {
+        self.control.clone()
+    }
+
+    /// Interleaved channels passing through, taken from the upstream node when linked
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    /// Frames waiting in the input ring
+    pub fn latency_frames(&self) -> usize {
+        self.input_signal.queued() / self.channels
+    }
+
+    pub(crate) fn set_channels(&mut self, channels: usize) {
+        self.channels = channels.max(1);
+    }
+
+    pub(crate) fn attach_clock(&mut self, id: NodeId, clock: RenderClock) {
+        self.clock = Some((id, clock));
+    }
+
+    pub(crate) fn begin_offline(&mut self) -> Result<(), String> {
+        self.state = AudioNodeState::RUNNING;
+        Ok(())
+    }
+
+    pub(crate) fn step_offline(&mut self) -> RenderStep {
+        let (consumer, output) = match (self.input_consumer.as_mut(), self.audio_producer.as_mut())
+        {
+            (Some(c), Some(o)) => (c, o),
+            _ => return RenderStep::Finished,
+        };
+        self.input_signal.discard_flushed(consumer);
+        match apply_gain(
+            consumer,
+            output,
+            &mut self.ramp,
+            &self.control,
+            &mut vec![0.0; self.channels],
+            usize::MAX,
+        ) {
+            0 if consumer.is_abandoned() && consumer.is_empty() => {
+                // dropping the producer tells downstream nothing more is coming
+                self.audio_producer = None;
+                self.state = AudioNodeState::STOPPED;
+                RenderStep::Finished
+            }
+            0 => RenderStep::Idle,
+            _ => RenderStep::Progress,
+        }
+    }
+}
+
+impl AudioNode for GainNode {
+    fn init() -> Self {
+        GainNode::new(GainControl::default())
+    }
+
+    fn start(&mut self) {
+        let output = match self.audio_producer.take() {
+            Some(p) => p,
+            None => panic!("GainNode: cannot start - no output producer"),
+        };
+        let consumer = match self.input_consumer.take() {
+            Some(c) => c,
+            None => panic!("GainNode: cannot start - no input consumer"),
+        };
+        let (id, clock) = match self.clock.as_ref() {
+            Some(c) => c,
+            None => panic!("GainNode: cannot start - no clock, start it through AudioGraph"),
+        };
+
+        clock.insert(
+            *id,
+            ClockJob::Gain(GainCore {
+                consumer,
+                signal: Arc::clone(&self.input_signal),
+                output,
+                ramp: self.ramp,
+                control: self.control.clone(),
+                frame: vec![0.0; self.channels],
+            }),
+        );
+        println!("[Gain] Gain attached to clock");
+
+        self.state = AudioNodeState::RUNNING;
+    }
+
+    fn stop(&mut self) {
+        if let Some((id, clock)) = self.clock.as_ref() {
+            if let Some(ClockJob::Gain(core)) = clock.remove(*id) {
+                self.input_consumer = Some(core.consumer);
+                self.audio_producer = Some(core.output);
+                self.ramp = core.ramp;
+            }
+        }
+
+        self.state = AudioNodeState::STOPPED;
+    }
+
+    fn get_type(&self) -> AudioNodeType {
+        AudioNodeType::GAIN
+    }
+
+    fn get_state(&self) -> AudioNodeState {
+        self.state
+    }
+}
+
+/// What the gain node needs on the clock
+pub(crate) struct GainCore {
+    consumer: Consumer<f32>,
+    signal: Arc<RoomSignal>,
+    output: FanOut,
+    ramp: GainRamp,
+    control: GainControl,
+    // one interleaved frame, the ramp steps once per frame
+    frame: Vec<f32>,
+}
+
+impl GainCore {
+    pub(crate) fn process(&mut self, samples: usize) {
+        self.signal.discard_flushed(&mut self.consumer);
+        let read = apply_gain(
+            &mut self.consumer,
+            &mut self.output,
+            &mut self.ramp,
+            &self.control,
+            &mut self.frame,
+            samples,
+        );
+        self.signal.consumed(&self.consumer, read);
+    }
+}
+
+/// Move up to `limit` samples (as many whole frames as fit) from input to output a frame at a
+/// time, every channel of a frame at the same gain. Returns the sample count.
+fn apply_gain(
+    consumer: &mut Consumer<f32>,
+    output: &mut FanOut,
+    ramp: &mut GainRamp,
+    control: &GainControl,
+    frame: &mut [f32],
+    limit: usize,
+) -> usize {
+    let channels = frame.len();
+    let samples = consumer.slots().min(output.slots()).min(limit);
+    let samples = samples - samples % channels;
+    let target = control.target();
+    for _ in 0..samples / channels {
+        let gain = ramp.next(target);
+        for value in frame.iter_mut() {
+            // the frame is there, slots were checked above
+            *value = consumer.pop().unwrap_or(0.0) * gain;
+        }
+        if !output.push_frame(frame) {
+            println!("[Gain] push error");
+        }
+    }
+    samples
+}
@@ -0,0 +1,271 @@
+/***
+ * @ Mod:       graph
+ * @ Author:    Leon Lin
+ * @ Date:      20261017
+ */
+
+use crate::audio_node::clock::RenderClock;
+use crate::audio_node::{link, unlink, AudioNode, AudioNodeEnum, AudioNodeState, AudioNodeType};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(usize);
+
+/// Runtime audio graph, owns every node by id and keeps the edge list in sync
+/// with the ring buffers handed between nodes.
+#[derive(Default)]
+pub struct AudioGraph {
+    nodes: Vec<Option<AudioNodeEnum>>,
+    edges: Vec<(NodeId, NodeId)>,
+}
+
+impl AudioGraph {
+    pub fn new() -> Self {
+        Self {
+            nodes: Vec::new(),
+            edges: Vec::new(),
+        }
+    }
+
+    pub fn add_node(&mut self, node: AudioNodeEnum) -> NodeId {
+        self.nodes.push(Some(node));
+        NodeId(self.nodes.len() - 1)
+    }
+
+    /// Stop a node, drop all its edges and hand it back to the caller
+    pub fn remove_node(&mut self, id: NodeId) -> Result<AudioNodeEnum, String> {
+        let node = self
+            .node_mut(id)
+            .ok_or(format!("node {:?} not found", id))?;
+        if matches!(
+            node.get_state(),
+            AudioNodeState::RUNNING | AudioNodeState::PAUSED
+        ) {
+            node.stop();
+        }
+
+        for (src, dst) in self.edges.clone() {
+            if src != id && dst != id {
+                continue;
+            }
+            if self.node(src).is_some_and(|n| n.can_rewire()) {
+                self.disconnect(src, dst)?;
+            } else {
+                // upstream stream still owns its producer, only the consumer side can be released
+                if let Some(AudioNodeEnum::Mixer(mixer)) = self.node_mut(dst) {
+                    mixer.remove_input(src);
+                }
+                self.edges.retain(|e| *e != (src, dst));
+            }
+        }
+
+        Ok(self.nodes[id.0].take().unwrap())
+    }
+
+    pub fn node(&self, id: NodeId) -> Option<&AudioNodeEnum> {
+        self.nodes.get(id.0).and_then(|n| n.as_ref())
+    }
+
+    pub fn node_mut(&mut self, id: NodeId) -> Option<&mut AudioNodeEnum> {
+        self.nodes.get_mut(id.0).and_then(|n| n.as_mut())
+    }
+
+    /// Start a node. Clocked nodes (mixer, gain, test tone) get the clock of the speaker they
+    /// end up in and are run by its callback, so wire the path to a speaker first.
+    pub fn start(&mut self, id: NodeId) -> Result<(), String> {
+        let node = self.node(id).ok_or(format!("node {:?} not found", id))?;
+        if !node.is_clocked() {
+            self.node_mut(id).unwrap().start();
+            return Ok(());
+        }
+
+        let (speaker, clock) = self.downstream_clock(id).ok_or(format!(
+            "{:?} does not reach a speaker, nothing can clock it",
+            id
+        ))?;
+        let node = self.node_mut(id).unwrap();
+        node.attach_clock(id, clock.clone());
+        node.start();
+        clock.set_order(&self.upstream_order(speaker));
+        Ok(())
+    }
+
+    pub fn stop(&mut self, id: NodeId) -> Result<(), String> {
+        self.node_mut(id)
+            .ok_or(format!("node {:?} not found", id))?
+            .stop();
+        Ok(())
+    }
+
+    pub fn is_connected(&self, src: NodeId, dst: NodeId) -> bool {
+        self.edges.contains(&(src, dst))
+    }
+
+    pub fn inputs(&self, id: NodeId) -> Vec<NodeId> {
+        self.edges
+            .iter()
+            .filter(|(_, dst)| *dst == id)
+            .map(|(src, _)| *src)
+            .collect()
+    }
+
+    pub fn outputs(&self, id: NodeId) -> Vec<NodeId> {
+        self.edges
+            .iter()
+            .filter(|(src, _)| *src == id)
+            .map(|(_, dst)| *dst)
+            .collect()
+    }
+
+    pub fn connect(&mut self, src: NodeId, dst: NodeId) -> Result<(), String> {
+        if src == dst {
+            return Err("cannot connect a node to itself".to_string());
+        }
+        let src_node = self.node(src).ok_or(format!("node {:?} not found", src))?;
+        let dst_node = self.node(dst).ok_or(format!("node {:?} not found", dst))?;
+
+        if self.is_connected(src, dst) {
+            return Err(format!("{:?} -> {:?} already connected", src, dst));
+        }
+        if !matches!(
+            src_node.get_type(),
+            AudioNodeType::SOURCE | AudioNodeType::GAIN | AudioNodeType::MIXER
+        ) {
+            return Err(format!("{:?} has no output", src));
+        }
+        if !matches!(
+            dst_node.get_type(),
+            AudioNodeType::GAIN | AudioNodeType::MIXER | AudioNodeType::DESTINATION
+        ) {
+            return Err(format!("{:?} has no input", dst));
+        }
+        if !src_node.can_rewire() {
+            return Err(format!("{:?} is running, stop it before rewiring", src));
+        }
+        if self.reachable(dst, src) {
+            return Err(format!("{:?} -> {:?} would create a cycle", src, dst));
+        }
+        // every edge carries whole interleaved frames of one channel count
+        if let (Some(out), Some(inp)) = (src_node.output_channels(), dst_node.input_channels()) {
+            if out != inp {
+                return Err(format!(
+                    "{:?} writes {} channels but {:?} reads {}",
+                    src, out, dst, inp
+                ));
+            }
+        }
+
+        let (src_node, dst_node) = self.pair_mut(src, dst);
+        link(src_node, dst_node, src, dst)?;
+        self.edges.push((src, dst));
+        Ok(())
+    }
+
+    pub fn disconnect(&mut self, src: NodeId, dst: NodeId) -> Result<(), String> {
+        if !self.is_connected(src, dst) {
+            return Err(format!("{:?} -> {:?} not connected", src, dst));
+        }
+        if !self.node(src).unwrap().can_rewire() {
+            return Err(format!("{:?} is running, stop it before rewiring", src));
+        }
+
+        let (src_node, dst_node) = self.pair_mut(src, dst);
+        unlink(src_node, dst_node, src, dst)?;
+        self.edges.retain(|e| *e != (src, dst));
+        Ok(())
+    }
+
+    /// Interleaved channels carried by the edge `src -> dst`
+    pub fn edge_channels(&self, src: NodeId, dst: NodeId) -> Option<usize> {
+        if !self.is_connected(src, dst) {
+            return None;
+        }
+        self.node(src)?
+            .output_channels()
+            .or(self.node(dst)?.input_channels())
+    }
+
+    /// Frames between `id` handing a sample to its output and a speaker playing it, along the
+    /// first path down to a speaker. Ring buffers between clocked nodes stay (almost) empty,
+    /// so this is mostly limiter look-ahead and device latency.
+    pub fn output_latency_frames(&self, id: NodeId) -> usize {
+        let mut latency = 0;
+        let mut cur = id;
+        while let Some(next) = self.outputs(cur).first().copied() {
+            let node = match self.node(next) {
+                Some(node) => node,
+                None => break,
+            };
+            latency += node.latency_frames();
+            if node.clock().is_some() {
+                break;
+            }
+            cur = next;
+        }
+        latency
+    }
+
+    /// Every node feeding `dest` (and `dest` itself), upstream nodes first
+    pub fn upstream_order(&self, dest: NodeId) -> Vec<NodeId> {
+        let mut order = Vec::new();
+        let mut visited = vec![false; self.nodes.len()];
+        self.visit_upstream(dest, &mut visited, &mut order);
+        order
+    }
+
+    fn visit_upstream(&self, id: NodeId, visited: &mut [bool], order: &mut Vec<NodeId>) {
+        if visited[id.0] {
+            return;
+        }
+        visited[id.0] = true;
+        for src in self.inputs(id) {
+            self.visit_upstream(src, visited, order);
+        }
+        order.push(id);
+    }
+
+    /// First destination below `from` that owns a clock
+    fn downstream_clock(&self, from: NodeId) -> Option<(NodeId, RenderClock)> {
+        let mut visited = vec![false; self.nodes.len()];
+        let mut stack = vec![from];
+        while let Some(cur) = stack.pop() {
+            if visited[cur.0] {
+                continue;
+            }
+            visited[cur.0] = true;
+            if let Some(clock) = self.node(cur).and_then(|n| n.clock()) {
+                return Some((cur, clock));
+            }
+            stack.extend(self.outputs(cur));
+        }
+        None
+    }
+
+    /// Depth first search along edges
+    fn reachable(&self, from: NodeId, to: NodeId) -> bool {
+        let mut visited = vec![false; self.nodes.len()];
+        let mut stack = vec![from];
+        while let Some(cur) = stack.pop() {
+            if cur == to {
+                return true;
+            }
+            if visited[cur.0] {
+                continue;
+            }
+            visited[cur.0] = true;
+            stack.extend(self.outputs(cur));
+        }
+        false
+    }
+
+    fn pair_mut(&mut self, a: NodeId, b: NodeId) -> (&mut AudioNodeEnum, &mut AudioNodeEnum) {
+        assert_ne!(a, b);
+        let (low, high) = self.nodes.split_at_mut(a.0.max(b.0));
+        let (first, second) = (low[a.0.min(b.0)].as_mut(), high[0].as_mut());
+        let (first, second) = (first.unwrap(), second.unwrap());
+        if a < b {
+            (first, second)
+        } else {
+            (second, first)
+        }
+    }
+}
@@ -0,0 +1,160 @@
+/***
+ * @ Mod:       limiter
+ * @ Author:    Leon Lin
+ * @ Date:      20261017
+ */
+
+use crate::audio_node::gain::{db_to_linear, linear_to_db};
+use crate::audio_node::node_const::{
+    LIMITER_CEILING_DB, LIMITER_LOOKAHEAD_FRAMES, LIMITER_RELEASE_FRAMES,
+};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+/// How hard the limiter is working, readable from the UI thread
+#[derive(Clone, Default)]
+pub struct LimiterMeter {
+    gain_reduction_bits: Arc<AtomicU32>,
+}
+
+impl LimiterMeter {
+    /// Largest gain reduction of the last processed block in dB, 0.0 means untouched
+    pub fn gain_reduction_db(&self) -> f32 {
+        f32::from_bits(self.gain_reduction_bits.load(Ordering::Relaxed))
+    }
+
+    fn store(&self, db: f32) {
+        self.gain_reduction_bits
+            .store(db.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// Look-ahead peak limiter on interleaved frames.
+/// Every frame is delayed by `LIMITER_LOOKAHEAD_FRAMES - 1`, the gain needed for a peak is
+/// held over the look-ahead window and faded in over the same length, so it is fully applied
+/// by the time the peak comes out and never overshoots the ceiling.
+pub struct Limiter {
+    ceiling: f32,
+    release_coeff: f32,
+    // scratch frame: mixer writes the input here, `process_frame` leaves the output here
+    frame: Vec<f32>,
+    delay: Vec<f32>,
+    // gain each frame in the window needs to stay under the ceiling
+    required: Vec<f32>,
+    envelope: Vec<f32>,
+    envelope_sum: f64,
+    release: f32,
+    pos: usize,
+    written: usize,
+    // real frames still inside the delay line
+    buffered: usize,
+    block_min_gain: f32,
+    meter: LimiterMeter,
+}
+
+impl Limiter {
+    pub fn new(channels: usize) -> Self {
+        let lookahead = LIMITER_LOOKAHEAD_FRAMES;
+        Self {
+            ceiling: db_to_linear(LIMITER_CEILING_DB),
+            release_coeff: 1.0 / LIMITER_RELEASE_FRAMES as f32,
+            frame: vec![0.0; channels],
+            delay: vec![0.0; lookahead * channels],
+            required: vec![1.0; lookahead],
+            envelope: vec![1.0; lookahead],
+            envelope_sum: lookahead as f64,
+            release: 1.0,
+            pos: 0,
+            written: 0,
+            buffered: 0,
+            block_min_gain: 1.0,
+            meter: LimiterMeter::default(),
+        }
+    }
+
+    pub fn meter(&self) -> LimiterMeter {
+        self.meter.clone()
+    }
+
+    pub fn channels(&self) -> usize {
+        self.frame.len()
+    }
+
+    pub fn frame_mut(&mut self) -> &mut [f32] {
+        &mut self.frame
+    }
+
+    pub fn frame(&self) -> &[f32] {
+        &self.frame
+    }
+
+    /// Push the scratch frame in, returns true when the scratch frame now holds an output
+    /// frame (false while the look-ahead is still filling up)
+    pub fn process_frame(&mut self) -> bool {
+        self.buffered += 1;
+        self.step()
+    }
+
+    /// Push silence to get the look-ahead tail out at end of stream, same return as
+    /// `process_frame`
+    pub fn flush_frame(&mut self) -> bool {
+        self.frame.fill(0.0);
+        self.step()
+    }
+
+    pub fn buffered_frames(&self) -> usize {
+        self.buffered
+    }
+
+    /// Publish the block's largest reduction to the meter and start a new block
+    pub fn publish_meter(&mut self) {
+        self.meter
+            .store(-linear_to_db(self.block_min_gain).min(0.0));
+        self.block_min_gain = 1.0;
+    }
+
+    fn step(&mut self) -> bool {
+        let channels = self.frame.len();
+        let lookahead = self.required.len();
+
+        let peak = self.frame.iter().fold(0.0f32, |acc, s| acc.max(s.abs()));
+        self.required[self.pos] = if peak > self.ceiling {
+            self.ceiling / peak
+        } else {
+            1.0
+        };
+        let hold = self.required.iter().fold(1.0f32, |acc, g| acc.min(*g));
+
+        // attack follows the hold at once (the box filter below smooths it),
+        // release creeps back up so the level does not pump
+        self.release = if hold < self.release {
+            hold
+        } else {
+            self.release + (hold - self.release) * self.release_coeff
+        };
+        self.envelope_sum += (self.release - self.envelope[self.pos]) as f64;
+        self.envelope[self.pos] = self.release;
+        let gain = ((self.envelope_sum / lookahead as f64) as f32).min(1.0);
+
+        let slot = self.pos * channels;
+        self.delay[slot..slot + channels].copy_from_slice(&self.frame);
+        self.pos = (self.pos + 1) % lookahead;
+        self.written += 1;
+
+        if self.written < lookahead || self.buffered == 0 {
+            return false;
+        }
+        // the oldest frame in the delay line is the one leaving now
+        let oldest = self.pos * channels;
+        for (out, sample) in self
+            .frame
+            .iter_mut()
+            .zip(self.delay[oldest..oldest + channels].iter())
+        {
+            *out = sample * gain;
+        }
+        self.buffered -= 1;
+        self.block_min_gain = self.block_min_gain.min(gain);
+        true
+    }
+}
@@ -1,55 +1,91 @@
-use crate::audio_node::node_const::{PUSH_RING_BUFFER_CAPACITY, RESAMPLE_BUFFER_CAPACITY};
-use crate::audio_node::utils::{generate_input_resolve_config, IOStreamConfig, ResamplingHandler};
+use crate::audio_node::backend::{default_backend, AudioBackend, AudioStream};
+use crate::audio_node::channel_map::ChannelMatrix;
+use crate::audio_node::fan_out::FanOut;
+use crate::audio_node::node_const::{
+    MIC_LOW_LATENCY_FRAMES, PUSH_RING_BUFFER_CAPACITY, RESAMPLE_BUFFER_CAPACITY,
+};
+use crate::audio_node::utils::{IOStreamConfig, ResampleQuality, ResamplingHandler};
 use crate::audio_node::{AudioNode, AudioNodeState, AudioNodeType};
-use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use cpal::{FromSample, Sample, SampleFormat, Stream, StreamError};
 use rtrb::{Consumer, Producer, RingBuffer};
+use std::sync::Arc;
 
 pub struct MicSrc {
     pub state: AudioNodeState,
-    pub audio_producer: Option<Producer<f32>>,
+    pub audio_producer: Option<FanOut>,
     pub input_producer_config: Option<IOStreamConfig>,
-    input_stream: Option<Stream>,
-    device: cpal::Device,
+    input_stream: Option<Box<dyn AudioStream>>,
+    backend: Arc<dyn AudioBackend>,
     config: IOStreamConfig,
     inner_producer: Option<Producer<f32>>,
     inner_consumer: Option<Consumer<f32>>,
+    // None means the standard mapping for the negotiated device channels
+    channel_matrix: Option<ChannelMatrix>,
+    resample_quality: ResampleQuality,
+    low_latency: bool,
+    // filter delay of the running resampler in output frames
+    resample_delay: usize,
 }
 
-impl AudioNode for MicSrc {
-    fn init() -> Self {
-        let host = cpal::default_host();
-        // 獲取預設輸入設備 (麥克風)
-        let input_device = host
-            .default_input_device()
-            .expect("no input device available");
-
-        println!(
-            "[HAL] Input Device: {:?}",
-            input_device.description().unwrap().name()
-        );
-
-        // negotiation function
-        let mut resolve_config_fn = generate_input_resolve_config("Mic".parse().unwrap());
+impl MicSrc {
+    pub fn with_backend(backend: Arc<dyn AudioBackend>) -> Result<Self, String> {
+        println!("[HAL] Input Backend: {}", backend.name());
 
         // 協商輸入配置
-        let input_config =
-            resolve_config_fn(&input_device).expect("failed to resolve input device config");
+        let input_config = backend.input_config()?;
         println!("[HAL] Negotiated Input Config: {:?}", input_config);
 
         // create mic cache buffer for resample usage
         let (producer, consumer) = RingBuffer::<f32>::new(PUSH_RING_BUFFER_CAPACITY);
 
-        Self {
+        Ok(Self {
             state: AudioNodeState::INITIALIZED,
-            audio_producer: None,
+            audio_producer: Some(FanOut::new()),
             input_producer_config: None,
             input_stream: None,
-            device: input_device,
+            backend,
             config: input_config,
             inner_producer: Option::from(producer),
             inner_consumer: Option::from(consumer),
-        }
+            channel_matrix: None,
+            resample_quality: ResampleQuality::default(),
+            low_latency: false,
+            resample_delay: 0,
+        })
+    }
+
+    /// Custom device to target channel mapping, takes effect on the first start
+    pub fn set_channel_matrix(&mut self, matrix: Option<ChannelMatrix>) {
+        self.channel_matrix = matrix;
+    }
+
+    /// Resampler used when the device rate differs from the target, takes effect on the first
+    /// start
+    pub fn set_resample_quality(&mut self, quality: ResampleQuality) {
+        self.resample_quality = quality;
+    }
+
+    /// Small chunks and a short filter so singers hear themselves sooner, takes effect on the
+    /// first start. The remaining filter delay is in `resample_delay_frames`.
+    pub fn set_low_latency(&mut self, low_latency: bool) {
+        self.low_latency = low_latency;
+    }
+
+    /// How many output frames the resampler delays the mic, 0 when the rates match
+    pub fn resample_delay_frames(&self) -> usize {
+        self.resample_delay
+    }
+
+    /// Channel count the device delivers
+    pub fn device_channels(&self) -> usize {
+        self.config.stream_config.channels as usize
+    }
+}
+
+impl AudioNode for MicSrc {
+    fn init() -> Self {
+        default_backend()
+            .and_then(MicSrc::with_backend)
+            .expect("no input device available")
     }
 
     fn start(&mut self) {
@@ -65,55 +101,40 @@ impl AudioNode for MicSrc {
             };
             let input_config = &self.config;
 
-            let resampler = ResamplingHandler::new(
+            let (chunk_frames, quality) = if self.low_latency {
+                // low latency never pays for the long filter
+                let quality = match self.resample_quality {
+                    ResampleQuality::HighSinc => ResampleQuality::FastSinc,
+                    other => other,
+                };
+                (MIC_LOW_LATENCY_FRAMES, quality)
+            } else {
+                (RESAMPLE_BUFFER_CAPACITY, self.resample_quality)
+            };
+            let mut resampler = ResamplingHandler::new(
                 producer,
                 input_config.stream_config.clone(),
                 producer_config.stream_config,
                 self.inner_producer.take().unwrap(),
                 self.inner_consumer.take().unwrap(),
-                RESAMPLE_BUFFER_CAPACITY,
+                chunk_frames,
+                quality,
             );
-
-            let stream = match input_config.sample_format {
-                SampleFormat::F32 => self
-                    .device
-                    .build_input_stream(
-                        &self.config.stream_config,
-                        data_input_callback_creator::<f32>(resampler),
-                        err_hdl_cb,
-                        None,
-                    )
-                    .expect("failed to build input stream"),
-                SampleFormat::I32 => self
-                    .device
-                    .build_input_stream(
-                        &self.config.stream_config,
-                        data_input_callback_creator::<i32>(resampler),
-                        err_hdl_cb,
-                        None,
-                    )
-                    .expect("failed to build input stream"),
-                SampleFormat::I16 => self
-                    .device
-                    .build_input_stream(
-                        &self.config.stream_config,
-                        data_input_callback_creator::<i16>(resampler),
-                        err_hdl_cb,
-                        None,
-                    )
-                    .expect("failed to build input stream"),
-                SampleFormat::U8 => self
-                    .device
-                    .build_input_stream(
-                        &self.config.stream_config,
-                        data_input_callback_creator::<u8>(resampler),
-                        err_hdl_cb,
-                        None,
-                    )
-                    .expect("failed to build input stream"),
-
-                _ => panic!("MicSrc: cannot start, no producer sample format"),
-            };
+            self.resample_delay = resampler.delay_frames();
+            println!("[MicSrc] Resample delay: {} frames", self.resample_delay);
+            if let Some(matrix) = self.channel_matrix.clone() {
+                if let Err(e) = resampler.set_channel_matrix(matrix) {
+                    println!("[MicSrc] {}, using the standard mapping", e);
+                }
+            }
+
+            let stream = self
+                .backend
+                .build_input_stream(
+                    input_config,
+                    Box::new(data_input_callback_creator(resampler)),
+                )
+                .expect("failed to build input stream");
 
             self.input_stream = Some(stream);
         }
@@ -138,22 +159,12 @@ impl AudioNode for MicSrc {
     }
 
     fn get_state(&self) -> AudioNodeState {
-        self.state.clone()
+        self.state
     }
 }
 
-fn err_hdl_cb(err: StreamError) {
-    eprintln!("[HAL] Input Stream Error: {}", err);
-}
-
-fn data_input_callback_creator<T>(
-    mut handler: ResamplingHandler,
-) -> impl FnMut(&[T], &cpal::InputCallbackInfo)
-where
-    T: Sample,
-    f32: FromSample<T>,
-{
-    move |data: &[T], _: &cpal::InputCallbackInfo| {
+fn data_input_callback_creator(mut handler: ResamplingHandler) -> impl FnMut(&[f32]) + Send {
+    move |data: &[f32]| {
         if handler.check_must_loss_all_data() {
             println!("[HAL] Producer full");
             return;
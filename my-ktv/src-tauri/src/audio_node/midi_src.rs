@@ -0,0 +1,78 @@
+/***
+ * @ Mod:       midi_src
+ * @ Author:    Leon Lin
+ * @ Date:      20261017
+ */
+
+pub mod synth;
+
+use crate::audio_node::file_src::{FileSrc, SampleSource};
+use crate::audio_node::midi_src::synth::MidiSynth;
+use crate::audio_node::{AudioNode, AudioNodeState, AudioNodeType};
+use rustysynth::SoundFont;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// `.mid` and `.kar` files, rodio can't decode them
+pub const MIDI_EXTENSIONS: [&str; 3] = ["mid", "midi", "kar"];
+
+pub fn is_midi(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| MIDI_EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()))
+}
+
+/// Renders a MIDI file through a SoundFont at the target rate. The synth stands in for the
+/// decoder of a `FileSrc`, so the ring buffers, pause, seek, position and the end of the song
+/// work the same way; `player` is that `FileSrc`.
+pub struct MidiSrc {
+    player: FileSrc,
+}
+
+impl MidiSrc {
+    pub fn set_config(
+        &mut self,
+        file_path: PathBuf,
+        soundfont: Arc<SoundFont>,
+        sample_rate: u32,
+        channels: usize,
+    ) {
+        self.player.set_config(file_path, sample_rate, channels);
+        self.player.set_opener(Arc::new(move |path: &Path| {
+            let synth = MidiSynth::open(path, &soundfont, sample_rate)?;
+            Ok(Box::new(synth) as SampleSource)
+        }));
+    }
+
+    pub fn player(&self) -> &FileSrc {
+        &self.player
+    }
+
+    pub fn player_mut(&mut self) -> &mut FileSrc {
+        &mut self.player
+    }
+}
+
+impl AudioNode for MidiSrc {
+    fn init() -> Self {
+        Self {
+            player: FileSrc::init(),
+        }
+    }
+
+    fn start(&mut self) {
+        self.player.start();
+    }
+
+    fn stop(&mut self) {
+        self.player.stop();
+    }
+
+    fn get_type(&self) -> AudioNodeType {
+        AudioNodeType::SOURCE
+    }
+
+    fn get_state(&self) -> AudioNodeState {
+        self.player.get_state()
+    }
+}
@@ -0,0 +1,273 @@
+/***
+ * @ Mod:       synth
+ * @ Author:    Leon Lin
+ * @ Date:      20261017
+ */
+
+use midly::{MetaMessage, MidiMessage, Smf, Timing, TrackEventKind};
+use rodio::source::SeekError;
+use rodio::Source;
+use rustysynth::{SoundFont, Synthesizer, SynthesizerSettings};
+use std::fs;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+// the synth renders this many frames between two looks at the event list, 1.3 ms at 48 kHz
+const BLOCK_FRAMES: usize = 64;
+// notes still ring after the last event, cutting them there clicks
+const RELEASE_TAIL: Duration = Duration::from_secs(1);
+// 120 bpm until the file says otherwise
+const DEFAULT_TEMPO_US: f64 = 500_000.0;
+
+/// A channel message in the form `Synthesizer::process_midi_message` takes
+#[derive(Clone, Copy, Debug)]
+struct ChannelEvent {
+    secs: f64,
+    channel: u8,
+    command: u8,
+    data1: u8,
+    data2: u8,
+}
+
+impl ChannelEvent {
+    fn is_note(&self) -> bool {
+        matches!(self.command, 0x80 | 0x90)
+    }
+}
+
+/// A text (FF 01) or lyric (FF 05) meta event, bytes as written, the encoding is up to the file
+#[derive(Clone, Debug, PartialEq)]
+pub struct MidiText {
+    pub time_ms: u64,
+    pub lyric: bool,
+    pub bytes: Vec<u8>,
+}
+
+/// A standard MIDI file with every track merged onto one clock, tempo changes already applied
+pub struct MidiSong {
+    events: Vec<ChannelEvent>,
+    pub texts: Vec<MidiText>,
+    pub length: Duration,
+}
+
+impl MidiSong {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let bytes = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Self::parse(&bytes).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Format 0, 1 and 2 alike: a tempo change in any track moves all of them
+    pub fn parse(bytes: &[u8]) -> Result<Self, String> {
+        let smf = Smf::parse(bytes).map_err(|e| format!("Failed to parse MIDI: {}", e))?;
+        // (tick, track, kind), stable sort keeps each track's order on the same tick
+        let mut merged = Vec::new();
+        for (track, events) in smf.tracks.iter().enumerate() {
+            let mut tick: u64 = 0;
+            for event in events {
+                tick += event.delta.as_int() as u64;
+                merged.push((tick, track, event.kind));
+            }
+        }
+        merged.sort_by_key(|(tick, track, _)| (*tick, *track));
+
+        let mut song = MidiSong {
+            events: Vec::new(),
+            texts: Vec::new(),
+            length: Duration::ZERO,
+        };
+        let mut tempo_us = DEFAULT_TEMPO_US;
+        let (mut last_tick, mut last_secs) = (0u64, 0f64);
+        for (tick, _, kind) in merged {
+            let secs = match smf.header.timing {
+                Timing::Metrical(per_beat) => {
+                    let per_beat = per_beat.as_int().max(1) as f64;
+                    last_secs + (tick - last_tick) as f64 * tempo_us / 1_000_000.0 / per_beat
+                }
+                Timing::Timecode(fps, sub) => tick as f64 / (fps.as_f32() as f64 * sub as f64),
+            };
+            (last_tick, last_secs) = (tick, secs);
+            match kind {
+                TrackEventKind::Midi { channel, message } => {
+                    let (command, data1, data2) = match message {
+                        MidiMessage::NoteOff { key, vel } => (0x80, key.as_int(), vel.as_int()),
+                        MidiMessage::NoteOn { key, vel } => (0x90, key.as_int(), vel.as_int()),
+                        MidiMessage::Controller { controller, value } => {
+                            (0xB0, controller.as_int(), value.as_int())
+                        }
+                        MidiMessage::ProgramChange { program } => (0xC0, program.as_int(), 0),
+                        MidiMessage::PitchBend { bend } => {
+                            let raw = bend.0.as_int();
+                            (0xE0, (raw & 0x7F) as u8, (raw >> 7) as u8)
+                        }
+                        // the synth has no aftertouch
+                        _ => continue,
+                    };
+                    song.events.push(ChannelEvent {
+                        secs,
+                        channel: channel.as_int(),
+                        command,
+                        data1,
+                        data2,
+                    });
+                }
+                TrackEventKind::Meta(MetaMessage::Tempo(us)) => tempo_us = us.as_int() as f64,
+                TrackEventKind::Meta(MetaMessage::Text(bytes)) => {
+                    song.push_text(secs, false, bytes)
+                }
+                TrackEventKind::Meta(MetaMessage::Lyric(bytes)) => {
+                    song.push_text(secs, true, bytes)
+                }
+                _ => {}
+            }
+            // end of track counts, some files hold a bar of silence after the last note
+            song.length = song.length.max(Duration::from_secs_f64(secs));
+        }
+        Ok(song)
+    }
+
+    fn push_text(&mut self, secs: f64, lyric: bool, bytes: &[u8]) {
+        self.texts.push(MidiText {
+            time_ms: (secs * 1000.0) as u64,
+            lyric,
+            bytes: bytes.to_vec(),
+        });
+    }
+}
+
+/// Read an SF2 file, one is enough for every song so the caller keeps it
+pub fn load_soundfont(path: &Path) -> Result<Arc<SoundFont>, String> {
+    let file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let soundfont = SoundFont::new(&mut BufReader::new(file))
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(Arc::new(soundfont))
+}
+
+/// Plays a `MidiSong` through a SoundFont as interleaved stereo at `sample_rate`, so the file
+/// never needs resampling
+pub struct MidiSynth {
+    song: Arc<MidiSong>,
+    synth: Synthesizer,
+    sample_rate: u32,
+    next_event: usize,
+    // frames rendered so far
+    frame: u64,
+    end_frame: u64,
+    left: Vec<f32>,
+    right: Vec<f32>,
+    // interleaved samples of the current block handed out
+    read: usize,
+}
+
+impl MidiSynth {
+    pub fn new(
+        song: Arc<MidiSong>,
+        soundfont: &Arc<SoundFont>,
+        sample_rate: u32,
+    ) -> Result<Self, String> {
+        let settings = SynthesizerSettings::new(sample_rate as i32);
+        let synth = Synthesizer::new(soundfont, &settings)
+            .map_err(|e| format!("Failed to start synth: {}", e))?;
+        let end = song.length + RELEASE_TAIL;
+        Ok(Self {
+            song,
+            synth,
+            sample_rate,
+            next_event: 0,
+            frame: 0,
+            end_frame: (end.as_secs_f64() * sample_rate as f64) as u64,
+            left: vec![0.0; BLOCK_FRAMES],
+            right: vec![0.0; BLOCK_FRAMES],
+            read: BLOCK_FRAMES * 2,
+        })
+    }
+
+    pub fn open(path: &Path, soundfont: &Arc<SoundFont>, sample_rate: u32) -> Result<Self, String> {
+        Self::new(Arc::new(MidiSong::open(path)?), soundfont, sample_rate)
+    }
+
+    fn frame_of(&self, secs: f64) -> u64 {
+        (secs * self.sample_rate as f64) as u64
+    }
+
+    /// Send what is due before the block ends and render it, `false` once the song is over
+    fn render_block(&mut self) -> bool {
+        if self.frame >= self.end_frame {
+            return false;
+        }
+        let block_end = self.frame + BLOCK_FRAMES as u64;
+        while let Some(event) = self.song.events.get(self.next_event) {
+            if self.frame_of(event.secs) >= block_end {
+                break;
+            }
+            self.synth.process_midi_message(
+                event.channel as i32,
+                event.command as i32,
+                event.data1 as i32,
+                event.data2 as i32,
+            );
+            self.next_event += 1;
+        }
+        self.synth.render(&mut self.left, &mut self.right);
+        self.frame = block_end;
+        self.read = 0;
+        true
+    }
+}
+
+impl Iterator for MidiSynth {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        if self.read == BLOCK_FRAMES * 2 && !self.render_block() {
+            return None;
+        }
+        let i = self.read / 2;
+        let sample = match self.read % 2 {
+            0 => self.left[i],
+            _ => self.right[i],
+        };
+        self.read += 1;
+        Some((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)
+    }
+}
+
+impl Source for MidiSynth {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        2
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        Some(self.song.length + RELEASE_TAIL)
+    }
+
+    /// Silence, then replay every program, controller and pitch bend before `pos` without the
+    /// notes, so the instruments are right when playing picks up
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.synth.reset();
+        let secs = pos.as_secs_f64();
+        let events = &self.song.events;
+        self.next_event = events.partition_point(|e| e.secs < secs);
+        for event in events[..self.next_event].iter().filter(|e| !e.is_note()) {
+            self.synth.process_midi_message(
+                event.channel as i32,
+                event.command as i32,
+                event.data1 as i32,
+                event.data2 as i32,
+            );
+        }
+        self.frame = self.frame_of(secs);
+        self.read = BLOCK_FRAMES * 2;
+        Ok(())
+    }
+}
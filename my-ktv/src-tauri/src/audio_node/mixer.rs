@@ -4,144 +4,399 @@
  * @ Date:      20260128
  */
 
-use crate::audio_node::node_const::PUSH_RING_BUFFER_CAPACITY;
+use crate::audio_node::clock::{ClockJob, RenderClock};
+use crate::audio_node::fan_out::{FanOut, RoomSignal};
+use crate::audio_node::gain::{GainControl, GainRamp};
+use crate::audio_node::graph::NodeId;
+use crate::audio_node::limiter::{Limiter, LimiterMeter};
+use crate::audio_node::node_const::{
+    LIMITER_LOOKAHEAD_FRAMES, MIXER_BLOCK_SAMPLES, MIXER_COMMAND_CAPACITY, MIXER_MAX_INPUTS,
+    PUSH_RING_BUFFER_CAPACITY,
+};
+use crate::audio_node::offline::RenderStep;
 use crate::audio_node::{AudioNode, AudioNodeState, AudioNodeType};
 use rtrb::{Consumer, Producer, RingBuffer};
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, Mutex};
-use std::thread;
-use std::thread::JoinHandle;
-use thread_priority::*;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+
+/// Channel strip of one mixer input, clones share the same settings so the UI can keep one
+/// while the mixer thread reads it
+#[derive(Clone, Default)]
+pub struct MixerInputControl {
+    gain: GainControl,
+    solo: Arc<AtomicBool>,
+    pan_bits: Arc<AtomicU32>,
+}
+
+impl MixerInputControl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Level and mute of this input
+    pub fn gain(&self) -> &GainControl {
+        &self.gain
+    }
+
+    /// While any input is soloed only soloed inputs are heard
+    pub fn set_solo(&self, solo: bool) {
+        self.solo.store(solo, Ordering::Relaxed);
+    }
+
+    pub fn is_solo(&self) -> bool {
+        self.solo.load(Ordering::Relaxed)
+    }
+
+    /// Stereo balance, -1.0 is full left, 0.0 center, 1.0 full right
+    pub fn set_pan(&self, pan: f32) {
+        self.pan_bits
+            .store(pan.clamp(-1.0, 1.0).to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn pan(&self) -> f32 {
+        f32::from_bits(self.pan_bits.load(Ordering::Relaxed))
+    }
+
+    /// Gain for one output channel, pan only applies to stereo output
+    fn target(&self, channel: usize, channels: usize, any_solo: bool) -> f32 {
+        if any_solo && !self.is_solo() {
+            return 0.0;
+        }
+        let gain = self.gain.target();
+        if channels != 2 {
+            return gain;
+        }
+        // balance law: center keeps both sides at unity, turning attenuates the other side
+        let pan = self.pan();
+        match channel {
+            0 => gain * (1.0 - pan).min(1.0),
+            _ => gain * (1.0 + pan).min(1.0),
+        }
+    }
+}
+
+// one input ring buffer tagged with the node feeding it
+struct MixerInput {
+    source: NodeId,
+    consumer: Consumer<f32>,
+    signal: Arc<RoomSignal>,
+    control: MixerInputControl,
+    // smoothing per output channel
+    ramps: Vec<GainRamp>,
+    // samples taken this block, reported to the source's signal
+    read: usize,
+}
+
+impl MixerInput {
+    fn new(
+        source: NodeId,
+        consumer: Consumer<f32>,
+        signal: Arc<RoomSignal>,
+        control: MixerInputControl,
+        channels: usize,
+    ) -> Self {
+        let ramps = (0..channels)
+            .map(|ch| GainRamp::new(control.target(ch, channels, false)))
+            .collect();
+        Self {
+            source,
+            consumer,
+            signal,
+            control,
+            ramps,
+            read: 0,
+        }
+    }
+}
+
+type MixerInputs = Vec<MixerInput>;
+
+impl MixerInput {
+    /// Source went away without disconnecting and everything it sent has been mixed
+    fn is_finished(&self) -> bool {
+        self.consumer.is_abandoned() && self.consumer.is_empty()
+    }
+}
+
+/// Changes to the input list, queued by the control side and applied by whoever owns the list
+enum MixerCommand {
+    Add(MixerInput),
+    Remove(NodeId),
+    SetControl(NodeId, MixerInputControl),
+}
+
+/// Things the audio thread took out of the list, handed back so they are freed on the control
+/// side instead of inside the callback
+enum Retired {
+    Input(MixerInput),
+    // only carried back so it is dropped on the control side
+    #[allow(dead_code)]
+    Control(MixerInputControl),
+}
 
 pub struct Mixer {
     pub state: AudioNodeState,
-    pub audio_producer: Option<Producer<f32>>,
-    input_consumers: Arc<Mutex<Vec<Consumer<f32>>>>,
-    pub input_producers: Vec<Producer<f32>>,
-    keep_running: Arc<AtomicBool>,
-    mixer_thread: Option<JoinHandle<Producer<f32>>>,
+    pub audio_producer: Option<FanOut>,
+    channels: usize,
+    // block size rounded down to whole frames so pan knows which channel a sample is
+    block_samples: usize,
+    // owned here while stopped, moved onto the clock while running
+    inputs: Option<MixerInputs>,
+    // control side copy of every input's strip, the audio thread never touches it
+    strips: Vec<(NodeId, MixerInputControl)>,
+    commands: Producer<MixerCommand>,
+    command_consumer: Option<Consumer<MixerCommand>>,
+    retired: Consumer<Retired>,
+    retired_producer: Option<Producer<Retired>>,
+    limiter: Option<Limiter>,
+    limiter_meter: LimiterMeter,
+    clock: Option<(NodeId, RenderClock)>,
 }
 
 impl Mixer {
-    /// Create a mixer with a specified number of input channels
-    pub fn new(num_inputs: usize) -> Self {
-        let mut input_producers = Vec::new();
-        let mut input_consumers = Vec::new();
-
-        // Create ring buffers for each input
-        for _ in 0..num_inputs {
-            let (producer, consumer) = RingBuffer::<f32>::new(PUSH_RING_BUFFER_CAPACITY);
-            input_producers.push(producer);
-            input_consumers.push(consumer);
-        }
+    pub fn new() -> Self {
+        Self::with_channels(2)
+    }
 
+    /// Mixer for interleaved audio with `channels` channels
+    pub fn with_channels(channels: usize) -> Self {
+        let channels = channels.max(1);
+        let limiter = Limiter::new(channels);
+        let (commands, command_consumer) = RingBuffer::new(MIXER_COMMAND_CAPACITY);
+        let (retired_producer, retired) = RingBuffer::new(MIXER_COMMAND_CAPACITY);
         Self {
             state: AudioNodeState::INITIALIZED,
-            audio_producer: None,
-            input_consumers: Arc::new(Mutex::new(input_consumers)),
-            input_producers,
-            keep_running: Arc::new(AtomicBool::new(false)),
-            mixer_thread: None,
+            audio_producer: Some(FanOut::new()),
+            channels,
+            block_samples: (MIXER_BLOCK_SAMPLES / channels).max(1) * channels,
+            // reserved up front so adding an input never reallocates on the audio thread
+            inputs: Some(Vec::with_capacity(MIXER_MAX_INPUTS)),
+            strips: Vec::new(),
+            commands,
+            command_consumer: Some(command_consumer),
+            retired,
+            retired_producer: Some(retired_producer),
+            limiter_meter: limiter.meter(),
+            limiter: Some(limiter),
+            clock: None,
         }
     }
 
-    /// Add a new input channel dynamically
-    pub fn add_input(&mut self) -> Producer<f32> {
+    /// Add a new input channel for `source`, works while the mixer is running
+    pub fn add_input(&mut self, source: NodeId) -> (Producer<f32>, Arc<RoomSignal>) {
         let (producer, consumer) = RingBuffer::<f32>::new(PUSH_RING_BUFFER_CAPACITY);
+        let signal = RoomSignal::new();
+        let control = MixerInputControl::new();
 
-        let mut consumers = self.input_consumers.lock().unwrap();
-        consumers.push(consumer);
+        self.strips.push((source, control.clone()));
+        self.send(MixerCommand::Add(MixerInput::new(
+            source,
+            consumer,
+            Arc::clone(&signal),
+            control,
+            self.channels,
+        )));
 
-        producer
+        (producer, signal)
     }
 
-    /// Get a producer for a specific input slot
-    pub fn take_input_producer(&mut self, index: usize) -> Option<Producer<f32>> {
-        if index < self.input_producers.len() {
-            Some(self.input_producers.remove(index))
-        } else {
-            None
-        }
+    /// Drop the input channel fed by `source`, works while the mixer is running
+    pub fn remove_input(&mut self, source: NodeId) {
+        self.strips.retain(|(id, _)| *id != source);
+        self.send(MixerCommand::Remove(source));
     }
-}
 
-impl AudioNode for Mixer {
-    fn init() -> Self {
-        Mixer::new(0)
+    /// Channel strip of the input fed by `source`
+    pub fn input_control(&self, source: NodeId) -> Option<MixerInputControl> {
+        self.strips
+            .iter()
+            .find(|(id, _)| *id == source)
+            .map(|(_, control)| control.clone())
     }
 
-    fn start(&mut self) {
-        let mut output_producer = match self.audio_producer.take() {
-            Some(p) => p,
-            None => panic!("Mixer: cannot start - no output producer"),
-        };
+    /// Let the input fed by `source` follow an existing channel strip, e.g. one the UI kept
+    /// from the previous song
+    pub fn set_input_control(
+        &mut self,
+        source: NodeId,
+        control: MixerInputControl,
+    ) -> Result<(), String> {
+        self.collect_retired();
+        let strip = self
+            .strips
+            .iter_mut()
+            .find(|(id, _)| *id == source)
+            .ok_or(format!("mixer has no input from {:?}", source))?;
+        strip.1 = control.clone();
+        self.send(MixerCommand::SetControl(source, control));
+        Ok(())
+    }
 
-        let input_consumers = Arc::clone(&self.input_consumers);
-        let keep_running = Arc::clone(&self.keep_running);
-        keep_running.store(true, Ordering::Relaxed);
+    /// Gain reduction of the master limiter
+    pub fn limiter_meter(&self) -> LimiterMeter {
+        self.limiter_meter.clone()
+    }
 
-        self.mixer_thread = Some(thread::spawn(move || {
-            println!("[Mixer] Mixer Thread Started");
-            assert!(set_current_thread_priority(ThreadPriority::Max).is_ok());
+    pub(crate) fn attach_clock(&mut self, id: NodeId, clock: RenderClock) {
+        self.clock = Some((id, clock));
+    }
 
-            while keep_running.load(Ordering::Relaxed) {
-                let mut consumers = input_consumers.lock().unwrap();
+    /// Interleaved channels of every input and of the output
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
 
-                // Process in very small chunks for low latency
-                let samples_to_process = 64;
+    /// Frames the limiter look-ahead holds back
+    pub fn latency_frames(&self) -> usize {
+        LIMITER_LOOKAHEAD_FRAMES - 1
+    }
 
-                if output_producer.slots() < samples_to_process {
-                    // println!("[Mixer] Next Producer Full");
-                    continue;
-                }
+    /// Inputs being mixed, one whose source went away (ring buffer abandoned and drained) is
+    /// gone once the audio thread has dropped it
+    pub fn input_count(&mut self) -> usize {
+        self.collect_retired();
+        self.strips.len()
+    }
 
-                // Mix samples from all inputs
-                for _round in 0..samples_to_process {
-                    let mut active_inputs_count = 0;
-                    let mut sample: f32 = 0.0;
-                    for consumer in consumers.iter_mut() {
-                        if consumer.is_empty() {
-                            continue;
-                        } else {
-                            active_inputs_count += 1;
-                            sample += consumer.pop().unwrap();
-                        }
-                    }
-                    // linear mix with clip
-                    if active_inputs_count > 0 {
-                        sample /= active_inputs_count as f32;
-                        sample = sample.max(-1.0).min(1.0);
-                    } else {
-                        sample = 0.0f32;
-                    }
+    /// Apply directly while the list is here, otherwise queue it for the audio thread
+    fn send(&mut self, command: MixerCommand) {
+        self.collect_retired();
+        match self.inputs.as_mut() {
+            Some(inputs) => {
+                apply_command(inputs, command);
+            }
+            None => {
+                if self.commands.push(command).is_err() {
+                    println!("[Mixer] Command queue full, command dropped");
+                }
+            }
+        }
+    }
 
-                    // push
-                    if output_producer.push(sample).is_err() {
-                        println!("[Mixer] Mixer Thread push error");
-                    }
+    /// Free what the audio thread handed back and forget inputs it pruned
+    fn collect_retired(&mut self) {
+        while let Ok(retired) = self.retired.pop() {
+            if let Retired::Input(input) = retired {
+                if input.is_finished() {
+                    self.strips.retain(|(id, _)| *id != input.source);
                 }
+            }
+        }
+    }
 
-                drop(consumers);
+    pub(crate) fn begin_offline(&mut self) -> Result<(), String> {
+        self.state = AudioNodeState::RUNNING;
+        Ok(())
+    }
 
-                // No sleep - process as fast as possible for lowest latency
+    /// Offline inputs are never mixed in as silence, a block waits until every unfinished
+    /// input has it. The mixer finishes when all of its inputs have.
+    pub(crate) fn step_offline(&mut self) -> RenderStep {
+        let (output, limiter, consumers) = match (
+            self.audio_producer.as_mut(),
+            self.limiter.as_mut(),
+            self.inputs.as_mut(),
+        ) {
+            (Some(o), Some(l), Some(i)) => (o, l, i),
+            _ => return RenderStep::Finished,
+        };
+        while let Some(index) = consumers.iter().position(|input| input.is_finished()) {
+            let input = consumers.remove(index);
+            self.strips.retain(|(id, _)| *id != input.source);
+        }
+
+        if consumers.is_empty() {
+            // get the look-ahead tail out before telling downstream we're done
+            while limiter.buffered_frames() > 0 {
+                if output.slots() < self.channels {
+                    return RenderStep::Idle;
+                }
+                if limiter.flush_frame() {
+                    push_frame(output, limiter.frame());
+                }
             }
+            limiter.publish_meter();
+            // dropping the producer tells downstream nothing more is coming
+            self.audio_producer = None;
+            self.state = AudioNodeState::STOPPED;
+            return RenderStep::Finished;
+        }
+        let block = self.block_samples;
+        let waiting_input = consumers
+            .iter()
+            .any(|input| !input.consumer.is_abandoned() && input.consumer.slots() < block);
+        if waiting_input || output.slots() < block {
+            return RenderStep::Idle;
+        }
 
-            println!("[Mixer] Mixer Thread Stopped");
-            output_producer
-        }));
+        mix_block(consumers, output, block, limiter);
+        RenderStep::Progress
+    }
+}
+
+impl Default for Mixer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AudioNode for Mixer {
+    fn init() -> Self {
+        Mixer::new()
+    }
+
+    fn start(&mut self) {
+        let output = match self.audio_producer.take() {
+            Some(p) => p,
+            None => panic!("Mixer: cannot start - no output producer"),
+        };
+        let limiter = match self.limiter.take() {
+            Some(l) => l,
+            None => panic!("Mixer: cannot start - limiter is in use"),
+        };
+        let (id, clock) = match self.clock.as_ref() {
+            Some(c) => c,
+            None => panic!("Mixer: cannot start - no clock, start it through AudioGraph"),
+        };
+        let (inputs, commands, retired) = match (
+            self.inputs.take(),
+            self.command_consumer.take(),
+            self.retired_producer.take(),
+        ) {
+            (Some(i), Some(c), Some(r)) => (i, c, r),
+            _ => panic!("Mixer: cannot start - inputs are in use"),
+        };
+
+        clock.insert(
+            *id,
+            ClockJob::Mixer(MixerCore {
+                inputs,
+                commands,
+                retired,
+                output,
+                limiter,
+                channels: self.channels,
+            }),
+        );
+        println!("[Mixer] Mixer attached to clock");
 
         self.state = AudioNodeState::RUNNING;
     }
 
     fn stop(&mut self) {
-        self.keep_running.store(false, Ordering::Relaxed);
-
-        if let Some(handle) = self.mixer_thread.take() {
-            if let Ok(producer) = handle.join() {
-                self.audio_producer = Some(producer);
+        if let Some((id, clock)) = self.clock.as_ref() {
+            if let Some(ClockJob::Mixer(mut core)) = clock.remove(*id) {
+                // whatever was queued after the last tick still has to land
+                core.apply_commands();
+                self.audio_producer = Some(core.output);
+                self.limiter = Some(core.limiter);
+                self.inputs = Some(core.inputs);
+                self.command_consumer = Some(core.commands);
+                self.retired_producer = Some(core.retired);
                 println!("[Mixer] Producer returned to struct");
             }
         }
+        self.collect_retired();
 
         self.state = AudioNodeState::STOPPED;
     }
@@ -154,3 +409,131 @@ impl AudioNode for Mixer {
         self.state
     }
 }
+
+/// What the mixer needs on the clock. Owns the input list outright, the control side only
+/// talks to it through `commands`, so the callback never waits on a lock.
+pub(crate) struct MixerCore {
+    inputs: MixerInputs,
+    commands: Consumer<MixerCommand>,
+    retired: Producer<Retired>,
+    output: FanOut,
+    limiter: Limiter,
+    channels: usize,
+}
+
+impl MixerCore {
+    pub(crate) fn process(&mut self, samples: usize) {
+        self.apply_commands();
+
+        // whole frames only, the clock always asks for whole frames
+        let samples = samples - samples % self.channels;
+        if self.output.slots() < samples {
+            return;
+        }
+        mix_block(
+            &mut self.inputs,
+            &mut self.output,
+            samples,
+            &mut self.limiter,
+        );
+    }
+
+    fn apply_commands(&mut self) {
+        while let Ok(command) = self.commands.pop() {
+            if let Some(retired) = apply_command(&mut self.inputs, command) {
+                self.retire(retired);
+            }
+        }
+        // sources that went away without disconnecting
+        while let Some(index) = self.inputs.iter().position(|input| input.is_finished()) {
+            let input = self.inputs.remove(index);
+            self.retire(Retired::Input(input));
+        }
+    }
+
+    fn retire(&mut self, retired: Retired) {
+        // if the control side never drains, the push fails and it is dropped right here
+        let _ = self.retired.push(retired);
+    }
+}
+
+/// Apply one command to the input list, returns what it took out
+fn apply_command(inputs: &mut MixerInputs, command: MixerCommand) -> Option<Retired> {
+    match command {
+        MixerCommand::Add(input) => {
+            inputs.push(input);
+            None
+        }
+        MixerCommand::Remove(source) => {
+            let index = inputs.iter().position(|input| input.source == source)?;
+            Some(Retired::Input(inputs.remove(index)))
+        }
+        MixerCommand::SetControl(source, control) => {
+            let any_solo = inputs.iter().any(|i| i.control.is_solo());
+            let input = inputs.iter_mut().find(|input| input.source == source)?;
+            let channels = input.ramps.len();
+            for (ch, ramp) in input.ramps.iter_mut().enumerate() {
+                *ramp = GainRamp::new(control.target(ch, channels, any_solo));
+            }
+            Some(Retired::Control(std::mem::replace(
+                &mut input.control,
+                control,
+            )))
+        }
+    }
+}
+
+/// Sum `samples_to_process` samples from all inputs into the output through the limiter,
+/// the block starts on a frame boundary.
+/// Inputs are summed at their strip gain, never scaled by how many happen to have data, so one
+/// input starting or stopping does not move the level of the others.
+fn mix_block(
+    consumers: &mut MixerInputs,
+    output_producer: &mut FanOut,
+    samples_to_process: usize,
+    limiter: &mut Limiter,
+) {
+    let channels = limiter.channels();
+    let any_solo = consumers.iter().any(|input| input.control.is_solo());
+    for input in consumers.iter_mut() {
+        input.signal.discard_flushed(&mut input.consumer);
+    }
+
+    for _ in 0..samples_to_process / channels {
+        limiter.frame_mut().fill(0.0);
+        for input in consumers.iter_mut() {
+            // an input is only read a whole frame at a time, one that is short this round is
+            // silent instead of shifting its channels
+            let has_frame = input.consumer.slots() >= channels;
+            for (channel, sample) in limiter.frame_mut().iter_mut().enumerate() {
+                // ramp keeps moving even without data so a returning input doesn't jump
+                let gain =
+                    input.ramps[channel].next(input.control.target(channel, channels, any_solo));
+                if has_frame {
+                    if let Ok(value) = input.consumer.pop() {
+                        *sample += value * gain;
+                        input.read += 1;
+                    }
+                }
+            }
+        }
+
+        if limiter.process_frame() {
+            push_frame(output_producer, limiter.frame());
+        }
+    }
+    limiter.publish_meter();
+
+    // wake the sources that wait for room
+    for input in consumers.iter_mut() {
+        input
+            .signal
+            .consumed(&input.consumer, std::mem::take(&mut input.read));
+    }
+}
+
+fn push_frame(output_producer: &mut FanOut, frame: &[f32]) {
+    if !output_producer.push_frame(frame) {
+        println!("[Mixer] Mixer Thread push error");
+    }
+}
@@ -4,3 +4,25 @@ pub const PUSH_RING_BUFFER_CAPACITY: usize = 65536 * 4;
 pub const PULL_RING_BUFFER_CAPACITY: usize = 1024;
 pub const RESAMPLE_BUFFER_CAPACITY: usize = 256;
 pub const RESAMPLE_INNER_CACHE_BUFFER_CAPACITY: usize = RESAMPLE_BUFFER_CAPACITY * 8;
+// Mic chunk in low latency mode, about 1.3ms at 48kHz
+pub const MIC_LOW_LATENCY_FRAMES: usize = 64;
+// Process in very small chunks for low latency
+pub const MIXER_BLOCK_SAMPLES: usize = 64;
+// Inputs the mixer can take without reallocating on the audio thread
+pub const MIXER_MAX_INPUTS: usize = 16;
+// Pending add/remove/strip changes between two callbacks
+pub const MIXER_COMMAND_CAPACITY: usize = 64;
+// Per frame step towards a new gain, about 10ms to settle at 48kHz
+pub const GAIN_SMOOTHING_COEFF: f32 = 0.002;
+// Master limiter after the mixer sum, 64 frames look-ahead is about 1.3ms at 48kHz
+pub const LIMITER_CEILING_DB: f32 = -0.3;
+pub const LIMITER_LOOKAHEAD_FRAMES: usize = 64;
+pub const LIMITER_RELEASE_FRAMES: usize = 4800;
+// Largest block the speaker callback pulls through the graph at once, below PULL_RING_BUFFER_CAPACITY
+pub const CLOCK_BLOCK_SAMPLES: usize = 512;
+// Old job lists a clock callback can hand back before the control side frees them
+pub const CLOCK_LIST_CAPACITY: usize = 64;
+// Parked decoders re-check this often even without a wake up
+pub const SOURCE_PARK_TIMEOUT_MS: u64 = 500;
+// Samples a seek without decoder support throws away between two checks for stop
+pub const SEEK_CATCH_UP_SAMPLES: u64 = 48000;
@@ -0,0 +1,98 @@
+/***
+ * @ Mod:       offline
+ * @ Author:    Leon Lin
+ * @ Date:      20261017
+ */
+
+use crate::audio_node::file_src::FileSrc;
+use crate::audio_node::graph::{AudioGraph, NodeId};
+use crate::audio_node::mixer::Mixer;
+use crate::audio_node::wav_file_dest::WavFileDest;
+use crate::audio_node::{AudioNode, AudioNodeEnum};
+use std::path::PathBuf;
+
+/// Result of driving one node a single step without a realtime clock
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RenderStep {
+    /// moved some data, call again
+    Progress,
+    /// waiting for upstream data or downstream room
+    Idle,
+    /// no more data will ever come out of this node
+    Finished,
+}
+
+/// Drives every node upstream of a destination on the calling thread, as fast as the CPU allows.
+/// Uses the same per-node code the live threads run, end of stream travels downstream by
+/// dropping the finished node's producers.
+pub struct OfflineRenderer {
+    graph: AudioGraph,
+    order: Vec<NodeId>,
+    dest: NodeId,
+}
+
+impl OfflineRenderer {
+    pub fn new(mut graph: AudioGraph, dest: NodeId) -> Result<Self, String> {
+        let order = graph.upstream_order(dest);
+        for id in order.iter() {
+            graph.node_mut(*id).unwrap().begin_offline()?;
+        }
+        Ok(Self { graph, order, dest })
+    }
+
+    /// Render until the destination finishes, returns the graph with the used nodes
+    pub fn run(mut self) -> Result<AudioGraph, String> {
+        let mut finished = vec![false; self.order.len()];
+
+        loop {
+            let mut any_progress = false;
+            for (idx, id) in self.order.iter().enumerate() {
+                if finished[idx] {
+                    continue;
+                }
+                match self.graph.node_mut(*id).unwrap().step_offline() {
+                    RenderStep::Progress => any_progress = true,
+                    RenderStep::Idle => {}
+                    RenderStep::Finished => {
+                        finished[idx] = true;
+                        any_progress = true;
+                        if *id == self.dest {
+                            return Ok(self.graph);
+                        }
+                    }
+                }
+            }
+            if !any_progress {
+                return Err(
+                    "offline render stalled, graph has a node that needs a clock".to_string(),
+                );
+            }
+        }
+    }
+
+    /// Batch helper: mix `inputs` into one WAV file at the given format
+    pub fn mix_files(
+        inputs: &[PathBuf],
+        output: PathBuf,
+        sample_rate: u32,
+        channels: usize,
+    ) -> Result<(), String> {
+        let mut graph = AudioGraph::new();
+        let mixer = graph.add_node(AudioNodeEnum::Mixer(Mixer::with_channels(channels)));
+
+        let mut dest = WavFileDest::init();
+        dest.set_config(output, sample_rate, channels);
+        let dest = graph.add_node(AudioNodeEnum::WavFileDest(dest));
+        graph.connect(mixer, dest)?;
+
+        for input in inputs {
+            let mut src = FileSrc::init();
+            src.set_config(input.clone(), sample_rate, channels);
+            let src = graph.add_node(AudioNodeEnum::FileSrc(src));
+            graph.connect(src, mixer)?;
+        }
+
+        OfflineRenderer::new(graph, dest)?.run()?;
+        Ok(())
+    }
+}
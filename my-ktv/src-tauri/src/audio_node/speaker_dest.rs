@@ -1,82 +1,73 @@
-use crate::audio_node::node_const::PULL_RING_BUFFER_CAPACITY;
-use crate::audio_node::utils::{generate_output_resolve_config, IOStreamConfig};
+use crate::audio_node::backend::{default_backend, AudioBackend, AudioStream};
+use crate::audio_node::clock::{ClockRunner, RenderClock};
+use crate::audio_node::fan_out::RoomSignal;
+use crate::audio_node::node_const::{CLOCK_BLOCK_SAMPLES, PULL_RING_BUFFER_CAPACITY};
+use crate::audio_node::utils::IOStreamConfig;
 use crate::audio_node::{AudioNode, AudioNodeState, AudioNodeType};
-use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use cpal::{FromSample, Sample, Stream, StreamError};
 use rtrb::{Consumer, Producer, RingBuffer};
 use std::cmp::min;
+use std::sync::Arc;
 
 pub struct SpeakerDest {
     pub state: AudioNodeState,
     pub audio_producer: Option<Producer<f32>>,
-    pub output_stream: Stream,
+    pub(crate) input_signal: Arc<RoomSignal>,
+    pub output_stream: Box<dyn AudioStream>,
     pub config: IOStreamConfig,
+    clock: RenderClock,
 }
 
-impl AudioNode for SpeakerDest {
-    fn init() -> Self {
-        let host = cpal::default_host();
-        println!("[HAL] Audio Host: {:?}", host.id());
-
-        // 獲取輸出設備 (DAC)
-        let output_device = host
-            .default_output_device()
-            .expect("no output device available");
-        println!(
-            "[HAL] Output Device: {:?}",
-            output_device.description().unwrap().name()
-        );
-
-        // negotiation function
-        let mut resolve_config_fn = generate_output_resolve_config("Speaker".parse().unwrap());
+impl SpeakerDest {
+    pub fn with_backend(backend: &dyn AudioBackend) -> Result<Self, String> {
+        println!("[HAL] Output Backend: {}", backend.name());
 
         // 協商並建立輸出流
-        let output_config =
-            resolve_config_fn(&output_device).expect("failed to resolve output device config");
+        let output_config = backend.output_config()?;
         println!("[HAL] Negotiated Output Config: {:?}", output_config);
 
         // 建立 Lock-free Ring Buffer
-        // 啟動節點時，前面的 node 就會不斷推 zero data 到這裡，所以該 buffer 的長度就會是 delay，因此不要太長
+        // callback 每次只向上游要剛好需要的量，buffer 只是中轉，長度就是最大的 delay，因此不要太長
         let (producer, consumer) = RingBuffer::<f32>::new(PULL_RING_BUFFER_CAPACITY);
 
         println!("[HAL] New Producer Size: {:?}", producer.slots());
 
-        let output_stream_ret = match output_config.sample_format {
-            cpal::SampleFormat::F32 => output_device.build_output_stream(
-                &output_config.stream_config,
-                data_hdl_cb_creator::<f32>(consumer),
-                err_hdl_cb,
-                None, // Timeout: blocking negotiation
-            ),
-            cpal::SampleFormat::I32 => output_device.build_output_stream(
-                &output_config.stream_config,
-                data_hdl_cb_creator::<i32>(consumer),
-                err_hdl_cb,
-                None, // Timeout: blocking negotiation
-            ),
-            cpal::SampleFormat::I16 => output_device.build_output_stream(
-                &output_config.stream_config,
-                data_hdl_cb_creator::<i16>(consumer),
-                err_hdl_cb,
-                None, // Timeout: blocking negotiation
-            ),
-            cpal::SampleFormat::U8 => output_device.build_output_stream(
-                &output_config.stream_config,
-                data_hdl_cb_creator::<u8>(consumer),
-                err_hdl_cb,
-                None, // Timeout: blocking negotiation
-            ),
-            _ => panic!("Unsupported format"),
-        };
-
-        let output_stream = output_stream_ret.expect("output stream created error");
-
-        Self {
+        let (clock, runner) = RenderClock::new();
+        let input_signal = RoomSignal::new();
+        let callback = data_hdl_cb_creator(
+            consumer,
+            runner,
+            Arc::clone(&input_signal),
+            output_config.stream_config.channels as usize,
+        );
+        let output_stream = backend.build_output_stream(&output_config, Box::new(callback))?;
+
+        Ok(Self {
             state: AudioNodeState::INITIALIZED,
             audio_producer: Option::from(producer),
+            input_signal,
             output_stream,
             config: output_config,
-        }
+            clock,
+        })
+    }
+
+    /// Frames between a sample entering the input ring and the device playing it
+    pub fn latency_frames(&self) -> usize {
+        let channels = self.config.stream_config.channels as usize;
+        self.input_signal.queued() / channels + self.output_stream.latency_frames()
+    }
+
+    /// Clock driving every node that feeds this speaker
+    pub fn clock(&self) -> RenderClock {
+        self.clock.clone()
+    }
+}
+
+impl AudioNode for SpeakerDest {
+    fn init() -> Self {
+        default_backend()
+            .and_then(|backend| SpeakerDest::with_backend(backend.as_ref()))
+            .expect("failed to open speaker")
     }
 
     fn start(&mut self) {
@@ -94,53 +85,66 @@ impl AudioNode for SpeakerDest {
     }
 
     fn get_state(&self) -> AudioNodeState {
-        self.state.clone()
+        self.state
     }
 }
 
-fn err_hdl_cb(err: StreamError) {
-    eprintln!("[HAL] Output Stream Error: {}", err);
+/// Output callback: pull each block through the clocked graph, then play it
+pub fn data_hdl_cb_creator(
+    mut consumer: Consumer<f32>,
+    mut clock: ClockRunner,
+    signal: Arc<RoomSignal>,
+    channels: usize,
+) -> impl FnMut(&mut [f32]) + Send {
+    // whole frames per block so every clocked node sees frame aligned requests
+    let block = (CLOCK_BLOCK_SAMPLES / channels).max(1) * channels;
+    move |data: &mut [f32]| {
+        signal.discard_flushed(&mut consumer);
+        let mut read = 0;
+        for chunk in data.chunks_mut(block) {
+            clock.tick(chunk.len());
+            read += play_buffered(&mut consumer, chunk, channels);
+        }
+        signal.consumed(&consumer, read);
+    }
 }
 
-pub fn data_hdl_cb_creator<T>(
-    mut consumer: Consumer<f32>,
-) -> impl FnMut(&mut [T], &cpal::OutputCallbackInfo)
-where
-    T: Sample + FromSample<f32>,
-{
-    move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
-        let target_len = data.len();
-        let source_len = consumer.slots();
-
-        let fetch_from_source_cnt = min(target_len, source_len);
-        let should_fill_zero_start = fetch_from_source_cnt;
-
-        match consumer.read_chunk(fetch_from_source_cnt) {
-            Ok(chunk) => {
-                let (first, second) = chunk.as_slices();
-                let first_len = first.len();
-
-                for (dest, &src) in data[..first_len].iter_mut().zip(first.iter()) {
-                    *dest = T::from_sample(src);
-                }
+/// Fill `data` from the ring, silence for what is missing, returns the samples read
+fn play_buffered(consumer: &mut Consumer<f32>, data: &mut [f32], channels: usize) -> usize {
+    let target_len = data.len();
+    // whole frames only, a half frame waits for the next callback
+    let source_len = consumer.slots() - consumer.slots() % channels;
 
-                if !second.is_empty() {
-                    for (dest, &src) in data[first_len..].iter_mut().zip(second.iter()) {
-                        *dest = T::from_sample(src);
-                    }
-                }
+    let fetch_from_source_cnt = min(target_len, source_len);
+    let should_fill_zero_start = fetch_from_source_cnt;
 
-                chunk.commit_all();
+    match consumer.read_chunk(fetch_from_source_cnt) {
+        Ok(chunk) => {
+            let (first, second) = chunk.as_slices();
+            let first_len = first.len();
+
+            for (dest, &src) in data[..first_len].iter_mut().zip(first.iter()) {
+                *dest = src;
             }
-            Err(err) => {
-                println!("[HAL] Error reading data {:?}", err);
-                data.fill(T::EQUILIBRIUM);
+
+            if !second.is_empty() {
+                for (dest, &src) in data[first_len..].iter_mut().zip(second.iter()) {
+                    *dest = src;
+                }
             }
-        }
 
-        if fetch_from_source_cnt < target_len {
-            // println!("[HAL] input is less than target len {}: {}", fetch_from_source_cnt, target_len);
-            data[should_fill_zero_start..].fill(T::EQUILIBRIUM);
+            chunk.commit_all();
         }
+        Err(err) => {
+            println!("[HAL] Error reading data {:?}", err);
+            data.fill(0.0);
+            return 0;
+        }
+    }
+
+    if fetch_from_source_cnt < target_len {
+        // println!("[HAL] input is less than target len {}: {}", fetch_from_source_cnt, target_len);
+        data[should_fill_zero_start..].fill(0.0);
     }
+    fetch_from_source_cnt
 }
@@ -1,8 +1,11 @@
+use crate::audio_node::channel_map::ChannelMatrix;
+use crate::audio_node::fan_out::FanOut;
 use cpal::traits::DeviceTrait;
 use cpal::{FromSample, Sample, SampleFormat, StreamConfig};
 use rtrb::{Consumer, Producer};
 use rubato::{
-    Resampler, SincFixedIn, SincInterpolationParameters, SincInterpolationType, WindowFunction,
+    FastFixedIn, PolynomialDegree, Resampler, SincFixedIn, SincInterpolationParameters,
+    SincInterpolationType, WindowFunction,
 };
 
 #[derive(Debug, Clone)]
@@ -145,34 +148,141 @@ pub fn generate_input_resolve_config(
     }
 }
 
+/// How a node converts sample rates. Whatever is picked, matching rates pass straight through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResampleQuality {
+    /// Never resample, a rate mismatch changes pitch and speed
+    Bypass,
+    /// Linear interpolation, cheapest real conversion, audible aliasing
+    Linear,
+    /// Short sinc filter, small delay, good enough for the mic
+    FastSinc,
+    /// Long sinc filter for music
+    #[default]
+    HighSinc,
+}
+
+/// rubato's `Resampler` trait can't be boxed, so keep the kinds we use side by side
+enum Engine {
+    Sinc(SincFixedIn<f32>),
+    Poly(FastFixedIn<f32>),
+}
+
+impl Engine {
+    fn new(
+        quality: ResampleQuality,
+        ratio: f64,
+        max_frames: usize,
+        channels: usize,
+    ) -> Result<Self, String> {
+        let params = match quality {
+            ResampleQuality::Linear => {
+                return FastFixedIn::<f32>::new(
+                    ratio,
+                    2.0,
+                    PolynomialDegree::Linear,
+                    max_frames,
+                    channels,
+                )
+                .map(Engine::Poly)
+                .map_err(|e| e.to_string());
+            }
+            ResampleQuality::FastSinc => SincInterpolationParameters {
+                sinc_len: 64,
+                f_cutoff: 0.915,
+                interpolation: SincInterpolationType::Linear,
+                oversampling_factor: 128,
+                window: WindowFunction::Blackman2,
+            },
+            ResampleQuality::HighSinc | ResampleQuality::Bypass => SincInterpolationParameters {
+                sinc_len: 256,
+                f_cutoff: 0.95,
+                interpolation: SincInterpolationType::Linear,
+                oversampling_factor: 256,
+                window: WindowFunction::BlackmanHarris2,
+            },
+        };
+        SincFixedIn::<f32>::new(ratio, 2.0, params, max_frames, channels)
+            .map(Engine::Sinc)
+            .map_err(|e| e.to_string())
+    }
+
+    fn resampler(&self) -> &dyn ResamplerInfo {
+        match self {
+            Engine::Sinc(r) => r,
+            Engine::Poly(r) => r,
+        }
+    }
+
+    fn reset(&mut self) {
+        match self {
+            Engine::Sinc(r) => r.reset(),
+            Engine::Poly(r) => r.reset(),
+        }
+    }
+
+    fn process(
+        &mut self,
+        input: &[Vec<f32>],
+        output: &mut [Vec<f32>],
+    ) -> rubato::ResampleResult<(usize, usize)> {
+        match self {
+            Engine::Sinc(r) => r.process_into_buffer(input, output, None),
+            Engine::Poly(r) => r.process_into_buffer(input, output, None),
+        }
+    }
+}
+
+/// The object safe part of rubato's `Resampler`
+trait ResamplerInfo {
+    fn round_samples(&self) -> usize;
+    fn delay(&self) -> usize;
+    fn buffers(&self) -> (Vec<Vec<f32>>, Vec<Vec<f32>>);
+}
+
+impl<R: Resampler<f32>> ResamplerInfo for R {
+    fn round_samples(&self) -> usize {
+        self.input_frames_max() * self.nbr_channels()
+    }
+
+    fn delay(&self) -> usize {
+        self.output_delay()
+    }
+
+    fn buffers(&self) -> (Vec<Vec<f32>>, Vec<Vec<f32>>) {
+        (
+            self.input_buffer_allocate(true),
+            self.output_buffer_allocate(true),
+        )
+    }
+}
+
 pub struct ResamplingHandler {
-    resampler: SincFixedIn<f32>,
+    // None when the rates match (or bypass), frames then go straight to the producer
+    engine: Option<Engine>,
     input_channels: Vec<Vec<f32>>,
     output_channels: Vec<Vec<f32>>,
     src_channels_cnt: usize,
     target_channels_cnt: usize,
-    pub producer: Producer<f32>,
+    matrix: ChannelMatrix,
+    // one source frame and one mapped frame, reused so the callback does not allocate
+    src_frame: Vec<f32>,
+    target_frame: Vec<f32>,
+    pub producer: FanOut,
     inner_producer: Producer<f32>,
     inner_consumer: Consumer<f32>,
 }
 
 impl ResamplingHandler {
     pub fn new(
-        producer: Producer<f32>,
+        producer: FanOut,
         src_cfg: StreamConfig,
         target_cfg: StreamConfig,
         inner_producer: Producer<f32>,
         inner_consumer: Consumer<f32>,
         max_frames: usize,
+        quality: ResampleQuality,
     ) -> Self {
-        let params = SincInterpolationParameters {
-            sinc_len: 256,
-            f_cutoff: 0.95,
-            interpolation: SincInterpolationType::Linear,
-            oversampling_factor: 256,
-            window: WindowFunction::BlackmanHarris2,
-        };
-
         if inner_producer.slots() < max_frames {
             panic!("mid buf should bigger than max frame");
         }
@@ -184,32 +294,95 @@ impl ResamplingHandler {
         let target_sample_rate = target_cfg.sample_rate;
 
         // 預估輸出緩衝區大小（加上安全邊際）
-        let ratio = (target_sample_rate / src_sample_rate) as f64;
-
-        let resampler =
-            SincFixedIn::<f32>::new(ratio, 2.0, params, max_frames, src_channels).unwrap();
-        let input_channels = resampler.input_buffer_allocate(true);
-        let output_channels = resampler.output_buffer_allocate(true);
+        let ratio = target_sample_rate as f64 / src_sample_rate as f64;
+
+        // 取樣率一樣就不用過濾波器，直接送
+        let engine = if quality == ResampleQuality::Bypass || src_sample_rate == target_sample_rate
+        {
+            None
+        } else {
+            Some(Engine::new(quality, ratio, max_frames, src_channels).unwrap())
+        };
+        let (input_channels, output_channels) = match engine.as_ref() {
+            Some(engine) => engine.resampler().buffers(),
+            None => (Vec::new(), Vec::new()),
+        };
+        println!(
+            "[HAL] Resample {}Hz -> {}Hz: {:?}",
+            src_sample_rate,
+            target_sample_rate,
+            if engine.is_some() {
+                quality
+            } else {
+                ResampleQuality::Bypass
+            }
+        );
 
         Self {
-            resampler,
+            engine,
             input_channels,
             output_channels,
             src_channels_cnt: src_channels,
             target_channels_cnt: target_channels,
+            matrix: ChannelMatrix::standard(src_channels, target_channels),
+            src_frame: vec![0.0; src_channels],
+            target_frame: vec![0.0; target_channels],
             producer,
             inner_producer,
             inner_consumer,
         }
     }
 
+    /// Filter delay in output frames, a signal comes out this much later than it went in
+    pub fn delay_frames(&self) -> usize {
+        self.engine
+            .as_ref()
+            .map(|engine| engine.resampler().delay())
+            .unwrap_or(0)
+    }
+
+    /// Forget every sample that went in so far, the next packet starts a new stream (seek)
+    pub fn reset(&mut self) {
+        let stale = self.inner_consumer.slots();
+        if let Ok(chunk) = self.inner_consumer.read_chunk(stale) {
+            chunk.commit_all();
+        }
+        if let Some(engine) = self.engine.as_mut() {
+            engine.reset();
+        }
+    }
+
+    /// Replace the standard channel mapping, must match the source and target channel count
+    pub fn set_channel_matrix(&mut self, matrix: ChannelMatrix) -> Result<(), String> {
+        if matrix.inputs() != self.src_channels_cnt || matrix.outputs() != self.target_channels_cnt
+        {
+            return Err(format!(
+                "channel matrix is {}->{}, stream is {}->{}",
+                matrix.inputs(),
+                matrix.outputs(),
+                self.src_channels_cnt,
+                self.target_channels_cnt
+            ));
+        }
+        self.matrix = matrix;
+        Ok(())
+    }
+
     pub fn process_packet<T>(&mut self, input_data: &[T])
     where
         T: Sample,
         f32: FromSample<T>,
     {
-        // push data into resample buffer
+        if self.engine.is_none() {
+            self.pass_through(input_data);
+            return;
+        }
+
+        // push data into resample buffer, whole frames only or the deinterleave below would
+        // shift every channel from then on
         let should_push_data_cnt = input_data.len().min(self.inner_producer.slots());
+        let should_push_data_cnt =
+            should_push_data_cnt - should_push_data_cnt % self.src_channels_cnt;
         if should_push_data_cnt == 0 {
             println!("[HAL] should_push_data_cnt is zero");
             return;
@@ -218,15 +391,14 @@ impl ResamplingHandler {
             Ok(mut chunk) => {
                 let (first, second) = chunk.as_mut_slices();
                 let mid = first.len();
-                let mut cursor = 0;
-                for i in 0..mid {
-                    first[cursor] = input_data[i].to_sample::<f32>();
-                    cursor += 1;
+                for (dest, src) in first.iter_mut().zip(input_data[..mid].iter()) {
+                    *dest = src.to_sample::<f32>();
                 }
-                cursor = 0;
-                for i in mid..should_push_data_cnt {
-                    second[cursor] = input_data[i].to_sample::<f32>();
-                    cursor += 1;
+                for (dest, src) in second
+                    .iter_mut()
+                    .zip(input_data[mid..should_push_data_cnt].iter())
+                {
+                    *dest = src.to_sample::<f32>();
                 }
                 chunk.commit_all();
             }
@@ -236,9 +408,11 @@ impl ResamplingHandler {
             }
         };
 
+        let Some(engine) = self.engine.as_mut() else {
+            return;
+        };
         loop {
-            let one_round_needed_sample_cnt = Resampler::input_frames_max(&self.resampler)
-                * Resampler::nbr_channels(&self.resampler);
+            let one_round_needed_sample_cnt = engine.resampler().round_samples();
 
             // check enough to resample
             if self.inner_consumer.slots() < one_round_needed_sample_cnt {
@@ -268,15 +442,17 @@ impl ResamplingHandler {
             }
 
             // resample
-            let ret = Resampler::process_into_buffer(
-                &mut self.resampler,
-                &mut self.input_channels,
-                &mut self.output_channels,
-                None,
-            );
+            let ret = engine.process(&self.input_channels, &mut self.output_channels);
             match ret {
                 Ok((_read, written)) => {
-                    self.handle_output(written);
+                    Self::handle_output(
+                        &self.output_channels,
+                        &mut self.src_frame,
+                        &mut self.target_frame,
+                        &self.matrix,
+                        &mut self.producer,
+                        written,
+                    );
                 }
                 Err(error) => {
                     println!("[HAL] Error processing data: {}", error);
@@ -297,26 +473,45 @@ impl ResamplingHandler {
         can_write_frame_with_chan < self.producer.slots()
     }
 
-    fn handle_output(&mut self, written: usize) {
-        // multi-chan algo: only dup first channel
-        let mut not_sent = written;
-        // println!("[HAL] not_sent {} samples to producer", not_sent);
-        let first_channel = &self.output_channels[0];
-        for idx in 0..first_channel.len() {
-            if self.producer.slots() < self.target_channels_cnt {
-                println!("[HAL] resample Output buffer full");
+    /// Same rate on both sides: only map channels
+    fn pass_through<T>(&mut self, input_data: &[T])
+    where
+        T: Sample,
+        f32: FromSample<T>,
+    {
+        for frame in input_data.chunks_exact(self.src_channels_cnt) {
+            for (dest, src) in self.src_frame.iter_mut().zip(frame.iter()) {
+                *dest = src.to_sample::<f32>();
+            }
+            self.matrix.apply(&self.src_frame, &mut self.target_frame);
+            if !self.producer.push_frame(&self.target_frame) {
+                println!("[HAL] pass-through Output buffer full");
                 break;
             }
-            if not_sent == 0 {
+        }
+    }
+
+    fn handle_output(
+        output_channels: &[Vec<f32>],
+        src_frame: &mut [f32],
+        target_frame: &mut [f32],
+        matrix: &ChannelMatrix,
+        producer: &mut FanOut,
+        written: usize,
+    ) {
+        for idx in 0..written {
+            if producer.slots() < target_frame.len() {
+                println!("[HAL] resample Output buffer full");
                 break;
             }
-            for _ in 0..self.target_channels_cnt {
-                if self.producer.push(first_channel[idx]).is_err() {
-                    println!("[HAL] Error sending resample data to producer {}", idx);
-                    break;
-                }
+            for (sample, channel) in src_frame.iter_mut().zip(output_channels.iter()) {
+                *sample = channel[idx];
+            }
+            matrix.apply(src_frame, target_frame);
+            if !producer.push_frame(target_frame) {
+                println!("[HAL] Error sending resample data to producer {}", idx);
+                break;
             }
-            not_sent -= 1;
         }
     }
 }
@@ -0,0 +1,322 @@
+/***
+ * @ Mod:       virtual_backend
+ * @ Author:    Leon Lin
+ * @ Date:      20261017
+ */
+
+use crate::audio_node::backend::{AudioBackend, AudioStream, InputCallback, OutputCallback};
+use crate::audio_node::utils::IOStreamConfig;
+use cpal::{BufferSize, SampleFormat, StreamConfig};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::Duration;
+
+// realtime clock period of the virtual device
+const VIRTUAL_PERIOD_MS: u64 = 10;
+
+/// What the virtual microphone records
+pub enum VirtualMicSignal {
+    Silence,
+    Sine {
+        frequency: f32,
+        amplitude: f32,
+    },
+    /// interleaved samples in the input config layout, played once then silence
+    Samples(Vec<f32>),
+}
+
+enum StreamCallback {
+    Output(OutputCallback),
+    Input(InputCallback),
+}
+
+struct VirtualStreamInner {
+    playing: AtomicBool,
+    closed: AtomicBool,
+    callback: Mutex<StreamCallback>,
+}
+
+struct MicState {
+    signal: VirtualMicSignal,
+    cursor: usize,
+}
+
+/// Sound card without hardware: the microphone plays a generated signal or a WAV,
+/// the speaker output can be captured into memory.
+/// `new` runs its own clock like a real device, `manual` only moves on `tick`.
+pub struct VirtualBackend {
+    output_config: IOStreamConfig,
+    input_config: IOStreamConfig,
+    realtime: bool,
+    capture: bool,
+    mic: Arc<Mutex<MicState>>,
+    captured: Arc<Mutex<Vec<f32>>>,
+    streams: Mutex<Vec<Weak<VirtualStreamInner>>>,
+}
+
+impl VirtualBackend {
+    /// Realtime clocked device for headless hosts, speaker output is discarded
+    pub fn new() -> Self {
+        Self {
+            output_config: virtual_config(48000, 2),
+            input_config: virtual_config(48000, 2),
+            realtime: true,
+            capture: false,
+            mic: Arc::new(Mutex::new(MicState {
+                signal: VirtualMicSignal::Silence,
+                cursor: 0,
+            })),
+            captured: Arc::new(Mutex::new(Vec::new())),
+            streams: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Device that only advances on `tick`, speaker output is captured
+    pub fn manual() -> Self {
+        Self {
+            realtime: false,
+            capture: true,
+            ..Self::new()
+        }
+    }
+
+    pub fn with_output_config(mut self, sample_rate: u32, channels: u16) -> Self {
+        self.output_config = virtual_config(sample_rate, channels);
+        self
+    }
+
+    pub fn with_input_config(mut self, sample_rate: u32, channels: u16) -> Self {
+        self.input_config = virtual_config(sample_rate, channels);
+        self
+    }
+
+    pub fn with_mic_signal(self, signal: VirtualMicSignal) -> Self {
+        *self.mic.lock().unwrap() = MicState { signal, cursor: 0 };
+        self
+    }
+
+    /// Microphone plays a WAV file, the input config follows the file
+    pub fn with_mic_wav(self, path: &Path) -> Result<Self, String> {
+        let mut reader = hound::WavReader::open(path).map_err(|e| e.to_string())?;
+        let spec = reader.spec();
+        let samples: Result<Vec<f32>, _> = match spec.sample_format {
+            hound::SampleFormat::Float => reader.samples::<f32>().collect(),
+            hound::SampleFormat::Int => {
+                let scale = (1i64 << (spec.bits_per_sample - 1)) as f32;
+                reader
+                    .samples::<i32>()
+                    .map(|s| s.map(|v| v as f32 / scale))
+                    .collect()
+            }
+        };
+        let samples = samples.map_err(|e| e.to_string())?;
+        Ok(self
+            .with_input_config(spec.sample_rate, spec.channels)
+            .with_mic_signal(VirtualMicSignal::Samples(samples)))
+    }
+
+    pub fn with_capture(mut self, capture: bool) -> Self {
+        self.capture = capture;
+        self
+    }
+
+    /// Advance every playing stream by `frames`, inputs before outputs
+    pub fn tick(&self, frames: usize) {
+        let streams: Vec<Arc<VirtualStreamInner>> = self
+            .streams
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|s| s.upgrade())
+            .collect();
+
+        for stream in streams.iter() {
+            if matches!(*stream.callback.lock().unwrap(), StreamCallback::Input(_)) {
+                run_stream(stream, frames, &self.shared());
+            }
+        }
+        for stream in streams.iter() {
+            if matches!(*stream.callback.lock().unwrap(), StreamCallback::Output(_)) {
+                run_stream(stream, frames, &self.shared());
+            }
+        }
+    }
+
+    /// Everything the virtual speaker played so far (interleaved)
+    pub fn captured(&self) -> Vec<f32> {
+        self.captured.lock().unwrap().clone()
+    }
+
+    pub fn clear_captured(&self) {
+        self.captured.lock().unwrap().clear();
+    }
+
+    fn shared(&self) -> DeviceShared {
+        DeviceShared {
+            output_channels: self.output_config.stream_config.channels as usize,
+            input_channels: self.input_config.stream_config.channels as usize,
+            input_sample_rate: self.input_config.stream_config.sample_rate,
+            capture: self.capture,
+            mic: Arc::clone(&self.mic),
+            captured: Arc::clone(&self.captured),
+        }
+    }
+
+    fn register(&self, callback: StreamCallback, sample_rate: u32) -> Box<dyn AudioStream> {
+        let inner = Arc::new(VirtualStreamInner {
+            playing: AtomicBool::new(false),
+            closed: AtomicBool::new(false),
+            callback: Mutex::new(callback),
+        });
+        let mut streams = self.streams.lock().unwrap();
+        streams.retain(|s| s.strong_count() > 0);
+        streams.push(Arc::downgrade(&inner));
+
+        if self.realtime {
+            let clock_stream = Arc::clone(&inner);
+            let shared = self.shared();
+            let frames = (sample_rate as u64 * VIRTUAL_PERIOD_MS / 1000) as usize;
+            thread::spawn(move || {
+                while !clock_stream.closed.load(Ordering::Relaxed) {
+                    run_stream(&clock_stream, frames, &shared);
+                    thread::sleep(Duration::from_millis(VIRTUAL_PERIOD_MS));
+                }
+            });
+        }
+
+        Box::new(VirtualStream(inner))
+    }
+}
+
+impl Default for VirtualBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AudioBackend for VirtualBackend {
+    fn name(&self) -> String {
+        "virtual".to_string()
+    }
+
+    fn output_config(&self) -> Result<IOStreamConfig, String> {
+        Ok(self.output_config.clone())
+    }
+
+    fn input_config(&self) -> Result<IOStreamConfig, String> {
+        Ok(self.input_config.clone())
+    }
+
+    fn build_output_stream(
+        &self,
+        config: &IOStreamConfig,
+        callback: OutputCallback,
+    ) -> Result<Box<dyn AudioStream>, String> {
+        Ok(self.register(
+            StreamCallback::Output(callback),
+            config.stream_config.sample_rate,
+        ))
+    }
+
+    fn build_input_stream(
+        &self,
+        config: &IOStreamConfig,
+        callback: InputCallback,
+    ) -> Result<Box<dyn AudioStream>, String> {
+        Ok(self.register(
+            StreamCallback::Input(callback),
+            config.stream_config.sample_rate,
+        ))
+    }
+}
+
+struct VirtualStream(Arc<VirtualStreamInner>);
+
+impl AudioStream for VirtualStream {
+    fn play(&self) -> Result<(), String> {
+        self.0.playing.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn pause(&self) -> Result<(), String> {
+        self.0.playing.store(false, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+impl Drop for VirtualStream {
+    fn drop(&mut self) {
+        self.0.closed.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Device state a stream clock needs, cloned into realtime clock threads
+struct DeviceShared {
+    output_channels: usize,
+    input_channels: usize,
+    input_sample_rate: u32,
+    capture: bool,
+    mic: Arc<Mutex<MicState>>,
+    captured: Arc<Mutex<Vec<f32>>>,
+}
+
+fn run_stream(stream: &VirtualStreamInner, frames: usize, shared: &DeviceShared) {
+    if !stream.playing.load(Ordering::Relaxed) || stream.closed.load(Ordering::Relaxed) {
+        return;
+    }
+    match &mut *stream.callback.lock().unwrap() {
+        StreamCallback::Output(callback) => {
+            let mut data = vec![0.0f32; frames * shared.output_channels];
+            callback(&mut data);
+            if shared.capture {
+                shared.captured.lock().unwrap().extend_from_slice(&data);
+            }
+        }
+        StreamCallback::Input(callback) => {
+            let data = record_mic(shared, frames);
+            callback(&data);
+        }
+    }
+}
+
+fn record_mic(shared: &DeviceShared, frames: usize) -> Vec<f32> {
+    let channels = shared.input_channels;
+    let mut data = vec![0.0f32; frames * channels];
+    let mut mic = shared.mic.lock().unwrap();
+    let cursor = mic.cursor;
+
+    match &mic.signal {
+        VirtualMicSignal::Silence => {}
+        VirtualMicSignal::Sine {
+            frequency,
+            amplitude,
+        } => {
+            for (idx, frame) in data.chunks_mut(channels).enumerate() {
+                let t = (cursor + idx) as f32 / shared.input_sample_rate as f32;
+                let v = (t * frequency * 2.0 * std::f32::consts::PI).sin() * amplitude;
+                frame.fill(v);
+            }
+        }
+        VirtualMicSignal::Samples(samples) => {
+            let start = (cursor * channels).min(samples.len());
+            let end = ((cursor + frames) * channels).min(samples.len());
+            data[..end - start].copy_from_slice(&samples[start..end]);
+        }
+    }
+
+    mic.cursor += frames;
+    data
+}
+
+fn virtual_config(sample_rate: u32, channels: u16) -> IOStreamConfig {
+    IOStreamConfig {
+        sample_format: SampleFormat::F32,
+        stream_config: StreamConfig {
+            channels,
+            sample_rate,
+            buffer_size: BufferSize::Default,
+        },
+    }
+}
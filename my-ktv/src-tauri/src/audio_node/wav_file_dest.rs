@@ -0,0 +1,194 @@
+/***
+ * @ Mod:       wav_file_dest
+ * @ Author:    Leon Lin
+ * @ Date:      20261017
+ */
+
+use crate::audio_node::fan_out::RoomSignal;
+use crate::audio_node::node_const::PUSH_RING_BUFFER_CAPACITY;
+use crate::audio_node::offline::RenderStep;
+use crate::audio_node::{AudioNode, AudioNodeState, AudioNodeType};
+use hound::{SampleFormat, WavSpec, WavWriter};
+use rtrb::{Consumer, Producer, RingBuffer};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::thread::JoinHandle;
+
+type FileWavWriter = WavWriter<BufWriter<File>>;
+
+/// Destination writing 32-bit float WAV, no sound card needed.
+/// Live it records whatever reaches it, offline it is the clock of the whole render.
+pub struct WavFileDest {
+    pub state: AudioNodeState,
+    pub audio_producer: Option<Producer<f32>>,
+    pub(crate) input_signal: Arc<RoomSignal>,
+    consumer: Option<Consumer<f32>>,
+    file_path: Option<PathBuf>,
+    sample_rate: u32,
+    channels: usize,
+    writer: Option<FileWavWriter>,
+    keep_running: Arc<AtomicBool>,
+    writer_thread: Option<JoinHandle<Consumer<f32>>>,
+    sleep_ms: u64,
+}
+
+impl WavFileDest {
+    pub fn set_config(&mut self, file_path: PathBuf, sample_rate: u32, channels: usize) {
+        self.file_path = Some(file_path);
+        self.sample_rate = sample_rate;
+        self.channels = channels;
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    fn create_writer(&self) -> Result<FileWavWriter, String> {
+        let file_path = match &self.file_path {
+            Some(p) => p,
+            None => return Err("WavFileDest: no file path".to_string()),
+        };
+        let spec = WavSpec {
+            channels: self.channels as u16,
+            sample_rate: self.sample_rate,
+            bits_per_sample: 32,
+            sample_format: SampleFormat::Float,
+        };
+        WavWriter::create(file_path, spec).map_err(|e| format!("WavFileDest: {}", e))
+    }
+
+    pub(crate) fn begin_offline(&mut self) -> Result<(), String> {
+        self.writer = Some(self.create_writer()?);
+        self.state = AudioNodeState::RUNNING;
+        Ok(())
+    }
+
+    pub(crate) fn step_offline(&mut self) -> RenderStep {
+        let (consumer, writer) = match (self.consumer.as_mut(), self.writer.as_mut()) {
+            (Some(c), Some(w)) => (c, w),
+            _ => return RenderStep::Finished,
+        };
+        self.input_signal.discard_flushed(consumer);
+
+        match drain_into(consumer, writer) {
+            Ok(0) if consumer.is_abandoned() => {
+                if let Err(e) = self.writer.take().unwrap().finalize() {
+                    eprintln!("[WavFileDest] Failed to finalize: {}", e);
+                }
+                self.state = AudioNodeState::STOPPED;
+                RenderStep::Finished
+            }
+            Ok(0) => RenderStep::Idle,
+            Ok(_) => RenderStep::Progress,
+            Err(e) => {
+                eprintln!("[WavFileDest] {}", e);
+                self.writer = None;
+                self.state = AudioNodeState::STOPPED;
+                RenderStep::Finished
+            }
+        }
+    }
+}
+
+impl AudioNode for WavFileDest {
+    fn init() -> Self {
+        let (producer, consumer) = RingBuffer::<f32>::new(PUSH_RING_BUFFER_CAPACITY);
+
+        Self {
+            state: AudioNodeState::INITIALIZED,
+            audio_producer: Some(producer),
+            input_signal: RoomSignal::new(),
+            consumer: Some(consumer),
+            file_path: None,
+            sample_rate: 48000,
+            channels: 2,
+            writer: None,
+            keep_running: Arc::new(AtomicBool::new(false)),
+            writer_thread: None,
+            sleep_ms: 10,
+        }
+    }
+
+    fn start(&mut self) {
+        let sleep_ms = self.sleep_ms;
+        let mut writer = self.create_writer().expect("failed to create wav file");
+        let mut consumer = match self.consumer.take() {
+            Some(c) => c,
+            None => panic!("WavFileDest: cannot start audio node - no consumer"),
+        };
+
+        let keep_running = Arc::clone(&self.keep_running);
+        keep_running.store(true, Ordering::Relaxed);
+        let signal = Arc::clone(&self.input_signal);
+
+        self.writer_thread = Some(thread::spawn(move || {
+            println!("[WavFileDest] Writer Thread Started");
+
+            while keep_running.load(Ordering::Relaxed) {
+                signal.discard_flushed(&mut consumer);
+                match drain_into(&mut consumer, &mut writer) {
+                    Ok(0) if consumer.is_abandoned() => break,
+                    Ok(0) => thread::sleep(std::time::Duration::from_millis(sleep_ms)),
+                    // room again, wake the decoder feeding us
+                    Ok(read) => signal.consumed(&consumer, read),
+                    Err(e) => {
+                        eprintln!("[WavFileDest] {}", e);
+                        break;
+                    }
+                }
+            }
+            let _ = drain_into(&mut consumer, &mut writer);
+            if let Err(e) = writer.finalize() {
+                eprintln!("[WavFileDest] Failed to finalize: {}", e);
+            }
+
+            println!("[WavFileDest] Writer Thread Stopped");
+            consumer
+        }));
+
+        self.state = AudioNodeState::RUNNING;
+    }
+
+    fn stop(&mut self) {
+        self.keep_running.store(false, Ordering::Relaxed);
+
+        if let Some(handle) = self.writer_thread.take() {
+            if let Ok(consumer) = handle.join() {
+                self.consumer = Some(consumer);
+            }
+        }
+
+        self.state = AudioNodeState::STOPPED;
+    }
+
+    fn get_type(&self) -> AudioNodeType {
+        AudioNodeType::DESTINATION
+    }
+
+    fn get_state(&self) -> AudioNodeState {
+        self.state
+    }
+}
+
+/// Write everything currently readable, returns the sample count
+fn drain_into(consumer: &mut Consumer<f32>, writer: &mut FileWavWriter) -> Result<usize, String> {
+    let available = consumer.slots();
+    if available == 0 {
+        return Ok(0);
+    }
+    let chunk = consumer
+        .read_chunk(available)
+        .map_err(|e| format!("{:?}", e))?;
+    let (first, second) = chunk.as_slices();
+    for sample in first.iter().chain(second.iter()) {
+        writer
+            .write_sample(*sample)
+            .map_err(|e| format!("write error: {}", e))?;
+    }
+    chunk.commit_all();
+    Ok(available)
+}
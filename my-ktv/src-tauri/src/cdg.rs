@@ -0,0 +1,315 @@
+/***
+ * @ Mod:       cdg
+ * @ Author:    Leon Lin
+ * @ Date:      20261017
+ */
+
+use crate::library::scan::find_sidecar;
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+pub const CDG_WIDTH: usize = 300;
+pub const CDG_HEIGHT: usize = 216;
+pub const PACKET_SIZE: usize = 24;
+pub const PACKETS_PER_SECOND: u64 = 300;
+
+const TILE_WIDTH: usize = 6;
+const TILE_HEIGHT: usize = 12;
+const TILE_COLUMNS: usize = CDG_WIDTH / TILE_WIDTH;
+const TILE_ROWS: usize = CDG_HEIGHT / TILE_HEIGHT;
+
+// subcode command of the TV graphics packets, every other packet is for something else
+const CDG_COMMAND: u8 = 0x09;
+const MEMORY_PRESET: u8 = 1;
+const BORDER_PRESET: u8 = 2;
+const TILE_BLOCK: u8 = 6;
+const SCROLL_PRESET: u8 = 20;
+const SCROLL_COPY: u8 = 24;
+const DEFINE_TRANSPARENT: u8 = 28;
+const LOAD_COLORS_LOW: u8 = 30;
+const LOAD_COLORS_HIGH: u8 = 31;
+const TILE_BLOCK_XOR: u8 = 38;
+
+/// Part of the screen, `pixels` are palette indices row by row
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct CdgRect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+/// What changed since the last update. `full` means `rects` is the whole 300x216 screen, sent
+/// after a clear, a scroll, a new palette or a seek. The visible window is the 288x192 inside
+/// the border moved by `offset_x`/`offset_y` (fine scrolling, up to 5 and 11 pixels).
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct CdgUpdate {
+    pub full: bool,
+    pub rects: Vec<CdgRect>,
+    // RGB, 4 bits a channel stretched to 8
+    pub palette: [[u8; 3]; 16],
+    pub transparent: Option<u8>,
+    pub offset_x: u8,
+    pub offset_y: u8,
+}
+
+/// Plays a `.cdg` file onto a 300x216 screen of 16 colors. Packets are applied up to the
+/// position of the audio, 300 a second; going back replays from the top.
+pub struct CdgDecoder {
+    data: Vec<u8>,
+    // next packet to apply
+    next: usize,
+    pixels: Vec<u8>,
+    palette: [[u8; 3]; 16],
+    transparent: Option<u8>,
+    offset_x: u8,
+    offset_y: u8,
+    // tiles drawn since the last update, `all_dirty` when that is the whole screen
+    dirty: Vec<bool>,
+    all_dirty: bool,
+}
+
+impl CdgDecoder {
+    pub fn new(data: Vec<u8>) -> Self {
+        Self {
+            data,
+            next: 0,
+            pixels: vec![0; CDG_WIDTH * CDG_HEIGHT],
+            palette: [[0; 3]; 16],
+            transparent: None,
+            offset_x: 0,
+            offset_y: 0,
+            dirty: vec![false; TILE_COLUMNS * TILE_ROWS],
+            all_dirty: true,
+        }
+    }
+
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let data = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(Self::new(data))
+    }
+
+    /// Length of the graphics, a packet is 1/300 s
+    pub fn duration(&self) -> Duration {
+        let packets = (self.data.len() / PACKET_SIZE) as u64;
+        Duration::from_millis(packets * 1000 / PACKETS_PER_SECOND)
+    }
+
+    /// Palette index at `x`, `y` of the 300x216 screen
+    pub fn pixel(&self, x: usize, y: usize) -> u8 {
+        self.pixels[y * CDG_WIDTH + x]
+    }
+
+    pub fn palette(&self) -> &[[u8; 3]; 16] {
+        &self.palette
+    }
+
+    /// Apply every packet up to `position`
+    pub fn advance_to(&mut self, position: Duration) {
+        let target = (position.as_millis() as u64 * PACKETS_PER_SECOND / 1000) as usize;
+        let target = target.min(self.data.len() / PACKET_SIZE);
+        if target < self.next {
+            // packets only draw over what is there, the screen before can't be undone
+            self.reset();
+        }
+        while self.next < target {
+            let start = self.next * PACKET_SIZE;
+            let packet: [u8; PACKET_SIZE] = self.data[start..start + PACKET_SIZE]
+                .try_into()
+                .expect("slice is one packet");
+            self.apply(&packet);
+            self.next += 1;
+        }
+    }
+
+    fn reset(&mut self) {
+        let data = std::mem::take(&mut self.data);
+        *self = Self::new(data);
+    }
+
+    /// Apply one 24 byte subcode packet: command, instruction, 2 parity bytes, 16 data bytes,
+    /// 4 parity bytes. The top two bits of every byte are channels P and Q, not graphics.
+    pub fn apply(&mut self, packet: &[u8; PACKET_SIZE]) {
+        if packet[0] & 0x3F != CDG_COMMAND {
+            return;
+        }
+        let mut data = [0u8; 16];
+        for (d, p) in data.iter_mut().zip(&packet[4..20]) {
+            *d = p & 0x3F;
+        }
+        match packet[1] & 0x3F {
+            MEMORY_PRESET => {
+                self.pixels.fill(data[0] & 0x0F);
+                self.all_dirty = true;
+            }
+            BORDER_PRESET => self.fill_border(data[0] & 0x0F),
+            TILE_BLOCK => self.draw_tile(&data, false),
+            TILE_BLOCK_XOR => self.draw_tile(&data, true),
+            SCROLL_PRESET => self.scroll(&data, false),
+            SCROLL_COPY => self.scroll(&data, true),
+            DEFINE_TRANSPARENT => self.transparent = Some(data[0] & 0x0F),
+            LOAD_COLORS_LOW => self.load_colors(&data, 0),
+            LOAD_COLORS_HIGH => self.load_colors(&data, 8),
+            _ => {}
+        }
+    }
+
+    /// The border is the outer tile all around, the visible window is inside it
+    fn fill_border(&mut self, color: u8) {
+        for y in 0..CDG_HEIGHT {
+            for x in 0..CDG_WIDTH {
+                let inside = (TILE_WIDTH..CDG_WIDTH - TILE_WIDTH).contains(&x)
+                    && (TILE_HEIGHT..CDG_HEIGHT - TILE_HEIGHT).contains(&y);
+                if !inside {
+                    self.pixels[y * CDG_WIDTH + x] = color;
+                }
+            }
+        }
+        self.all_dirty = true;
+    }
+
+    /// 6x12 pixels, one data byte a row with the leftmost pixel in bit 5. A set bit is color 1.
+    /// XOR blocks flip the palette index that is there instead.
+    fn draw_tile(&mut self, data: &[u8; 16], xor: bool) {
+        let colors = [data[0] & 0x0F, data[1] & 0x0F];
+        let (row, column) = ((data[2] & 0x1F) as usize, (data[3] & 0x3F) as usize);
+        if row >= TILE_ROWS || column >= TILE_COLUMNS {
+            return;
+        }
+        for (dy, bits) in data[4..16].iter().enumerate() {
+            let y = row * TILE_HEIGHT + dy;
+            for dx in 0..TILE_WIDTH {
+                let color = colors[((bits >> (5 - dx)) & 1) as usize];
+                let pixel = &mut self.pixels[y * CDG_WIDTH + column * TILE_WIDTH + dx];
+                *pixel = if xor { *pixel ^ color } else { color };
+            }
+        }
+        self.dirty[row * TILE_COLUMNS + column] = true;
+    }
+
+    /// Coarse scrolling moves the screen a tile (1 right, 2 left / 1 down, 2 up), `copy` wraps
+    /// what falls off round to the other side, a preset fills the gap with the color. Fine
+    /// scrolling only moves the visible window.
+    fn scroll(&mut self, data: &[u8; 16], copy: bool) {
+        let color = data[0] & 0x0F;
+        let (h, v) = (data[1], data[2]);
+        self.offset_x = (h & 0x07).min(TILE_WIDTH as u8 - 1);
+        self.offset_y = (v & 0x0F).min(TILE_HEIGHT as u8 - 1);
+        let dx: isize = match (h & 0x30) >> 4 {
+            1 => TILE_WIDTH as isize,
+            2 => -(TILE_WIDTH as isize),
+            _ => 0,
+        };
+        let dy: isize = match (v & 0x30) >> 4 {
+            1 => TILE_HEIGHT as isize,
+            2 => -(TILE_HEIGHT as isize),
+            _ => 0,
+        };
+        if dx != 0 || dy != 0 {
+            let old = self.pixels.clone();
+            let (w, h) = (CDG_WIDTH as isize, CDG_HEIGHT as isize);
+            for y in 0..h {
+                for x in 0..w {
+                    let (sx, sy) = (x - dx, y - dy);
+                    let inside = (0..w).contains(&sx) && (0..h).contains(&sy);
+                    self.pixels[(y * w + x) as usize] = match (inside, copy) {
+                        (true, _) => old[(sy * w + sx) as usize],
+                        (false, true) => old[(sy.rem_euclid(h) * w + sx.rem_euclid(w)) as usize],
+                        (false, false) => color,
+                    };
+                }
+            }
+        }
+        self.all_dirty = true;
+    }
+
+    /// Eight entries of two bytes: `--RRRRGG` `--GGBBBB`
+    fn load_colors(&mut self, data: &[u8; 16], first: usize) {
+        for i in 0..8 {
+            let (hi, lo) = (data[2 * i], data[2 * i + 1]);
+            let r = (hi & 0x3C) >> 2;
+            let g = ((hi & 0x03) << 2) | ((lo & 0x30) >> 4);
+            let b = lo & 0x0F;
+            self.palette[first + i] = [r * 17, g * 17, b * 17];
+        }
+        self.all_dirty = true;
+    }
+
+    /// The tiles drawn since the last call, neighbours on a row merged into one rect. `None`
+    /// when nothing changed.
+    pub fn take_update(&mut self) -> Option<CdgUpdate> {
+        if self.all_dirty {
+            return Some(self.frame());
+        }
+        let mut rects = Vec::new();
+        for row in 0..TILE_ROWS {
+            let mut column = 0;
+            while column < TILE_COLUMNS {
+                if !self.dirty[row * TILE_COLUMNS + column] {
+                    column += 1;
+                    continue;
+                }
+                let start = column;
+                while column < TILE_COLUMNS && self.dirty[row * TILE_COLUMNS + column] {
+                    column += 1;
+                }
+                rects.push(self.rect(
+                    start * TILE_WIDTH,
+                    row * TILE_HEIGHT,
+                    (column - start) * TILE_WIDTH,
+                    TILE_HEIGHT,
+                ));
+            }
+        }
+        if rects.is_empty() {
+            return None;
+        }
+        self.dirty.fill(false);
+        Some(self.update(false, rects))
+    }
+
+    /// The whole screen, for a frontend that just started to look
+    pub fn frame(&mut self) -> CdgUpdate {
+        self.all_dirty = false;
+        self.dirty.fill(false);
+        let rects = vec![self.rect(0, 0, CDG_WIDTH, CDG_HEIGHT)];
+        self.update(true, rects)
+    }
+
+    fn rect(&self, x: usize, y: usize, width: usize, height: usize) -> CdgRect {
+        let mut pixels = Vec::with_capacity(width * height);
+        for row in y..y + height {
+            pixels
+                .extend_from_slice(&self.pixels[row * CDG_WIDTH + x..row * CDG_WIDTH + x + width]);
+        }
+        CdgRect {
+            x,
+            y,
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    fn update(&self, full: bool, rects: Vec<CdgRect>) -> CdgUpdate {
+        CdgUpdate {
+            full,
+            rects,
+            palette: self.palette,
+            transparent: self.transparent,
+            offset_x: self.offset_x,
+            offset_y: self.offset_y,
+        }
+    }
+}
+
+/// The `.cdg` next to an MP3+G song, `None` when there is none
+pub fn load_sidecar(audio: &Path) -> Result<Option<CdgDecoder>, String> {
+    match find_sidecar(audio, "cdg") {
+        Some(path) => Ok(Some(CdgDecoder::open(&path)?)),
+        None => Ok(None),
+    }
+}
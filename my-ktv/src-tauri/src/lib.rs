@@ -1,36 +1,570 @@
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
+use crate::audio_node::backend::{default_backend, AudioBackend};
 use crate::audio_node::file_src::FileSrc;
+use crate::audio_node::gain::{GainControl, GainNode};
+use crate::audio_node::graph::{AudioGraph, NodeId};
 use crate::audio_node::mic_src::MicSrc;
-use crate::audio_node::mixer::Mixer;
+use crate::audio_node::midi_src::synth::load_soundfont;
+use crate::audio_node::midi_src::{is_midi, MidiSrc};
+use crate::audio_node::mixer::{Mixer, MixerInputControl};
 use crate::audio_node::speaker_dest::SpeakerDest;
-use crate::audio_node::{connect, AudioNode, AudioNodeEnum};
+use crate::audio_node::{AudioNode, AudioNodeEnum, AudioNodeState, IOStreamConfig};
+use crate::cdg::{CdgDecoder, CdgUpdate};
+use crate::library::catalog::{read_catalog, write_catalog, ImportReport};
+use crate::library::scan::{scan, scan_paths, AUDIO_EXTENSIONS};
+use crate::library::search::{SearchQuery, SingerEntry, SingerKind};
+use crate::library::watch::FolderWatcher;
+use crate::library::{Library, ScanReport, Song, SongPage};
+use crate::lyrics::ultrastar::{self, UltraStarSong};
+use crate::lyrics::{load_lyrics_with_notes, Lyrics, WordProgress};
+use crate::song_queue::rotation::policy_by_name;
+use crate::song_queue::{QueueEntry, QueueSnapshot, SongQueue};
+use rustysynth::SoundFont;
+use serde::Serialize;
+use std::collections::HashMap;
 use std::path::PathBuf;
-use std::sync::Mutex;
-use tauri::State;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::path::BaseDirectory;
+use tauri::{Emitter, Manager, State};
 
 pub mod audio_node;
+pub mod cdg;
+pub mod library;
+pub mod lyrics;
+pub mod song_queue;
+
+// Pushed while a song is loaded, the payload is `PlaybackProgress`
+const PROGRESS_EVENT: &str = "playback://progress";
+const DEFAULT_PROGRESS_INTERVAL_MS: u64 = 250;
+// Pushed once when a song played to the end, the payload is `PlaybackEnded`
+const ENDED_EVENT: &str = "playback://ended";
+// Pushed whenever the song queue changes, the payload is `QueueSnapshot`
+const QUEUE_EVENT: &str = "queue://changed";
+// Pushed when songs were added, updated or removed, or MIDI songs can be listed now that a
+// SoundFont is set; the payload is `ScanReport`
+const LIBRARY_EVENT: &str = "library://changed";
+// a copy in progress keeps touching the file, import once it has been quiet this long
+const LIBRARY_DEBOUNCE: Duration = Duration::from_secs(2);
+// Pushed when the song on air changes, the payload is `LyricsChanged`
+const LYRICS_EVENT: &str = "lyrics://changed";
+// Pushed when the audible position reaches another lyric line, the payload is `LyricLineChanged`
+const LYRIC_LINE_EVENT: &str = "lyrics://line";
+// Pushed while a word with enhanced LRC timing is sung, when it starts or ends and with every
+// progress tick, the payload is `LyricWordProgress`
+const LYRIC_WORD_EVENT: &str = "lyrics://word";
+const LYRIC_MIN_WAIT: Duration = Duration::from_millis(5);
+// Pushed when the song on air changes, the payload is `GraphicsChanged`
+const GRAPHICS_EVENT: &str = "cdg://changed";
+// Pushed while an MP3+G song plays and its screen changed, the payload is `CdgFrame`
+const CDG_FRAME_EVENT: &str = "cdg://frame";
+// 25 fps, CD+G draws slower than that anyway
+const CDG_FRAME_INTERVAL: Duration = Duration::from_millis(40);
+// shipped with the app, MIDI files play through it unless another one is set
+const BUNDLED_SOUNDFONT: &str = "soundfonts/default.sf2";
 
 pub struct SendWrapper<T>(pub T);
 unsafe impl<T> Send for SendWrapper<T> {}
 unsafe impl<T> Sync for SendWrapper<T> {}
 
-// Audio state to manage playback
+// Audio state to manage playback, every mode is a set of edits on one graph
 pub struct AudioState {
-    file_src: Option<AudioNodeEnum>,
-    mic_src: Option<AudioNodeEnum>,
-    mixer: Option<AudioNodeEnum>,
-    speaker_dest: Option<AudioNodeEnum>,
-    current_file: Option<String>,
+    // the device and the speaker node on it, or why it couldn't be opened
+    output: Result<(Arc<dyn AudioBackend>, NodeId), String>,
+    graph: AudioGraph,
+    master_gain: GainControl,
+    master: Option<NodeId>,
+    // channel strips outlive the nodes, settings carry over to the next song
+    music_strip: MixerInputControl,
+    mic_strip: MixerInputControl,
+    mixer: Option<NodeId>,
+    // the loaded song, a FileSrc or a MidiSrc
+    file_src: Option<NodeId>,
+    mic_src: Option<NodeId>,
+    // the song on air is the queue's current entry
+    queue: SongQueue,
+    progress_interval: Duration,
+    lyrics: Option<SongLyrics>,
+    graphics: Option<SongGraphics>,
+    soundfont_path: Option<PathBuf>,
+    // read on the first MIDI song, SoundFonts are big
+    soundfont: Option<Arc<SoundFont>>,
+}
+
+// lyrics of the loaded song and what the frontend was last told about them
+struct SongLyrics {
+    path: String,
+    lyrics: Option<Lyrics>,
+    // the UltraStar note tracks, for the pitch lanes
+    notes: Option<UltraStarSong>,
+    announced: bool,
+    line: Option<usize>,
+    // (line, word, finished) last sent, the wipe only needs an event when that changes
+    word: Option<(usize, usize, bool)>,
+}
+
+// CD+G graphics of the loaded song, `cdg` is None for songs without a .cdg
+struct SongGraphics {
+    path: String,
+    cdg: Option<CdgDecoder>,
+    announced: bool,
 }
 
 impl AudioState {
     fn new() -> Self {
+        let mut graph = AudioGraph::new();
+        // no device is not fatal, every command that needs one says why it can't play
+        let output = default_backend()
+            .and_then(|backend| {
+                let speaker = SpeakerDest::with_backend(backend.as_ref())?;
+                let id = graph.add_node(AudioNodeEnum::SpeakerDest(speaker));
+                Ok((backend, id))
+            })
+            .inspect_err(|e| println!("[HAL] {}", e));
         Self {
+            output,
+            graph,
+            master_gain: GainControl::default(),
+            master: None,
+            music_strip: MixerInputControl::new(),
+            mic_strip: MixerInputControl::new(),
+            mixer: None,
             file_src: None,
             mic_src: None,
-            mixer: None,
-            speaker_dest: Some(AudioNodeEnum::SpeakerDest(SpeakerDest::init())),
-            current_file: None,
+            queue: SongQueue::new(),
+            progress_interval: Duration::from_millis(DEFAULT_PROGRESS_INTERVAL_MS),
+            lyrics: None,
+            graphics: None,
+            soundfont_path: None,
+            soundfont: None,
+        }
+    }
+
+    fn backend(&self) -> Result<Arc<dyn AudioBackend>, String> {
+        self.output
+            .as_ref()
+            .map(|(backend, _)| Arc::clone(backend))
+            .map_err(|e| e.clone())
+    }
+
+    fn speaker_dest(&self) -> Result<NodeId, String> {
+        self.output
+            .as_ref()
+            .map(|(_, id)| *id)
+            .map_err(|e| e.clone())
+    }
+
+    fn speaker_config(&self) -> Result<IOStreamConfig, String> {
+        match self.graph.node(self.speaker_dest()?) {
+            Some(AudioNodeEnum::SpeakerDest(dest)) => Ok(dest.config.clone()),
+            _ => Err("Speaker not available".to_string()),
+        }
+    }
+
+    /// Make sure speaker, master gain and the shared mixer are running, returns the mixer sources
+    /// feed into (dest node buffer 太小，會掉資料，一定要墊一個 push node)
+    fn ensure_output(&mut self) -> Result<NodeId, String> {
+        let speaker_dest = self.speaker_dest()?;
+        let speaker = self
+            .graph
+            .node_mut(speaker_dest)
+            .ok_or("Speaker not available".to_string())?;
+        if !matches!(speaker.get_state(), AudioNodeState::RUNNING) {
+            speaker.start();
+            println!("[Graph] Started speaker");
+        }
+
+        let master = match self.master {
+            Some(id) => id,
+            None => {
+                // 先接喇叭，聲道數要先照喇叭設好
+                let channels = self.speaker_config()?.stream_config.channels;
+                let gain =
+                    GainNode::low_latency(self.master_gain.clone()).with_channels(channels.into());
+                let id = self.graph.add_node(AudioNodeEnum::GainNode(gain));
+                self.graph
+                    .connect(id, speaker_dest)
+                    .map_err(|e| format!("Gain->Speaker connection failed: {}", e))?;
+                println!("[Graph] Connected master gain to speaker");
+                self.master = Some(id);
+                id
+            }
+        };
+
+        let mixer = match self.mixer {
+            Some(id) => id,
+            None => {
+                let channels = self.speaker_config()?.stream_config.channels;
+                let mixer = Mixer::with_channels(channels.into());
+                let id = self.graph.add_node(AudioNodeEnum::Mixer(mixer));
+                self.graph
+                    .connect(id, master)
+                    .map_err(|e| format!("Mixer->Gain connection failed: {}", e))?;
+                println!("[Graph] Connected mixer to master gain");
+                self.mixer = Some(id);
+                id
+            }
+        };
+
+        for id in [master, mixer] {
+            if !matches!(
+                self.graph.node(id).map(|n| n.get_state()),
+                Some(AudioNodeState::RUNNING)
+            ) {
+                self.graph.start(id)?;
+                println!("[Graph] Started {:?}", id);
+            }
+        }
+        Ok(mixer)
+    }
+
+    /// Create a FileSrc (a MidiSrc for MIDI files) for `file_path` and wire it into the mixer
+    fn attach_file_src(&mut self, file_path: PathBuf) -> Result<NodeId, String> {
+        let mixer = self.ensure_output()?;
+        let config = self.speaker_config()?;
+        let sample_rate = config.stream_config.sample_rate;
+        let channels = config.stream_config.channels.into();
+
+        let node = if is_midi(&file_path) {
+            let mut midi_src = MidiSrc::init();
+            midi_src.set_config(file_path, self.soundfont()?, sample_rate, channels);
+            AudioNodeEnum::MidiSrc(midi_src)
+        } else {
+            let mut file_src = FileSrc::init();
+            file_src.set_config(file_path, sample_rate, channels);
+            AudioNodeEnum::FileSrc(file_src)
+        };
+
+        let id = self.graph.add_node(node);
+        self.file_src = Some(id);
+        self.graph
+            .connect(id, mixer)
+            .map_err(|e| format!("File->Mixer connection failed: {}", e))?;
+        self.follow_strip(mixer, id, self.music_strip.clone())?;
+        Ok(id)
+    }
+
+    /// Replace whatever song is loaded with `path` and start it, the mic is left alone. A
+    /// missing file leaves the old song playing; any later failure has already torn it down,
+    /// so the queue is told nothing is on air and auto-advance keeps going.
+    fn play_file(&mut self, path: &str) -> Result<(), String> {
+        let file_path = PathBuf::from(path);
+        if !file_path.exists() {
+            return Err(format!("File not found: {}", path));
+        }
+
+        self.load_file(file_path, path).inspect_err(|_| {
+            if let Err(e) = self.remove(|s| &mut s.file_src) {
+                println!("[Play] Teardown failed: {}", e);
+            }
+            self.lyrics = None;
+            self.graphics = None;
+            self.queue.finish_current();
+        })
+    }
+
+    fn load_file(&mut self, file_path: PathBuf, path: &str) -> Result<(), String> {
+        self.remove(|s| &mut s.file_src)?;
+
+        // 歌詞壞了照樣唱
+        let notes = ultrastar::load_sidecar(&file_path).unwrap_or_else(|e| {
+            println!("[Lyrics] {}", e);
+            None
+        });
+        if let Some(notes) = &notes {
+            println!("[Lyrics] {} note tracks for {}", notes.tracks.len(), path);
+        }
+        let lyrics = load_lyrics_with_notes(&file_path, notes.as_ref()).unwrap_or_else(|e| {
+            println!("[Lyrics] {}", e);
+            None
+        });
+        if let Some(lyrics) = &lyrics {
+            println!("[Lyrics] {} lines for {}", lyrics.lines.len(), path);
+        }
+        // MP3+G: the graphics are a .cdg next to the MP3
+        let cdg = cdg::load_sidecar(&file_path).unwrap_or_else(|e| {
+            println!("[Cdg] {}", e);
+            None
+        });
+        if let Some(cdg) = &cdg {
+            println!("[Cdg] {:?} of graphics for {}", cdg.duration(), path);
+        }
+
+        let src = self.attach_file_src(file_path)?;
+        println!("[Play] Connected file source to mixer");
+        self.graph.start(src)?;
+        println!("[Play] Started playback: {}", path);
+        self.lyrics = Some(SongLyrics {
+            path: path.to_string(),
+            lyrics,
+            notes,
+            announced: false,
+            line: None,
+            word: None,
+        });
+        self.graphics = Some(SongGraphics {
+            path: path.to_string(),
+            cdg,
+            announced: false,
+        });
+        Ok(())
+    }
+
+    /// Put the next queued song on air, songs that can't be played are skipped. When the queue
+    /// is empty the loaded song is dropped and nothing is on air.
+    fn play_next(&mut self) -> Option<QueueEntry> {
+        while let Some(entry) = self.queue.advance() {
+            match self.play_file(&entry.path) {
+                Ok(()) => return Some(entry),
+                Err(e) => println!("[Queue] Skipping {}: {}", entry.path, e),
+            }
+        }
+        if let Err(e) = self.remove(|s| &mut s.file_src) {
+            println!("[Queue] Teardown failed: {}", e);
+        }
+        println!("[Queue] Queue is empty");
+        None
+    }
+
+    /// Start the queue if nothing is on air
+    fn play_if_idle(&mut self) {
+        if self.queue.current().is_none() {
+            self.play_next();
+        }
+    }
+
+    /// Create a MicSrc and wire it into the mixer
+    fn attach_mic_src(&mut self) -> Result<NodeId, String> {
+        let mixer = self.ensure_output()?;
+
+        let mut mic_src = MicSrc::with_backend(self.backend()?)?;
+        mic_src.input_producer_config = Some(self.speaker_config()?);
+
+        let id = self.graph.add_node(AudioNodeEnum::MicSrc(mic_src));
+        self.mic_src = Some(id);
+        self.graph
+            .connect(id, mixer)
+            .map_err(|e| format!("Mic->Mixer connection failed: {}", e))?;
+        self.follow_strip(mixer, id, self.mic_strip.clone())?;
+        Ok(id)
+    }
+
+    /// Make the mixer input fed by `source` follow a channel strip
+    fn follow_strip(
+        &mut self,
+        mixer: NodeId,
+        source: NodeId,
+        strip: MixerInputControl,
+    ) -> Result<(), String> {
+        match self.graph.node_mut(mixer) {
+            Some(AudioNodeEnum::Mixer(mixer)) => mixer.set_input_control(source, strip),
+            _ => Err("Mixer not available".to_string()),
+        }
+    }
+
+    /// The SoundFont MIDI songs play through, read once and kept
+    fn soundfont(&mut self) -> Result<Arc<SoundFont>, String> {
+        if let Some(soundfont) = &self.soundfont {
+            return Ok(Arc::clone(soundfont));
+        }
+        let path = self
+            .soundfont_path
+            .clone()
+            .ok_or("No SoundFont to play MIDI files with".to_string())?;
+        let soundfont = load_soundfont(&path)?;
+        println!("[Midi] SoundFont: {}", path.display());
+        self.soundfont = Some(Arc::clone(&soundfont));
+        Ok(soundfont)
+    }
+
+    /// Use another SoundFont from the next MIDI song on
+    fn set_soundfont_path(&mut self, path: PathBuf) {
+        self.soundfont_path = Some(path);
+        self.soundfont = None;
+    }
+
+    /// The FileSrc under the loaded song, a MidiSrc plays through one too
+    fn song_player(&self) -> Option<&FileSrc> {
+        match self.file_src.and_then(|id| self.graph.node(id)) {
+            Some(AudioNodeEnum::FileSrc(file_src)) => Some(file_src),
+            Some(AudioNodeEnum::MidiSrc(midi_src)) => Some(midi_src.player()),
+            _ => None,
+        }
+    }
+
+    fn file_src_mut(&mut self) -> Result<&mut FileSrc, String> {
+        match self.file_src.and_then(|id| self.graph.node_mut(id)) {
+            Some(AudioNodeEnum::FileSrc(file_src)) => Ok(file_src),
+            Some(AudioNodeEnum::MidiSrc(midi_src)) => Ok(midi_src.player_mut()),
+            _ => Err("No audio playing".to_string()),
+        }
+    }
+
+    /// Where the loaded song is, corrected for everything between the decoder and the DAC
+    fn progress(&self) -> Option<PlaybackProgress> {
+        let id = self.file_src?;
+        let file_src = self.song_player()?;
+        let rate = self.speaker_config().ok()?.stream_config.sample_rate;
+        let latency = self.graph.output_latency_frames(id) as f64 / rate as f64;
+        let position = file_src
+            .position()
+            .saturating_sub(Duration::from_secs_f64(latency));
+        Some(PlaybackProgress {
+            path: self
+                .queue
+                .current()
+                .map(|e| e.path.clone())
+                .unwrap_or_default(),
+            position_ms: position.as_millis() as u64,
+            duration_ms: file_src.duration().map(|d| d.as_millis() as u64),
+            paused: matches!(file_src.get_state(), AudioNodeState::PAUSED),
+        })
+    }
+
+    /// What to tell the frontend about lyrics at `progress`: new lyrics when the song changed,
+    /// the line when the audible position moved onto another one (seeking back included), the
+    /// word being sung when it started or ended or `tick` asks for its fraction. Also returns
+    /// when the next line or word boundary comes, so the watcher can wake up right then.
+    fn lyric_tick(&mut self, progress: Option<&PlaybackProgress>, tick: bool) -> LyricTick {
+        let mut out = LyricTick::default();
+        let (song, progress) = match (self.lyrics.as_mut(), progress) {
+            (Some(song), Some(progress)) => (song, progress),
+            // nothing on air any more, clear the screen once
+            (Some(song), None) if self.file_src.is_none() => {
+                out.lyrics = Some(LyricsChanged {
+                    path: song.path.clone(),
+                    lyrics: None,
+                    notes: None,
+                });
+                self.lyrics = None;
+                return out;
+            }
+            _ => return out,
+        };
+        if !song.announced {
+            song.announced = true;
+            out.lyrics = Some(LyricsChanged {
+                path: song.path.clone(),
+                lyrics: song.lyrics.clone(),
+                notes: song.notes.clone(),
+            });
+        }
+        let lyrics = match &song.lyrics {
+            Some(lyrics) => lyrics,
+            None => return out,
+        };
+        let position = Duration::from_millis(progress.position_ms);
+        let index = lyrics.line_at(position);
+        if index != song.line || out.lyrics.is_some() {
+            song.line = index;
+            let line = index.map(|i| &lyrics.lines[i]);
+            out.line = Some(LyricLineChanged {
+                path: song.path.clone(),
+                index,
+                time_ms: line.map(|l| l.time_ms),
+                text: line.map(|l| l.text.clone()).unwrap_or_default(),
+            });
+        }
+        let word = lyrics.word_at(position);
+        let key = word.as_ref().map(|w| (w.line, w.word, w.fraction >= 1.0));
+        if key != song.word || (tick && key.is_some_and(|(_, _, done)| !done)) {
+            song.word = key;
+            out.word = word.map(|word| LyricWordProgress {
+                path: song.path.clone(),
+                position_ms: progress.position_ms,
+                word,
+            });
+        }
+        if !progress.paused {
+            out.until_next = lyrics.next_change(position).map(|next| next - position);
+        }
+        out
+    }
+
+    /// What to tell the frontend about CD+G graphics at `progress`: whether the song changed to
+    /// one with or without them, and what was drawn up to the audible position. While playing
+    /// the watcher comes back after `CDG_FRAME_INTERVAL`.
+    fn graphics_tick(&mut self, progress: Option<&PlaybackProgress>) -> GraphicsTick {
+        let mut out = GraphicsTick::default();
+        let (song, progress) = match (self.graphics.as_mut(), progress) {
+            (Some(song), Some(progress)) => (song, progress),
+            // nothing on air any more, clear the screen once
+            (Some(song), None) if self.file_src.is_none() => {
+                out.changed = Some(GraphicsChanged {
+                    path: song.path.clone(),
+                    available: false,
+                });
+                self.graphics = None;
+                return out;
+            }
+            _ => return out,
+        };
+        if !song.announced {
+            song.announced = true;
+            out.changed = Some(GraphicsChanged {
+                path: song.path.clone(),
+                available: song.cdg.is_some(),
+            });
+        }
+        let cdg = match song.cdg.as_mut() {
+            Some(cdg) => cdg,
+            None => return out,
+        };
+        cdg.advance_to(Duration::from_millis(progress.position_ms));
+        out.frame = cdg.take_update().map(|update| CdgFrame {
+            path: song.path.clone(),
+            position_ms: progress.position_ms,
+            update,
+        });
+        if !progress.paused {
+            out.until_next = Some(CDG_FRAME_INTERVAL);
+        }
+        out
+    }
+
+    /// Drop the song once it played out (or failed to start), the mixer and the mic keep
+    /// running. Returns what ended, this is where the next song gets its turn.
+    fn take_ended(&mut self) -> Option<PlaybackEnded> {
+        // nothing loaded, nothing ended
+        self.file_src?;
+        let ended = match self.song_player() {
+            Some(file_src) => {
+                file_src.is_finished() || matches!(file_src.get_state(), AudioNodeState::STOPPED)
+            }
+            None => true,
+        };
+        if !ended {
+            return None;
+        }
+        if let Err(e) = self.remove(|s| &mut s.file_src) {
+            println!("[Ended] Teardown failed: {}", e);
+        }
+        Some(PlaybackEnded {
+            path: self
+                .queue
+                .finish_current()
+                .map(|e| e.path)
+                .unwrap_or_default(),
+        })
+    }
+
+    fn strip(&self, channel: &str) -> Result<&MixerInputControl, String> {
+        match channel {
+            "music" => Ok(&self.music_strip),
+            "mic" => Ok(&self.mic_strip),
+            _ => Err(format!("Unknown channel: {}", channel)),
+        }
+    }
+
+    /// Stop a node and drop it together with its edges
+    fn remove(&mut self, slot: fn(&mut Self) -> &mut Option<NodeId>) -> Result<bool, String> {
+        match slot(self).take() {
+            Some(id) => {
+                self.graph.remove_node(id)?;
+                Ok(true)
+            }
+            None => Ok(false),
         }
     }
 }
@@ -43,7 +577,7 @@ async fn upload_audio_file(app: tauri::AppHandle) -> Result<String, String> {
     let file_path = app
         .dialog()
         .file()
-        .add_filter("Audio Files", &["mp3", "wav", "flac", "ogg", "m4a"])
+        .add_filter("Audio Files", &AUDIO_EXTENSIONS)
         .blocking_pick_file();
 
     match file_path {
@@ -59,77 +593,310 @@ async fn upload_audio_file(app: tauri::AppHandle) -> Result<String, String> {
 #[tauri::command]
 fn play_audio_file(
     path: String,
+    app: tauri::AppHandle,
     audio_state: State<'_, Mutex<AudioState>>,
 ) -> Result<String, String> {
     let mut state = audio_state.lock().map_err(|e| e.to_string())?;
 
     println!("[Play] Attempting to play: {}", path);
 
-    // Stop any existing playback
-    if let Some(ref mut src) = state.file_src {
-        src.stop();
+    // Stop any existing playback, the queue waits until this one is done
+    if let Err(e) = state.play_file(&path) {
+        emit_queue(&app, state.queue.snapshot());
+        return Err(e);
+    }
+    state.queue.play_now(path.clone());
+    emit_queue(&app, state.queue.snapshot());
+
+    Ok(format!("Playing: {}", path))
+}
+
+#[tauri::command]
+fn stop_audio(
+    app: tauri::AppHandle,
+    audio_state: State<'_, Mutex<AudioState>>,
+) -> Result<String, String> {
+    let mut state = audio_state.lock().map_err(|e| e.to_string())?;
+
+    if state.remove(|s| &mut s.file_src)? {
+        // the queue stays, the next enqueue or skip starts it again
+        state.queue.finish_current();
+        emit_queue(&app, state.queue.snapshot());
+        println!("[Stop] Stopped playback");
+        Ok("Playback stopped".to_string())
+    } else {
+        Err("No audio playing".to_string())
     }
+}
+
+#[tauri::command]
+fn pause_audio(audio_state: State<'_, Mutex<AudioState>>) -> Result<String, String> {
+    let mut state = audio_state.lock().map_err(|e| e.to_string())?;
+
+    state.file_src_mut()?.pause()?;
+    println!("[Pause] Paused playback");
+
+    Ok("Playback paused".to_string())
+}
+
+#[tauri::command]
+fn resume_audio(audio_state: State<'_, Mutex<AudioState>>) -> Result<String, String> {
+    let mut state = audio_state.lock().map_err(|e| e.to_string())?;
+
+    state.file_src_mut()?.resume()?;
+    println!("[Resume] Resumed playback");
+
+    Ok("Playback resumed".to_string())
+}
+
+#[tauri::command]
+fn seek_audio(
+    position_ms: u64,
+    audio_state: State<'_, Mutex<AudioState>>,
+) -> Result<String, String> {
+    let mut state = audio_state.lock().map_err(|e| e.to_string())?;
+
+    // 跳過去之前的舊音訊會被清掉，不會再播出來
+    state
+        .file_src_mut()?
+        .seek(Duration::from_millis(position_ms))?;
+    println!("[Seek] Seek to {} ms", position_ms);
+
+    Ok(format!("Seek to {} ms", position_ms))
+}
+
+#[derive(Serialize, Clone)]
+struct PlaybackProgress {
+    path: String,
+    position_ms: u64,
+    // unknown for formats that don't carry a length
+    duration_ms: Option<u64>,
+    paused: bool,
+}
+
+#[derive(Serialize, Clone)]
+struct PlaybackEnded {
+    path: String,
+}
+
+#[derive(Serialize, Clone)]
+struct LyricsChanged {
+    path: String,
+    // null for a song without .lrc and once nothing is on air
+    lyrics: Option<Lyrics>,
+    // null unless the song has an UltraStar .txt
+    notes: Option<UltraStarSong>,
+}
+
+#[derive(Serialize, Clone)]
+struct LyricLineChanged {
+    path: String,
+    // null before the first line starts
+    index: Option<usize>,
+    time_ms: Option<u64>,
+    text: String,
+}
+
+#[derive(Serialize, Clone)]
+struct LyricWordProgress {
+    path: String,
+    position_ms: u64,
+    #[serde(flatten)]
+    word: WordProgress,
+}
+
+#[derive(Serialize, Clone)]
+struct GraphicsChanged {
+    path: String,
+    // false for a song without .cdg and once nothing is on air
+    available: bool,
+}
+
+#[derive(Serialize, Clone)]
+struct CdgFrame {
+    path: String,
+    position_ms: u64,
+    #[serde(flatten)]
+    update: CdgUpdate,
+}
+
+#[derive(Default)]
+struct GraphicsTick {
+    changed: Option<GraphicsChanged>,
+    frame: Option<CdgFrame>,
+    until_next: Option<Duration>,
+}
 
-    // Create new FileSrc with the selected file
-    let file_path = PathBuf::from(&path);
-    if !file_path.exists() {
-        return Err(format!("File not found: {}", path));
+#[derive(Default)]
+struct LyricTick {
+    lyrics: Option<LyricsChanged>,
+    line: Option<LyricLineChanged>,
+    word: Option<LyricWordProgress>,
+    until_next: Option<Duration>,
+}
+
+#[tauri::command]
+fn get_playback_progress(
+    audio_state: State<'_, Mutex<AudioState>>,
+) -> Result<PlaybackProgress, String> {
+    let state = audio_state.lock().map_err(|e| e.to_string())?;
+
+    state.progress().ok_or("No audio playing".to_string())
+}
+
+#[tauri::command]
+fn set_progress_rate(hz: f32, audio_state: State<'_, Mutex<AudioState>>) -> Result<String, String> {
+    let mut state = audio_state.lock().map_err(|e| e.to_string())?;
+
+    // 太快只是白白搶 AudioState 的鎖
+    let hz = hz.clamp(1.0, 60.0);
+    state.progress_interval = Duration::from_secs_f32(1.0 / hz);
+    println!("[Progress] Rate: {:.1} Hz", hz);
+
+    Ok(format!("Progress rate: {:.1} Hz", hz))
+}
+
+#[tauri::command]
+fn set_soundfont(
+    path: String,
+    app: tauri::AppHandle,
+    library: State<'_, Mutex<Library>>,
+    audio_state: State<'_, Mutex<AudioState>>,
+) -> Result<String, String> {
+    // read before locking, a big SoundFont takes a while
+    let soundfont = load_soundfont(&PathBuf::from(&path))?;
+    {
+        let mut state = audio_state.lock().map_err(|e| e.to_string())?;
+        state.set_soundfont_path(PathBuf::from(&path));
+        state.soundfont = Some(soundfont);
+    }
+    println!("[Midi] SoundFont set to {}", path);
+
+    // only matters when the bundled SoundFont was missing, MIDI songs are listed from now on
+    library
+        .lock()
+        .map_err(|e| e.to_string())?
+        .set_midi_playable(true);
+    if let Err(e) = app.emit(LIBRARY_EVENT, ScanReport::default()) {
+        println!("[Library] Emit failed: {}", e);
     }
 
-    let mut src_node = FileSrc::init();
+    Ok(format!("SoundFont: {}", path))
+}
 
-    // Connect to speaker if available
-    if let Some(ref mut dest) = state.speaker_dest {
-        // Start speaker first if not running
-        if !matches!(dest.get_state(), crate::audio_node::AudioNodeState::RUNNING) {
-            dest.start();
-            println!("[Play] Started speaker");
+fn emit_queue(app: &tauri::AppHandle, snapshot: QueueSnapshot) {
+    if let Err(e) = app.emit(QUEUE_EVENT, snapshot) {
+        println!("[Queue] Emit failed: {}", e);
+    }
+}
+
+/// Emit `PROGRESS_EVENT` for the loaded song, as often as `set_progress_rate` asks. A song that
+/// played to the end is cleaned up, reported with `ENDED_EVENT` and the queue moves on. Lyric
+/// lines go out with `LYRIC_LINE_EVENT` when they start, not on the next progress tick.
+fn spawn_playback_watcher(app: tauri::AppHandle) {
+    thread::spawn(move || {
+        let mut next_progress = Instant::now();
+        loop {
+            let progress_due = Instant::now() >= next_progress;
+            let (interval, ended, progress, lyric, graphics) = {
+                let audio_state = app.state::<Mutex<AudioState>>();
+                let mut state = match audio_state.lock() {
+                    Ok(state) => state,
+                    Err(_) => return,
+                };
+                // plain playback and karaoke alike, the mic keeps running across songs
+                let ended = state.take_ended().map(|ended| {
+                    state.play_next();
+                    (ended, state.queue.snapshot())
+                });
+                let progress = state.progress();
+                let lyric = state.lyric_tick(progress.as_ref(), progress_due);
+                let graphics = state.graphics_tick(progress.as_ref());
+                (state.progress_interval, ended, progress, lyric, graphics)
+            };
+            if let Some((ended, queue)) = ended {
+                println!("[Ended] {}", ended.path);
+                if let Err(e) = app.emit(ENDED_EVENT, ended) {
+                    println!("[Ended] Emit failed: {}", e);
+                }
+                emit_queue(&app, queue);
+            }
+            if let Some(lyrics) = lyric.lyrics {
+                if let Err(e) = app.emit(LYRICS_EVENT, lyrics) {
+                    println!("[Lyrics] Emit failed: {}", e);
+                }
+            }
+            if let Some(line) = lyric.line {
+                if let Err(e) = app.emit(LYRIC_LINE_EVENT, line) {
+                    println!("[Lyrics] Emit failed: {}", e);
+                }
+            }
+            if let Some(word) = lyric.word {
+                if let Err(e) = app.emit(LYRIC_WORD_EVENT, word) {
+                    println!("[Lyrics] Emit failed: {}", e);
+                }
+            }
+            if let Some(changed) = graphics.changed {
+                if let Err(e) = app.emit(GRAPHICS_EVENT, changed) {
+                    println!("[Cdg] Emit failed: {}", e);
+                }
+            }
+            if let Some(frame) = graphics.frame {
+                if let Err(e) = app.emit(CDG_FRAME_EVENT, frame) {
+                    println!("[Cdg] Emit failed: {}", e);
+                }
+            }
+            let now = Instant::now();
+            if progress_due {
+                if let Some(progress) = progress {
+                    if let Err(e) = app.emit(PROGRESS_EVENT, progress) {
+                        println!("[Progress] Emit failed: {}", e);
+                    }
+                }
+                next_progress = now + interval;
+            }
+            // a few ms late is fine, waking up for nothing is not
+            let until_next = lyric
+                .until_next
+                .into_iter()
+                .chain(graphics.until_next)
+                .min();
+            let wake = until_next.map_or(next_progress, |d| now + d.max(LYRIC_MIN_WAIT));
+            thread::sleep(wake.min(next_progress).saturating_duration_since(now));
         }
+    });
+}
 
-        // set src node config
-        let dest_node = match dest {
-            AudioNodeEnum::SpeakerDest(dest) => dest,
-            _ => return Err("Speaker not available".to_string()),
-        };
-        src_node.set_config(
-            file_path,
-            dest_node.config.stream_config.sample_rate,
-            dest_node.config.stream_config.channels.into(),
-        );
-        let mut src = AudioNodeEnum::FileSrc(src_node);
-
-        // new mixer node (dest node buffer 太小，會掉資料，一定要墊一個 push node)
-        let mixer = Mixer::new(0);
-        let mut mixer_enum = AudioNodeEnum::Mixer(mixer);
-
-        // Connect source to destination
-        connect(&mut src, &mut mixer_enum).map_err(|e| format!("Connection failed: {}", e))?;
-        connect(&mut mixer_enum, dest).map_err(|e| format!("Connection failed: {}", e))?;
-        println!("[Play] Connected file source to speaker");
-
-        mixer_enum.start();
-        src.start();
-        println!("[Play] Started playback");
-
-        state.file_src = Some(src);
-        state.current_file = Some(path.clone());
-
-        Ok(format!("Playing: {}", path))
-    } else {
-        Err("Speaker not available".to_string())
+#[tauri::command]
+fn get_lyrics(audio_state: State<'_, Mutex<AudioState>>) -> Result<Option<Lyrics>, String> {
+    let state = audio_state.lock().map_err(|e| e.to_string())?;
+
+    // 換歌之後才打開歌詞畫面也拿得到
+    match (&state.file_src, &state.lyrics) {
+        (Some(_), Some(song)) => Ok(song.lyrics.clone()),
+        _ => Ok(None),
+    }
+}
+
+#[tauri::command]
+fn get_notes(audio_state: State<'_, Mutex<AudioState>>) -> Result<Option<UltraStarSong>, String> {
+    let state = audio_state.lock().map_err(|e| e.to_string())?;
+
+    match (&state.file_src, &state.lyrics) {
+        (Some(_), Some(song)) => Ok(song.notes.clone()),
+        _ => Ok(None),
     }
 }
 
 #[tauri::command]
-fn stop_audio(audio_state: State<'_, Mutex<AudioState>>) -> Result<String, String> {
+fn get_cdg_frame(audio_state: State<'_, Mutex<AudioState>>) -> Result<Option<CdgUpdate>, String> {
     let mut state = audio_state.lock().map_err(|e| e.to_string())?;
 
-    if let Some(ref mut src) = state.file_src {
-        src.stop();
-        println!("[Stop] Stopped playback");
-        Ok("Playback stopped".to_string())
-    } else {
-        Err("No audio playing".to_string())
+    // the whole screen, a frontend that opens late only gets changes from the events
+    let loaded = state.file_src.is_some();
+    match state.graphics.as_mut().and_then(|g| g.cdg.as_mut()) {
+        Some(cdg) if loaded => Ok(Some(cdg.frame())),
+        _ => Ok(None),
     }
 }
 
@@ -137,70 +904,195 @@ fn stop_audio(audio_state: State<'_, Mutex<AudioState>>) -> Result<String, Strin
 fn get_current_file(audio_state: State<'_, Mutex<AudioState>>) -> Result<String, String> {
     let state = audio_state.lock().map_err(|e| e.to_string())?;
 
-    match &state.current_file {
-        Some(path) => Ok(path.clone()),
+    match state.queue.current() {
+        Some(entry) => Ok(entry.path.clone()),
         None => Ok("No file loaded".to_string()),
     }
 }
 
 #[tauri::command]
-fn start_mic_only(audio_state: State<'_, Mutex<AudioState>>) -> Result<String, String> {
+fn get_queue(audio_state: State<'_, Mutex<AudioState>>) -> Result<QueueSnapshot, String> {
+    let state = audio_state.lock().map_err(|e| e.to_string())?;
+
+    Ok(state.queue.snapshot())
+}
+
+#[tauri::command]
+fn enqueue_song(
+    path: String,
+    singer: Option<String>,
+    app: tauri::AppHandle,
+    audio_state: State<'_, Mutex<AudioState>>,
+) -> Result<QueueSnapshot, String> {
     let mut state = audio_state.lock().map_err(|e| e.to_string())?;
 
-    println!("[Mic] Starting microphone only mode");
+    let entry = state.queue.enqueue(path, singer);
+    println!(
+        "[Queue] Enqueued {}: {} ({:?})",
+        entry.id, entry.path, entry.singer
+    );
+    state.play_if_idle();
 
-    // Stop any existing microphone
-    if let Some(ref mut mic) = state.mic_src {
-        mic.stop();
-    }
-    if let Some(ref mut mixer) = state.mixer {
-        mixer.stop();
-    }
+    let snapshot = state.queue.snapshot();
+    emit_queue(&app, snapshot.clone());
+    Ok(snapshot)
+}
 
-    // Start speaker if not running
-    if let Some(ref mut dest) = state.speaker_dest {
-        if !matches!(dest.get_state(), crate::audio_node::AudioNodeState::RUNNING) {
-            dest.start();
-            println!("[Mic] Started speaker");
-        }
+#[tauri::command]
+fn enqueue_song_by_code(
+    code: i64,
+    singer: Option<String>,
+    app: tauri::AppHandle,
+    library: State<'_, Mutex<Library>>,
+    audio_state: State<'_, Mutex<AudioState>>,
+) -> Result<QueueSnapshot, String> {
+    // 點歌號碼，先查出檔案再照一般點歌排進去
+    let song = library
+        .lock()
+        .map_err(|e| e.to_string())?
+        .song_by_code(code)?
+        .ok_or_else(|| format!("No song with code {}", code))?;
+    println!("[Queue] Code {}: {}", code, song.title);
+
+    enqueue_song(song.path, singer, app, audio_state)
+}
 
-        // Get speaker config
-        let dest_config = match dest {
-            AudioNodeEnum::SpeakerDest(dest) => dest.config.clone(),
-            _ => return Err("Speaker not available".to_string()),
-        };
+#[tauri::command]
+fn insert_song_top(
+    path: String,
+    singer: Option<String>,
+    app: tauri::AppHandle,
+    audio_state: State<'_, Mutex<AudioState>>,
+) -> Result<QueueSnapshot, String> {
+    let mut state = audio_state.lock().map_err(|e| e.to_string())?;
 
-        // Create mic source
-        let mut mic_src = MicSrc::init();
-        mic_src.input_producer_config = Some(dest_config);
+    // 插歌：下一首就是它
+    let entry = state.queue.insert_top(path, singer);
+    println!(
+        "[Queue] Inserted on top {}: {} ({:?})",
+        entry.id, entry.path, entry.singer
+    );
+    state.play_if_idle();
+
+    let snapshot = state.queue.snapshot();
+    emit_queue(&app, snapshot.clone());
+    Ok(snapshot)
+}
 
-        // Wrap in enum
-        let mut mic_src_enum = AudioNodeEnum::MicSrc(mic_src);
+#[tauri::command]
+fn promote_song(
+    id: u64,
+    app: tauri::AppHandle,
+    audio_state: State<'_, Mutex<AudioState>>,
+) -> Result<QueueSnapshot, String> {
+    let mut state = audio_state.lock().map_err(|e| e.to_string())?;
 
-        // new mixer node
-        let mixer = Mixer::new(0);
-        let mut mixer_enum = AudioNodeEnum::Mixer(mixer);
+    // 已經點的歌改成插歌
+    state.queue.promote(id)?;
+    println!("[Queue] Promoted {}", id);
 
-        // Connect
-        connect(&mut mic_src_enum, &mut mixer_enum)
-            .map_err(|e| format!("Mic->Speaker connection failed: {}", e))?;
-        connect(&mut mixer_enum, dest)
-            .map_err(|e| format!("Mic->Speaker connection failed: {}", e))?;
-        println!("[Mic] Connected microphone to speaker");
+    let snapshot = state.queue.snapshot();
+    emit_queue(&app, snapshot.clone());
+    Ok(snapshot)
+}
 
-        // Start microphone
-        mic_src_enum.start();
-        println!("[Mic] Started microphone");
-        mixer_enum.start();
-        println!("[Mic] Started mixer");
+#[tauri::command]
+fn set_rotation_policy(
+    policy: String,
+    weights: Option<HashMap<String, f64>>,
+    app: tauri::AppHandle,
+    audio_state: State<'_, Mutex<AudioState>>,
+) -> Result<QueueSnapshot, String> {
+    let mut state = audio_state.lock().map_err(|e| e.to_string())?;
 
-        // Store in state
-        state.mic_src = Some(mic_src_enum);
+    // fifo / round_robin / weighted，weights 只有 weighted 用得到
+    let policy = policy_by_name(&policy, weights.unwrap_or_default())?;
+    state.queue.set_policy(policy);
+    println!("[Queue] Rotation: {}", state.queue.policy_name());
 
-        Ok("Microphone started".to_string())
-    } else {
-        Err("Speaker not available".to_string())
+    let snapshot = state.queue.snapshot();
+    emit_queue(&app, snapshot.clone());
+    Ok(snapshot)
+}
+
+#[tauri::command]
+fn remove_song(
+    id: u64,
+    app: tauri::AppHandle,
+    audio_state: State<'_, Mutex<AudioState>>,
+) -> Result<QueueSnapshot, String> {
+    let mut state = audio_state.lock().map_err(|e| e.to_string())?;
+
+    let entry = state.queue.remove(id)?;
+    println!("[Queue] Removed {}: {}", entry.id, entry.path);
+
+    let snapshot = state.queue.snapshot();
+    emit_queue(&app, snapshot.clone());
+    Ok(snapshot)
+}
+
+#[tauri::command]
+fn move_song(
+    id: u64,
+    index: usize,
+    app: tauri::AppHandle,
+    audio_state: State<'_, Mutex<AudioState>>,
+) -> Result<QueueSnapshot, String> {
+    let mut state = audio_state.lock().map_err(|e| e.to_string())?;
+
+    state.queue.move_to(id, index)?;
+    println!("[Queue] Moved {} to {}", id, index);
+
+    let snapshot = state.queue.snapshot();
+    emit_queue(&app, snapshot.clone());
+    Ok(snapshot)
+}
+
+#[tauri::command]
+fn skip_song(
+    app: tauri::AppHandle,
+    audio_state: State<'_, Mutex<AudioState>>,
+) -> Result<QueueSnapshot, String> {
+    let mut state = audio_state.lock().map_err(|e| e.to_string())?;
+
+    // 切歌：不等這首放完，沒有下一首就停下來
+    match state.play_next() {
+        Some(entry) => println!("[Queue] Skipped to {}: {}", entry.id, entry.path),
+        None => println!("[Queue] Skipped, nothing left"),
     }
+
+    let snapshot = state.queue.snapshot();
+    emit_queue(&app, snapshot.clone());
+    Ok(snapshot)
+}
+
+#[tauri::command]
+fn replay_song(audio_state: State<'_, Mutex<AudioState>>) -> Result<String, String> {
+    let mut state = audio_state.lock().map_err(|e| e.to_string())?;
+
+    // 重唱：同一首從頭開始，暫停中就停在開頭
+    state.file_src_mut()?.seek(Duration::ZERO)?;
+    println!("[Queue] Replay");
+
+    Ok("Replaying".to_string())
+}
+
+#[tauri::command]
+fn start_mic_only(audio_state: State<'_, Mutex<AudioState>>) -> Result<String, String> {
+    let mut state = audio_state.lock().map_err(|e| e.to_string())?;
+
+    println!("[Mic] Starting microphone only mode");
+
+    // Stop any existing microphone
+    state.remove(|s| &mut s.mic_src)?;
+
+    let mic = state.attach_mic_src()?;
+    println!("[Mic] Connected microphone to mixer");
+
+    state.graph.start(mic)?;
+    println!("[Mic] Started microphone");
+
+    Ok("Microphone started".to_string())
 }
 
 #[tauri::command]
@@ -209,9 +1101,7 @@ fn stop_mic(audio_state: State<'_, Mutex<AudioState>>) -> Result<String, String>
 
     println!("[Mic] Stopping microphone");
 
-    if let Some(ref mut mic) = state.mic_src {
-        mic.stop();
-        state.mic_src = None;
+    if state.remove(|s| &mut s.mic_src)? {
         println!("[Mic] Stopped microphone");
         Ok("Microphone stopped".to_string())
     } else {
@@ -222,123 +1112,428 @@ fn stop_mic(audio_state: State<'_, Mutex<AudioState>>) -> Result<String, String>
 #[tauri::command]
 fn start_karaoke(
     path: String,
+    app: tauri::AppHandle,
     audio_state: State<'_, Mutex<AudioState>>,
 ) -> Result<String, String> {
     let mut state = audio_state.lock().map_err(|e| e.to_string())?;
 
     println!("[Karaoke] Starting karaoke mode with: {}", path);
 
-    // Stop any existing playback
-    if let Some(ref mut src) = state.file_src {
-        src.stop();
+    // Stop any existing microphone
+    state.remove(|s| &mut s.mic_src)?;
+
+    // Connect: file_src -> mixer, mic_src -> mixer, mixer -> speaker
+    if let Err(e) = state.play_file(&path) {
+        emit_queue(&app, state.queue.snapshot());
+        return Err(e);
     }
-    if let Some(ref mut mic) = state.mic_src {
-        mic.stop();
+    println!("[Karaoke] Started file playback");
+    // the song is on air from here, even if the mic fails
+    state.queue.play_now(path.clone());
+    emit_queue(&app, state.queue.snapshot());
+
+    let mic_src = state.attach_mic_src()?;
+    println!("[Karaoke] Connected mic source to mixer");
+
+    state.graph.start(mic_src)?;
+    println!("[Karaoke] Started microphone");
+
+    Ok(format!("Karaoke started: {}", path))
+}
+
+#[tauri::command]
+fn stop_karaoke(
+    app: tauri::AppHandle,
+    audio_state: State<'_, Mutex<AudioState>>,
+) -> Result<String, String> {
+    let mut state = audio_state.lock().map_err(|e| e.to_string())?;
+
+    println!("[Karaoke] Stopping karaoke mode");
+
+    // Stop all nodes, removing the master gain hands the speaker input back
+    if state.remove(|s| &mut s.mic_src)? {
+        println!("[Karaoke] Stopped microphone");
     }
-    if let Some(ref mut mixer) = state.mixer {
-        mixer.stop();
+    if state.remove(|s| &mut s.file_src)? {
+        println!("[Karaoke] Stopped file playback");
     }
-
-    // Verify file exists
-    let file_path = PathBuf::from(&path);
-    if !file_path.exists() {
-        return Err(format!("File not found: {}", path));
+    if state.remove(|s| &mut s.mixer)? {
+        println!("[Karaoke] Stopped mixer");
     }
+    state.remove(|s| &mut s.master)?;
 
-    // Start speaker if not running
-    if let Some(ref mut dest) = state.speaker_dest {
-        if !matches!(dest.get_state(), crate::audio_node::AudioNodeState::RUNNING) {
-            dest.start();
-            println!("[Karaoke] Started speaker");
-        }
+    state.queue.finish_current();
+    emit_queue(&app, state.queue.snapshot());
 
-        // Get speaker config and clone it to avoid borrowing issues
-        let (sample_rate, channels, dest_config) = match dest {
-            AudioNodeEnum::SpeakerDest(dest) => (
-                dest.config.stream_config.sample_rate,
-                dest.config.stream_config.channels,
-                dest.config.clone(),
-            ),
-            _ => return Err("Speaker not available".to_string()),
-        };
+    Ok("Karaoke stopped".to_string())
+}
+
+#[tauri::command]
+fn set_master_volume(db: f32, audio_state: State<'_, Mutex<AudioState>>) -> Result<String, String> {
+    let state = audio_state.lock().map_err(|e| e.to_string())?;
 
-        let mixer = Mixer::new(0);
+    // 只改目標值，音訊執行緒自己平滑過去
+    state.master_gain.set_gain_db(db);
+    println!("[Gain] Master volume: {:.1} dB", db);
 
-        let mut file_src = FileSrc::init();
-        file_src.set_config(file_path, sample_rate, channels.into());
+    Ok(format!("Master volume: {:.1} dB", db))
+}
 
-        let mut mic_src = MicSrc::init();
-        mic_src.input_producer_config = Some(dest_config);
+#[tauri::command]
+fn set_master_mute(
+    muted: bool,
+    audio_state: State<'_, Mutex<AudioState>>,
+) -> Result<String, String> {
+    let state = audio_state.lock().map_err(|e| e.to_string())?;
 
-        let mut mixer_enum = AudioNodeEnum::Mixer(mixer);
-        let mut file_src_enum = AudioNodeEnum::FileSrc(file_src);
-        let mut mic_src_enum = AudioNodeEnum::MicSrc(mic_src);
+    state.master_gain.set_muted(muted);
+    println!("[Gain] Master muted: {}", muted);
 
-        connect(&mut mixer_enum, dest)
-            .map_err(|e| format!("Mixer->Speaker connection failed: {}", e))?;
-        println!("[Karaoke] Connected mixer to speaker");
+    Ok(format!("Master muted: {}", muted))
+}
 
-        // Connect: file_src -> mixer (input 0)
-        connect(&mut file_src_enum, &mut mixer_enum)
-            .map_err(|e| format!("File->Mixer connection failed: {}", e))?;
-        println!("[Karaoke] Connected file source to mixer");
+#[tauri::command]
+fn get_limiter_gain_reduction(audio_state: State<'_, Mutex<AudioState>>) -> Result<f32, String> {
+    let state = audio_state.lock().map_err(|e| e.to_string())?;
 
-        // Connect: mic_src -> mixer (input 1)
-        connect(&mut mic_src_enum, &mut mixer_enum)
-            .map_err(|e| format!("Mic->Mixer connection failed: {}", e))?;
-        println!("[Karaoke] Connected mic source to mixer");
+    // 沒有 mixer 就沒有 limiter 在動
+    match state.mixer.and_then(|id| state.graph.node(id)) {
+        Some(AudioNodeEnum::Mixer(mixer)) => Ok(mixer.limiter_meter().gain_reduction_db()),
+        _ => Ok(0.0),
+    }
+}
 
-        // Start all nodes
-        mixer_enum.start();
-        println!("[Karaoke] Started mixer");
+#[derive(Serialize)]
+struct ChannelStrip {
+    channel: String,
+    gain_db: f32,
+    muted: bool,
+    solo: bool,
+    pan: f32,
+}
 
-        file_src_enum.start();
-        println!("[Karaoke] Started file playback");
+#[tauri::command]
+fn get_channel_strips(
+    audio_state: State<'_, Mutex<AudioState>>,
+) -> Result<Vec<ChannelStrip>, String> {
+    let state = audio_state.lock().map_err(|e| e.to_string())?;
 
-        mic_src_enum.start();
-        println!("[Karaoke] Started microphone");
+    ["music", "mic"]
+        .iter()
+        .map(|channel| {
+            let strip = state.strip(channel)?;
+            Ok(ChannelStrip {
+                channel: channel.to_string(),
+                gain_db: strip.gain().gain_db(),
+                muted: strip.gain().is_muted(),
+                solo: strip.is_solo(),
+                pan: strip.pan(),
+            })
+        })
+        .collect()
+}
 
-        // Store in state
-        state.mixer = Some(mixer_enum);
-        state.file_src = Some(file_src_enum);
-        state.mic_src = Some(mic_src_enum);
-        state.current_file = Some(path.clone());
+#[tauri::command]
+fn set_channel_gain(
+    channel: String,
+    db: f32,
+    audio_state: State<'_, Mutex<AudioState>>,
+) -> Result<String, String> {
+    let state = audio_state.lock().map_err(|e| e.to_string())?;
 
-        Ok(format!("Karaoke started: {}", path))
-    } else {
-        Err("Speaker not available".to_string())
-    }
+    state.strip(&channel)?.gain().set_gain_db(db);
+    println!("[Mixer] {} gain: {:.1} dB", channel, db);
+
+    Ok(format!("{} gain: {:.1} dB", channel, db))
 }
 
 #[tauri::command]
-fn stop_karaoke(audio_state: State<'_, Mutex<AudioState>>) -> Result<String, String> {
-    let mut state = audio_state.lock().map_err(|e| e.to_string())?;
+fn set_channel_mute(
+    channel: String,
+    muted: bool,
+    audio_state: State<'_, Mutex<AudioState>>,
+) -> Result<String, String> {
+    let state = audio_state.lock().map_err(|e| e.to_string())?;
 
-    println!("[Karaoke] Stopping karaoke mode");
+    state.strip(&channel)?.gain().set_muted(muted);
+    println!("[Mixer] {} muted: {}", channel, muted);
 
-    // Stop all nodes
-    if let Some(ref mut mic) = state.mic_src {
-        mic.stop();
-        println!("[Karaoke] Stopped microphone");
-    }
+    Ok(format!("{} muted: {}", channel, muted))
+}
 
-    if let Some(ref mut src) = state.file_src {
-        src.stop();
-        println!("[Karaoke] Stopped file playback");
+#[tauri::command]
+fn set_channel_solo(
+    channel: String,
+    solo: bool,
+    audio_state: State<'_, Mutex<AudioState>>,
+) -> Result<String, String> {
+    let state = audio_state.lock().map_err(|e| e.to_string())?;
+
+    state.strip(&channel)?.set_solo(solo);
+    println!("[Mixer] {} solo: {}", channel, solo);
+
+    Ok(format!("{} solo: {}", channel, solo))
+}
+
+#[tauri::command]
+fn set_channel_pan(
+    channel: String,
+    pan: f32,
+    audio_state: State<'_, Mutex<AudioState>>,
+) -> Result<String, String> {
+    let state = audio_state.lock().map_err(|e| e.to_string())?;
+
+    let strip = state.strip(&channel)?;
+    strip.set_pan(pan);
+    println!("[Mixer] {} pan: {:.2}", channel, strip.pan());
+
+    Ok(format!("{} pan: {:.2}", channel, strip.pan()))
+}
+
+/// Library database in the app data folder, created on first run
+fn open_library(app: &tauri::AppHandle) -> Result<Library, String> {
+    let dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let db_path = dir.join("library.db");
+    println!("[Library] Database: {}", db_path.display());
+    Library::open(&db_path)
+}
+
+/// Only reading the database and writing the result hold the lock, walking the folders and
+/// reading tags doesn't, so browsing keeps working during a long scan
+fn rescan_library(app: &tauri::AppHandle) -> Result<ScanReport, String> {
+    let library = app.state::<Mutex<Library>>();
+    let (folders, known) = {
+        let library = library.lock().map_err(|e| e.to_string())?;
+        (library.folders()?, library.known_files()?)
+    };
+    let result = scan(&folders, &known);
+    let report = library.lock().map_err(|e| e.to_string())?.apply(result)?;
+    println!("[Library] Rescan: {:?}", report);
+    emit_library(app, &report);
+    Ok(report)
+}
+
+/// Same split as `rescan_library`, for just the paths the watcher saw change
+fn refresh_library(app: &tauri::AppHandle, paths: &[PathBuf]) -> Result<ScanReport, String> {
+    let library = app.state::<Mutex<Library>>();
+    let (folders, known) = {
+        let library = library.lock().map_err(|e| e.to_string())?;
+        (library.folders()?, library.known_files()?)
+    };
+    let result = scan_paths(&folders, &known, paths);
+    let report = library.lock().map_err(|e| e.to_string())?.apply(result)?;
+    println!("[Library] Watched {} paths: {:?}", paths.len(), report);
+    emit_library(app, &report);
+    Ok(report)
+}
+
+fn emit_library(app: &tauri::AppHandle, report: &ScanReport) {
+    if report.added + report.updated + report.removed == 0 {
+        return;
+    }
+    if let Err(e) = app.emit(LIBRARY_EVENT, report) {
+        println!("[Library] Emit failed: {}", e);
     }
+}
 
-    if let Some(ref mut mixer) = state.mixer {
-        mixer.stop();
-        println!("[Karaoke] Stopped mixer");
+/// Watch every library folder, new songs come in without a rescan
+fn start_folder_watcher(app: &tauri::AppHandle) -> Result<FolderWatcher, String> {
+    let handle = app.clone();
+    let mut watcher = FolderWatcher::new(LIBRARY_DEBOUNCE, move |paths| {
+        if let Err(e) = refresh_library(&handle, &paths) {
+            println!("[Library] Refresh failed: {}", e);
+        }
+    })?;
+    let folders = app
+        .state::<Mutex<Library>>()
+        .lock()
+        .map_err(|e| e.to_string())?
+        .folders()?;
+    for folder in folders {
+        // an unplugged disk is not watched until it is added again
+        if let Err(e) = watcher.watch(&folder) {
+            println!("[Library] {}", e);
+        }
     }
+    Ok(watcher)
+}
 
-    // Clear state
-    state.mic_src = None;
-    state.file_src = None;
-    state.mixer = None;
-    state.current_file = None;
+#[tauri::command]
+fn library_folders(library: State<'_, Mutex<Library>>) -> Result<Vec<String>, String> {
+    let library = library.lock().map_err(|e| e.to_string())?;
+
+    Ok(library
+        .folders()?
+        .iter()
+        .map(|f| f.to_string_lossy().to_string())
+        .collect())
+}
 
-    Ok("Karaoke stopped".to_string())
+#[tauri::command]
+fn library_add_folder(
+    path: String,
+    library: State<'_, Mutex<Library>>,
+    watcher: State<'_, Mutex<FolderWatcher>>,
+) -> Result<String, String> {
+    let mut library = library.lock().map_err(|e| e.to_string())?;
+
+    // 只登記資料夾，已經在裡面的歌要等 library_rescan，之後放進來的會自己匯入
+    library.add_folder(&PathBuf::from(&path))?;
+    watcher
+        .lock()
+        .map_err(|e| e.to_string())?
+        .watch(&PathBuf::from(&path))?;
+    println!("[Library] Added folder: {}", path);
+
+    Ok(format!("Folder added: {}", path))
+}
+
+#[tauri::command]
+fn library_remove_folder(
+    app: tauri::AppHandle,
+    path: String,
+    library: State<'_, Mutex<Library>>,
+    watcher: State<'_, Mutex<FolderWatcher>>,
+) -> Result<String, String> {
+    let mut library = library.lock().map_err(|e| e.to_string())?;
+
+    watcher
+        .lock()
+        .map_err(|e| e.to_string())?
+        .unwatch(&PathBuf::from(&path));
+    let removed = library.remove_folder(&PathBuf::from(&path))?;
+    println!("[Library] Removed folder: {} ({} songs)", path, removed);
+    emit_library(
+        &app,
+        &ScanReport {
+            removed,
+            ..ScanReport::default()
+        },
+    );
+
+    Ok(format!("Folder removed: {}", path))
+}
+
+#[tauri::command]
+async fn library_rescan(app: tauri::AppHandle) -> Result<ScanReport, String> {
+    // 五萬首第一次掃要讀很久的 tag，不要卡住 async runtime
+    tauri::async_runtime::spawn_blocking(move || rescan_library(&app))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+fn library_list(
+    offset: usize,
+    limit: usize,
+    library: State<'_, Mutex<Library>>,
+) -> Result<SongPage, String> {
+    let library = library.lock().map_err(|e| e.to_string())?;
+
+    library.list(offset, limit)
+}
+
+#[tauri::command]
+fn library_search(
+    query: String,
+    offset: usize,
+    limit: usize,
+    library: State<'_, Mutex<Library>>,
+) -> Result<SongPage, String> {
+    let library = library.lock().map_err(|e| e.to_string())?;
+
+    library.search(&query, offset, limit)
+}
+
+#[tauri::command]
+fn library_find(
+    query: SearchQuery,
+    library: State<'_, Mutex<Library>>,
+) -> Result<SongPage, String> {
+    let mut library = library.lock().map_err(|e| e.to_string())?;
+
+    // 每打一個字就查一次，索引在記憶體裡
+    library.find(&query)
+}
+
+#[tauri::command]
+fn library_singers(
+    kind: Option<SingerKind>,
+    text: Option<String>,
+    library: State<'_, Mutex<Library>>,
+) -> Result<Vec<SingerEntry>, String> {
+    let mut library = library.lock().map_err(|e| e.to_string())?;
+
+    library.singers(kind, text.as_deref().unwrap_or(""))
+}
+
+#[tauri::command]
+fn library_set_singer_kind(
+    name: String,
+    kind: Option<SingerKind>,
+    library: State<'_, Mutex<Library>>,
+) -> Result<String, String> {
+    let mut library = library.lock().map_err(|e| e.to_string())?;
+
+    library.set_singer_kind(&name, kind)?;
+    println!("[Library] Singer {}: {:?}", name, kind);
+
+    Ok(format!("Singer {}: {:?}", name, kind))
+}
+
+#[tauri::command]
+fn library_song_by_code(
+    code: i64,
+    library: State<'_, Mutex<Library>>,
+) -> Result<Option<Song>, String> {
+    let library = library.lock().map_err(|e| e.to_string())?;
+
+    library.song_by_code(code)
+}
+
+#[tauri::command]
+fn library_import_catalog(
+    path: String,
+    app: tauri::AppHandle,
+    library: State<'_, Mutex<Library>>,
+) -> Result<ImportReport, String> {
+    // 先讀完檔案再鎖歌庫
+    let entries = read_catalog(&PathBuf::from(&path))?;
+    let report = library
+        .lock()
+        .map_err(|e| e.to_string())?
+        .import_catalog(&entries)?;
+    println!(
+        "[Library] Imported {}: {} rows, {} skipped",
+        path,
+        report.updated,
+        report.skipped.len()
+    );
+    emit_library(
+        &app,
+        &ScanReport {
+            updated: report.updated,
+            ..ScanReport::default()
+        },
+    );
+
+    Ok(report)
+}
+
+#[tauri::command]
+fn library_export_catalog(
+    path: String,
+    library: State<'_, Mutex<Library>>,
+) -> Result<String, String> {
+    let entries = library
+        .lock()
+        .map_err(|e| e.to_string())?
+        .export_catalog()?;
+    write_catalog(&PathBuf::from(&path), &entries)?;
+    println!("[Library] Exported {} songs to {}", entries.len(), path);
+
+    Ok(format!("Exported {} songs", entries.len()))
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -347,15 +1542,74 @@ pub fn run() {
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_dialog::init())
         .manage(Mutex::new(AudioState::new()))
+        .setup(|app| {
+            let mut library = open_library(app.handle())?;
+            let soundfont = app
+                .path()
+                .resolve(BUNDLED_SOUNDFONT, BaseDirectory::Resource)?;
+            if soundfont.is_file() {
+                app.state::<Mutex<AudioState>>()
+                    .lock()
+                    .map_err(|e| e.to_string())?
+                    .set_soundfont_path(soundfont);
+                library.set_midi_playable(true);
+            } else {
+                println!("[Midi] Bundled SoundFont missing: {}", soundfont.display());
+            }
+            app.manage(Mutex::new(library));
+            let watcher = start_folder_watcher(app.handle())?;
+            app.manage(Mutex::new(watcher));
+            spawn_playback_watcher(app.handle().clone());
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             upload_audio_file,
             play_audio_file,
             stop_audio,
+            pause_audio,
+            resume_audio,
+            seek_audio,
+            get_playback_progress,
+            set_progress_rate,
+            set_soundfont,
             get_current_file,
+            get_lyrics,
+            get_notes,
+            get_cdg_frame,
+            get_queue,
+            enqueue_song,
+            enqueue_song_by_code,
+            insert_song_top,
+            promote_song,
+            set_rotation_policy,
+            remove_song,
+            move_song,
+            skip_song,
+            replay_song,
             start_mic_only,
             stop_mic,
             start_karaoke,
-            stop_karaoke
+            stop_karaoke,
+            set_master_volume,
+            set_master_mute,
+            get_channel_strips,
+            set_channel_gain,
+            set_channel_mute,
+            set_channel_solo,
+            set_channel_pan,
+            get_limiter_gain_reduction,
+            library_folders,
+            library_add_folder,
+            library_remove_folder,
+            library_rescan,
+            library_list,
+            library_search,
+            library_find,
+            library_singers,
+            library_set_singer_kind,
+            library_song_by_code,
+            library_import_catalog,
+            library_export_catalog
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
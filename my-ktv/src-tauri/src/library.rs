@@ -0,0 +1,590 @@
+/***
+ * @ Mod:       library
+ * @ Author:    Leon Lin
+ * @ Date:      20261017
+ */
+
+pub mod catalog;
+pub mod scan;
+pub mod search;
+pub mod tags;
+pub mod watch;
+
+use crate::audio_node::midi_src::MIDI_EXTENSIONS;
+use crate::library::catalog::{CatalogEntry, ImportReport};
+use crate::library::scan::{FileStamp, ScanResult};
+use crate::library::search::{SearchIndex, SearchQuery, SingerEntry, SingerKind};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS folders (
+    path TEXT PRIMARY KEY
+);
+CREATE TABLE IF NOT EXISTS songs (
+    id          INTEGER PRIMARY KEY,
+    path        TEXT NOT NULL UNIQUE,
+    folder      TEXT NOT NULL,
+    title       TEXT NOT NULL,
+    artist      TEXT,
+    language    TEXT,
+    duration_ms INTEGER,
+    mtime_ms    INTEGER NOT NULL,
+    size        INTEGER NOT NULL,
+    hash        TEXT
+);
+CREATE INDEX IF NOT EXISTS songs_folder ON songs(folder);
+CREATE INDEX IF NOT EXISTS songs_title ON songs(title);
+CREATE INDEX IF NOT EXISTS songs_artist ON songs(artist);
+CREATE INDEX IF NOT EXISTS songs_hash ON songs(hash);
+CREATE TABLE IF NOT EXISTS singers (
+    name TEXT PRIMARY KEY,
+    kind TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS catalog (
+    hash     TEXT PRIMARY KEY,
+    code     INTEGER NOT NULL UNIQUE,
+    title    TEXT,
+    artist   TEXT,
+    language TEXT
+);
+CREATE VIEW IF NOT EXISTS library_songs AS
+SELECT s.id, s.path,
+       COALESCE(c.title, s.title) AS title,
+       COALESCE(c.artist, s.artist) AS artist,
+       COALESCE(c.language, s.language) AS language,
+       s.duration_ms, c.code, s.hash
+FROM songs s LEFT JOIN catalog c ON c.hash = s.hash;
+";
+
+// read from `library_songs`: catalog imports win over tags
+const SONG_COLUMNS: &str = "id, path, title, artist, language, duration_ms, code";
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Song {
+    pub id: i64,
+    pub path: String,
+    pub title: String,
+    pub artist: Option<String>,
+    pub language: Option<String>,
+    pub duration_ms: Option<i64>,
+    // 點歌號碼, stays with the file's content across renames and rescans
+    pub code: Option<i64>,
+}
+
+impl Song {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            path: row.get(1)?,
+            title: row.get(2)?,
+            artist: row.get(3)?,
+            language: row.get(4)?,
+            duration_ms: row.get(5)?,
+            code: row.get(6)?,
+        })
+    }
+}
+
+/// One page of a list or search, `total` counts every match
+#[derive(Serialize, Clone, Debug)]
+pub struct SongPage {
+    pub total: usize,
+    pub songs: Vec<Song>,
+}
+
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct ScanReport {
+    pub added: usize,
+    pub updated: usize,
+    pub removed: usize,
+    pub unchanged: usize,
+}
+
+/// Songs found in the configured folders, kept in SQLite so a restart doesn't scan again
+pub struct Library {
+    conn: Connection,
+    // built on the first search after a change
+    index: Option<SearchIndex>,
+    // MIDI songs only play through a SoundFont, without one they are kept but not listed
+    midi_playable: bool,
+}
+
+fn db_err(e: rusqlite::Error) -> String {
+    format!("Library: {}", e)
+}
+
+impl Library {
+    pub fn open(db_path: &Path) -> Result<Self, String> {
+        Self::with_connection(Connection::open(db_path).map_err(db_err)?)
+    }
+
+    pub fn open_in_memory() -> Result<Self, String> {
+        Self::with_connection(Connection::open_in_memory().map_err(db_err)?)
+    }
+
+    fn with_connection(conn: Connection) -> Result<Self, String> {
+        conn.execute_batch(SCHEMA).map_err(db_err)?;
+        Ok(Self {
+            conn,
+            index: None,
+            midi_playable: false,
+        })
+    }
+
+    pub fn add_folder(&mut self, folder: &Path) -> Result<(), String> {
+        if !folder.is_dir() {
+            return Err(format!("Not a folder: {}", folder.display()));
+        }
+        self.conn
+            .execute(
+                "INSERT OR IGNORE INTO folders (path) VALUES (?1)",
+                params![folder.to_string_lossy()],
+            )
+            .map_err(db_err)?;
+        Ok(())
+    }
+
+    /// Forget a folder and every song found in it
+    pub fn remove_folder(&mut self, folder: &Path) -> Result<usize, String> {
+        let folder = folder.to_string_lossy();
+        let tx = self.conn.transaction().map_err(db_err)?;
+        tx.execute("DELETE FROM folders WHERE path = ?1", params![folder])
+            .map_err(db_err)?;
+        let removed = tx
+            .execute("DELETE FROM songs WHERE folder = ?1", params![folder])
+            .map_err(db_err)?;
+        tx.commit().map_err(db_err)?;
+        self.index = None;
+        Ok(removed)
+    }
+
+    pub fn folders(&self) -> Result<Vec<PathBuf>, String> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT path FROM folders ORDER BY path")
+            .map_err(db_err)?;
+        let rows = stmt
+            .query_map([], |row| row.get::<_, String>(0))
+            .map_err(db_err)?;
+        rows.map(|r| r.map(PathBuf::from).map_err(db_err)).collect()
+    }
+
+    /// Stamp of every file in the library, what `scan::scan` compares against
+    pub fn known_files(&self) -> Result<HashMap<PathBuf, FileStamp>, String> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT path, mtime_ms, size FROM songs")
+            .map_err(db_err)?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    PathBuf::from(row.get::<_, String>(0)?),
+                    FileStamp {
+                        mtime_ms: row.get(1)?,
+                        size: row.get(2)?,
+                    },
+                ))
+            })
+            .map_err(db_err)?;
+        rows.map(|r| r.map_err(db_err)).collect()
+    }
+
+    /// Write a scan back in one transaction
+    pub fn apply(&mut self, result: ScanResult) -> Result<ScanReport, String> {
+        let mut report = ScanReport {
+            unchanged: result.unchanged,
+            ..ScanReport::default()
+        };
+        let tx = self.conn.transaction().map_err(db_err)?;
+        {
+            let mut old_hash = tx
+                .prepare("SELECT hash FROM songs WHERE path = ?1")
+                .map_err(db_err)?;
+            let mut upsert = tx
+                .prepare(
+                    "INSERT INTO songs
+                        (path, folder, title, artist, language, duration_ms, mtime_ms, size, hash)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+                     ON CONFLICT(path) DO UPDATE SET
+                        folder = excluded.folder, title = excluded.title,
+                        artist = excluded.artist, language = excluded.language,
+                        duration_ms = excluded.duration_ms,
+                        mtime_ms = excluded.mtime_ms, size = excluded.size,
+                        hash = excluded.hash",
+                )
+                .map_err(db_err)?;
+            // a code is never handed out twice, even after its song is gone
+            let mut assign = tx
+                .prepare(
+                    "INSERT OR IGNORE INTO catalog (hash, code)
+                     VALUES (?1, (SELECT COALESCE(MAX(code), 0) + 1 FROM catalog))",
+                )
+                .map_err(db_err)?;
+            // an edited tag changes the hash, the code stays with the path
+            let mut rekey = tx
+                .prepare(
+                    "UPDATE OR IGNORE catalog SET hash = ?1 WHERE hash = ?2
+                     AND NOT EXISTS (SELECT 1 FROM songs WHERE hash = ?2 AND path != ?3)",
+                )
+                .map_err(db_err)?;
+            for song in &result.changed {
+                let path = song.path.to_string_lossy();
+                match old_hash
+                    .query_row(params![path], |r| r.get::<_, Option<String>>(0))
+                    .optional()
+                    .map_err(db_err)?
+                {
+                    Some(old) => {
+                        report.updated += 1;
+                        if let (Some(old), Some(new)) = (old, &song.hash) {
+                            if old != *new {
+                                rekey.execute(params![new, old, path]).map_err(db_err)?;
+                            }
+                        }
+                    }
+                    None => report.added += 1,
+                }
+                upsert
+                    .execute(params![
+                        path,
+                        song.folder.to_string_lossy(),
+                        song.title,
+                        song.artist,
+                        song.language,
+                        song.duration_ms,
+                        song.stamp.mtime_ms,
+                        song.stamp.size,
+                        song.hash,
+                    ])
+                    .map_err(db_err)?;
+                if let Some(hash) = &song.hash {
+                    assign.execute(params![hash]).map_err(db_err)?;
+                }
+            }
+            let mut delete = tx
+                .prepare("DELETE FROM songs WHERE path = ?1")
+                .map_err(db_err)?;
+            for path in &result.missing {
+                report.removed += delete
+                    .execute(params![path.to_string_lossy()])
+                    .map_err(db_err)?;
+            }
+        }
+        tx.commit().map_err(db_err)?;
+        if report.added + report.updated + report.removed > 0 {
+            self.index = None;
+        }
+        Ok(report)
+    }
+
+    /// Scan every folder and apply it, holds the library the whole time. The app splits this up
+    /// so list and search keep working during a long scan.
+    pub fn rescan(&mut self) -> Result<ScanReport, String> {
+        let result = scan::scan(&self.folders()?, &self.known_files()?);
+        self.apply(result)
+    }
+
+    /// Bring just `paths` up to date, what the folder watcher reports
+    pub fn refresh(&mut self, paths: &[PathBuf]) -> Result<ScanReport, String> {
+        let result = scan::scan_paths(&self.folders()?, &self.known_files()?, paths);
+        self.apply(result)
+    }
+
+    pub fn song(&self, id: i64) -> Result<Option<Song>, String> {
+        self.conn
+            .query_row(
+                &format!("SELECT {} FROM library_songs WHERE id = ?1", SONG_COLUMNS),
+                params![id],
+                Song::from_row,
+            )
+            .optional()
+            .map_err(db_err)
+    }
+
+    /// Whether `.mid` / `.kar` songs are listed, searched and found by code. They stay in the
+    /// library either way, set this once a SoundFont can play them.
+    pub fn set_midi_playable(&mut self, playable: bool) {
+        if self.midi_playable != playable {
+            self.midi_playable = playable;
+            self.index = None;
+        }
+    }
+
+    /// `AND ...` leaving out what can't be played, empty when everything can
+    fn playable_filter(&self) -> String {
+        if self.midi_playable {
+            return String::new();
+        }
+        MIDI_EXTENSIONS
+            .iter()
+            .map(|ext| format!(" AND lower(path) NOT LIKE '%.{}'", ext))
+            .collect()
+    }
+
+    /// The song a guest means by typing its code
+    pub fn song_by_code(&self, code: i64) -> Result<Option<Song>, String> {
+        self.conn
+            .query_row(
+                &format!(
+                    "SELECT {} FROM library_songs WHERE code = ?1{} ORDER BY id LIMIT 1",
+                    SONG_COLUMNS,
+                    self.playable_filter()
+                ),
+                params![code],
+                Song::from_row,
+            )
+            .optional()
+            .map_err(db_err)
+    }
+
+    /// Codes and metadata from a catalog file, in one transaction. A row's code may not take
+    /// another song's; a hash not in the library yet waits there for its file.
+    pub fn import_catalog(&mut self, entries: &[CatalogEntry]) -> Result<ImportReport, String> {
+        let mut report = ImportReport::default();
+        let tx = self.conn.transaction().map_err(db_err)?;
+        {
+            let mut by_path = tx
+                .prepare("SELECT hash FROM songs WHERE path = ?1")
+                .map_err(db_err)?;
+            let mut holder = tx
+                .prepare("SELECT hash FROM catalog WHERE code = ?1")
+                .map_err(db_err)?;
+            let mut upsert = tx
+                .prepare(
+                    "INSERT INTO catalog (hash, code, title, artist, language)
+                     VALUES (?1, COALESCE(?2, (SELECT COALESCE(MAX(code), 0) + 1 FROM catalog)),
+                             ?3, ?4, ?5)
+                     ON CONFLICT(hash) DO UPDATE SET
+                        code = COALESCE(?2, code), title = COALESCE(?3, title),
+                        artist = COALESCE(?4, artist), language = COALESCE(?5, language)",
+                )
+                .map_err(db_err)?;
+            for (i, entry) in entries.iter().enumerate() {
+                let row = i + 1;
+                let hash = match (&entry.hash, &entry.path) {
+                    (Some(hash), _) => Some(hash.clone()),
+                    (None, Some(path)) => by_path
+                        .query_row(params![path], |r| r.get::<_, Option<String>>(0))
+                        .optional()
+                        .map_err(db_err)?
+                        .flatten(),
+                    (None, None) => None,
+                };
+                let hash = match hash {
+                    Some(hash) => hash,
+                    None => {
+                        report
+                            .skipped
+                            .push(format!("Row {}: no song with this path or hash", row));
+                        continue;
+                    }
+                };
+                if let Some(code) = entry.code {
+                    if code <= 0 {
+                        report
+                            .skipped
+                            .push(format!("Row {}: code {} is not positive", row, code));
+                        continue;
+                    }
+                    let taken = holder
+                        .query_row(params![code], |r| r.get::<_, String>(0))
+                        .optional()
+                        .map_err(db_err)?;
+                    if taken.is_some_and(|other| other != hash) {
+                        report.skipped.push(format!(
+                            "Row {}: code {} belongs to another song",
+                            row, code
+                        ));
+                        continue;
+                    }
+                }
+                upsert
+                    .execute(params![
+                        hash,
+                        entry.code,
+                        entry.title,
+                        entry.artist,
+                        entry.language
+                    ])
+                    .map_err(db_err)?;
+                report.updated += 1;
+            }
+        }
+        tx.commit().map_err(db_err)?;
+        if report.updated > 0 {
+            self.index = None;
+        }
+        Ok(report)
+    }
+
+    /// Every song that has a code, by code, ready for `catalog::write_catalog`
+    pub fn export_catalog(&self) -> Result<Vec<CatalogEntry>, String> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT code, path, hash, title, artist, language FROM library_songs
+                 WHERE code IS NOT NULL ORDER BY code, path",
+            )
+            .map_err(db_err)?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok(CatalogEntry {
+                    code: row.get(0)?,
+                    path: row.get(1)?,
+                    hash: row.get(2)?,
+                    title: row.get(3)?,
+                    artist: row.get(4)?,
+                    language: row.get(5)?,
+                })
+            })
+            .map_err(db_err)?;
+        rows.map(|r| r.map_err(db_err)).collect()
+    }
+
+    pub fn count(&self) -> Result<usize, String> {
+        self.conn
+            .query_row("SELECT COUNT(*) FROM songs", [], |row| row.get::<_, i64>(0))
+            .map(|n| n as usize)
+            .map_err(db_err)
+    }
+
+    /// Every song by title
+    pub fn list(&self, offset: usize, limit: usize) -> Result<SongPage, String> {
+        self.page("1", params![], offset, limit)
+    }
+
+    /// Songs whose title or artist contains `query`, by title
+    pub fn search(&self, query: &str, offset: usize, limit: usize) -> Result<SongPage, String> {
+        let query = query.trim();
+        if query.is_empty() {
+            return self.list(offset, limit);
+        }
+        let pattern = format!("%{}%", escape_like(query));
+        self.page(
+            "(title LIKE ?1 ESCAPE '\\' OR artist LIKE ?1 ESCAPE '\\')",
+            params![pattern],
+            offset,
+            limit,
+        )
+    }
+
+    /// Playable songs matching the `filter` condition, by title
+    fn page(
+        &self,
+        filter: &str,
+        args: &[&dyn rusqlite::ToSql],
+        offset: usize,
+        limit: usize,
+    ) -> Result<SongPage, String> {
+        let filter = format!("WHERE {}{}", filter, self.playable_filter());
+        let total = self
+            .conn
+            .query_row(
+                &format!("SELECT COUNT(*) FROM library_songs {}", filter),
+                args,
+                |row| row.get::<_, i64>(0),
+            )
+            .map_err(db_err)? as usize;
+        let mut stmt = self
+            .conn
+            .prepare(&format!(
+                "SELECT {} FROM library_songs {} ORDER BY title, artist, id LIMIT {} OFFSET {}",
+                SONG_COLUMNS, filter, limit, offset
+            ))
+            .map_err(db_err)?;
+        let songs = stmt
+            .query_map(args, Song::from_row)
+            .map_err(db_err)?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_err)?;
+        Ok(SongPage { total, songs })
+    }
+
+    /// Male / female / group, what the tags don't say. `None` forgets it.
+    pub fn set_singer_kind(&mut self, name: &str, kind: Option<SingerKind>) -> Result<(), String> {
+        match kind {
+            Some(kind) => self.conn.execute(
+                "INSERT INTO singers (name, kind) VALUES (?1, ?2)
+                 ON CONFLICT(name) DO UPDATE SET kind = excluded.kind",
+                params![name, kind.as_str()],
+            ),
+            None => self
+                .conn
+                .execute("DELETE FROM singers WHERE name = ?1", params![name]),
+        }
+        .map_err(db_err)?;
+        self.index = None;
+        Ok(())
+    }
+
+    fn singer_kinds(&self) -> Result<HashMap<String, SingerKind>, String> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT name, kind FROM singers")
+            .map_err(db_err)?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })
+            .map_err(db_err)?;
+        let mut kinds = HashMap::new();
+        for row in rows {
+            let (name, kind) = row.map_err(db_err)?;
+            if let Some(kind) = SingerKind::parse(&kind) {
+                kinds.insert(name, kind);
+            }
+        }
+        Ok(kinds)
+    }
+
+    fn search_index(&mut self) -> Result<&SearchIndex, String> {
+        if self.index.is_none() {
+            let mut stmt = self
+                .conn
+                .prepare(&format!(
+                    "SELECT {} FROM library_songs WHERE 1{}",
+                    SONG_COLUMNS,
+                    self.playable_filter()
+                ))
+                .map_err(db_err)?;
+            let songs = stmt
+                .query_map([], Song::from_row)
+                .map_err(db_err)?
+                .collect::<rusqlite::Result<Vec<_>>>()
+                .map_err(db_err)?;
+            drop(stmt);
+            let index = SearchIndex::build(songs, &self.singer_kinds()?);
+            println!("[Library] Search index: {} songs", index.len());
+            self.index = Some(index);
+        }
+        Ok(self.index.as_ref().unwrap())
+    }
+
+    /// KTV search: initials, 字數, singer, language and singer kind, best match first
+    pub fn find(&mut self, query: &SearchQuery) -> Result<SongPage, String> {
+        Ok(self.search_index()?.search(query))
+    }
+
+    /// Singers to browse, filtered by kind and the same text matching as `find`
+    pub fn singers(
+        &mut self,
+        kind: Option<SingerKind>,
+        text: &str,
+    ) -> Result<Vec<SingerEntry>, String> {
+        Ok(self.search_index()?.singers(kind, text))
+    }
+}
+
+fn escape_like(query: &str) -> String {
+    let mut escaped = String::with_capacity(query.len());
+    for c in query.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
@@ -0,0 +1,115 @@
+/***
+ * @ Mod:       catalog
+ * @ Author:    Leon Lin
+ * @ Date:      20261017
+ */
+
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+// head and tail are enough to tell songs apart, reading 50k whole files is not
+const HASH_SPAN: u64 = 64 * 1024;
+
+/// One row of a catalog file. Import matches a row to a song by `hash` first, then by `path`;
+/// empty fields leave what the library has.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct CatalogEntry {
+    pub code: Option<i64>,
+    pub path: Option<String>,
+    pub hash: Option<String>,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub language: Option<String>,
+}
+
+/// What an import did, `skipped` says why for every row it couldn't use
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct ImportReport {
+    pub updated: usize,
+    pub skipped: Vec<String>,
+}
+
+/// FNV-1a over the size, the first and the last 64 KiB. Survives renames and moves to another
+/// disk, so a code follows the song and an exported catalog fits another machine.
+pub fn content_hash(path: &Path) -> Option<String> {
+    let mut file = File::open(path).ok()?;
+    let size = file.metadata().ok()?.len();
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let mut feed = |bytes: &[u8]| {
+        for b in bytes {
+            hash ^= *b as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    };
+    feed(&size.to_le_bytes());
+    let mut buf = vec![0u8; HASH_SPAN.min(size) as usize];
+    file.read_exact(&mut buf).ok()?;
+    feed(&buf);
+    if size > HASH_SPAN {
+        let tail = HASH_SPAN.min(size - HASH_SPAN);
+        file.seek(SeekFrom::End(-(tail as i64))).ok()?;
+        buf.truncate(tail as usize);
+        file.read_exact(&mut buf).ok()?;
+        feed(&buf);
+    }
+    Some(format!("{:016x}", hash))
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("json"))
+}
+
+/// A `.json` file is an array of entries, anything else is CSV with a header row naming the
+/// columns (any order, any subset)
+pub fn read_catalog(path: &Path) -> Result<Vec<CatalogEntry>, String> {
+    let file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut entries: Vec<CatalogEntry> = if is_json(path) {
+        serde_json::from_reader(file).map_err(|e| format!("{}: {}", path.display(), e))?
+    } else {
+        csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(file)
+            .deserialize()
+            .collect::<Result<_, _>>()
+            .map_err(|e| format!("{}: {}", path.display(), e))?
+    };
+    // spreadsheets write empty cells, JSON may write ""
+    for entry in &mut entries {
+        for field in [
+            &mut entry.path,
+            &mut entry.hash,
+            &mut entry.title,
+            &mut entry.artist,
+            &mut entry.language,
+        ] {
+            if field.as_deref().is_some_and(str::is_empty) {
+                *field = None;
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// Same formats `read_catalog` takes, chosen by extension
+pub fn write_catalog(path: &Path, entries: &[CatalogEntry]) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    if is_json(path) {
+        serde_json::to_writer_pretty(file, entries)
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    } else {
+        let mut writer = csv::Writer::from_writer(file);
+        for entry in entries {
+            writer
+                .serialize(entry)
+                .map_err(|e| format!("{}: {}", path.display(), e))?;
+        }
+        writer
+            .flush()
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
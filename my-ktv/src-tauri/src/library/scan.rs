@@ -0,0 +1,208 @@
+/***
+ * @ Mod:       scan
+ * @ Author:    Leon Lin
+ * @ Date:      20261017
+ */
+
+use crate::library::catalog::content_hash;
+use crate::library::tags::{read_tags, tags_from_file_name};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+// what FileSrc and MidiSrc can play
+pub const AUDIO_EXTENSIONS: [&str; 8] = ["mp3", "wav", "flac", "ogg", "m4a", "mid", "midi", "kar"];
+
+/// Modification time (ms since epoch) and size, a file is read again when either changed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FileStamp {
+    pub mtime_ms: i64,
+    pub size: i64,
+}
+
+impl FileStamp {
+    pub fn of(path: &Path) -> Option<Self> {
+        let meta = fs::metadata(path).ok()?;
+        let mtime_ms = meta
+            .modified()
+            .ok()?
+            .duration_since(UNIX_EPOCH)
+            .ok()?
+            .as_millis() as i64;
+        Some(Self {
+            mtime_ms,
+            size: meta.len() as i64,
+        })
+    }
+}
+
+/// A new or changed file, tags already read
+#[derive(Clone, Debug)]
+pub struct ScannedSong {
+    pub path: PathBuf,
+    pub folder: PathBuf,
+    pub stamp: FileStamp,
+    pub title: String,
+    pub artist: Option<String>,
+    pub language: Option<String>,
+    pub duration_ms: Option<i64>,
+    pub hash: Option<String>,
+}
+
+/// Difference between the folders on disk and what the library knew
+#[derive(Default, Debug)]
+pub struct ScanResult {
+    pub changed: Vec<ScannedSong>,
+    pub missing: Vec<PathBuf>,
+    pub unchanged: usize,
+}
+
+pub fn is_audio_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| AUDIO_EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()))
+}
+
+/// Walk `folders` and read tags only of files whose stamp differs from `known`. Needs no
+/// database, so the library stays usable while this runs.
+pub fn scan(folders: &[PathBuf], known: &HashMap<PathBuf, FileStamp>) -> ScanResult {
+    let mut result = ScanResult::default();
+    let mut seen = HashSet::new();
+    for folder in folders {
+        let mut files = Vec::new();
+        collect_audio_files(folder, &mut files);
+        for path in files {
+            let stamp = match FileStamp::of(&path) {
+                Some(stamp) => stamp,
+                None => continue,
+            };
+            seen.insert(path.clone());
+            if known.get(&path) == Some(&stamp) {
+                result.unchanged += 1;
+                continue;
+            }
+            result.changed.push(scan_file(path, folder.clone(), stamp));
+        }
+    }
+    // the folder itself may be gone (unplugged disk), its songs go too
+    result.missing = known
+        .keys()
+        .filter(|path| !seen.contains(*path))
+        .cloned()
+        .collect();
+    result
+}
+
+/// Like `scan` but only looks at `paths`, what the folder watcher saw change. A path may be a
+/// file or a whole folder dropped in; a path that is gone takes every song under it along.
+pub fn scan_paths(
+    folders: &[PathBuf],
+    known: &HashMap<PathBuf, FileStamp>,
+    paths: &[PathBuf],
+) -> ScanResult {
+    let mut result = ScanResult::default();
+    let mut seen = HashSet::new();
+    let mut gone = HashSet::new();
+    for path in paths {
+        // the innermost one when a library folder sits inside another
+        let folder = match folders
+            .iter()
+            .filter(|f| path.starts_with(f))
+            .max_by_key(|f| f.components().count())
+        {
+            Some(folder) => folder,
+            None => continue,
+        };
+        let mut files = Vec::new();
+        if path.is_dir() {
+            collect_audio_files(path, &mut files);
+        } else if path.is_file() && is_audio_file(path) {
+            files.push(path.clone());
+        }
+        for file in files {
+            if seen.contains(&file) {
+                continue;
+            }
+            let stamp = match FileStamp::of(&file) {
+                Some(stamp) => stamp,
+                None => continue,
+            };
+            seen.insert(file.clone());
+            if known.get(&file) == Some(&stamp) {
+                result.unchanged += 1;
+                continue;
+            }
+            result.changed.push(scan_file(file, folder.clone(), stamp));
+        }
+        // deleted, renamed away, or a folder that lost some of its files
+        gone.extend(known.keys().filter(|k| k.starts_with(path)));
+    }
+    result.missing = gone
+        .into_iter()
+        .filter(|path| !seen.contains(*path))
+        .cloned()
+        .collect();
+    result
+}
+
+pub fn scan_file(path: PathBuf, folder: PathBuf, stamp: FileStamp) -> ScannedSong {
+    let tags = read_tags(&path).unwrap_or_else(|e| {
+        println!("[Library] {}: {}", path.display(), e);
+        Default::default()
+    });
+    let (name_title, name_artist) = tags_from_file_name(&path);
+    ScannedSong {
+        title: tags.title.unwrap_or(name_title),
+        artist: tags.artist.or(name_artist),
+        language: tags.language,
+        duration_ms: tags.duration.map(|d| d.as_millis() as i64),
+        hash: content_hash(&path),
+        path,
+        folder,
+        stamp,
+    }
+}
+
+fn collect_audio_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            println!("[Library] Cannot read {}: {}", dir.display(), e);
+            return;
+        }
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        match entry.file_type() {
+            // symlinks are not followed, a link back up would never end
+            Ok(t) if t.is_dir() => collect_audio_files(&path, files),
+            Ok(t) if t.is_file() && is_audio_file(&path) => files.push(path),
+            _ => {}
+        }
+    }
+}
+
+/// `song.<extension>` next to `song.mp3`, any case of the extension
+pub fn find_sidecar(audio: &Path, extension: &str) -> Option<PathBuf> {
+    let stem = audio.file_stem()?;
+    let dir = audio.parent()?;
+    // not with_extension, `a.b.mp3` would become `a.lrc`
+    let mut name = stem.to_os_string();
+    name.push(".");
+    name.push(extension);
+    let exact = dir.join(name);
+    if exact.is_file() {
+        return Some(exact);
+    }
+    fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .map(|e| e.path())
+        .find(|p| {
+            p.file_stem() == Some(stem)
+                && p.extension()
+                    .and_then(|e| e.to_str())
+                    .is_some_and(|e| e.eq_ignore_ascii_case(extension))
+        })
+}
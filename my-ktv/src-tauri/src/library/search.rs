@@ -0,0 +1,471 @@
+/***
+ * @ Mod:       search
+ * @ Author:    Leon Lin
+ * @ Date:      20261017
+ */
+
+use crate::library::{Song, SongPage};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+const PINYIN_READINGS: &str = include_str!("data/pinyin.txt");
+const ZHUYIN_READINGS: &str = include_str!("data/zhuyin.txt");
+
+// a song only matching the singer ranks a little below the same match on its title
+const SINGER_PENALTY: u32 = 5;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum SingerKind {
+    Male,
+    Female,
+    Group,
+}
+
+impl SingerKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SingerKind::Male => "male",
+            SingerKind::Female => "female",
+            SingerKind::Group => "group",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "male" => Some(SingerKind::Male),
+            "female" => Some(SingerKind::Female),
+            "group" => Some(SingerKind::Group),
+            _ => None,
+        }
+    }
+}
+
+/// Everything the song picker can ask at once, empty fields don't filter. `text` is matched
+/// against title and singer as typed, as pinyin initials (`xxy` → 小幸運) and as zhuyin,
+/// initials or whole syllables (`ㄒㄒㄩ` or `ㄒㄧㄠˇㄒㄧㄥˋ`). Every reading of a 破音字 counts.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct SearchQuery {
+    pub text: String,
+    // 字數: CJK characters, a latin word counts as one
+    pub title_len: Option<usize>,
+    pub singer: Option<String>,
+    pub language: Option<String>,
+    pub singer_kind: Option<SingerKind>,
+    pub offset: usize,
+    pub limit: usize,
+}
+
+/// A singer to browse, with how many songs the library has
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct SingerEntry {
+    pub name: String,
+    pub kind: Option<SingerKind>,
+    pub songs: usize,
+}
+
+/// Every reading of every unit of a title or singer, a latin word reads as itself
+type Readings = Vec<Vec<Cow<'static, str>>>;
+
+/// Keys of one song, computed once so a query is a plain scan (50k songs take some tens of ms)
+struct IndexedSong {
+    song: Song,
+    title_key: String,
+    artist_key: String,
+    // usual reading of every unit, the order songs are browsed in
+    title_sort: String,
+    title_pinyin: Readings,
+    title_zhuyin: Readings,
+    artist_pinyin: Readings,
+    artist_zhuyin: Readings,
+    title_len: usize,
+    language_key: String,
+    kind: Option<SingerKind>,
+}
+
+/// In memory index over the whole library, rebuilt when the library changes
+pub struct SearchIndex {
+    songs: Vec<IndexedSong>,
+}
+
+impl SearchIndex {
+    pub fn build(songs: Vec<Song>, kinds: &HashMap<String, SingerKind>) -> Self {
+        let mut songs: Vec<IndexedSong> = songs
+            .into_iter()
+            .map(|song| {
+                let artist = song.artist.clone().unwrap_or_default();
+                let title_units = units(&song.title);
+                let artist_units = units(&artist);
+                let title_pinyin = readings(&title_units, pinyin_table());
+                IndexedSong {
+                    title_key: normalize(&song.title),
+                    artist_key: normalize(&artist),
+                    title_sort: title_pinyin
+                        .iter()
+                        .filter_map(|r| r.first())
+                        .map(|r| r.as_ref())
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                    title_pinyin,
+                    title_zhuyin: readings(&title_units, zhuyin_table()),
+                    artist_pinyin: readings(&artist_units, pinyin_table()),
+                    artist_zhuyin: readings(&artist_units, zhuyin_table()),
+                    title_len: title_units.len(),
+                    language_key: normalize(song.language.as_deref().unwrap_or("")),
+                    kind: kinds.get(&artist).copied(),
+                    song,
+                }
+            })
+            .collect();
+        // browse order: by pinyin like the song books, then by title
+        songs.sort_by(|a, b| {
+            (&a.title_sort, &a.song.title, a.song.id).cmp(&(
+                &b.title_sort,
+                &b.song.title,
+                b.song.id,
+            ))
+        });
+        Self { songs }
+    }
+
+    pub fn len(&self) -> usize {
+        self.songs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.songs.is_empty()
+    }
+
+    pub fn search(&self, query: &SearchQuery) -> SongPage {
+        let text = TextQuery::parse(&query.text);
+        let singer = query
+            .singer
+            .as_deref()
+            .map(normalize)
+            .map(|n| (letters(&n), n));
+        let language = query.language.as_deref().map(normalize);
+
+        let mut hits: Vec<(u32, &IndexedSong)> = self
+            .songs
+            .iter()
+            .filter(|s| query.title_len.is_none_or(|n| s.title_len == n))
+            .filter(|s| query.singer_kind.is_none_or(|k| s.kind == Some(k)))
+            .filter(|s| language.as_ref().is_none_or(|l| s.language_key == *l))
+            .filter(|s| {
+                singer.as_ref().is_none_or(|(initials, n)| {
+                    s.artist_key == *n || match_readings(initials, &s.artist_pinyin) >= 75
+                })
+            })
+            .filter_map(|s| text.score(s).map(|score| (score, s)))
+            .collect();
+        // stable: equal scores stay in browse order
+        hits.sort_by_key(|(score, s)| (Reverse(*score), s.title_len));
+
+        let limit = if query.limit == 0 { 50 } else { query.limit };
+        SongPage {
+            total: hits.len(),
+            songs: hits
+                .into_iter()
+                .skip(query.offset)
+                .take(limit)
+                .map(|(_, s)| s.song.clone())
+                .collect(),
+        }
+    }
+
+    /// Singers with songs in the library, most songs first. `text` works like in `search`.
+    pub fn singers(&self, kind: Option<SingerKind>, text: &str) -> Vec<SingerEntry> {
+        let text = TextQuery::parse(text);
+        let mut found: HashMap<&str, (u32, &IndexedSong, usize)> = HashMap::new();
+        for s in &self.songs {
+            let name = match s.song.artist.as_deref() {
+                Some(name) if !name.is_empty() => name,
+                _ => continue,
+            };
+            if kind.is_some_and(|k| s.kind != Some(k)) {
+                continue;
+            }
+            let score = match text.score_artist(s) {
+                Some(score) => score,
+                None => continue,
+            };
+            found.entry(name).or_insert((score, s, 0)).2 += 1;
+        }
+        let mut singers: Vec<(u32, SingerEntry)> = found
+            .into_iter()
+            .map(|(name, (score, s, songs))| {
+                (
+                    score,
+                    SingerEntry {
+                        name: name.to_string(),
+                        kind: s.kind,
+                        songs,
+                    },
+                )
+            })
+            .collect();
+        singers.sort_by(|(sa, a), (sb, b)| {
+            (Reverse(*sa), Reverse(a.songs), &a.name).cmp(&(
+                Reverse(*sb),
+                Reverse(b.songs),
+                &b.name,
+            ))
+        });
+        singers.into_iter().map(|(_, s)| s).collect()
+    }
+}
+
+/// What was typed, already turned into the keys it is compared with
+enum TextQuery {
+    All,
+    // hanzi or anything else, compared as typed
+    Text(String),
+    // latin letters: as typed or every letter a pinyin initial
+    Latin(String, Vec<String>),
+    // 注音: every symbol an initial, or split into syllables, each the start of a reading
+    Zhuyin(Vec<String>, Vec<String>),
+}
+
+impl TextQuery {
+    fn parse(text: &str) -> Self {
+        let key = normalize(text);
+        if key.is_empty() {
+            return TextQuery::All;
+        }
+        if key.chars().all(|c| is_zhuyin(c) || is_tone(c)) {
+            return TextQuery::Zhuyin(letters(&key), syllables(&key));
+        }
+        if key.chars().all(|c| c.is_ascii_alphanumeric()) {
+            let letters = letters(&key);
+            return TextQuery::Latin(key, letters);
+        }
+        TextQuery::Text(key)
+    }
+
+    fn score(&self, s: &IndexedSong) -> Option<u32> {
+        if let TextQuery::All = self {
+            return Some(0);
+        }
+        let title = self.score_keys(&s.title_key, &s.title_pinyin, &s.title_zhuyin);
+        let artist = self
+            .score_keys(&s.artist_key, &s.artist_pinyin, &s.artist_zhuyin)
+            .saturating_sub(SINGER_PENALTY);
+        Some(title.max(artist)).filter(|score| *score > 0)
+    }
+
+    fn score_artist(&self, s: &IndexedSong) -> Option<u32> {
+        if let TextQuery::All = self {
+            return Some(0);
+        }
+        Some(self.score_keys(&s.artist_key, &s.artist_pinyin, &s.artist_zhuyin))
+            .filter(|score| *score > 0)
+    }
+
+    fn score_keys(&self, key: &str, pinyin: &Readings, zhuyin: &Readings) -> u32 {
+        match self {
+            TextQuery::All => 0,
+            TextQuery::Text(q) => match_text(q, key),
+            TextQuery::Latin(q, letters) => match_text(q, key).max(match_readings(letters, pinyin)),
+            TextQuery::Zhuyin(letters, syllables) => {
+                match_readings(letters, zhuyin).max(match_readings(syllables, zhuyin))
+            }
+        }
+    }
+}
+
+/// Typed text against a title or singer: whole, prefix, inside, then letters in order
+fn match_text(query: &str, key: &str) -> u32 {
+    if key.is_empty() {
+        return 0;
+    }
+    if key == query {
+        return 100;
+    }
+    if key.starts_with(query) {
+        return 80;
+    }
+    if key.contains(query) {
+        return 60;
+    }
+    fuzzy(query, key)
+}
+
+/// Typed initials or syllables against readings, each the start of a reading of one unit: all
+/// of them, the first ones, some inside. No fuzzy, two letters would match almost anything.
+fn match_readings(query: &[String], readings: &Readings) -> u32 {
+    if query.is_empty() || query.len() > readings.len() {
+        return 0;
+    }
+    let starts_at = |first: usize| {
+        query
+            .iter()
+            .zip(&readings[first..])
+            .all(|(typed, unit)| unit.iter().any(|r| r.starts_with(typed.as_str())))
+    };
+    if starts_at(0) {
+        return if query.len() == readings.len() {
+            90
+        } else {
+            75
+        };
+    }
+    if (1..=readings.len() - query.len()).any(starts_at) {
+        return 55;
+    }
+    0
+}
+
+/// Every query char in order with gaps allowed, fewer gaps score higher (1..=40)
+fn fuzzy(query: &str, key: &str) -> u32 {
+    let mut gaps = 0;
+    let mut started = false;
+    let mut chars = key.chars();
+    for q in query.chars() {
+        loop {
+            match chars.next() {
+                Some(c) if c == q => {
+                    started = true;
+                    break;
+                }
+                Some(_) => {
+                    if started {
+                        gaps += 1;
+                    }
+                }
+                None => return 0,
+            }
+        }
+    }
+    40u32.saturating_sub(gaps).max(1)
+}
+
+/// Lowercase, full-width letters and digits to ASCII, no spaces or punctuation
+fn normalize(text: &str) -> String {
+    text.chars()
+        .map(half_width)
+        .filter(|c| is_kept(*c))
+        .flat_map(|c| c.to_lowercase())
+        .collect()
+}
+
+fn half_width(c: char) -> char {
+    match c {
+        '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+        _ => c,
+    }
+}
+
+fn is_kept(c: char) -> bool {
+    c.is_alphanumeric() || is_zhuyin(c) || is_tone(c)
+}
+
+/// What 字數 counts: every CJK character, every latin word or number
+fn units(text: &str) -> Vec<String> {
+    let mut units = Vec::new();
+    let mut word = String::new();
+    // spaces and punctuation only end a word, normalize would glue `Let It Go` together
+    for c in text.chars().map(half_width) {
+        if c.is_ascii_alphanumeric() {
+            word.push(c.to_ascii_lowercase());
+            continue;
+        }
+        if !word.is_empty() {
+            units.push(std::mem::take(&mut word));
+        }
+        if is_kept(c) {
+            units.extend(c.to_lowercase().map(String::from));
+        }
+    }
+    if !word.is_empty() {
+        units.push(word);
+    }
+    units
+}
+
+fn readings(units: &[String], table: &'static HashMap<char, Vec<&'static str>>) -> Readings {
+    units
+        .iter()
+        .filter_map(|u| {
+            let first = u.chars().next()?;
+            // a latin word reads as itself in both schemes
+            if first.is_ascii_alphanumeric() {
+                return Some(vec![Cow::Owned(u.clone())]);
+            }
+            let readings = table.get(&first)?;
+            Some(readings.iter().map(|r| Cow::Borrowed(*r)).collect())
+        })
+        .collect()
+}
+
+fn pinyin_table() -> &'static HashMap<char, Vec<&'static str>> {
+    static TABLE: OnceLock<HashMap<char, Vec<&'static str>>> = OnceLock::new();
+    TABLE.get_or_init(|| reading_table(PINYIN_READINGS))
+}
+
+fn zhuyin_table() -> &'static HashMap<char, Vec<&'static str>> {
+    static TABLE: OnceLock<HashMap<char, Vec<&'static str>>> = OnceLock::new();
+    TABLE.get_or_init(|| reading_table(ZHUYIN_READINGS))
+}
+
+/// Data files hold one line per syllable: the syllable, a space, every character read that
+/// way. A 破音字 is on a line for each reading, the usual one comes first in the file.
+fn reading_table(data: &'static str) -> HashMap<char, Vec<&'static str>> {
+    let mut map: HashMap<char, Vec<&'static str>> = HashMap::new();
+    for line in data.lines().filter(|l| !l.starts_with('#')) {
+        let (syllable, chars) = match line.split_once(' ') {
+            Some(split) => split,
+            None => continue,
+        };
+        for c in chars.chars().filter(|c| !c.is_whitespace()) {
+            let readings = map.entry(c).or_default();
+            if !readings.contains(&syllable) {
+                readings.push(syllable);
+            }
+        }
+    }
+    map
+}
+
+fn is_zhuyin(c: char) -> bool {
+    ('\u{3105}'..='\u{3129}').contains(&c)
+}
+
+fn is_tone(c: char) -> bool {
+    matches!(c, 'ˊ' | 'ˇ' | 'ˋ' | '˙')
+}
+
+/// Every letter or symbol on its own, tones dropped: `xxy`, `ㄒㄒㄩ`
+fn letters(text: &str) -> Vec<String> {
+    text.chars()
+        .filter(|c| !is_tone(*c))
+        .map(String::from)
+        .collect()
+}
+
+/// Full zhuyin split into syllables without tones, `ㄒㄧㄠˇㄒㄧㄥˋ` → `ㄒㄧㄠ` `ㄒㄧㄥ`
+fn syllables(text: &str) -> Vec<String> {
+    // 0 nothing yet, 1 initial, 2 medial, 3 final: a symbol that can't follow starts a syllable
+    let mut stage = 0;
+    let mut out: Vec<String> = Vec::new();
+    for c in text.chars() {
+        if is_tone(c) {
+            stage = 0;
+            continue;
+        }
+        let class = match c {
+            '\u{3105}'..='\u{3119}' => 1,
+            'ㄧ' | 'ㄨ' | 'ㄩ' => 2,
+            _ => 3,
+        };
+        match out.last_mut() {
+            Some(syllable) if stage != 0 && class > stage => syllable.push(c),
+            _ => out.push(c.to_string()),
+        }
+        stage = class;
+    }
+    out
+}
@@ -0,0 +1,108 @@
+/***
+ * @ Mod:       tags
+ * @ Author:    Leon Lin
+ * @ Date:      20261017
+ */
+
+use crate::audio_node::midi_src::is_midi;
+use crate::audio_node::midi_src::synth::MidiSong;
+use crate::lyrics::kar;
+use std::fs::File;
+use std::path::Path;
+use std::time::Duration;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::{MetadataOptions, MetadataRevision, StandardTagKey};
+use symphonia::core::probe::Hint;
+
+/// What the library keeps from a file's tags, anything missing falls back to the file name
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SongTags {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub language: Option<String>,
+    pub duration: Option<Duration>,
+}
+
+/// Read ID3 (mp3), Vorbis comments (ogg, flac), MP4 atoms (m4a) or RIFF INFO (wav). Only the
+/// headers are read, nothing is decoded. MIDI files have their length and the `.kar` headers.
+pub fn read_tags(path: &Path) -> Result<SongTags, String> {
+    if is_midi(path) {
+        return read_midi_tags(path);
+    }
+    let file = File::open(path).map_err(|e| format!("Failed to open file: {}", e))?;
+    let stream = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut hint = Hint::new();
+    if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
+        hint.with_extension(ext);
+    }
+    let mut probed = symphonia::default::get_probe()
+        .format(
+            &hint,
+            stream,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .map_err(|e| format!("Failed to probe file: {}", e))?;
+
+    let mut tags = SongTags::default();
+    // tags in front of the container (ID3v2) first, the container's own win
+    if let Some(metadata) = probed.metadata.get() {
+        if let Some(revision) = metadata.current() {
+            apply_revision(&mut tags, revision);
+        }
+    }
+    if let Some(revision) = probed.format.metadata().current() {
+        apply_revision(&mut tags, revision);
+    }
+
+    if let Some(track) = probed.format.default_track() {
+        let params = &track.codec_params;
+        if let (Some(frames), Some(rate)) = (params.n_frames, params.sample_rate) {
+            tags.duration = Some(Duration::from_secs_f64(frames as f64 / rate as f64));
+        }
+    }
+    Ok(tags)
+}
+
+fn apply_revision(tags: &mut SongTags, revision: &MetadataRevision) {
+    for tag in revision.tags() {
+        let slot = match tag.std_key {
+            Some(StandardTagKey::TrackTitle) => &mut tags.title,
+            Some(StandardTagKey::Artist) => &mut tags.artist,
+            Some(StandardTagKey::Language) => &mut tags.language,
+            _ => continue,
+        };
+        // RIFF INFO values carry their terminating NUL
+        let value = tag.value.to_string();
+        let value = value.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+        if !value.is_empty() {
+            *slot = Some(value.to_string());
+        }
+    }
+}
+
+/// KTV collections are mostly named "歌手 - 歌名", use that when the tags say nothing
+pub fn tags_from_file_name(path: &Path) -> (String, Option<String>) {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    match stem.split_once(" - ") {
+        Some((artist, title)) if !artist.trim().is_empty() && !title.trim().is_empty() => {
+            (title.trim().to_string(), Some(artist.trim().to_string()))
+        }
+        _ => (stem.trim().to_string(), None),
+    }
+}
+
+fn read_midi_tags(path: &Path) -> Result<SongTags, String> {
+    let song = MidiSong::open(path)?;
+    let lyrics = kar::from_midi(&song.texts).unwrap_or_default();
+    Ok(SongTags {
+        title: lyrics.title,
+        artist: lyrics.artist,
+        language: None,
+        duration: Some(song.length),
+    })
+}
@@ -0,0 +1,90 @@
+/***
+ * @ Mod:       watch
+ * @ Author:    Leon Lin
+ * @ Date:      20261017
+ */
+
+use notify::event::EventKind;
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+/// Watches the library folders (inotify on Linux) and hands over what changed once the folder
+/// was quiet for `debounce`, so copying a hundred songs in is one import and not a thousand.
+pub struct FolderWatcher {
+    watcher: RecommendedWatcher,
+    watched: HashSet<PathBuf>,
+}
+
+impl FolderWatcher {
+    /// `on_change` runs on the watcher's own thread with every path touched during the burst
+    pub fn new<F>(debounce: Duration, on_change: F) -> Result<Self, String>
+    where
+        F: FnMut(Vec<PathBuf>) + Send + 'static,
+    {
+        let (tx, rx) = channel();
+        let watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+            // the receiver is gone only after the watcher, nothing left to tell
+            let _ = tx.send(event);
+        })
+        .map_err(|e| e.to_string())?;
+        thread::spawn(move || debounce_events(rx, debounce, on_change));
+        Ok(Self {
+            watcher,
+            watched: HashSet::new(),
+        })
+    }
+
+    pub fn watch(&mut self, folder: &Path) -> Result<(), String> {
+        if self.watched.contains(folder) {
+            return Ok(());
+        }
+        self.watcher
+            .watch(folder, RecursiveMode::Recursive)
+            .map_err(|e| format!("Cannot watch {}: {}", folder.display(), e))?;
+        self.watched.insert(folder.to_path_buf());
+        Ok(())
+    }
+
+    pub fn unwatch(&mut self, folder: &Path) {
+        if self.watched.remove(folder) {
+            // already gone when the folder itself was deleted
+            let _ = self.watcher.unwatch(folder);
+        }
+    }
+}
+
+/// Collect paths until nothing happened for `debounce`, then flush. Ends with the watcher.
+fn debounce_events<F>(rx: Receiver<notify::Result<Event>>, debounce: Duration, mut on_change: F)
+where
+    F: FnMut(Vec<PathBuf>),
+{
+    let mut pending: HashSet<PathBuf> = HashSet::new();
+    loop {
+        let event = if pending.is_empty() {
+            rx.recv().map_err(|_| RecvTimeoutError::Disconnected)
+        } else {
+            rx.recv_timeout(debounce)
+        };
+        match event {
+            Ok(Ok(event)) => {
+                // opening or reading a song changes nothing
+                if matches!(event.kind, EventKind::Access(_)) {
+                    continue;
+                }
+                pending.extend(event.paths);
+            }
+            Ok(Err(e)) => println!("[Library] Watch error: {}", e),
+            Err(RecvTimeoutError::Timeout) => on_change(pending.drain().collect()),
+            Err(RecvTimeoutError::Disconnected) => {
+                if !pending.is_empty() {
+                    on_change(pending.into_iter().collect());
+                }
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,314 @@
+/***
+ * @ Mod:       lyrics
+ * @ Author:    Leon Lin
+ * @ Date:      20261017
+ */
+
+pub mod encoding;
+pub mod kar;
+pub mod ultrastar;
+
+use crate::audio_node::midi_src::is_midi;
+use crate::library::scan;
+use crate::lyrics::encoding::decode_text;
+use crate::lyrics::ultrastar::UltraStarSong;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// a last word nothing ends (no closing tag) is wiped in at most this long
+const LAST_WORD_MAX_MS: u64 = 2000;
+
+/// One sung line, `time_ms` already moved by the file's `[offset:]`. `words` has the enhanced
+/// LRC timing when the file has `<mm:ss.xx>` tags, their texts make up `text`.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct LyricLine {
+    pub time_ms: u64,
+    pub text: String,
+    pub words: Vec<LyricWord>,
+}
+
+/// A word (or a character, or a syllable, whatever the file tags) and when it is sung
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct LyricWord {
+    pub text: String,
+    pub start_ms: u64,
+    pub end_ms: u64,
+}
+
+/// How far the color wipe is: the word being sung and how much of it
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct WordProgress {
+    pub line: usize,
+    pub word: usize,
+    pub start_ms: u64,
+    pub end_ms: u64,
+    // 0 at start_ms, 1 from end_ms until the next word starts
+    pub fraction: f32,
+}
+
+// a word as written, `None` start is the line's stamp, `None` end is up to the next line
+struct RawWord {
+    text: String,
+    start_ms: Option<u64>,
+    end_ms: Option<u64>,
+}
+
+/// A parsed LRC file, lines sorted by time. A line stays on screen until the next one starts,
+/// an empty line clears it.
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct Lyrics {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub lines: Vec<LyricLine>,
+}
+
+impl Lyrics {
+    /// Lenient LRC: `[mm:ss]`, `[mm:ss.x]` to `[mm:ss.xxx]` and `[mm:ss:xx]`, several stamps
+    /// before one text, `[offset:±ms]` anywhere in the file. Lines without a stamp are dropped.
+    /// Enhanced LRC (A2) `<mm:ss.xx>` tags inside a line time its words.
+    pub fn parse(text: &str) -> Self {
+        let mut lyrics = Lyrics::default();
+        let mut offset_ms: i64 = 0;
+        let mut stamped: Vec<(u64, u64, &str)> = Vec::new();
+        for line in text.lines() {
+            let mut rest = line.trim();
+            let mut stamps = Vec::new();
+            while let Some(tag) = rest.strip_prefix('[') {
+                let end = match tag.find(']') {
+                    Some(end) => end,
+                    None => break,
+                };
+                let (tag, after) = (&tag[..end], &tag[end + 1..]);
+                if let Some(ms) = parse_time(tag) {
+                    stamps.push(ms);
+                } else if let Some((key, value)) = tag.split_once(':') {
+                    let value = value.trim();
+                    match key.trim().to_ascii_lowercase().as_str() {
+                        "offset" => offset_ms = value.trim_start_matches('+').parse().unwrap_or(0),
+                        "ti" if !value.is_empty() => lyrics.title = Some(value.to_string()),
+                        "ar" if !value.is_empty() => lyrics.artist = Some(value.to_string()),
+                        _ => {}
+                    }
+                } else {
+                    break;
+                }
+                rest = after.trim_start();
+            }
+            // word tags are written for the first stamp, a repeat moves them along
+            if let Some(first) = stamps.first().copied() {
+                for ms in stamps {
+                    stamped.push((ms, first, rest.trim_end()));
+                }
+            }
+        }
+        // positive offset: the lyrics come earlier
+        let shift = |ms: i64| (ms - offset_ms).max(0) as u64;
+        lyrics.lines = stamped
+            .into_iter()
+            .map(|(ms, first, text)| {
+                let time_ms = shift(ms as i64);
+                let moved = |t: u64| shift(t as i64 + ms as i64 - first as i64);
+                let (text, raw) = parse_words(text);
+                let words = raw
+                    .into_iter()
+                    .map(|w| LyricWord {
+                        text: w.text,
+                        start_ms: w.start_ms.map_or(time_ms, moved),
+                        end_ms: w.end_ms.map_or(u64::MAX, moved),
+                    })
+                    .collect();
+                LyricLine {
+                    time_ms,
+                    text,
+                    words,
+                }
+            })
+            .collect();
+        // stable, lines on the same stamp keep the file's order
+        lyrics.lines.sort_by_key(|l| l.time_ms);
+        lyrics.end_last_words();
+        lyrics
+    }
+
+    /// A line's last word with `u64::MAX` for its end is sung until the next line, at most
+    /// `LAST_WORD_MAX_MS`
+    fn end_last_words(&mut self) {
+        for i in 0..self.lines.len() {
+            let next_line = self.lines.get(i + 1).map(|l| l.time_ms);
+            let last = self.lines[i].words.last_mut();
+            if let Some(word) = last.filter(|w| w.end_ms == u64::MAX) {
+                let cap = word.start_ms + LAST_WORD_MAX_MS;
+                word.end_ms = next_line.map_or(cap, |n| n.min(cap)).max(word.start_ms);
+            }
+        }
+    }
+
+    /// The line being sung at `position`, `None` before the first one
+    pub fn line_at(&self, position: Duration) -> Option<usize> {
+        let ms = position.as_millis() as u64;
+        self.lines
+            .partition_point(|l| l.time_ms <= ms)
+            .checked_sub(1)
+    }
+
+    /// The word being sung at `position`, `None` on lines without word timing and before the
+    /// line's first word
+    pub fn word_at(&self, position: Duration) -> Option<WordProgress> {
+        let ms = position.as_millis() as u64;
+        let line = self.line_at(position)?;
+        let words = &self.lines[line].words;
+        let word = words.partition_point(|w| w.start_ms <= ms).checked_sub(1)?;
+        let w = &words[word];
+        let fraction = match w.end_ms > w.start_ms {
+            true => ((ms - w.start_ms) as f32 / (w.end_ms - w.start_ms) as f32).min(1.0),
+            false => 1.0,
+        };
+        Some(WordProgress {
+            line,
+            word,
+            start_ms: w.start_ms,
+            end_ms: w.end_ms,
+            fraction,
+        })
+    }
+
+    /// When the next line or a word of the current one starts or ends
+    pub fn next_change(&self, position: Duration) -> Option<Duration> {
+        let ms = position.as_millis() as u64;
+        let next = self.lines.partition_point(|l| l.time_ms <= ms);
+        let next_line = self.lines.get(next).map(|l| l.time_ms);
+        let next_word = next
+            .checked_sub(1)
+            .into_iter()
+            .flat_map(|line| &self.lines[line].words)
+            .flat_map(|w| [w.start_ms, w.end_ms])
+            .find(|t| *t > ms);
+        [next_line, next_word]
+            .into_iter()
+            .flatten()
+            .min()
+            .map(Duration::from_millis)
+    }
+}
+
+/// Split a line's text at its `<mm:ss.xx>` tags. Text before the first tag starts with the
+/// line, a tag with no text after it only ends the word before. No tags, no words.
+fn parse_words(line: &str) -> (String, Vec<RawWord>) {
+    let mut words = Vec::new();
+    let mut text = String::new();
+    let mut word = String::new();
+    let mut start = None;
+    let mut rest = line;
+    while let Some(open) = rest.find('<') {
+        let tag = rest[open + 1..]
+            .find('>')
+            .and_then(|close| Some((close, parse_time(&rest[open + 1..open + 1 + close])?)));
+        let (close, ms) = match tag {
+            Some(tag) => tag,
+            None => {
+                // not a time, `<3` stays text
+                word.push_str(&rest[..open + 1]);
+                rest = &rest[open + 1..];
+                continue;
+            }
+        };
+        word.push_str(&rest[..open]);
+        text.push_str(&word);
+        // a space before the first tag is not a word
+        if !word.trim().is_empty() {
+            words.push(RawWord {
+                text: word.clone(),
+                start_ms: start,
+                end_ms: Some(ms),
+            });
+        }
+        word.clear();
+        start = Some(ms);
+        rest = &rest[open + 1 + close + 1..];
+    }
+    word.push_str(rest);
+    if start.is_none() {
+        return (word, Vec::new());
+    }
+    text.push_str(&word);
+    if !word.trim().is_empty() {
+        words.push(RawWord {
+            text: word,
+            start_ms: start,
+            end_ms: None,
+        });
+    }
+    (text, words)
+}
+
+/// `mm:ss`, `mm:ss.xx`, `mm:ss.xxx` or `mm:ss:xx` in ms, minutes may go past 59
+fn parse_time(tag: &str) -> Option<u64> {
+    let (min, rest) = tag.trim().split_once(':')?;
+    let (sec, frac) = match rest.find(['.', ':']) {
+        Some(i) => (&rest[..i], &rest[i + 1..]),
+        None => (rest, ""),
+    };
+    let all_digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    if !all_digits(min) || !all_digits(sec) || (!frac.is_empty() && !all_digits(frac)) {
+        return None;
+    }
+    let frac_ms = match frac.len() {
+        0 => 0,
+        1 => frac.parse::<u64>().ok()? * 100,
+        2 => frac.parse::<u64>().ok()? * 10,
+        _ => frac[..3].parse::<u64>().ok()?,
+    };
+    Some(min.parse::<u64>().ok()? * 60_000 + sec.parse::<u64>().ok()? * 1000 + frac_ms)
+}
+
+/// `song.lrc` next to `song.mp3`, any case of the extension
+pub fn find_sidecar(audio: &Path) -> Option<PathBuf> {
+    scan::find_sidecar(audio, "lrc")
+}
+
+/// Lyrics for a song file, `None` when it has no `.lrc`
+pub fn load_sidecar(audio: &Path) -> Result<Option<Lyrics>, String> {
+    let path = match find_sidecar(audio) {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+    let bytes = fs::read(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(Some(Lyrics::parse(&decode_text(&bytes))))
+}
+
+/// Lyrics for a song file: the `.lrc` next to it first, then the syllables of an UltraStar
+/// `.txt`, then what a MIDI file carries inside. `None` when there are none of them.
+pub fn load_lyrics(audio: &Path) -> Result<Option<Lyrics>, String> {
+    if let Some(lyrics) = load_sidecar(audio)? {
+        return Ok(Some(lyrics));
+    }
+    let notes = ultrastar::load_sidecar(audio)?;
+    lyrics_without_lrc(audio, notes.as_ref())
+}
+
+/// `load_lyrics` for a song whose UltraStar `.txt` is already read, `notes` is what
+/// `ultrastar::load_sidecar` gave
+pub fn load_lyrics_with_notes(
+    audio: &Path,
+    notes: Option<&UltraStarSong>,
+) -> Result<Option<Lyrics>, String> {
+    if let Some(lyrics) = load_sidecar(audio)? {
+        return Ok(Some(lyrics));
+    }
+    lyrics_without_lrc(audio, notes)
+}
+
+fn lyrics_without_lrc(
+    audio: &Path,
+    notes: Option<&UltraStarSong>,
+) -> Result<Option<Lyrics>, String> {
+    if let Some(song) = notes {
+        return Ok(Some(song.lyrics()));
+    }
+    if is_midi(audio) {
+        return kar::load_embedded(audio);
+    }
+    Ok(None)
+}
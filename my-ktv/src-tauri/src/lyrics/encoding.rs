@@ -0,0 +1,48 @@
+/***
+ * @ Mod:       encoding
+ * @ Author:    Leon Lin
+ * @ Date:      20261017
+ */
+
+use encoding_rs::{Encoding, BIG5, GBK, UTF_8};
+
+// 歌詞裡最常見的字，繁簡都有。Big5 的檔案用 GBK 解（或反過來）也常常「解得開」，
+// 只是解出來都是冷僻字，數這些字就分得出來
+const COMMON: &str = "的一是不了我你他她人在有這这們们個个說说來来時时對对麼么為为會会過过\
+還还沒没後后無无開开頭头問问見见給给長长邊边樣样現现經经覺觉讓让門门愛爱心想要就也都好\
+情夢梦走風风雨淚泪天地再等到看最多誰谁能知道那裡里己回憶忆永遠远美麗丽";
+
+/// Lyrics files come in UTF-8 (with or without BOM), UTF-16 with BOM, Big5 from Taiwanese
+/// sites and GBK from mainland ones. Tries them in that order, Big5 against GBK by which one
+/// reads like Chinese.
+pub fn decode_text(bytes: &[u8]) -> String {
+    if let Some((encoding, bom)) = Encoding::for_bom(bytes) {
+        return encoding
+            .decode_without_bom_handling(&bytes[bom..])
+            .0
+            .into_owned();
+    }
+    if let Ok(text) = std::str::from_utf8(bytes) {
+        return text.to_string();
+    }
+    let candidates = [BIG5, GBK].map(|encoding| {
+        let (text, had_errors) = encoding.decode_without_bom_handling(bytes);
+        (had_errors, text.into_owned())
+    });
+    let score = |text: &str| text.chars().filter(|c| COMMON.contains(*c)).count();
+    // ties go to Big5, most of the library is Taiwanese
+    match candidates {
+        [(false, big5), (false, gbk)] if score(&gbk) > score(&big5) => gbk,
+        [(false, big5), _] => big5,
+        [_, (false, gbk)] => gbk,
+        [(_, big5), _] => {
+            // broken either way, keep what can be read
+            let lossy = UTF_8.decode_without_bom_handling(bytes).0.into_owned();
+            if score(&big5) > score(&lossy) {
+                big5
+            } else {
+                lossy
+            }
+        }
+    }
+}
@@ -0,0 +1,112 @@
+/***
+ * @ Mod:       kar
+ * @ Author:    Leon Lin
+ * @ Date:      20261017
+ */
+
+use crate::audio_node::midi_src::synth::{MidiSong, MidiText};
+use crate::lyrics::encoding::decode_text;
+use crate::lyrics::{LyricLine, LyricWord, Lyrics};
+use std::path::Path;
+
+/// Lyrics a MIDI file carries. A `.kar` (the `@KMIDI KARAOKE FILE` header) sings its text
+/// events, other files their lyric events. Every event is a syllable timed by where it sits,
+/// a leading `/` starts a new line and `\` a new verse, so does a `\r` or `\n` anywhere.
+/// `@T` headers are the title, then the artist. `None` when nothing is sung.
+pub fn from_midi(texts: &[MidiText]) -> Option<Lyrics> {
+    let decoded = decode_all(texts);
+    let is_kar = texts
+        .iter()
+        .zip(&decoded)
+        .any(|(t, text)| !t.lyric && text.starts_with("@K"));
+
+    let mut lyrics = Lyrics::default();
+    // the last line still takes syllables, a break closes it
+    let mut open = false;
+    for (event, text) in texts.iter().zip(&decoded) {
+        // text events of a .kar, lyric events of anything else
+        if event.lyric == is_kar {
+            continue;
+        }
+        if is_kar {
+            if let Some(header) = text.strip_prefix('@') {
+                if let Some(value) = header.strip_prefix('T').map(str::trim) {
+                    match (&lyrics.title, &lyrics.artist) {
+                        (None, _) => lyrics.title = Some(value.to_string()),
+                        (Some(_), None) => lyrics.artist = Some(value.to_string()),
+                        _ => {}
+                    }
+                }
+                continue;
+            }
+        }
+        let mut rest = text.as_str();
+        if let Some(after) = rest.strip_prefix(['/', '\\']) {
+            open = false;
+            rest = after;
+        }
+        for (i, part) in rest.split(['\r', '\n']).enumerate() {
+            if i > 0 {
+                open = false;
+            }
+            // a space between two lines is not a word
+            if part.trim().is_empty() && !open {
+                continue;
+            }
+            if !open {
+                lyrics.lines.push(LyricLine {
+                    time_ms: event.time_ms,
+                    text: String::new(),
+                    words: Vec::new(),
+                });
+                open = true;
+            }
+            if let Some(line) = lyrics.lines.last_mut() {
+                line.text.push_str(part);
+                line.words.push(LyricWord {
+                    text: part.to_string(),
+                    start_ms: event.time_ms,
+                    end_ms: u64::MAX,
+                });
+            }
+        }
+    }
+    if lyrics.lines.is_empty() {
+        return None;
+    }
+    // a syllable lasts until the next one of its line
+    for line in &mut lyrics.lines {
+        line.text = line.text.trim_end().to_string();
+        for i in 1..line.words.len() {
+            line.words[i - 1].end_ms = line.words[i].start_ms;
+        }
+    }
+    lyrics.end_last_words();
+    Some(lyrics)
+}
+
+/// Lyrics inside a `.mid` or `.kar` file, `None` when it has none
+pub fn load_embedded(path: &Path) -> Result<Option<Lyrics>, String> {
+    Ok(from_midi(&MidiSong::open(path)?.texts))
+}
+
+/// One syllable is too short to tell Big5 from GBK, so the file's texts are decoded together
+fn decode_all(texts: &[MidiText]) -> Vec<String> {
+    // NUL is never part of a Big5, GBK or UTF-8 character
+    let joined = texts
+        .iter()
+        .map(|t| t.bytes.as_slice())
+        .collect::<Vec<_>>()
+        .join(&0);
+    let decoded: Vec<String> = decode_text(&joined)
+        .split('\0')
+        .map(str::to_string)
+        .collect();
+    if decoded.len() == texts.len() {
+        return decoded;
+    }
+    texts
+        .iter()
+        .map(|t| decode_text(&t.bytes).replace('\0', ""))
+        .collect()
+}
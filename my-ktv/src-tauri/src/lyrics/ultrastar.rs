@@ -0,0 +1,352 @@
+/***
+ * @ Mod:       ultrastar
+ * @ Author:    Leon Lin
+ * @ Date:      20261017
+ */
+
+use crate::library::scan::find_sidecar;
+use crate::lyrics::encoding::decode_text;
+use crate::lyrics::{LyricLine, LyricWord, Lyrics};
+use serde::Serialize;
+use std::fs;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+/// How a note is sung and scored
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NoteKind {
+    // `:`
+    Normal,
+    // `*`, scores double
+    Golden,
+    // `F`, not scored, the pitch means nothing
+    Freestyle,
+    // `R`, scored on timing only
+    Rap,
+    // `G`
+    GoldenRap,
+}
+
+/// One syllable on one pitch, `pitch` in semitones from C4. `text` keeps its spaces, a `~`
+/// holds the syllable before on another pitch.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Note {
+    pub kind: NoteKind,
+    pub start_ms: u64,
+    pub end_ms: u64,
+    pub pitch: i32,
+    pub text: String,
+}
+
+/// The notes between two line breaks
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct NoteLine {
+    pub start_ms: u64,
+    pub end_ms: u64,
+    pub notes: Vec<Note>,
+}
+
+/// What one singer sings. A solo song has one track, a duet P1 then P2.
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct NoteTrack {
+    pub singer: Option<String>,
+    pub lines: Vec<NoteLine>,
+}
+
+/// A parsed UltraStar `.txt`, beats already turned into ms. `audio` is the file `#MP3` (or
+/// `#AUDIO`) names.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct UltraStarSong {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub audio: Option<String>,
+    pub bpm: f64,
+    pub gap_ms: u64,
+    pub tracks: Vec<NoteTrack>,
+}
+
+// a note as written, beats still relative to nothing
+struct RawNote {
+    kind: NoteKind,
+    start: i64,
+    length: i64,
+    pitch: i32,
+    text: String,
+}
+
+impl UltraStarSong {
+    /// `#KEY:value` headers, then `: start length pitch text` notes (`*` golden, `F` freestyle,
+    /// `R` rap, `G` golden rap) and `- beat` line breaks up to `E`. Duets switch singers with
+    /// `P1` / `P2`, `P3` is both. A beat is a quarter of `#BPM`, counted from `#GAP` ms;
+    /// `#RELATIVE:YES` files count each line from the break before it. Broken note lines are
+    /// skipped, a file without `#BPM` or notes is not a song.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let (mut title, mut artist, mut audio) = (None, None, None);
+        let (mut bpm, mut gap_ms, mut relative) = (None, 0.0, false);
+        let mut singers: [Option<String>; 2] = [None, None];
+        // notes line by line for P1 and P2, a solo song only fills P1
+        let mut tracks: [Vec<Vec<RawNote>>; 2] = [Vec::new(), Vec::new()];
+        // whether the last line of a track still takes notes
+        let mut open = [false; 2];
+        let mut singing: &[usize] = &[0];
+        let mut duet = false;
+        let mut offset: i64 = 0;
+        for line in text.lines() {
+            let line = line.trim_start();
+            let mut chars = line.chars();
+            let (first, rest) = match chars.next() {
+                Some(first) => (first, chars.as_str()),
+                None => continue,
+            };
+            match first {
+                '#' => {
+                    let (key, value) = match rest.split_once(':') {
+                        Some((key, value)) => (key.trim().to_ascii_uppercase(), value.trim()),
+                        None => continue,
+                    };
+                    let text = (!value.is_empty()).then(|| value.to_string());
+                    match key.as_str() {
+                        "TITLE" => title = text,
+                        "ARTIST" => artist = text,
+                        "MP3" | "AUDIO" if text.is_some() => audio = text,
+                        "BPM" => bpm = parse_number(value).filter(|b| *b > 0.0),
+                        "GAP" => gap_ms = parse_number(value).unwrap_or(0.0),
+                        "RELATIVE" => relative = value.eq_ignore_ascii_case("yes"),
+                        "DUETSINGERP1" | "P1" => singers[0] = text,
+                        "DUETSINGERP2" | "P2" => singers[1] = text,
+                        _ => {}
+                    }
+                }
+                ':' | '*' | 'F' | 'R' | 'G' => {
+                    let kind = match first {
+                        '*' => NoteKind::Golden,
+                        'F' => NoteKind::Freestyle,
+                        'R' => NoteKind::Rap,
+                        'G' => NoteKind::GoldenRap,
+                        _ => NoteKind::Normal,
+                    };
+                    let (start, length, pitch, text) = match parse_note(rest) {
+                        Some(note) => note,
+                        None => continue,
+                    };
+                    for &track in singing {
+                        if !open[track] {
+                            tracks[track].push(Vec::new());
+                            open[track] = true;
+                        }
+                        if let Some(notes) = tracks[track].last_mut() {
+                            notes.push(RawNote {
+                                kind,
+                                start: start + offset,
+                                length,
+                                pitch,
+                                text: text.to_string(),
+                            });
+                        }
+                    }
+                }
+                '-' => {
+                    for &track in singing {
+                        open[track] = false;
+                    }
+                    // `- 12` or, relative, `- 12 16`: the next line counts from beat 16
+                    if relative {
+                        let beats: Vec<i64> = rest
+                            .split_whitespace()
+                            .map_while(|b| b.parse().ok())
+                            .collect();
+                        offset += beats.get(1).or(beats.first()).copied().unwrap_or(0);
+                    }
+                }
+                'P' => {
+                    duet = true;
+                    singing = match rest.trim() {
+                        "1" => &[0],
+                        "2" => &[1],
+                        "3" => &[0, 1],
+                        _ => continue,
+                    };
+                    // a new singer starts a new line
+                    open = [false; 2];
+                }
+                'E' => break,
+                _ => {}
+            }
+        }
+        let bpm = bpm.ok_or("Not an UltraStar song: no #BPM")?;
+        if tracks.iter().all(|t| t.is_empty()) {
+            return Err("Not an UltraStar song: no notes".to_string());
+        }
+        let beat_ms = 60_000.0 / (bpm * 4.0);
+        let ms = |beat: i64| (gap_ms + beat as f64 * beat_ms).round().max(0.0) as u64;
+        let track_count = if duet { 2 } else { 1 };
+        let tracks = tracks
+            .into_iter()
+            .zip(singers)
+            .take(track_count)
+            .map(|(lines, singer)| NoteTrack {
+                singer,
+                lines: lines
+                    .into_iter()
+                    .map(|notes| {
+                        let notes: Vec<Note> = notes
+                            .into_iter()
+                            .map(|n| Note {
+                                kind: n.kind,
+                                start_ms: ms(n.start),
+                                end_ms: ms(n.start + n.length.max(0)),
+                                pitch: n.pitch,
+                                text: n.text,
+                            })
+                            .collect();
+                        NoteLine {
+                            start_ms: notes.iter().map(|n| n.start_ms).min().unwrap_or(0),
+                            end_ms: notes.iter().map(|n| n.end_ms).max().unwrap_or(0),
+                            notes,
+                        }
+                    })
+                    .collect(),
+            })
+            .collect();
+        Ok(Self {
+            title,
+            artist,
+            audio,
+            bpm,
+            gap_ms: gap_ms.max(0.0) as u64,
+            tracks,
+        })
+    }
+
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let bytes = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Self::parse(&decode_text(&bytes)).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// The song as lyrics: a line for every note line of every track, a word for every note.
+    /// `~` notes only stretch the syllable before, a duet's shared lines show once.
+    pub fn lyrics(&self) -> Lyrics {
+        let mut lines = Vec::new();
+        for line in self.tracks.iter().flat_map(|t| &t.lines) {
+            let mut words: Vec<LyricWord> = Vec::new();
+            for note in &line.notes {
+                let text = note.text.replace('~', "");
+                match words.last_mut() {
+                    Some(word) if text.trim().is_empty() => word.end_ms = note.end_ms,
+                    _ => words.push(LyricWord {
+                        text,
+                        start_ms: note.start_ms,
+                        end_ms: note.end_ms,
+                    }),
+                }
+            }
+            let text: String = words.iter().map(|w| w.text.as_str()).collect();
+            lines.push(LyricLine {
+                time_ms: line.start_ms,
+                text: text.trim().to_string(),
+                words,
+            });
+        }
+        // stable, P1 before P2 on the same beat
+        lines.sort_by_key(|l| l.time_ms);
+        lines.dedup();
+        Lyrics {
+            title: self.title.clone(),
+            artist: self.artist.clone(),
+            lines,
+        }
+    }
+}
+
+/// `start length pitch text`, one space before the text and the rest of it as is
+fn parse_note(line: &str) -> Option<(i64, i64, i32, &str)> {
+    let mut rest = line;
+    let mut fields = [0i64; 3];
+    for field in &mut fields {
+        rest = rest.trim_start();
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        *field = rest[..end].parse().ok()?;
+        rest = &rest[end..];
+    }
+    let text = rest.strip_prefix([' ', '\t']).unwrap_or(rest);
+    Some((fields[0], fields[1], i32::try_from(fields[2]).ok()?, text))
+}
+
+/// German tools write `#BPM:276,5`
+fn parse_number(value: &str) -> Option<f64> {
+    value.trim().replace(',', ".").parse().ok()
+}
+
+/// The UltraStar song of an audio file: `song.txt` next to `song.mp3`, else the `.txt` in the
+/// same folder whose `#MP3` names it, the way UltraStar lays its folders out (`Artist -
+/// Title.txt` next to `Title.mp3`). `None` when there is neither.
+pub fn load_sidecar(audio: &Path) -> Result<Option<UltraStarSong>, String> {
+    if let Some(path) = find_sidecar(audio, "txt") {
+        let bytes = fs::read(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        // a `song.txt` may as well be notes about the song
+        if let Ok(song) = UltraStarSong::parse(&decode_text(&bytes)) {
+            return Ok(Some(song));
+        }
+    }
+    let (name, stem) = match (
+        audio.file_name().and_then(|n| n.to_str()),
+        audio.file_stem().and_then(|s| s.to_str()),
+    ) {
+        (Some(name), Some(stem)) => (name.to_lowercase(), stem.to_lowercase()),
+        _ => return Ok(None),
+    };
+    let entries = match audio.parent().map(fs::read_dir) {
+        Some(Ok(entries)) => entries,
+        _ => return Ok(None),
+    };
+    for path in entries.flatten().map(|e| e.path()) {
+        let is_txt = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| e.eq_ignore_ascii_case("txt"));
+        // a big folder has many songs, only open the ones named after this one
+        let named_after = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .is_some_and(|s| s.to_lowercase().contains(&stem));
+        if !is_txt || !named_after {
+            continue;
+        }
+        if header_audio(&path).map(|a| a.to_lowercase()) == Some(name.clone()) {
+            if let Ok(song) = UltraStarSong::open(&path) {
+                return Ok(Some(song));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// `#MP3` (or `#AUDIO`) of an UltraStar `.txt`, reading stops at the first line that isn't a
+/// `#` tag so the notes are never read
+fn header_audio(path: &Path) -> Option<String> {
+    let mut reader = BufReader::new(File::open(path).ok()?);
+    let mut header = Vec::new();
+    let mut line = Vec::new();
+    while reader.read_until(b'\n', &mut line).ok()? > 0 {
+        // UTF-16 doesn't split on bytes, read it the slow way
+        let utf16 = line.starts_with(b"\xFF\xFE") || line.starts_with(b"\xFE\xFF");
+        if header.is_empty() && utf16 {
+            return UltraStarSong::open(path).ok()?.audio;
+        }
+        let tag = line.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(&line);
+        let tag = tag.trim_ascii_start();
+        if !tag.is_empty() && !tag.starts_with(b"#") {
+            break;
+        }
+        header.append(&mut line);
+    }
+    // the last one wins, as in `UltraStarSong::parse`
+    decode_text(&header).lines().rev().find_map(|line| {
+        let (key, value) = line.trim_start().strip_prefix('#')?.split_once(':')?;
+        let key = key.trim().to_ascii_uppercase();
+        let value = value.trim();
+        (matches!(key.as_str(), "MP3" | "AUDIO") && !value.is_empty()).then(|| value.to_string())
+    })
+}
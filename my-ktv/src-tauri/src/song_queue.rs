@@ -0,0 +1,209 @@
+/***
+ * @ Mod:       song_queue
+ * @ Author:    Leon Lin
+ * @ Date:      20261017
+ */
+
+pub mod rotation;
+
+use crate::song_queue::rotation::{Fifo, RotationHistory, RotationPolicy};
+use serde::Serialize;
+use std::collections::VecDeque;
+
+/// One request in the queue, the id stays the same wherever the song moves
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct QueueEntry {
+    pub id: u64,
+    pub path: String,
+    // songs without a singer share one anonymous turn
+    pub singer: Option<String>,
+    // 插歌, goes ahead of the rotation
+    pub priority: bool,
+}
+
+impl QueueEntry {
+    fn singer_key(&self) -> &str {
+        self.singer.as_deref().unwrap_or("")
+    }
+}
+
+/// What the frontend shows: the song on air and what comes after it, in play order
+#[derive(Serialize, Clone, Debug)]
+pub struct QueueSnapshot {
+    pub current: Option<QueueEntry>,
+    pub upcoming: Vec<QueueEntry>,
+    pub policy: &'static str,
+}
+
+/// Song list of a room. Knows nothing about audio, the engine asks it what to play next.
+/// Songs are kept in request order, the rotation policy decides the play order from that.
+pub struct SongQueue {
+    current: Option<QueueEntry>,
+    upcoming: VecDeque<QueueEntry>,
+    next_id: u64,
+    policy: Box<dyn RotationPolicy>,
+    history: RotationHistory,
+}
+
+impl Default for SongQueue {
+    fn default() -> Self {
+        Self {
+            current: None,
+            upcoming: VecDeque::new(),
+            next_id: 0,
+            policy: Box::new(Fifo),
+            history: RotationHistory::default(),
+        }
+    }
+}
+
+impl SongQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Change the rotation, turns already taken still count
+    pub fn set_policy(&mut self, policy: Box<dyn RotationPolicy>) {
+        self.policy = policy;
+    }
+
+    pub fn policy_name(&self) -> &'static str {
+        self.policy.name()
+    }
+
+    fn entry(&mut self, path: String, singer: Option<String>, priority: bool) -> QueueEntry {
+        self.next_id += 1;
+        let entry = QueueEntry {
+            id: self.next_id,
+            path,
+            singer,
+            priority,
+        };
+        self.history.join(entry.singer_key());
+        entry
+    }
+
+    /// 點歌: add to the end
+    pub fn enqueue(&mut self, path: String, singer: Option<String>) -> QueueEntry {
+        let entry = self.entry(path, singer, false);
+        self.upcoming.push_back(entry.clone());
+        entry
+    }
+
+    /// 插歌: add right after the song on air, ahead of the rotation
+    pub fn insert_top(&mut self, path: String, singer: Option<String>) -> QueueEntry {
+        let entry = self.entry(path, singer, true);
+        self.upcoming.push_front(entry.clone());
+        entry
+    }
+
+    /// Make a queued song a 插歌, it goes behind the other ones already inserted on top
+    pub fn promote(&mut self, id: u64) -> Result<(), String> {
+        let index = self.index_of(id)?;
+        let mut entry = self.upcoming.remove(index).unwrap();
+        entry.priority = true;
+        let at = self.upcoming.iter().take_while(|e| e.priority).count();
+        self.upcoming.insert(at, entry);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, id: u64) -> Result<QueueEntry, String> {
+        let index = self.index_of(id)?;
+        Ok(self.upcoming.remove(index).unwrap())
+    }
+
+    /// Move a queued song to `index` in request order, past the end means last. With a
+    /// rotation this reorders a singer's own songs and breaks ties between singers.
+    pub fn move_to(&mut self, id: u64, index: usize) -> Result<(), String> {
+        let from = self.index_of(id)?;
+        let entry = self.upcoming.remove(from).unwrap();
+        let index = index.min(self.upcoming.len());
+        self.upcoming.insert(index, entry);
+        Ok(())
+    }
+
+    fn index_of(&self, id: u64) -> Result<usize, String> {
+        self.upcoming
+            .iter()
+            .position(|e| e.id == id)
+            .ok_or(format!("Song {} not in queue", id))
+    }
+
+    /// Play order as indices into `upcoming`: 插歌 first as they are, then the rotation played
+    /// forward song by song
+    fn arranged(&self) -> Vec<usize> {
+        let mut history = self.history.clone();
+        let (mut order, mut rest): (Vec<usize>, Vec<usize>) =
+            (0..self.upcoming.len()).partition(|i| self.upcoming[*i].priority);
+        for i in &order {
+            history.record(self.upcoming[*i].singer_key(), self.policy.as_ref());
+        }
+
+        while !rest.is_empty() {
+            // `rest` is in request order, the first of equal ranks wins
+            let mut best = 0;
+            let mut best_rank = f64::INFINITY;
+            for (at, i) in rest.iter().enumerate() {
+                let singer = self.upcoming[*i].singer_key();
+                let rank = self.policy.rank(singer, &history.stats(singer));
+                if rank < best_rank {
+                    best = at;
+                    best_rank = rank;
+                }
+            }
+            let i = rest.remove(best);
+            history.record(self.upcoming[i].singer_key(), self.policy.as_ref());
+            order.push(i);
+        }
+        order
+    }
+
+    /// Put the next song on air, `None` (and nothing on air) when the queue ran dry
+    pub fn advance(&mut self) -> Option<QueueEntry> {
+        self.current = self
+            .arranged()
+            .first()
+            .and_then(|i| self.upcoming.remove(*i));
+        if let Some(entry) = &self.current {
+            self.history
+                .record(entry.singer_key(), self.policy.as_ref());
+        }
+        self.current.clone()
+    }
+
+    /// Put a song on air without queueing it, the upcoming ones stay as they are
+    pub fn play_now(&mut self, path: String) -> QueueEntry {
+        let entry = self.entry(path, None, false);
+        self.current = Some(entry.clone());
+        entry
+    }
+
+    /// The song on air is done (ended or stopped)
+    pub fn finish_current(&mut self) -> Option<QueueEntry> {
+        self.current.take()
+    }
+
+    pub fn current(&self) -> Option<&QueueEntry> {
+        self.current.as_ref()
+    }
+
+    pub fn len(&self) -> usize {
+        self.upcoming.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.upcoming.is_empty()
+    }
+
+    pub fn snapshot(&self) -> QueueSnapshot {
+        QueueSnapshot {
+            current: self.current.clone(),
+            upcoming: self
+                .arranged()
+                .into_iter()
+                .map(|i| self.upcoming[i].clone())
+                .collect(),
+            policy: self.policy.name(),
+        }
+    }
+}
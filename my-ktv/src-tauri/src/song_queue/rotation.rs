@@ -0,0 +1,139 @@
+/***
+ * @ Mod:       rotation
+ * @ Author:    Leon Lin
+ * @ Date:      20261017
+ */
+
+use std::collections::HashMap;
+
+/// What the queue remembers about one singer
+#[derive(Clone, Debug, Default)]
+pub struct SingerStats {
+    pub songs: u32,
+    // turn number of the last song on air, `None` before the first one
+    pub last_turn: Option<u64>,
+    // virtual time for weighted sharing, grows by 1 / weight per song
+    pub vtime: f64,
+}
+
+/// Decides which singer goes next. The queue asks for a rank of every singer with a song
+/// waiting, the lowest one sings and ties go to whoever asked first.
+pub trait RotationPolicy: Send {
+    fn name(&self) -> &'static str;
+
+    fn rank(&self, singer: &str, stats: &SingerStats) -> f64;
+
+    /// Charge a singer for a song going on air, `songs` and `last_turn` are kept by the queue
+    fn on_turn(&self, _singer: &str, _stats: &mut SingerStats) {}
+}
+
+/// Request order, singers don't matter
+pub struct Fifo;
+
+impl RotationPolicy for Fifo {
+    fn name(&self) -> &'static str {
+        "fifo"
+    }
+
+    fn rank(&self, _singer: &str, _stats: &SingerStats) -> f64 {
+        0.0
+    }
+}
+
+/// Whoever waited longest since their last song, new singers first
+pub struct RoundRobin;
+
+impl RotationPolicy for RoundRobin {
+    fn name(&self) -> &'static str {
+        "round_robin"
+    }
+
+    fn rank(&self, _singer: &str, stats: &SingerStats) -> f64 {
+        stats.last_turn.map(|t| t as f64).unwrap_or(-1.0)
+    }
+}
+
+/// Songs in proportion to a weight (default 1), someone with 2 sings twice per round.
+/// Late comers join at the current virtual time instead of catching up on what they missed.
+pub struct Weighted {
+    weights: HashMap<String, f64>,
+}
+
+impl Weighted {
+    pub fn new(weights: HashMap<String, f64>) -> Self {
+        Self { weights }
+    }
+
+    fn weight(&self, singer: &str) -> f64 {
+        // 0 or less would never be charged and sing forever
+        self.weights.get(singer).copied().unwrap_or(1.0).max(0.01)
+    }
+}
+
+impl RotationPolicy for Weighted {
+    fn name(&self) -> &'static str {
+        "weighted"
+    }
+
+    fn rank(&self, _singer: &str, stats: &SingerStats) -> f64 {
+        stats.vtime
+    }
+
+    fn on_turn(&self, singer: &str, stats: &mut SingerStats) {
+        stats.vtime += 1.0 / self.weight(singer);
+    }
+}
+
+/// Turns taken so far, the queue keeps one and plays it forward to plan the order
+#[derive(Clone, Debug, Default)]
+pub struct RotationHistory {
+    turn: u64,
+    // latest virtual time a song on air ran up to, where new singers start
+    clock: f64,
+    singers: HashMap<String, SingerStats>,
+}
+
+impl RotationHistory {
+    pub fn stats(&self, singer: &str) -> SingerStats {
+        match self.singers.get(singer) {
+            Some(stats) => stats.clone(),
+            None => SingerStats {
+                vtime: self.clock,
+                ..SingerStats::default()
+            },
+        }
+    }
+
+    /// Start keeping track of `singer` when the first song is queued, later songs of the ones
+    /// who are on the list already count from there
+    pub fn join(&mut self, singer: &str) {
+        if !self.singers.contains_key(singer) {
+            let stats = self.stats(singer);
+            self.singers.insert(singer.to_string(), stats);
+        }
+    }
+
+    /// `singer` goes on air
+    pub fn record(&mut self, singer: &str, policy: &dyn RotationPolicy) {
+        let mut stats = self.stats(singer);
+        policy.on_turn(singer, &mut stats);
+        self.clock = self.clock.max(stats.vtime);
+        stats.songs += 1;
+        stats.last_turn = Some(self.turn);
+        self.turn += 1;
+        self.singers.insert(singer.to_string(), stats);
+    }
+}
+
+/// Policy by name, as the frontend sends it
+pub fn policy_by_name(
+    name: &str,
+    weights: HashMap<String, f64>,
+) -> Result<Box<dyn RotationPolicy>, String> {
+    match name {
+        "fifo" => Ok(Box::new(Fifo)),
+        "round_robin" => Ok(Box::new(RoundRobin)),
+        "weighted" => Ok(Box::new(Weighted::new(weights))),
+        _ => Err(format!("Unknown rotation policy: {}", name)),
+    }
+}
@@ -0,0 +1,213 @@
+use my_ktv_lib::cdg::{load_sidecar, CdgDecoder, CdgRect, CDG_HEIGHT, CDG_WIDTH, PACKET_SIZE};
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+// 不需要音效卡：手寫 CD+G packet，解出來的畫面、調色盤、更新範圍要對
+
+fn packet(instruction: u8, data: &[u8]) -> [u8; PACKET_SIZE] {
+    let mut packet = [0u8; PACKET_SIZE];
+    packet[0] = 0x09;
+    packet[1] = instruction;
+    packet[4..4 + data.len()].copy_from_slice(data);
+    packet
+}
+
+fn memory_preset(color: u8) -> [u8; PACKET_SIZE] {
+    packet(1, &[color])
+}
+
+/// `rows` are 6 bits each, the leftmost pixel in bit 5
+fn tile(
+    instruction: u8,
+    colors: [u8; 2],
+    row: u8,
+    column: u8,
+    rows: [u8; 12],
+) -> [u8; PACKET_SIZE] {
+    let mut data = vec![colors[0], colors[1], row, column];
+    data.extend_from_slice(&rows);
+    packet(instruction, &data)
+}
+
+/// Colors 0..8 (or 8..16) as 4 bit RGB
+fn load_colors(high: bool, colors: [[u8; 3]; 8]) -> [u8; PACKET_SIZE] {
+    let data: Vec<u8> = colors
+        .iter()
+        .flat_map(|[r, g, b]| [(r << 2) | (g >> 2), ((g & 0x03) << 4) | b])
+        .collect();
+    packet(if high { 31 } else { 30 }, &data)
+}
+
+fn stream(packets: &[[u8; PACKET_SIZE]]) -> Vec<u8> {
+    packets.iter().flatten().copied().collect()
+}
+
+fn temp_folder(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("my_ktv_{}_{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_palette_and_tiles() {
+    let mut cdg = CdgDecoder::new(Vec::new());
+    let mut colors = [[0u8; 3]; 8];
+    colors[1] = [15, 0, 0];
+    colors[2] = [0, 15, 0];
+    colors[3] = [1, 2, 3];
+    cdg.apply(&load_colors(false, colors));
+    cdg.apply(&load_colors(true, [[0, 0, 15]; 8]));
+    assert_eq!(cdg.palette()[1], [255, 0, 0]);
+    assert_eq!(cdg.palette()[2], [0, 255, 0]);
+    assert_eq!(cdg.palette()[3], [17, 34, 51]);
+    assert_eq!(cdg.palette()[15], [0, 0, 255]);
+
+    cdg.apply(&memory_preset(2));
+    assert_eq!(cdg.pixel(0, 0), 2);
+    assert_eq!(cdg.pixel(CDG_WIDTH - 1, CDG_HEIGHT - 1), 2);
+
+    // 第 1 列第 2 格：上半 101010、下半全部底色
+    let mut rows = [0u8; 12];
+    rows[..6].fill(0b101010);
+    cdg.apply(&tile(6, [0, 1], 1, 2, rows));
+    assert_eq!((cdg.pixel(12, 12), cdg.pixel(13, 12)), (1, 0));
+    assert_eq!(cdg.pixel(17, 17), 0);
+    assert_eq!(cdg.pixel(12, 18), 0);
+    assert_eq!(cdg.pixel(18, 12), 2);
+
+    // XOR：1 ^ 3 = 2，0 ^ 3 = 3
+    cdg.apply(&tile(38, [0, 3], 1, 2, [0b100000; 12]));
+    assert_eq!((cdg.pixel(12, 12), cdg.pixel(13, 12)), (2, 0));
+    assert_eq!(cdg.pixel(12, 18), 3);
+
+    // 不在畫面裡的格子、不是圖形的 packet、P/Q 兩個 bit 都不算
+    cdg.apply(&tile(6, [5, 5], 18, 0, [0x3F; 12]));
+    cdg.apply(&tile(6, [5, 5], 0, 50, [0x3F; 12]));
+    let mut other = memory_preset(7);
+    other[0] = 0x08;
+    cdg.apply(&other);
+    assert_eq!(cdg.pixel(0, 0), 2);
+    let mut marked = memory_preset(0xC4);
+    marked[0] |= 0xC0;
+    marked[1] |= 0xC0;
+    cdg.apply(&marked);
+    assert_eq!(cdg.pixel(0, 0), 4);
+}
+
+#[test]
+fn test_updates_merge_dirty_tiles() {
+    let mut cdg = CdgDecoder::new(Vec::new());
+    // 一開始、清畫面之後送整張
+    let update = cdg.take_update().unwrap();
+    assert!(update.full);
+    assert_eq!(update.rects[0].pixels.len(), CDG_WIDTH * CDG_HEIGHT);
+    assert_eq!(cdg.take_update(), None);
+
+    cdg.apply(&tile(6, [0, 1], 3, 4, [0x3F; 12]));
+    cdg.apply(&tile(6, [0, 1], 3, 5, [0; 12]));
+    cdg.apply(&tile(6, [0, 1], 3, 7, [0x3F; 12]));
+    cdg.apply(&tile(6, [0, 1], 4, 0, [0x3F; 12]));
+    let update = cdg.take_update().unwrap();
+    assert!(!update.full);
+    let areas: Vec<_> = update
+        .rects
+        .iter()
+        .map(|r| (r.x, r.y, r.width, r.height))
+        .collect();
+    assert_eq!(areas, [(24, 36, 12, 12), (42, 36, 6, 12), (0, 48, 6, 12)]);
+    let first: &CdgRect = &update.rects[0];
+    assert_eq!(&first.pixels[..12], &[1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(cdg.take_update(), None);
+
+    cdg.apply(&memory_preset(3));
+    cdg.apply(&tile(6, [0, 1], 0, 0, [0; 12]));
+    assert!(cdg.take_update().unwrap().full);
+
+    // 新的透明色只改設定
+    cdg.apply(&packet(28, &[6]));
+    cdg.apply(&tile(6, [0, 1], 0, 0, [0; 12]));
+    let update = cdg.take_update().unwrap();
+    assert_eq!(update.transparent, Some(6));
+}
+
+#[test]
+fn test_border_and_scroll() {
+    let mut cdg = CdgDecoder::new(Vec::new());
+    cdg.apply(&memory_preset(1));
+    cdg.apply(&packet(2, &[9]));
+    assert_eq!(cdg.pixel(0, 0), 9);
+    assert_eq!(cdg.pixel(5, 100), 9);
+    assert_eq!(cdg.pixel(6, 12), 1);
+    assert_eq!(cdg.pixel(CDG_WIDTH - 7, CDG_HEIGHT - 13), 1);
+    assert_eq!(cdg.pixel(CDG_WIDTH - 6, 100), 9);
+    assert_eq!(cdg.take_update().map(|u| u.full), Some(true));
+
+    // 往左捲一格，空出來的補顏色 4，同時細調 3 / 5 像素
+    cdg.apply(&memory_preset(0));
+    cdg.apply(&tile(6, [0, 1], 1, 1, [0x3F; 12]));
+    cdg.take_update();
+    cdg.apply(&packet(20, &[4, 0x20 | 3, 5]));
+    assert_eq!(cdg.pixel(0, 12), 1);
+    assert_eq!(cdg.pixel(6, 12), 0);
+    assert_eq!(cdg.pixel(CDG_WIDTH - 1, 0), 4);
+    let update = cdg.take_update().unwrap();
+    assert!(update.full);
+    assert_eq!((update.offset_x, update.offset_y), (3, 5));
+
+    // copy 捲出去的會從另一邊回來：往上兩格再往下一格
+    cdg.apply(&packet(24, &[0, 0, 0x20]));
+    assert_eq!(cdg.pixel(0, 0), 1);
+    cdg.apply(&packet(24, &[0, 0, 0x20]));
+    assert_eq!(cdg.pixel(0, 0), 0);
+    assert_eq!(cdg.pixel(0, CDG_HEIGHT - 1), 1);
+    cdg.apply(&packet(24, &[0, 0, 0x10]));
+    assert_eq!(cdg.pixel(0, 0), 1);
+    assert_eq!(cdg.pixel(0, CDG_HEIGHT - 1), 0);
+    assert_eq!(
+        (cdg.pixel(CDG_WIDTH - 1, 0), cdg.pixel(CDG_WIDTH - 1, 100)),
+        (4, 4)
+    );
+}
+
+#[test]
+fn test_advance_with_the_audio() {
+    // 300 個 packet 一秒：第 0 個清畫面，第 150 個（0.5 秒）畫一格
+    let mut packets = vec![[0u8; PACKET_SIZE]; 300];
+    packets[0] = memory_preset(2);
+    packets[150] = tile(6, [0, 1], 0, 0, [0x3F; 12]);
+    let mut cdg = CdgDecoder::new(stream(&packets));
+    assert_eq!(cdg.duration(), Duration::from_secs(1));
+
+    cdg.advance_to(Duration::from_millis(100));
+    assert_eq!(cdg.pixel(0, 0), 2);
+    assert!(cdg.take_update().unwrap().full);
+    cdg.advance_to(Duration::from_millis(499));
+    assert_eq!(cdg.take_update(), None);
+    cdg.advance_to(Duration::from_millis(510));
+    assert_eq!(cdg.pixel(0, 0), 1);
+    assert_eq!(cdg.take_update().unwrap().rects.len(), 1);
+    // 檔案結束之後不會出事
+    cdg.advance_to(Duration::from_secs(5));
+    assert_eq!(cdg.take_update(), None);
+
+    // 倒回去要從頭重畫
+    cdg.advance_to(Duration::from_millis(200));
+    assert_eq!(cdg.pixel(0, 0), 2);
+    assert!(cdg.take_update().unwrap().full);
+}
+
+#[test]
+fn test_sidecar_next_to_mp3() {
+    let dir = temp_folder("cdg_sidecar");
+    let mp3 = dir.join("Take On Me.mp3");
+    fs::write(&mp3, b"not really an mp3").unwrap();
+    assert!(load_sidecar(&mp3).unwrap().is_none());
+
+    fs::write(dir.join("Take On Me.CDG"), stream(&[memory_preset(5); 600])).unwrap();
+    let mut cdg = load_sidecar(&mp3).unwrap().unwrap();
+    assert_eq!(cdg.duration(), Duration::from_secs(2));
+    cdg.advance_to(Duration::from_millis(10));
+    assert_eq!(cdg.frame().rects[0].pixels[0], 5);
+}
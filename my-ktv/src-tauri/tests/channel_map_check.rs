@@ -0,0 +1,142 @@
+use my_ktv_lib::audio_node::channel_map::ChannelMatrix;
+use my_ktv_lib::audio_node::file_src::FileSrc;
+use my_ktv_lib::audio_node::graph::AudioGraph;
+use my_ktv_lib::audio_node::offline::OfflineRenderer;
+use my_ktv_lib::audio_node::wav_file_dest::WavFileDest;
+use my_ktv_lib::audio_node::{AudioNode, AudioNodeEnum};
+use std::path::PathBuf;
+
+// 不需要音效卡：不同聲道數的檔案經過 FileSrc 之後，左右聲道要對
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("my_ktv_{}_{}.wav", std::process::id(), name))
+}
+
+/// Constant level per channel, easy to follow through the matrix
+fn write_levels(name: &str, levels: &[f32]) -> PathBuf {
+    let path = temp_path(name);
+    let spec = hound::WavSpec {
+        channels: levels.len() as u16,
+        sample_rate: 48000,
+        bits_per_sample: 32,
+        sample_format: hound::SampleFormat::Float,
+    };
+    let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+    for _ in 0..24000 {
+        for level in levels {
+            writer.write_sample(*level).unwrap();
+        }
+    }
+    writer.finalize().unwrap();
+    path
+}
+
+/// Render `input` to stereo, returns the frame in the middle of the output
+fn render(name: &str, input: PathBuf, matrix: Option<ChannelMatrix>) -> (f32, f32) {
+    let output = temp_path(name);
+    let mut graph = AudioGraph::new();
+    let mut file_src = FileSrc::init();
+    file_src.set_config(input, 48000, 2);
+    file_src.set_channel_matrix(matrix);
+    let src = graph.add_node(AudioNodeEnum::FileSrc(file_src));
+    let mut wav_dest = WavFileDest::init();
+    wav_dest.set_config(output.clone(), 48000, 2);
+    let dest = graph.add_node(AudioNodeEnum::WavFileDest(wav_dest));
+    graph.connect(src, dest).unwrap();
+
+    OfflineRenderer::new(graph, dest).unwrap().run().unwrap();
+
+    let mut reader = hound::WavReader::open(&output).unwrap();
+    let samples: Vec<f32> = reader.samples::<f32>().map(|s| s.unwrap()).collect();
+    let mid = samples.len() / 4 * 2;
+    (samples[mid], samples[mid + 1])
+}
+
+fn close(a: f32, b: f32) -> bool {
+    (a - b).abs() < 0.01
+}
+
+#[test]
+fn test_standard_matrix() {
+    let stereo = ChannelMatrix::standard(2, 2);
+    assert_eq!(
+        stereo,
+        ChannelMatrix::new(vec![vec![1.0, 0.0], vec![0.0, 1.0]]).unwrap()
+    );
+
+    let mono = ChannelMatrix::standard(1, 2);
+    assert_eq!((mono.inputs(), mono.outputs()), (1, 2));
+    assert_eq!(mono.gain(0, 0), 1.0);
+    assert_eq!(mono.gain(1, 0), 1.0);
+
+    // 5.1: L R C LFE Ls Rs
+    let surround = ChannelMatrix::standard(6, 2);
+    let mut out = [0.0; 2];
+    surround.apply(&[0.0, 0.0, 1.0, 1.0, 0.0, 0.0], &mut out);
+    assert!(
+        close(out[0], 0.707) && close(out[1], 0.707),
+        "中置 -3dB: {:?}",
+        out
+    );
+    surround.apply(&[0.0, 0.0, 0.0, 0.0, 1.0, 0.0], &mut out);
+    assert!(
+        close(out[0], 0.707) && out[1] == 0.0,
+        "左環繞只進左聲道: {:?}",
+        out
+    );
+
+    let to_mono = ChannelMatrix::standard(2, 1);
+    let mut out = [0.0; 1];
+    to_mono.apply(&[0.4, 0.2], &mut out);
+    assert!(close(out[0], 0.3));
+}
+
+#[test]
+fn test_invalid_matrix() {
+    assert!(ChannelMatrix::new(vec![]).is_err());
+    assert!(ChannelMatrix::new(vec![vec![1.0, 0.0], vec![1.0]]).is_err());
+}
+
+#[test]
+fn test_stereo_stays_stereo() {
+    let input = write_levels("map_stereo_in", &[0.5, -0.25]);
+    let (left, right) = render("map_stereo_out", input, None);
+    assert!(close(left, 0.5), "left: {}", left);
+    assert!(close(right, -0.25), "right: {}", right);
+}
+
+#[test]
+fn test_mono_upmix() {
+    let input = write_levels("map_mono_in", &[0.4]);
+    let (left, right) = render("map_mono_out", input, None);
+    assert!(close(left, 0.4), "left: {}", left);
+    assert!(close(right, 0.4), "right: {}", right);
+}
+
+#[test]
+fn test_surround_downmix() {
+    let input = write_levels("map_51_in", &[0.1, 0.2, 0.2, 0.5, 0.1, 0.0]);
+    let (left, right) = render("map_51_out", input, None);
+    // L + 0.707 C + 0.707 Ls，LFE 不要
+    assert!(
+        close(left, 0.1 + 0.707 * 0.2 + 0.707 * 0.1),
+        "left: {}",
+        left
+    );
+    assert!(close(right, 0.2 + 0.707 * 0.2), "right: {}", right);
+}
+
+#[test]
+fn test_custom_matrix() {
+    // 左右對調
+    let swap = ChannelMatrix::new(vec![vec![0.0, 1.0], vec![1.0, 0.0]]).unwrap();
+    let input = write_levels("map_swap_in", &[0.5, -0.25]);
+    let (left, right) = render("map_swap_out", input.clone(), Some(swap));
+    assert!(close(left, -0.25), "left: {}", left);
+    assert!(close(right, 0.5), "right: {}", right);
+
+    // 尺寸不合就退回標準的對應
+    let wrong = ChannelMatrix::new(vec![vec![1.0], vec![1.0]]).unwrap();
+    let (left, right) = render("map_wrong_out", input, Some(wrong));
+    assert!(close(left, 0.5) && close(right, -0.25));
+}
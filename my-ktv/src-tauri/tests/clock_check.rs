@@ -0,0 +1,211 @@
+use my_ktv_lib::audio_node::backend::AudioBackend;
+use my_ktv_lib::audio_node::fake_audio_wave_src::FakeAudioWaveSRC;
+use my_ktv_lib::audio_node::file_src::FileSrc;
+use my_ktv_lib::audio_node::graph::{AudioGraph, NodeId};
+use my_ktv_lib::audio_node::mixer::{Mixer, MixerInputControl};
+use my_ktv_lib::audio_node::speaker_dest::SpeakerDest;
+use my_ktv_lib::audio_node::virtual_backend::VirtualBackend;
+use my_ktv_lib::audio_node::{AudioNode, AudioNodeEnum};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+// 不需要音效卡：喇叭的 callback 就是整張圖的時鐘，手動推進虛擬喇叭
+
+const TICK_FRAMES: usize = 480;
+
+fn peak(samples: &[f32]) -> f32 {
+    samples.iter().fold(0.0f32, |acc, s| acc.max(s.abs()))
+}
+
+fn add_speaker(graph: &mut AudioGraph, backend: &Arc<VirtualBackend>) -> NodeId {
+    let dyn_backend: Arc<dyn AudioBackend> = backend.clone();
+    graph.add_node(AudioNodeEnum::SpeakerDest(
+        SpeakerDest::with_backend(dyn_backend.as_ref()).unwrap(),
+    ))
+}
+
+#[test]
+fn test_speaker_clocks_mixer() {
+    let backend = Arc::new(VirtualBackend::manual());
+    let mut graph = AudioGraph::new();
+    let src = graph.add_node(AudioNodeEnum::FakeAudioWaveSRC(FakeAudioWaveSRC::init()));
+    let mixer = graph.add_node(AudioNodeEnum::Mixer(Mixer::new()));
+    let spk = add_speaker(&mut graph, &backend);
+    graph.connect(src, mixer).unwrap();
+    graph.connect(mixer, spk).unwrap();
+
+    graph.start(spk).unwrap();
+    graph.start(mixer).unwrap();
+    graph.start(src).unwrap();
+    for _ in 0..20 {
+        backend.tick(TICK_FRAMES);
+    }
+
+    let captured = backend.captured();
+    assert_eq!(captured.len(), 20 * TICK_FRAMES * 2);
+    // 沒有任何 thread，同一個 tick 裡面上游就算好了，過了 limiter 的 look-ahead 就有聲音
+    let tail = &captured[TICK_FRAMES * 2..];
+    assert!((peak(tail) - 0.1).abs() < 0.01, "peak: {}", peak(tail));
+    assert!(
+        tail.chunks(TICK_FRAMES * 2).all(|block| peak(block) > 0.09),
+        "每個 callback 都要拿到完整的聲音，不能有空洞"
+    );
+
+    graph.stop(src).unwrap();
+    graph.stop(mixer).unwrap();
+    graph.stop(spk).unwrap();
+}
+
+#[test]
+fn test_graph_edits_never_drop_a_tick() {
+    let backend = Arc::new(VirtualBackend::manual());
+    let mut graph = AudioGraph::new();
+    let src = graph.add_node(AudioNodeEnum::FakeAudioWaveSRC(FakeAudioWaveSRC::init()));
+    // 第二個音源靜音，只是拿來一直改 clock 的工作清單
+    let extra = graph.add_node(AudioNodeEnum::FakeAudioWaveSRC(FakeAudioWaveSRC::init()));
+    let mixer = graph.add_node(AudioNodeEnum::Mixer(Mixer::new()));
+    let spk = add_speaker(&mut graph, &backend);
+    graph.connect(src, mixer).unwrap();
+    graph.connect(extra, mixer).unwrap();
+    graph.connect(mixer, spk).unwrap();
+    let muted = MixerInputControl::new();
+    muted.gain().set_muted(true);
+    if let Some(AudioNodeEnum::Mixer(inner)) = graph.node_mut(mixer) {
+        inner.set_input_control(extra, muted).unwrap();
+    }
+
+    graph.start(spk).unwrap();
+    graph.start(mixer).unwrap();
+    graph.start(src).unwrap();
+
+    // callback 在另一個 thread 跑，這邊同時一直開關 extra
+    let done = Arc::new(AtomicBool::new(false));
+    let ticker = {
+        let backend = Arc::clone(&backend);
+        let done = Arc::clone(&done);
+        thread::spawn(move || {
+            for _ in 0..2000 {
+                backend.tick(TICK_FRAMES);
+            }
+            done.store(true, Ordering::Relaxed);
+        })
+    };
+    let mut edits = 0;
+    while !done.load(Ordering::Relaxed) {
+        graph.start(extra).unwrap();
+        graph.stop(extra).unwrap();
+        edits += 1;
+    }
+    ticker.join().unwrap();
+    assert!(edits > 10, "edits: {}", edits);
+
+    let captured = backend.captured();
+    let tail = &captured[TICK_FRAMES * 2..];
+    let silent = tail
+        .chunks(TICK_FRAMES * 2)
+        .filter(|block| peak(block) < 0.09)
+        .count();
+    assert_eq!(silent, 0, "改圖的時候 callback 不能掉 tick");
+
+    graph.stop(src).unwrap();
+    graph.stop(mixer).unwrap();
+    graph.stop(spk).unwrap();
+}
+
+#[test]
+fn test_newest_edit_wins_without_ticks() {
+    let backend = Arc::new(VirtualBackend::manual());
+    let mut graph = AudioGraph::new();
+    let src = graph.add_node(AudioNodeEnum::FakeAudioWaveSRC(FakeAudioWaveSRC::init()));
+    let mixer = graph.add_node(AudioNodeEnum::Mixer(Mixer::new()));
+    let spk = add_speaker(&mut graph, &backend);
+    graph.connect(src, mixer).unwrap();
+    graph.connect(mixer, spk).unwrap();
+    graph.start(spk).unwrap();
+    graph.start(mixer).unwrap();
+
+    // callback 一直沒跑，改了幾百次清單，最後一次一定要被拿到
+    for _ in 0..200 {
+        graph.start(src).unwrap();
+        graph.stop(src).unwrap();
+    }
+    graph.start(src).unwrap();
+    for _ in 0..10 {
+        backend.tick(TICK_FRAMES);
+    }
+
+    let captured = backend.captured();
+    let tail = &captured[TICK_FRAMES * 2..];
+    assert!((peak(tail) - 0.1).abs() < 0.01, "peak: {}", peak(tail));
+
+    graph.stop(src).unwrap();
+    graph.stop(mixer).unwrap();
+    graph.stop(spk).unwrap();
+}
+
+#[test]
+fn test_clock_requires_speaker() {
+    let mut graph = AudioGraph::new();
+    let src = graph.add_node(AudioNodeEnum::FakeAudioWaveSRC(FakeAudioWaveSRC::init()));
+    let mixer = graph.add_node(AudioNodeEnum::Mixer(Mixer::new()));
+    graph.connect(src, mixer).unwrap();
+
+    assert!(graph.start(mixer).is_err(), "下游沒有喇叭就沒有人推它");
+    assert!(graph.start(src).is_err());
+    // 失敗時節點要保持可以重新接線
+    assert!(graph.node(src).unwrap().can_rewire());
+}
+
+#[test]
+fn test_file_src_refills_on_demand() {
+    // 比 ring buffer 長很多的檔案，decoder 要靠 buffer 的通知一直補
+    let path = std::env::temp_dir().join(format!("my_ktv_{}_clock_file.wav", std::process::id()));
+    let spec = hound::WavSpec {
+        channels: 2,
+        sample_rate: 48000,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+    for i in 0..48000 * 3 {
+        let t = i as f32 / 48000.0;
+        let v = (t * 440.0 * 2.0 * std::f32::consts::PI).sin() * 0.3;
+        let v = (v * i16::MAX as f32) as i16;
+        writer.write_sample(v).unwrap();
+        writer.write_sample(v).unwrap();
+    }
+    writer.finalize().unwrap();
+
+    let backend = Arc::new(VirtualBackend::manual());
+    let mut graph = AudioGraph::new();
+    let mut file_src = FileSrc::init();
+    file_src.set_config(path, 48000, 2);
+    let src = graph.add_node(AudioNodeEnum::FileSrc(file_src));
+    let mixer = graph.add_node(AudioNodeEnum::Mixer(Mixer::new()));
+    let spk = add_speaker(&mut graph, &backend);
+    graph.connect(src, mixer).unwrap();
+    graph.connect(mixer, spk).unwrap();
+
+    graph.start(spk).unwrap();
+    graph.start(mixer).unwrap();
+    graph.start(src).unwrap();
+
+    // 等 decoder 先填滿 ring buffer
+    std::thread::sleep(Duration::from_millis(200));
+    let ticks = 48000 * 2 / TICK_FRAMES;
+    for _ in 0..ticks {
+        backend.tick(TICK_FRAMES);
+        std::thread::sleep(Duration::from_millis(1));
+    }
+
+    let captured = backend.captured();
+    // 兩秒後還在播，代表 decoder 被叫醒補資料
+    let last_block = &captured[captured.len() - 4800..];
+    assert!(peak(last_block) > 0.25, "peak: {}", peak(last_block));
+
+    graph.stop(src).unwrap();
+    graph.stop(mixer).unwrap();
+    graph.stop(spk).unwrap();
+}
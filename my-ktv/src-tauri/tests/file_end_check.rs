@@ -0,0 +1,102 @@
+use my_ktv_lib::audio_node::backend::AudioBackend;
+use my_ktv_lib::audio_node::file_src::FileSrc;
+use my_ktv_lib::audio_node::gain::GainNode;
+use my_ktv_lib::audio_node::graph::{AudioGraph, NodeId};
+use my_ktv_lib::audio_node::speaker_dest::SpeakerDest;
+use my_ktv_lib::audio_node::virtual_backend::VirtualBackend;
+use my_ktv_lib::audio_node::{AudioNode, AudioNodeEnum};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+// 不需要音效卡：歌放完要知道，而且要等下游真的拿走最後一段才算
+
+const TICK_FRAMES: usize = 480;
+const RATE: u32 = 48000;
+const FILE_FRAMES: u32 = RATE / 4;
+
+/// A quarter second of constant 0.25 on both channels
+fn write_short(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("my_ktv_{}_{}.wav", std::process::id(), name));
+    let spec = hound::WavSpec {
+        channels: 2,
+        sample_rate: RATE,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+    for _ in 0..FILE_FRAMES * 2 {
+        writer.write_sample(8192i16).unwrap();
+    }
+    writer.finalize().unwrap();
+    path
+}
+
+fn setup(backend: &Arc<VirtualBackend>, name: &str) -> (AudioGraph, NodeId) {
+    let dyn_backend: Arc<dyn AudioBackend> = backend.clone();
+    let mut graph = AudioGraph::new();
+    let mut file_src = FileSrc::init();
+    file_src.set_config(write_short(name), RATE, 2);
+    let src = graph.add_node(AudioNodeEnum::FileSrc(file_src));
+    let gain = graph.add_node(AudioNodeEnum::GainNode(GainNode::init()));
+    let spk = graph.add_node(AudioNodeEnum::SpeakerDest(
+        SpeakerDest::with_backend(dyn_backend.as_ref()).unwrap(),
+    ));
+    graph.connect(src, gain).unwrap();
+    graph.connect(gain, spk).unwrap();
+    graph.start(spk).unwrap();
+    graph.start(gain).unwrap();
+    graph.start(src).unwrap();
+    (graph, src)
+}
+
+fn file_src(graph: &mut AudioGraph, id: NodeId) -> &mut FileSrc {
+    match graph.node_mut(id) {
+        Some(AudioNodeEnum::FileSrc(inner)) => inner,
+        _ => panic!("Wrong type"),
+    }
+}
+
+/// Tick until the source says it is done, returns the audible frames heard by then
+fn play_to_end(backend: &VirtualBackend, graph: &mut AudioGraph, src: NodeId) -> usize {
+    backend.clear_captured();
+    for _ in 0..5000 {
+        if file_src(graph, src).is_finished() {
+            return backend
+                .captured()
+                .chunks(2)
+                .filter(|f| (f[0] - 0.25).abs() < 0.001)
+                .count();
+        }
+        backend.tick(TICK_FRAMES);
+        std::thread::sleep(Duration::from_micros(200));
+    }
+    panic!("never finished");
+}
+
+#[test]
+fn test_finished_after_last_frame() {
+    let backend = Arc::new(VirtualBackend::manual());
+    let (mut graph, src) = setup(&backend, "end");
+    assert!(!file_src(&mut graph, src).is_finished());
+
+    let heard = play_to_end(&backend, &mut graph, src);
+    // 結束的時候整首歌都已經送到喇叭了，一個 frame 都沒少
+    assert_eq!(heard, FILE_FRAMES as usize, "放完前就說結束了");
+
+    // 收掉之後圖上就沒有它了
+    graph.remove_node(src).unwrap();
+    assert!(graph.node(src).is_none());
+}
+
+#[test]
+fn test_seek_after_end_plays_again() {
+    let backend = Arc::new(VirtualBackend::manual());
+    let (mut graph, src) = setup(&backend, "end_seek");
+    play_to_end(&backend, &mut graph, src);
+
+    file_src(&mut graph, src).seek(Duration::ZERO).unwrap();
+    assert!(!file_src(&mut graph, src).is_finished(), "跳回去還是結束");
+    let heard = play_to_end(&backend, &mut graph, src);
+    assert_eq!(heard, FILE_FRAMES as usize);
+}
@@ -0,0 +1,233 @@
+use my_ktv_lib::audio_node::backend::AudioBackend;
+use my_ktv_lib::audio_node::file_src::{FileSrc, SampleSource};
+use my_ktv_lib::audio_node::gain::GainNode;
+use my_ktv_lib::audio_node::graph::{AudioGraph, NodeId};
+use my_ktv_lib::audio_node::speaker_dest::SpeakerDest;
+use my_ktv_lib::audio_node::virtual_backend::VirtualBackend;
+use my_ktv_lib::audio_node::{AudioNode, AudioNodeEnum};
+use rodio::Source;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// 不需要音效卡：檔案裡是一條斜線，從播出來的值就知道播到哪裡
+// 檔案 -> gain -> 喇叭，gain 沒有 look-ahead，喇叭聽到的就是檔案送出來的
+
+const TICK_FRAMES: usize = 480;
+const RATE: u32 = 48000;
+const FILE_FRAMES: u32 = RATE * 2;
+const RAMP_PEAK: f32 = 0.5;
+
+/// Two seconds, both channels rise from 0 to `RAMP_PEAK`
+fn write_ramp(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("my_ktv_{}_{}.wav", std::process::id(), name));
+    let spec = hound::WavSpec {
+        channels: 2,
+        sample_rate: RATE,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+    for i in 0..FILE_FRAMES {
+        let v = (ramp_at(i as f32 / RATE as f32) * 32768.0) as i16;
+        writer.write_sample(v).unwrap();
+        writer.write_sample(v).unwrap();
+    }
+    writer.finalize().unwrap();
+    path
+}
+
+fn ramp_at(secs: f32) -> f32 {
+    secs * RATE as f32 / FILE_FRAMES as f32 * RAMP_PEAK
+}
+
+/// The same ramp made up on the fly, slow to decode and without `try_seek`
+struct SlowRamp {
+    sample: u32,
+}
+
+impl Iterator for SlowRamp {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        let frame = self.sample / 2;
+        if frame >= FILE_FRAMES {
+            return None;
+        }
+        if self.sample.is_multiple_of(RATE / 5) {
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        self.sample += 1;
+        Some((ramp_at(frame as f32 / RATE as f32) * 32768.0) as i16)
+    }
+}
+
+impl Source for SlowRamp {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        2
+    }
+
+    fn sample_rate(&self) -> u32 {
+        RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        Some(Duration::from_secs((FILE_FRAMES / RATE) as u64))
+    }
+}
+
+fn setup(backend: &Arc<VirtualBackend>, path: PathBuf) -> (AudioGraph, NodeId, NodeId) {
+    let dyn_backend: Arc<dyn AudioBackend> = backend.clone();
+    let mut graph = AudioGraph::new();
+    let mut file_src = FileSrc::init();
+    file_src.set_config(path, RATE, 2);
+    let src = graph.add_node(AudioNodeEnum::FileSrc(file_src));
+    let gain = graph.add_node(AudioNodeEnum::GainNode(GainNode::init()));
+    let spk = graph.add_node(AudioNodeEnum::SpeakerDest(
+        SpeakerDest::with_backend(dyn_backend.as_ref()).unwrap(),
+    ));
+    graph.connect(src, gain).unwrap();
+    graph.connect(gain, spk).unwrap();
+    graph.start(spk).unwrap();
+    graph.start(gain).unwrap();
+    (graph, src, spk)
+}
+
+fn file_src(graph: &mut AudioGraph, id: NodeId) -> &mut FileSrc {
+    match graph.node_mut(id) {
+        Some(AudioNodeEnum::FileSrc(inner)) => inner,
+        _ => panic!("Wrong type"),
+    }
+}
+
+/// Tick until `frames` frames of audio came out (underruns are silent and skipped), returns
+/// the left channel
+fn play(backend: &VirtualBackend, frames: usize) -> Vec<f32> {
+    backend.clear_captured();
+    for _ in 0..5000 {
+        let heard = audible_left(&backend.captured());
+        if heard.len() >= frames {
+            return heard;
+        }
+        backend.tick(TICK_FRAMES);
+        std::thread::sleep(Duration::from_micros(200));
+    }
+    panic!("decoder never caught up");
+}
+
+fn audible_left(captured: &[f32]) -> Vec<f32> {
+    captured
+        .chunks(2)
+        .map(|f| f[0])
+        .filter(|s| *s != 0.0)
+        .collect()
+}
+
+#[test]
+fn test_seek_drops_stale_audio() {
+    let backend = Arc::new(VirtualBackend::manual());
+    let (mut graph, src, _spk) = setup(&backend, write_ramp("seek"));
+    graph.start(src).unwrap();
+    play(&backend, 4800);
+
+    file_src(&mut graph, src)
+        .seek(Duration::from_millis(1000))
+        .unwrap();
+    let heard = play(&backend, 4800);
+
+    // 跳過去之後第一個聲音就是一秒的位置，前面的舊資料都被清掉
+    let expected = ramp_at(1.0);
+    assert!((heard[0] - expected).abs() < 0.001, "first: {}", heard[0]);
+    assert!(heard.iter().all(|s| *s >= expected - 0.001), "舊資料還在");
+}
+
+#[test]
+fn test_pause_resume_continues() {
+    let backend = Arc::new(VirtualBackend::manual());
+    let (mut graph, src, _spk) = setup(&backend, write_ramp("pause"));
+    graph.start(src).unwrap();
+    let before = play(&backend, 9600);
+    let last = *before.last().unwrap();
+
+    file_src(&mut graph, src).pause().unwrap();
+    backend.clear_captured();
+    for _ in 0..10 {
+        backend.tick(TICK_FRAMES);
+    }
+    // 暫停時緩衝裡剩下的也不能播
+    assert!(audible_left(&backend.captured()).is_empty(), "暫停還有聲音");
+
+    file_src(&mut graph, src).resume().unwrap();
+    let after = play(&backend, 4800);
+    // 從停下來的地方接著播，沒有跳掉緩衝的那一段
+    assert!((after[0] - last).abs() < 0.0005, "{} -> {}", last, after[0]);
+}
+
+#[test]
+fn test_stopped_source_restarts() {
+    let backend = Arc::new(VirtualBackend::manual());
+    let (mut graph, src, _spk) = setup(&backend, write_ramp("restart"));
+    graph.start(src).unwrap();
+    play(&backend, 9600);
+
+    graph.stop(src).unwrap();
+    graph.start(src).unwrap();
+    let heard = play(&backend, 4800);
+    assert!(heard[0] < 0.001, "要從頭開始: {}", heard[0]);
+}
+
+#[test]
+fn test_seek_before_start() {
+    let backend = Arc::new(VirtualBackend::manual());
+    let (mut graph, src, _spk) = setup(&backend, write_ramp("preseek"));
+    file_src(&mut graph, src)
+        .seek(Duration::from_millis(500))
+        .unwrap();
+    graph.start(src).unwrap();
+
+    let heard = play(&backend, 4800);
+    assert!(
+        (heard[0] - ramp_at(0.5)).abs() < 0.001,
+        "first: {}",
+        heard[0]
+    );
+}
+
+#[test]
+fn test_seek_without_decoder_support() {
+    let backend = Arc::new(VirtualBackend::manual());
+    let (mut graph, src, _spk) = setup(&backend, PathBuf::from("slow_ramp"));
+    file_src(&mut graph, src).set_opener(Arc::new(|_: &Path| {
+        let source: SampleSource = Box::new(SlowRamp { sample: 0 });
+        Ok(source)
+    }));
+    graph.start(src).unwrap();
+    play(&backend, 4800);
+
+    // 從頭解到 1.5 秒要 300ms 以上，seek 不能等它
+    let begin = Instant::now();
+    file_src(&mut graph, src)
+        .seek(Duration::from_millis(1500))
+        .unwrap();
+    assert!(
+        begin.elapsed() < Duration::from_millis(150),
+        "{:?}",
+        begin.elapsed()
+    );
+    let position = file_src(&mut graph, src).position();
+    assert!(
+        (position.as_secs_f32() - 1.5).abs() < 0.001,
+        "{:?}",
+        position
+    );
+
+    // 解到之前什麼都不播，之後第一個聲音就是 1.5 秒的位置
+    let heard = play(&backend, 4800);
+    let expected = ramp_at(1.5);
+    assert!((heard[0] - expected).abs() < 0.001, "first: {}", heard[0]);
+    assert!(heard.iter().all(|s| *s >= expected - 0.001), "舊資料還在");
+}
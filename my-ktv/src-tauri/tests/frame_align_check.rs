@@ -0,0 +1,120 @@
+use my_ktv_lib::audio_node::backend::AudioBackend;
+use my_ktv_lib::audio_node::fake_audio_wave_src::FakeAudioWaveSRC;
+use my_ktv_lib::audio_node::file_src::FileSrc;
+use my_ktv_lib::audio_node::gain::{GainControl, GainNode};
+use my_ktv_lib::audio_node::graph::AudioGraph;
+use my_ktv_lib::audio_node::mixer::Mixer;
+use my_ktv_lib::audio_node::speaker_dest::SpeakerDest;
+use my_ktv_lib::audio_node::virtual_backend::VirtualBackend;
+use my_ktv_lib::audio_node::{AudioNode, AudioNodeEnum};
+use std::sync::Arc;
+
+// 不需要音效卡：每條邊都是完整的 interleaved frame，半個 frame 不能被混進去
+
+const TICK_FRAMES: usize = 480;
+
+#[test]
+fn test_partial_frame_is_not_mixed() {
+    let backend = Arc::new(VirtualBackend::manual());
+    let dyn_backend: Arc<dyn AudioBackend> = backend.clone();
+    let mut graph = AudioGraph::new();
+    let src = graph.add_node(AudioNodeEnum::FakeAudioWaveSRC(FakeAudioWaveSRC::init()));
+    let mixer = graph.add_node(AudioNodeEnum::Mixer(Mixer::new()));
+    let spk = graph.add_node(AudioNodeEnum::SpeakerDest(
+        SpeakerDest::with_backend(dyn_backend.as_ref()).unwrap(),
+    ));
+    graph.connect(mixer, spk).unwrap();
+    graph.start(spk).unwrap();
+    graph.start(mixer).unwrap();
+
+    let Some(AudioNodeEnum::Mixer(inner)) = graph.node_mut(mixer) else {
+        unreachable!()
+    };
+    let (mut producer, _signal) = inner.add_input(src);
+
+    // 只有左聲道到了：這一輪不能讀
+    producer.push(0.5).unwrap();
+    backend.tick(TICK_FRAMES);
+    assert!(backend.captured().iter().all(|s| *s == 0.0));
+
+    // 右聲道補上之後左右要對
+    producer.push(-0.5).unwrap();
+    for _ in 0..TICK_FRAMES * 4 {
+        producer.push(0.5).unwrap();
+        producer.push(-0.5).unwrap();
+    }
+    for _ in 0..4 {
+        backend.tick(TICK_FRAMES);
+    }
+
+    let captured = backend.captured();
+    let left: Vec<f32> = captured.chunks(2).map(|f| f[0]).collect();
+    let right: Vec<f32> = captured.chunks(2).map(|f| f[1]).collect();
+    assert!(left.iter().all(|s| *s >= 0.0), "左聲道不能出現右聲道的資料");
+    assert!(
+        right.iter().all(|s| *s <= 0.0),
+        "右聲道不能出現左聲道的資料"
+    );
+    assert!(left.iter().any(|s| (*s - 0.5).abs() < 1e-3));
+}
+
+#[test]
+fn test_channel_mismatch_is_rejected() {
+    let mut graph = AudioGraph::new();
+    let mut file_src = FileSrc::init();
+    file_src.set_config(std::env::temp_dir().join("unused.wav"), 48000, 1);
+    let mono = graph.add_node(AudioNodeEnum::FileSrc(file_src));
+    let mixer = graph.add_node(AudioNodeEnum::Mixer(Mixer::with_channels(2)));
+
+    assert!(
+        graph.connect(mono, mixer).is_err(),
+        "單聲道不能直接接雙聲道 mixer"
+    );
+
+    let tone = graph.add_node(AudioNodeEnum::FakeAudioWaveSRC(FakeAudioWaveSRC::init()));
+    graph.connect(tone, mixer).unwrap();
+    assert_eq!(graph.edge_channels(tone, mixer), Some(2));
+    assert_eq!(graph.edge_channels(mono, mixer), None);
+}
+
+#[test]
+fn test_gain_follows_upstream_channels() {
+    let mut graph = AudioGraph::new();
+    let mixer = graph.add_node(AudioNodeEnum::Mixer(Mixer::with_channels(1)));
+    let gain = graph.add_node(AudioNodeEnum::GainNode(GainNode::new(
+        GainControl::default(),
+    )));
+    graph.connect(mixer, gain).unwrap();
+
+    assert_eq!(graph.edge_channels(mixer, gain), Some(1));
+    match graph.node(gain) {
+        Some(AudioNodeEnum::GainNode(inner)) => assert_eq!(inner.channels(), 1),
+        _ => panic!("Wrong type"),
+    }
+}
+
+#[test]
+fn test_master_gain_on_mono_speaker() {
+    let backend = Arc::new(VirtualBackend::manual().with_output_config(48000, 1));
+    let dyn_backend: Arc<dyn AudioBackend> = backend.clone();
+    let mut graph = AudioGraph::new();
+    let spk = graph.add_node(AudioNodeEnum::SpeakerDest(
+        SpeakerDest::with_backend(dyn_backend.as_ref()).unwrap(),
+    ));
+    // 跟 AudioState 一樣先接 gain -> 喇叭，再接 mixer -> gain
+    let gain = graph.add_node(AudioNodeEnum::GainNode(
+        GainNode::low_latency(GainControl::default()).with_channels(1),
+    ));
+    graph.connect(gain, spk).unwrap();
+    let mixer = graph.add_node(AudioNodeEnum::Mixer(Mixer::with_channels(1)));
+    graph.connect(mixer, gain).unwrap();
+    assert_eq!(graph.edge_channels(gain, spk), Some(1));
+
+    graph.start(spk).unwrap();
+    graph.start(gain).unwrap();
+    graph.start(mixer).unwrap();
+    for _ in 0..4 {
+        backend.tick(TICK_FRAMES);
+    }
+    assert_eq!(backend.captured().len(), 4 * TICK_FRAMES);
+}
@@ -0,0 +1,136 @@
+use my_ktv_lib::audio_node::file_src::FileSrc;
+use my_ktv_lib::audio_node::gain::{db_to_linear, linear_to_db, GainControl, GainNode, GainRamp};
+use my_ktv_lib::audio_node::graph::AudioGraph;
+use my_ktv_lib::audio_node::offline::OfflineRenderer;
+use my_ktv_lib::audio_node::wav_file_dest::WavFileDest;
+use my_ktv_lib::audio_node::{AudioNode, AudioNodeEnum};
+use std::path::PathBuf;
+
+// 不需要音效卡：FileSrc -> GainNode -> WavFileDest 離線渲染
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("my_ktv_{}_{}.wav", std::process::id(), name))
+}
+
+fn write_sine(name: &str, amplitude: f32) -> PathBuf {
+    let path = temp_path(name);
+    let spec = hound::WavSpec {
+        channels: 2,
+        sample_rate: 48000,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+    for i in 0..24000 {
+        let t = i as f32 / 48000.0;
+        let v = (t * 440.0 * 2.0 * std::f32::consts::PI).sin() * amplitude;
+        let v = (v * i16::MAX as f32) as i16;
+        writer.write_sample(v).unwrap();
+        writer.write_sample(v).unwrap();
+    }
+    writer.finalize().unwrap();
+    path
+}
+
+fn render_through_gain(name: &str, control: GainControl) -> Vec<f32> {
+    render_through(name, GainNode::new(control))
+}
+
+fn render_through(name: &str, gain_node: GainNode) -> Vec<f32> {
+    let input = write_sine(&format!("{}_in", name), 0.5);
+    let output = temp_path(&format!("{}_out", name));
+
+    let mut graph = AudioGraph::new();
+    let mut file_src = FileSrc::init();
+    file_src.set_config(input, 48000, 2);
+    let src = graph.add_node(AudioNodeEnum::FileSrc(file_src));
+    let gain = graph.add_node(AudioNodeEnum::GainNode(gain_node));
+    let mut wav_dest = WavFileDest::init();
+    wav_dest.set_config(output.clone(), 48000, 2);
+    let dest = graph.add_node(AudioNodeEnum::WavFileDest(wav_dest));
+    graph.connect(src, gain).unwrap();
+    graph.connect(gain, dest).unwrap();
+
+    OfflineRenderer::new(graph, dest).unwrap().run().unwrap();
+
+    let mut reader = hound::WavReader::open(&output).unwrap();
+    reader.samples::<f32>().map(|s| s.unwrap()).collect()
+}
+
+fn peak(samples: &[f32]) -> f32 {
+    samples.iter().fold(0.0f32, |acc, s| acc.max(s.abs()))
+}
+
+#[test]
+fn test_db_conversion() {
+    assert!((db_to_linear(0.0) - 1.0).abs() < 1e-6);
+    assert!((db_to_linear(-6.0) - 0.501).abs() < 0.001);
+    assert_eq!(db_to_linear(-120.0), 0.0, "低於下限視為靜音");
+    assert!((linear_to_db(0.5) + 6.02).abs() < 0.01);
+    assert!((linear_to_db(db_to_linear(-12.0)) + 12.0).abs() < 1e-3);
+}
+
+#[test]
+fn test_gain_node_scales_signal() {
+    let control = GainControl::default();
+    control.set_gain_db(-6.0);
+    let samples = render_through_gain("gain_half", control);
+
+    assert!(
+        (samples.len() as i64 - 48000).abs() < 2048,
+        "samples: {}",
+        samples.len()
+    );
+    assert!(
+        (peak(&samples) - 0.25).abs() < 0.02,
+        "peak: {}",
+        peak(&samples)
+    );
+}
+
+#[test]
+fn test_gain_node_mute() {
+    let control = GainControl::default();
+    control.set_muted(true);
+    let samples = render_through_gain("gain_mute", control.clone());
+
+    assert!(!samples.is_empty());
+    assert_eq!(peak(&samples), 0.0, "靜音後應該沒有聲音");
+    // 靜音不影響原本的音量設定
+    assert_eq!(control.gain(), 1.0);
+}
+
+#[test]
+fn test_gain_ramp_is_click_free() {
+    let control = GainControl::default();
+    let mut ramp = GainRamp::new(control.target());
+
+    control.set_muted(true);
+    let mut last = ramp.current();
+    let mut max_step = 0.0f32;
+    for _ in 0..10000 {
+        let next = ramp.next(control.target());
+        max_step = max_step.max((next - last).abs());
+        last = next;
+    }
+
+    // 不會一次跳到目標值，但最後會收斂
+    assert!(max_step < 0.01, "max step: {}", max_step);
+    assert!(last.abs() < 1e-4, "last: {}", last);
+}
+
+#[test]
+fn test_ramp_moves_whole_frames() {
+    // 節點建好之後才調音量，渲染一開始就在 ramp 裡
+    let control = GainControl::default();
+    let node = GainNode::new(control.clone());
+    control.set_gain_db(-12.0);
+    let samples = render_through("gain_ramp_frames", node);
+
+    // 左右聲道一樣，同一個 frame 的兩個 sample 要乘同一個增益
+    let skewed = samples.chunks(2).filter(|f| f[0] != f[1]).count();
+    assert_eq!(skewed, 0);
+    let head = peak(&samples[..200]);
+    let tail = peak(&samples[samples.len() - 4800..]);
+    assert!(head > tail * 1.5, "head: {}, tail: {}", head, tail);
+}
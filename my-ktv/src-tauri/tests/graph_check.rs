@@ -0,0 +1,106 @@
+use my_ktv_lib::audio_node::fake_audio_wave_src::FakeAudioWaveSRC;
+use my_ktv_lib::audio_node::graph::AudioGraph;
+use my_ktv_lib::audio_node::mixer::Mixer;
+use my_ktv_lib::audio_node::speaker_dest::SpeakerDest;
+use my_ktv_lib::audio_node::virtual_backend::VirtualBackend;
+use my_ktv_lib::audio_node::{AudioNode, AudioNodeEnum};
+
+// 不需要音效卡：只用 FakeAudioWaveSRC 與 Mixer 驗證圖的連接邏輯
+
+#[test]
+fn test_fan_out_and_fan_in() {
+    let mut graph = AudioGraph::new();
+    let src = graph.add_node(AudioNodeEnum::FakeAudioWaveSRC(FakeAudioWaveSRC::init()));
+    let mixer_a = graph.add_node(AudioNodeEnum::Mixer(Mixer::new()));
+    let mixer_b = graph.add_node(AudioNodeEnum::Mixer(Mixer::new()));
+    let src_2 = graph.add_node(AudioNodeEnum::FakeAudioWaveSRC(FakeAudioWaveSRC::init()));
+
+    graph.connect(src, mixer_a).unwrap();
+    graph.connect(src, mixer_b).unwrap();
+    graph.connect(src_2, mixer_a).unwrap();
+
+    assert_eq!(graph.outputs(src), vec![mixer_a, mixer_b]);
+    assert_eq!(graph.inputs(mixer_a), vec![src, src_2]);
+    match graph.node(src) {
+        Some(AudioNodeEnum::FakeAudioWaveSRC(inner)) => {
+            assert_eq!(inner.audio_producer.as_ref().unwrap().len(), 2);
+        }
+        _ => panic!("Wrong type"),
+    }
+    match graph.node_mut(mixer_a) {
+        Some(AudioNodeEnum::Mixer(inner)) => assert_eq!(inner.input_count(), 2),
+        _ => panic!("Wrong type"),
+    }
+}
+
+#[test]
+fn test_disconnect_releases_mixer_input() {
+    let mut graph = AudioGraph::new();
+    let src = graph.add_node(AudioNodeEnum::FakeAudioWaveSRC(FakeAudioWaveSRC::init()));
+    let mixer = graph.add_node(AudioNodeEnum::Mixer(Mixer::new()));
+
+    graph.connect(src, mixer).unwrap();
+    graph.disconnect(src, mixer).unwrap();
+
+    assert!(!graph.is_connected(src, mixer));
+    assert!(graph.disconnect(src, mixer).is_err());
+    match graph.node_mut(mixer) {
+        Some(AudioNodeEnum::Mixer(inner)) => assert_eq!(inner.input_count(), 0),
+        _ => panic!("Wrong type"),
+    }
+
+    // 可以重新連接
+    graph.connect(src, mixer).unwrap();
+}
+
+#[test]
+fn test_reject_invalid_edges() {
+    let mut graph = AudioGraph::new();
+    let src = graph.add_node(AudioNodeEnum::FakeAudioWaveSRC(FakeAudioWaveSRC::init()));
+    let mixer_a = graph.add_node(AudioNodeEnum::Mixer(Mixer::new()));
+    let mixer_b = graph.add_node(AudioNodeEnum::Mixer(Mixer::new()));
+
+    graph.connect(mixer_a, mixer_b).unwrap();
+    assert!(graph.connect(mixer_a, mixer_b).is_err(), "重複連接");
+    assert!(graph.connect(mixer_b, mixer_a).is_err(), "形成迴圈");
+    assert!(graph.connect(mixer_a, mixer_a).is_err(), "自己連自己");
+    assert!(graph.connect(mixer_a, src).is_err(), "source 沒有輸入");
+}
+
+#[test]
+fn test_remove_node_drops_edges() {
+    let mut graph = AudioGraph::new();
+    let src = graph.add_node(AudioNodeEnum::FakeAudioWaveSRC(FakeAudioWaveSRC::init()));
+    let mixer = graph.add_node(AudioNodeEnum::Mixer(Mixer::new()));
+    graph.connect(src, mixer).unwrap();
+
+    graph.remove_node(src).unwrap();
+
+    assert!(graph.node(src).is_none());
+    assert!(graph.inputs(mixer).is_empty());
+    match graph.node_mut(mixer) {
+        Some(AudioNodeEnum::Mixer(inner)) => assert_eq!(inner.input_count(), 0),
+        _ => panic!("Wrong type"),
+    }
+}
+
+#[test]
+fn test_running_source_cannot_be_rewired() {
+    let mut graph = AudioGraph::new();
+    let src = graph.add_node(AudioNodeEnum::FakeAudioWaveSRC(FakeAudioWaveSRC::init()));
+    let mixer_a = graph.add_node(AudioNodeEnum::Mixer(Mixer::new()));
+    let mixer_b = graph.add_node(AudioNodeEnum::Mixer(Mixer::new()));
+    // 跑起來需要下游有喇叭的 clock
+    let spk = graph.add_node(AudioNodeEnum::SpeakerDest(
+        SpeakerDest::with_backend(&VirtualBackend::manual()).unwrap(),
+    ));
+    graph.connect(src, mixer_a).unwrap();
+    graph.connect(mixer_a, spk).unwrap();
+
+    graph.start(src).unwrap();
+    assert!(graph.connect(src, mixer_b).is_err());
+    assert!(graph.disconnect(src, mixer_a).is_err());
+    graph.stop(src).unwrap();
+
+    graph.connect(src, mixer_b).unwrap();
+}
@@ -0,0 +1,169 @@
+use encoding_rs::BIG5;
+use midly::num::{u15, u24, u28, u4, u7};
+use midly::{Format, Header, MetaMessage, MidiMessage, Smf, Timing, TrackEvent, TrackEventKind};
+use my_ktv_lib::audio_node::midi_src::synth::MidiSong;
+use my_ktv_lib::lyrics::kar::from_midi;
+use my_ktv_lib::lyrics::load_lyrics;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+// 不需要音效卡：從 .kar / .mid 裡拿出歌詞，時間要跟著 tempo 走
+
+// 480 ticks a beat, at the default 120 bpm a beat is 500 ms
+const PER_BEAT: u16 = 480;
+
+/// Meta events with the gap before each in beats
+fn meta_track(events: Vec<(u32, MetaMessage<'static>)>) -> Vec<TrackEvent<'static>> {
+    let mut track: Vec<_> = events
+        .into_iter()
+        .map(|(beats, message)| TrackEvent {
+            delta: u28::new(beats * PER_BEAT as u32),
+            kind: TrackEventKind::Meta(message),
+        })
+        .collect();
+    track.push(TrackEvent {
+        delta: u28::new(0),
+        kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+    });
+    track
+}
+
+/// One note, `beats` long
+fn note_track(beats: u32) -> Vec<TrackEvent<'static>> {
+    let midi = |delta: u32, message| TrackEvent {
+        delta: u28::new(delta),
+        kind: TrackEventKind::Midi {
+            channel: u4::new(0),
+            message,
+        },
+    };
+    vec![
+        midi(
+            0,
+            MidiMessage::NoteOn {
+                key: u7::new(69),
+                vel: u7::new(100),
+            },
+        ),
+        midi(
+            beats * PER_BEAT as u32,
+            MidiMessage::NoteOff {
+                key: u7::new(69),
+                vel: u7::new(0),
+            },
+        ),
+        TrackEvent {
+            delta: u28::new(0),
+            kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+        },
+    ]
+}
+
+fn midi_bytes(tracks: Vec<Vec<TrackEvent<'static>>>) -> Vec<u8> {
+    let mut smf = Smf::new(Header::new(
+        Format::Parallel,
+        Timing::Metrical(u15::new(PER_BEAT)),
+    ));
+    smf.tracks = tracks;
+    let mut bytes = Vec::new();
+    smf.write_std(&mut bytes).unwrap();
+    bytes
+}
+
+fn big5(text: &str) -> &'static [u8] {
+    Box::leak(BIG5.encode(text).0.into_owned().into_boxed_slice())
+}
+
+/// A Tune 1000 style .kar: headers and syllables as Big5 text events
+fn moon_kar() -> Vec<u8> {
+    let words = meta_track(vec![
+        (0, MetaMessage::Text(b"@KMIDI KARAOKE FILE")),
+        (0, MetaMessage::Text(big5("@T月亮代表我的心"))),
+        (0, MetaMessage::Text(big5("@T鄧麗君"))),
+        (0, MetaMessage::Text(b"@LCHI")),
+        (2, MetaMessage::Text(big5("\\你"))),
+        (1, MetaMessage::Text(big5("問"))),
+        (1, MetaMessage::Text(big5("我"))),
+        (2, MetaMessage::Text(big5("/愛"))),
+        (1, MetaMessage::Text(big5("你"))),
+        // lyric events of a .kar are a copy, they don't count
+        (0, MetaMessage::Lyric(b"dup")),
+    ]);
+    midi_bytes(vec![meta_track(Vec::new()), words, note_track(8)])
+}
+
+fn temp_folder(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("my_ktv_{}_{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_kar_text_events() {
+    let song = MidiSong::parse(&moon_kar()).unwrap();
+    assert_eq!(song.length, Duration::from_secs(4));
+
+    let lyrics = from_midi(&song.texts).unwrap();
+    assert_eq!(lyrics.title.as_deref(), Some("月亮代表我的心"));
+    assert_eq!(lyrics.artist.as_deref(), Some("鄧麗君"));
+    let texts: Vec<_> = lyrics.lines.iter().map(|l| l.text.as_str()).collect();
+    assert_eq!(texts, ["你問我", "愛你"]);
+    assert_eq!(lyrics.lines[1].time_ms, 3000);
+
+    // 一個音節唱到下一個，行尾最多唱到下一行
+    let words: Vec<_> = lyrics.lines[0]
+        .words
+        .iter()
+        .map(|w| (w.text.as_str(), w.start_ms, w.end_ms))
+        .collect();
+    assert_eq!(
+        words,
+        [("你", 1000, 1500), ("問", 1500, 2000), ("我", 2000, 3000)]
+    );
+    assert_eq!(lyrics.lines[1].words[1].end_ms, 3500 + 2000);
+}
+
+#[test]
+fn test_lyric_events_follow_tempo() {
+    // 一般 .mid 用 lyric event，換行寫在音節後面；第二拍起 tempo 快一倍
+    let track = meta_track(vec![
+        (0, MetaMessage::Lyric(b"Hel")),
+        (1, MetaMessage::Lyric(b"lo \r")),
+        (0, MetaMessage::Tempo(u24::new(250_000))),
+        (1, MetaMessage::Lyric(b"world")),
+        (1, MetaMessage::Text(b"not sung")),
+    ]);
+    let song = MidiSong::parse(&midi_bytes(vec![track])).unwrap();
+    assert_eq!(song.length, Duration::from_secs(1));
+
+    let lyrics = from_midi(&song.texts).unwrap();
+    assert_eq!(lyrics.title, None);
+    let lines: Vec<_> = lyrics
+        .lines
+        .iter()
+        .map(|l| (l.time_ms, l.text.as_str()))
+        .collect();
+    assert_eq!(lines, [(0, "Hello"), (750, "world")]);
+    assert_eq!(lyrics.lines[0].words[1].start_ms, 500);
+
+    // 沒有歌詞的 MIDI
+    let song = MidiSong::parse(&midi_bytes(vec![note_track(1)])).unwrap();
+    assert_eq!(from_midi(&song.texts), None);
+}
+
+#[test]
+fn test_lrc_beats_embedded_lyrics() {
+    let dir = temp_folder("kar_lyrics");
+    let kar = dir.join("月亮代表我的心.KAR");
+    fs::write(&kar, moon_kar()).unwrap();
+    let lyrics = load_lyrics(&kar).unwrap().unwrap();
+    assert_eq!(lyrics.lines[0].text, "你問我");
+
+    fs::write(dir.join("月亮代表我的心.lrc"), "[00:01.00]從 lrc 來的\n").unwrap();
+    let lyrics = load_lyrics(&kar).unwrap().unwrap();
+    assert_eq!(lyrics.lines[0].text, "從 lrc 來的");
+
+    assert!(MidiSong::open(&dir.join("月亮代表我的心.lrc")).is_err());
+}
@@ -0,0 +1,178 @@
+use my_ktv_lib::library::catalog::{read_catalog, write_catalog, CatalogEntry};
+use my_ktv_lib::library::Library;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// 不需要音效卡：點歌號碼要固定，歌本可以匯出、匯入到另一台
+
+/// Mono 16-bit WAV, `tenths` of 0.1 s; `tone` makes files with the same length differ
+fn write_wav(path: &Path, tenths: u32, tone: i16) {
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate: 8000,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(path, spec).unwrap();
+    for _ in 0..tenths * 800 {
+        writer.write_sample(tone).unwrap();
+    }
+    writer.finalize().unwrap();
+}
+
+fn temp_folder(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("my_ktv_{}_{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn library_with(dir: &Path) -> Library {
+    let mut library = Library::open_in_memory().unwrap();
+    library.add_folder(dir).unwrap();
+    library.rescan().unwrap();
+    library
+}
+
+fn code_of(library: &Library, title: &str) -> Option<i64> {
+    library.search(title, 0, 10).unwrap().songs[0].code
+}
+
+#[test]
+fn test_codes_are_stable() {
+    let dir = temp_folder("codes");
+    write_wav(&dir.join("周杰倫 - 晴天.wav"), 1, 1);
+    write_wav(&dir.join("田馥甄 - 小幸運.wav"), 1, 2);
+    let mut library = library_with(&dir);
+    let sunny = code_of(&library, "晴天").unwrap();
+    let lucky = code_of(&library, "小幸運").unwrap();
+    assert_ne!(sunny, lucky);
+
+    // 改名、搬資料夾，號碼跟著歌走
+    fs::create_dir_all(dir.join("國語")).unwrap();
+    fs::rename(
+        dir.join("周杰倫 - 晴天.wav"),
+        dir.join("國語/周杰倫 - 晴天.wav"),
+    )
+    .unwrap();
+    library.rescan().unwrap();
+    assert_eq!(code_of(&library, "晴天"), Some(sunny));
+
+    // 改了 tag 內容也一樣
+    write_wav(&dir.join("國語/周杰倫 - 晴天.wav"), 2, 1);
+    library.rescan().unwrap();
+    assert_eq!(code_of(&library, "晴天"), Some(sunny));
+
+    // 刪掉的歌號碼不會給新歌
+    fs::remove_file(dir.join("田馥甄 - 小幸運.wav")).unwrap();
+    write_wav(&dir.join("新歌.wav"), 1, 3);
+    library.rescan().unwrap();
+    let new = code_of(&library, "新歌").unwrap();
+    assert!(new != sunny && new != lucky);
+
+    let song = library.song_by_code(sunny).unwrap().unwrap();
+    assert_eq!(song.title, "晴天");
+    assert!(library.song_by_code(lucky).unwrap().is_none());
+}
+
+#[test]
+fn test_import_by_path_and_conflicts() {
+    let dir = temp_folder("import");
+    let sunny = dir.join("晴天.wav");
+    write_wav(&sunny, 1, 1);
+    write_wav(&dir.join("小幸運.wav"), 1, 2);
+    let mut library = library_with(&dir);
+    let lucky_code = code_of(&library, "小幸運").unwrap();
+
+    let entries = vec![
+        CatalogEntry {
+            code: Some(10001),
+            path: Some(sunny.to_string_lossy().to_string()),
+            artist: Some("周杰倫".to_string()),
+            language: Some("國語".to_string()),
+            ..Default::default()
+        },
+        CatalogEntry {
+            code: Some(10001),
+            path: Some(dir.join("小幸運.wav").to_string_lossy().to_string()),
+            ..Default::default()
+        },
+        CatalogEntry {
+            code: Some(10002),
+            path: Some("/nowhere.wav".to_string()),
+            ..Default::default()
+        },
+    ];
+    let report = library.import_catalog(&entries).unwrap();
+    assert_eq!(report.updated, 1);
+    assert_eq!(report.skipped.len(), 2, "{:?}", report.skipped);
+
+    let song = library.song_by_code(10001).unwrap().unwrap();
+    assert_eq!(song.artist.as_deref(), Some("周杰倫"));
+    assert_eq!(song.language.as_deref(), Some("國語"));
+    // 匯入的資料蓋過 tag，搜尋也找得到
+    assert_eq!(library.search("周杰倫", 0, 10).unwrap().total, 1);
+    assert_eq!(code_of(&library, "小幸運"), Some(lucky_code));
+
+    // 檔案被改過再掃一次，匯入的資料還在
+    write_wav(&sunny, 2, 1);
+    library.rescan().unwrap();
+    let song = library.search("晴天", 0, 10).unwrap().songs[0].clone();
+    assert_eq!(song.artist.as_deref(), Some("周杰倫"));
+}
+
+#[test]
+fn test_export_then_import_on_another_machine() {
+    let dir = temp_folder("export");
+    write_wav(&dir.join("晴天.wav"), 1, 1);
+    write_wav(&dir.join("小幸運.wav"), 1, 2);
+    let mut library = library_with(&dir);
+    library
+        .import_catalog(&[CatalogEntry {
+            code: Some(888),
+            path: Some(dir.join("晴天.wav").to_string_lossy().to_string()),
+            title: Some("晴天 (Live)".to_string()),
+            ..Default::default()
+        }])
+        .unwrap();
+    let exported = library.export_catalog().unwrap();
+    assert_eq!(exported.len(), 2);
+    assert_eq!(exported.last().unwrap().code, Some(888));
+
+    // 另一台：同樣的檔案在別的路徑，靠 hash 對上
+    let other = temp_folder("export_other");
+    fs::copy(dir.join("晴天.wav"), other.join("a.wav")).unwrap();
+    fs::copy(dir.join("小幸運.wav"), other.join("b.wav")).unwrap();
+    for name in ["catalog.csv", "catalog.json"] {
+        let file = other.join(name);
+        write_catalog(&file, &exported).unwrap();
+        let entries = read_catalog(&file).unwrap();
+        assert_eq!(entries, exported, "{}", name);
+
+        let mut copy = library_with(&other);
+        let report = copy.import_catalog(&entries).unwrap();
+        assert_eq!(report.updated, 2, "{:?}", report.skipped);
+        let song = copy.song_by_code(888).unwrap().unwrap();
+        assert_eq!(song.title, "晴天 (Live)");
+        assert!(song.path.ends_with("a.wav"));
+    }
+}
+
+#[test]
+fn test_read_spreadsheet_csv() {
+    let dir = temp_folder("csv");
+    let file = dir.join("歌本.csv");
+    // 欄位順序隨意、只有部分欄位、空格子、逗號在引號裡
+    fs::write(
+        &file,
+        "title,code,artist\n\"Hello, World\",12, \nMoon,,Teresa\n",
+    )
+    .unwrap();
+    let entries = read_catalog(&file).unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].title.as_deref(), Some("Hello, World"));
+    assert_eq!(entries[0].code, Some(12));
+    assert_eq!(entries[0].artist, None);
+    assert_eq!(entries[1].code, None);
+    assert_eq!(entries[1].artist.as_deref(), Some("Teresa"));
+}
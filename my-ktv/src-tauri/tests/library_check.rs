@@ -0,0 +1,187 @@
+use my_ktv_lib::library::scan::FileStamp;
+use my_ktv_lib::library::search::SearchQuery;
+use my_ktv_lib::library::tags::read_tags;
+use my_ktv_lib::library::Library;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// 不需要音效卡：掃資料夾、讀 tag、第二次只讀有變的檔案
+
+const RATE: u32 = 8000;
+
+/// Mono 16-bit WAV of `frames` silent frames, `info` goes into a RIFF INFO list
+fn write_wav(path: &Path, frames: u32, info: &[(&[u8; 4], &str)]) {
+    let mut list = b"INFO".to_vec();
+    for (key, value) in info {
+        let mut bytes = value.as_bytes().to_vec();
+        bytes.push(0);
+        list.extend_from_slice(*key);
+        list.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+        if bytes.len() % 2 == 1 {
+            bytes.push(0);
+        }
+        list.extend_from_slice(&bytes);
+    }
+
+    let mut body = b"WAVE".to_vec();
+    body.extend_from_slice(b"fmt ");
+    body.extend_from_slice(&16u32.to_le_bytes());
+    body.extend_from_slice(&1u16.to_le_bytes());
+    body.extend_from_slice(&1u16.to_le_bytes());
+    body.extend_from_slice(&RATE.to_le_bytes());
+    body.extend_from_slice(&(RATE * 2).to_le_bytes());
+    body.extend_from_slice(&2u16.to_le_bytes());
+    body.extend_from_slice(&16u16.to_le_bytes());
+    if !info.is_empty() {
+        body.extend_from_slice(b"LIST");
+        body.extend_from_slice(&(list.len() as u32).to_le_bytes());
+        body.extend_from_slice(&list);
+    }
+    body.extend_from_slice(b"data");
+    body.extend_from_slice(&(frames * 2).to_le_bytes());
+    body.extend(std::iter::repeat_n(0u8, frames as usize * 2));
+
+    let mut file = b"RIFF".to_vec();
+    file.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    file.extend_from_slice(&body);
+    fs::write(path, file).unwrap();
+}
+
+fn temp_folder(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("my_ktv_{}_{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("國語")).unwrap();
+    dir
+}
+
+#[test]
+fn test_read_riff_tags() {
+    let dir = temp_folder("tags");
+    let path = dir.join("a.wav");
+    write_wav(
+        &path,
+        RATE * 3,
+        &[
+            (b"INAM", "月亮代表我的心"),
+            (b"IART", "鄧麗君"),
+            (b"ILNG", "zh-TW"),
+        ],
+    );
+
+    let tags = read_tags(&path).unwrap();
+    assert_eq!(tags.title.as_deref(), Some("月亮代表我的心"));
+    assert_eq!(tags.artist.as_deref(), Some("鄧麗君"));
+    assert_eq!(tags.language.as_deref(), Some("zh-TW"));
+    assert_eq!(tags.duration, Some(Duration::from_secs(3)));
+}
+
+#[test]
+fn test_scan_list_and_search() {
+    let dir = temp_folder("scan");
+    write_wav(
+        &dir.join("國語/x.wav"),
+        RATE,
+        &[(b"INAM", "小幸運"), (b"IART", "田馥甄")],
+    );
+    // 沒有 tag 就看檔名「歌手 - 歌名」
+    write_wav(&dir.join("周杰倫 - 晴天.wav"), RATE, &[]);
+    write_wav(&dir.join("隨便.wav"), RATE, &[]);
+    fs::write(dir.join("歌詞.txt"), "不是歌").unwrap();
+
+    let mut library = Library::open_in_memory().unwrap();
+    library.add_folder(&dir).unwrap();
+    let report = library.rescan().unwrap();
+    assert_eq!(report.added, 3, "子資料夾也要掃、文字檔不算");
+
+    let page = library.list(0, 2).unwrap();
+    assert_eq!(page.total, 3);
+    assert_eq!(page.songs.len(), 2);
+    let rest = library.list(2, 2).unwrap();
+    assert_eq!(rest.songs.len(), 1);
+
+    let found = library.search("周杰", 0, 10).unwrap();
+    assert_eq!(found.total, 1);
+    assert_eq!(found.songs[0].title, "晴天");
+    assert_eq!(found.songs[0].artist.as_deref(), Some("周杰倫"));
+    assert_eq!(
+        library.search("幸運", 0, 10).unwrap().songs[0]
+            .artist
+            .as_deref(),
+        Some("田馥甄")
+    );
+    // % 不是萬用字元
+    assert_eq!(library.search("%", 0, 10).unwrap().total, 0);
+
+    let song = library.song(found.songs[0].id).unwrap().unwrap();
+    assert_eq!(song, found.songs[0]);
+}
+
+#[test]
+fn test_midi_songs_wait_for_a_soundfont() {
+    let dir = temp_folder("midi");
+    write_wav(&dir.join("晴天.wav"), RATE, &[]);
+    fs::write(dir.join("月亮代表我的心.KAR"), b"MThd").unwrap();
+
+    let mut library = Library::open_in_memory().unwrap();
+    library.add_folder(&dir).unwrap();
+    assert_eq!(library.rescan().unwrap().added, 2);
+    // 沒有 SoundFont 唱不出來，先不列出來
+    let titles = |library: &mut Library| -> Vec<String> {
+        library
+            .list(0, 10)
+            .unwrap()
+            .songs
+            .into_iter()
+            .map(|s| s.title)
+            .collect()
+    };
+    assert_eq!(titles(&mut library), ["晴天"]);
+    assert_eq!(library.search("月亮", 0, 10).unwrap().total, 0);
+    let query = SearchQuery {
+        text: "yl".to_string(),
+        ..Default::default()
+    };
+    assert_eq!(library.find(&query).unwrap().total, 0);
+
+    library.set_midi_playable(true);
+    assert_eq!(titles(&mut library), ["晴天", "月亮代表我的心"]);
+    assert_eq!(library.find(&query).unwrap().total, 1);
+}
+
+#[test]
+fn test_rescan_is_incremental() {
+    let dir = temp_folder("rescan");
+    let keep = dir.join("a - keep.wav");
+    let change = dir.join("b - change.wav");
+    let gone = dir.join("c - gone.wav");
+    for path in [&keep, &change, &gone] {
+        write_wav(path, RATE, &[]);
+    }
+
+    let mut library = Library::open_in_memory().unwrap();
+    library.add_folder(&dir).unwrap();
+    library.rescan().unwrap();
+    let known = library.known_files().unwrap();
+    assert_eq!(known.get(&keep), FileStamp::of(&keep).as_ref());
+
+    // 什麼都沒動，一個檔案都不用重讀
+    let report = library.rescan().unwrap();
+    assert_eq!((report.added, report.updated, report.removed), (0, 0, 0));
+    assert_eq!(report.unchanged, 3);
+
+    write_wav(&change, RATE * 2, &[(b"INAM", "新歌名")]);
+    fs::remove_file(&gone).unwrap();
+    write_wav(&dir.join("d - new.wav"), RATE, &[]);
+    let report = library.rescan().unwrap();
+    assert_eq!(report.added, 1);
+    assert_eq!(report.updated, 1);
+    assert_eq!(report.removed, 1);
+    assert_eq!(report.unchanged, 1);
+    assert_eq!(library.search("新歌名", 0, 10).unwrap().total, 1);
+
+    // 拿掉資料夾，歌也跟著不見
+    library.remove_folder(&dir).unwrap();
+    assert_eq!(library.count().unwrap(), 0);
+    assert!(library.folders().unwrap().is_empty());
+}
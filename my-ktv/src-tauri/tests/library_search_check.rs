@@ -0,0 +1,195 @@
+use my_ktv_lib::library::search::{SearchIndex, SearchQuery, SingerKind};
+use my_ktv_lib::library::{Library, Song};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+// 不需要音效卡：點歌機的查法，注音、拼音首字、字數、歌手
+
+const SONGS: [(&str, &str, &str); 6] = [
+    ("小幸運", "田馥甄", "國語"),
+    ("晴天", "周杰倫", "國語"),
+    ("七里香", "周杰倫", "國語"),
+    ("愛情釀的酒", "紅花樂團", "台語"),
+    ("小手拉大手", "梁靜茹", "國語"),
+    ("Let It Go", "Idina Menzel", "英語"),
+];
+
+fn song(id: i64, title: &str, artist: &str, language: &str) -> Song {
+    Song {
+        id,
+        path: format!("{}.mp3", id),
+        title: title.to_string(),
+        artist: Some(artist.to_string()),
+        language: Some(language.to_string()),
+        duration_ms: None,
+        code: None,
+    }
+}
+
+fn index() -> SearchIndex {
+    let songs = SONGS
+        .iter()
+        .enumerate()
+        .map(|(i, (title, artist, language))| song(i as i64, title, artist, language))
+        .collect();
+    let kinds = HashMap::from([
+        ("田馥甄".to_string(), SingerKind::Female),
+        ("梁靜茹".to_string(), SingerKind::Female),
+        ("周杰倫".to_string(), SingerKind::Male),
+        ("紅花樂團".to_string(), SingerKind::Group),
+    ]);
+    SearchIndex::build(songs, &kinds)
+}
+
+fn titles(index: &SearchIndex, query: SearchQuery) -> Vec<String> {
+    index
+        .search(&query)
+        .songs
+        .into_iter()
+        .map(|s| s.title)
+        .collect()
+}
+
+fn text(text: &str) -> SearchQuery {
+    SearchQuery {
+        text: text.to_string(),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_phonetic_initials() {
+    let index = index();
+    assert_eq!(titles(&index, text("xxy")), ["小幸運"]);
+    assert_eq!(titles(&index, text("ㄒㄒㄩ")), ["小幸運"]);
+    // 打完整的注音也算，每個音節都要對上
+    assert_eq!(titles(&index, text("ㄒㄧㄠˇㄒㄧㄥˋ")), ["小幸運"]);
+    // 開頭對上的排前面
+    assert_eq!(titles(&index, text("x"))[..2], ["小幸運", "小手拉大手"]);
+    // 拼音首字也能找歌手
+    assert_eq!(titles(&index, text("zjl")).len(), 2);
+}
+
+#[test]
+fn test_whole_syllables_narrow_down() {
+    let songs = [
+        ("夏天", "阿杜"),
+        ("小", "某人"),
+        ("長城", "某人"),
+        ("音樂盒", "某人"),
+    ]
+    .iter()
+    .enumerate()
+    .map(|(i, (title, artist))| song(i as i64, title, artist, "國語"))
+    .collect();
+    let index = SearchIndex::build(songs, &HashMap::new());
+    // 只打ㄒ兩首都有，多打幾個符號就只剩小
+    assert_eq!(titles(&index, text("ㄒ")), ["小", "夏天"]);
+    assert_eq!(titles(&index, text("ㄒㄧ")), ["小", "夏天"]);
+    assert_eq!(titles(&index, text("ㄒㄧㄠˇ")), ["小"]);
+    assert_eq!(titles(&index, text("ㄒㄧㄚ")), ["夏天"]);
+    // 破音字每個讀音都找得到
+    assert_eq!(titles(&index, text("ㄔㄤˊㄔㄥˊ")), ["長城"]);
+    assert_eq!(titles(&index, text("ㄓㄔ")), ["長城"]);
+    assert_eq!(titles(&index, text("ylh")), ["音樂盒"]);
+    assert_eq!(titles(&index, text("yyh")), ["音樂盒"]);
+}
+
+#[test]
+fn test_ranking_and_fuzzy() {
+    let index = index();
+    // 整個對上 > 開頭 > 中間
+    assert_eq!(titles(&index, text("晴天")), ["晴天"]);
+    assert_eq!(titles(&index, text("小"))[0], "小幸運");
+    assert_eq!(titles(&index, text("大手")), ["小手拉大手"]);
+    // 漏打字也找得到
+    assert_eq!(titles(&index, text("愛情酒")), ["愛情釀的酒"]);
+    // 全形、大小寫、空白不影響
+    assert_eq!(titles(&index, text("ＬＥＴ it")), ["Let It Go"]);
+    assert!(titles(&index, text("完全沒有")).is_empty());
+}
+
+#[test]
+fn test_filters() {
+    let index = index();
+    let two = SearchQuery {
+        title_len: Some(2),
+        ..Default::default()
+    };
+    assert_eq!(titles(&index, two), ["晴天"]);
+    // 英文一個字算一個
+    let three = SearchQuery {
+        title_len: Some(3),
+        ..Default::default()
+    };
+    assert_eq!(titles(&index, three).len(), 3);
+
+    let taiwanese = SearchQuery {
+        language: Some("台語".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(titles(&index, taiwanese), ["愛情釀的酒"]);
+
+    let female = SearchQuery {
+        text: "x".to_string(),
+        singer_kind: Some(SingerKind::Female),
+        ..Default::default()
+    };
+    assert_eq!(titles(&index, female).len(), 2);
+
+    let jay = SearchQuery {
+        singer: Some("zjl".to_string()),
+        offset: 1,
+        limit: 1,
+        ..Default::default()
+    };
+    let page = index.search(&jay);
+    assert_eq!(page.total, 2);
+    assert_eq!(page.songs.len(), 1);
+}
+
+#[test]
+fn test_browse_singers() {
+    let index = index();
+    let all = index.singers(None, "");
+    assert_eq!(all.len(), 5);
+    assert_eq!(all[0].name, "周杰倫");
+    assert_eq!(all[0].songs, 2);
+
+    let female: Vec<_> = index
+        .singers(Some(SingerKind::Female), "")
+        .into_iter()
+        .map(|s| s.name)
+        .collect();
+    assert_eq!(female, ["梁靜茹", "田馥甄"]);
+    assert_eq!(index.singers(None, "ㄏㄏ")[0].name, "紅花樂團");
+}
+
+#[test]
+fn test_singer_kind_kept_in_library() {
+    let mut library = Library::open_in_memory().unwrap();
+    library
+        .set_singer_kind("周杰倫", Some(SingerKind::Male))
+        .unwrap();
+    library.set_singer_kind("周杰倫", None).unwrap();
+    assert!(library.singers(None, "").unwrap().is_empty());
+}
+
+#[test]
+fn test_fast_enough_to_search_while_typing() {
+    let songs = (0..50_000)
+        .map(|i| {
+            let (title, artist, language) = SONGS[i % SONGS.len()];
+            song(i as i64, &format!("{}{}", title, i), artist, language)
+        })
+        .collect();
+    let index = SearchIndex::build(songs, &HashMap::new());
+
+    let start = Instant::now();
+    for typed in ["x", "xx", "xxy", "ㄒ", "ㄒㄒ", "小幸"] {
+        let page = index.search(&text(typed));
+        assert!(page.total > 0);
+    }
+    // debug build 也要在打字的速度內
+    assert!(start.elapsed() < Duration::from_secs(2));
+}
@@ -0,0 +1,126 @@
+use my_ktv_lib::library::scan::scan_paths;
+use my_ktv_lib::library::watch::FolderWatcher;
+use my_ktv_lib::library::Library;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::channel;
+use std::time::Duration;
+
+// 不需要音效卡：店員把新歌丟進資料夾，不用重新掃描就進歌庫
+
+const DEBOUNCE: Duration = Duration::from_millis(300);
+const WAIT: Duration = Duration::from_secs(5);
+
+/// Mono 16-bit WAV, 0.1 s of silence per `tenths`
+fn write_wav(path: &Path, tenths: u32) {
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate: 8000,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(path, spec).unwrap();
+    for _ in 0..tenths * 800 {
+        writer.write_sample(0i16).unwrap();
+    }
+    writer.finalize().unwrap();
+}
+
+fn temp_folder(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("my_ktv_{}_{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_scan_paths_only_touches_given_paths() {
+    let dir = temp_folder("scan_paths");
+    write_wav(&dir.join("a - old.wav"), 1);
+    let mut library = Library::open_in_memory().unwrap();
+    library.add_folder(&dir).unwrap();
+    library.rescan().unwrap();
+
+    // 整個資料夾丟進來
+    let album = dir.join("新專輯");
+    fs::create_dir_all(&album).unwrap();
+    write_wav(&album.join("b - one.wav"), 1);
+    write_wav(&album.join("b - two.wav"), 1);
+    write_wav(&dir.join("c - unseen.wav"), 1);
+    let report = library.refresh(std::slice::from_ref(&album)).unwrap();
+    assert_eq!(report.added, 2, "沒回報的檔案不去碰");
+    assert_eq!(library.count().unwrap(), 3);
+
+    // 整個資料夾刪掉，裡面的歌一起走
+    fs::remove_dir_all(&album).unwrap();
+    let report = library.refresh(&[album]).unwrap();
+    assert_eq!(report.removed, 2);
+
+    // 資料夾外面的路徑不算
+    let outside = temp_folder("scan_paths_outside");
+    write_wav(&outside.join("x.wav"), 1);
+    let known = library.known_files().unwrap();
+    let result = scan_paths(
+        &library.folders().unwrap(),
+        &known,
+        &[outside.join("x.wav")],
+    );
+    assert!(result.changed.is_empty() && result.missing.is_empty());
+}
+
+#[test]
+fn test_watcher_debounces_a_burst() {
+    let dir = temp_folder("watch_burst");
+    let (tx, rx) = channel();
+    let mut watcher = FolderWatcher::new(DEBOUNCE, move |paths| tx.send(paths).unwrap()).unwrap();
+    watcher.watch(&dir).unwrap();
+
+    for i in 0..5 {
+        write_wav(&dir.join(format!("song{}.wav", i)), 2);
+    }
+    let paths: HashSet<PathBuf> = rx.recv_timeout(WAIT).unwrap().into_iter().collect();
+    for i in 0..5 {
+        assert!(paths.contains(&dir.join(format!("song{}.wav", i))));
+    }
+    assert!(
+        rx.recv_timeout(DEBOUNCE * 2).is_err(),
+        "一次複製進來只匯入一次"
+    );
+}
+
+#[test]
+fn test_watcher_imports_updates_and_removes() {
+    let dir = temp_folder("watch_import");
+    let mut library = Library::open_in_memory().unwrap();
+    library.add_folder(&dir).unwrap();
+    let (tx, rx) = channel();
+    let mut watcher = FolderWatcher::new(DEBOUNCE, move |paths| tx.send(paths).unwrap()).unwrap();
+    watcher.watch(&dir).unwrap();
+
+    let song = dir.join("田馥甄 - 小幸運.wav");
+    write_wav(&song, 1);
+    let report = library.refresh(&rx.recv_timeout(WAIT).unwrap()).unwrap();
+    assert_eq!(report.added, 1);
+    assert_eq!(library.search("小幸運", 0, 10).unwrap().total, 1);
+
+    write_wav(&song, 3);
+    let report = library.refresh(&rx.recv_timeout(WAIT).unwrap()).unwrap();
+    assert_eq!(report.updated, 1);
+
+    // 改名是舊的刪掉、新的加進來
+    let renamed = dir.join("田馥甄 - 小幸運 (Live).wav");
+    fs::rename(&song, &renamed).unwrap();
+    let report = library.refresh(&rx.recv_timeout(WAIT).unwrap()).unwrap();
+    assert_eq!((report.added, report.removed), (1, 1));
+
+    fs::remove_file(&renamed).unwrap();
+    let report = library.refresh(&rx.recv_timeout(WAIT).unwrap()).unwrap();
+    assert_eq!(report.removed, 1);
+    assert_eq!(library.count().unwrap(), 0);
+
+    // 不再看的資料夾，放什麼進去都不會通知
+    watcher.unwatch(&dir);
+    write_wav(&dir.join("late.wav"), 1);
+    assert!(rx.recv_timeout(DEBOUNCE * 3).is_err());
+}
@@ -0,0 +1,133 @@
+use my_ktv_lib::audio_node::file_src::FileSrc;
+use my_ktv_lib::audio_node::graph::AudioGraph;
+use my_ktv_lib::audio_node::limiter::{Limiter, LimiterMeter};
+use my_ktv_lib::audio_node::mixer::Mixer;
+use my_ktv_lib::audio_node::offline::OfflineRenderer;
+use my_ktv_lib::audio_node::wav_file_dest::WavFileDest;
+use my_ktv_lib::audio_node::{AudioNode, AudioNodeEnum};
+use std::path::PathBuf;
+
+// 不需要音效卡：mixer 相加後經過 limiter，檢查不爆音、不抽動
+
+const CEILING: f32 = 0.966;
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("my_ktv_{}_{}.wav", std::process::id(), name))
+}
+
+fn write_sine(name: &str, secs: f32, amplitude: f32) -> PathBuf {
+    let path = temp_path(name);
+    let spec = hound::WavSpec {
+        channels: 2,
+        sample_rate: 48000,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+    for i in 0..(48000.0 * secs) as usize {
+        let t = i as f32 / 48000.0;
+        let v = (t * 440.0 * 2.0 * std::f32::consts::PI).sin() * amplitude;
+        let v = (v * i16::MAX as f32) as i16;
+        writer.write_sample(v).unwrap();
+        writer.write_sample(v).unwrap();
+    }
+    writer.finalize().unwrap();
+    path
+}
+
+/// Mix `inputs` offline, returns the left channel and the limiter meter
+fn mix(name: &str, inputs: &[PathBuf]) -> (Vec<f32>, LimiterMeter) {
+    let output = temp_path(name);
+    let mut graph = AudioGraph::new();
+    let mixer = Mixer::with_channels(2);
+    let meter = mixer.limiter_meter();
+    let mixer = graph.add_node(AudioNodeEnum::Mixer(mixer));
+    let mut wav_dest = WavFileDest::init();
+    wav_dest.set_config(output.clone(), 48000, 2);
+    let dest = graph.add_node(AudioNodeEnum::WavFileDest(wav_dest));
+    graph.connect(mixer, dest).unwrap();
+    for input in inputs {
+        let mut file_src = FileSrc::init();
+        file_src.set_config(input.clone(), 48000, 2);
+        let src = graph.add_node(AudioNodeEnum::FileSrc(file_src));
+        graph.connect(src, mixer).unwrap();
+    }
+
+    OfflineRenderer::new(graph, dest).unwrap().run().unwrap();
+
+    let mut reader = hound::WavReader::open(&output).unwrap();
+    let samples: Vec<f32> = reader.samples::<f32>().map(|s| s.unwrap()).collect();
+    (samples.chunks(2).map(|f| f[0]).collect(), meter)
+}
+
+fn peak(samples: &[f32]) -> f32 {
+    samples.iter().fold(0.0f32, |acc, s| acc.max(s.abs()))
+}
+
+#[test]
+fn test_limiter_holds_ceiling() {
+    let a = write_sine("limit_a", 0.5, 0.7);
+    let b = write_sine("limit_b", 0.5, 0.7);
+    let (left, meter) = mix("limit_out", &[a, b]);
+
+    // 1.4 的總和被壓在天花板以下，而且不是硬切
+    assert!(peak(&left) <= CEILING + 1e-4, "peak: {}", peak(&left));
+    assert!(peak(&left) > 0.9, "peak: {}", peak(&left));
+    assert!(
+        (left.len() as i64 - 24000).abs() < 1024,
+        "frames: {}",
+        left.len()
+    );
+    // 正負兩邊都算：440Hz 半秒有 440 個波峰，每個波峰頂多一兩個 sample 碰到天花板；
+    // 硬切的話每個波峰有一大段被削平
+    let clipped = left.iter().filter(|s| s.abs() >= CEILING).count();
+    assert!(clipped < 2 * 440, "clipped: {}", clipped);
+    assert!(
+        meter.gain_reduction_db() > 2.0,
+        "gr: {}",
+        meter.gain_reduction_db()
+    );
+}
+
+#[test]
+fn test_quiet_mix_is_untouched() {
+    let a = write_sine("quiet_a", 0.5, 0.3);
+    let (left, meter) = mix("quiet_out", &[a]);
+
+    assert!((peak(&left) - 0.3).abs() < 0.01, "peak: {}", peak(&left));
+    assert_eq!(meter.gain_reduction_db(), 0.0);
+}
+
+#[test]
+fn test_silent_input_does_not_duck_music() {
+    let music = write_sine("duck_music", 1.0, 0.3);
+    let mic = write_sine("duck_mic", 0.25, 0.0);
+    let (left, _) = mix("duck_out", &[music, mic]);
+
+    // 以前有資料的 input 數變了，音樂音量就會跳
+    let head = &left[..12000];
+    let tail = &left[24000..];
+    assert!((peak(head) - 0.3).abs() < 0.01, "head: {}", peak(head));
+    assert!((peak(tail) - 0.3).abs() < 0.01, "tail: {}", peak(tail));
+}
+
+#[test]
+fn test_limiter_keeps_every_frame() {
+    let mut limiter = Limiter::new(2);
+    let mut out = Vec::new();
+    for i in 0..100 {
+        limiter.frame_mut().fill(i as f32 / 1000.0);
+        if limiter.process_frame() {
+            out.push(limiter.frame()[0]);
+        }
+    }
+    while limiter.buffered_frames() > 0 {
+        if limiter.flush_frame() {
+            out.push(limiter.frame()[0]);
+        }
+    }
+
+    // look-ahead 只延遲，不多也不少
+    let expected: Vec<f32> = (0..100).map(|i| i as f32 / 1000.0).collect();
+    assert_eq!(out, expected);
+}
@@ -0,0 +1,168 @@
+use encoding_rs::{BIG5, GBK};
+use my_ktv_lib::lyrics::encoding::decode_text;
+use my_ktv_lib::lyrics::{find_sidecar, load_sidecar, Lyrics};
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+// 不需要音效卡：讀 .lrc、對時間、認得 Big5 和 GBK
+
+const MOON: &str = "[ti:月亮代表我的心]
+[ar:鄧麗君]
+[00:15.20]你問我愛你有多深
+[00:21.5]我愛你有幾分
+[00:28.123][01:30.00]我的情也真 我的愛也真
+[01:02:50]月亮代表我的心
+這行沒有時間
+[01:40.00]
+";
+
+fn temp_folder(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("my_ktv_{}_{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn times(lyrics: &Lyrics) -> Vec<u64> {
+    lyrics.lines.iter().map(|l| l.time_ms).collect()
+}
+
+#[test]
+fn test_parse_stamps_and_tags() {
+    let lyrics = Lyrics::parse(MOON);
+    assert_eq!(lyrics.title.as_deref(), Some("月亮代表我的心"));
+    assert_eq!(lyrics.artist.as_deref(), Some("鄧麗君"));
+    // 一行兩個時間拆成兩行，照時間排
+    assert_eq!(
+        times(&lyrics),
+        [15_200, 21_500, 28_123, 62_500, 90_000, 100_000]
+    );
+    assert_eq!(lyrics.lines[4].text, "我的情也真 我的愛也真");
+    assert_eq!(lyrics.lines[5].text, "", "空行用來清掉畫面");
+}
+
+#[test]
+fn test_offset_tag() {
+    // 正的 offset 歌詞提早，寫在哪裡都算
+    let early = Lyrics::parse("[00:10.00]a\n[00:00.20]b\n[offset:+500]\n");
+    assert_eq!(times(&early), [0, 9_500]);
+    let late = Lyrics::parse("[offset:-250]\n[00:10.00]a\n");
+    assert_eq!(times(&late), [10_250]);
+}
+
+#[test]
+fn test_line_at_position() {
+    let lyrics = Lyrics::parse(MOON);
+    assert_eq!(lyrics.line_at(Duration::from_secs(1)), None);
+    assert_eq!(lyrics.line_at(Duration::from_millis(15_200)), Some(0));
+    assert_eq!(lyrics.line_at(Duration::from_secs(25)), Some(1));
+    // 往回跳也對
+    assert_eq!(lyrics.line_at(Duration::from_secs(16)), Some(0));
+    assert_eq!(
+        lyrics.next_change(Duration::from_secs(25)),
+        Some(Duration::from_millis(28_123))
+    );
+    assert_eq!(lyrics.next_change(Duration::from_secs(200)), None);
+}
+
+#[test]
+fn test_decode_big5_gbk_and_unicode() {
+    let traditional = "[00:01.00]你問我愛你有多深，我愛你有幾分";
+    let simplified = "[00:01.00]你问我爱你有多深，我爱你有几分";
+    assert_eq!(decode_text(&BIG5.encode(traditional).0), traditional);
+    assert_eq!(decode_text(&GBK.encode(simplified).0), simplified);
+    assert_eq!(decode_text(traditional.as_bytes()), traditional);
+
+    let mut bom = vec![0xEF, 0xBB, 0xBF];
+    bom.extend_from_slice(traditional.as_bytes());
+    assert_eq!(decode_text(&bom), traditional);
+
+    let mut utf16 = vec![0xFF, 0xFE];
+    for unit in traditional.encode_utf16() {
+        utf16.extend_from_slice(&unit.to_le_bytes());
+    }
+    assert_eq!(decode_text(&utf16), traditional);
+}
+
+#[test]
+fn test_sidecar_next_to_song() {
+    let dir = temp_folder("lyrics_sidecar");
+    let song = dir.join("鄧麗君 - 月亮代表我的心.v2.mp3");
+    fs::write(&song, b"").unwrap();
+    assert_eq!(load_sidecar(&song).unwrap(), None);
+
+    // 副檔名大小寫不拘，檔名裡的點不算副檔名
+    let lrc = dir.join("鄧麗君 - 月亮代表我的心.v2.LRC");
+    fs::write(&lrc, BIG5.encode(MOON).0).unwrap();
+    assert_eq!(find_sidecar(&song), Some(lrc));
+    let lyrics = load_sidecar(&song).unwrap().unwrap();
+    assert_eq!(lyrics.lines[0].text, "你問我愛你有多深");
+}
+
+#[test]
+fn test_enhanced_lrc_words() {
+    let lyrics = Lyrics::parse(
+        "[00:10.00]<00:10.00>月<00:10.50>亮 <00:11.00>代表<00:12.00>\n\
+         [00:13.00]我的<00:13.40>心\n\
+         [00:20.00]沒有字的時間\n",
+    );
+    let line = &lyrics.lines[0];
+    assert_eq!(line.text, "月亮 代表");
+    let words: Vec<_> = line
+        .words
+        .iter()
+        .map(|w| (w.text.as_str(), w.start_ms, w.end_ms))
+        .collect();
+    assert_eq!(
+        words,
+        [
+            ("月", 10_000, 10_500),
+            ("亮 ", 10_500, 11_000),
+            ("代表", 11_000, 12_000)
+        ]
+    );
+    // 第一個標記前的字從行首開始，最後一個字沒人結束就唱到下一行（最多兩秒）
+    let line = &lyrics.lines[1];
+    assert_eq!(line.text, "我的心");
+    assert_eq!(
+        (line.words[0].start_ms, line.words[0].end_ms),
+        (13_000, 13_400)
+    );
+    assert_eq!(
+        (line.words[1].start_ms, line.words[1].end_ms),
+        (13_400, 15_400)
+    );
+    assert!(lyrics.lines[2].words.is_empty());
+}
+
+#[test]
+fn test_word_progress() {
+    let lyrics = Lyrics::parse("[offset:500]\n[00:10.00]<00:10.00>月<00:11.00>亮<00:12.00>\n");
+    // offset 也搬字的時間
+    let at = |ms| lyrics.word_at(Duration::from_millis(ms));
+    assert_eq!(at(9_000), None);
+    let half = at(10_000).unwrap();
+    assert_eq!((half.line, half.word), (0, 0));
+    assert_eq!((half.start_ms, half.end_ms), (9_500, 10_500));
+    assert!((half.fraction - 0.5).abs() < 1e-6);
+    assert_eq!(at(10_700).unwrap().word, 1);
+    // 唱完停在 1，下一行之前都是
+    assert_eq!(at(20_000).unwrap().fraction, 1.0);
+
+    // 下一個要叫醒的時間：字的開始和結束都算
+    assert_eq!(
+        lyrics.next_change(Duration::from_millis(9_600)),
+        Some(Duration::from_millis(10_500))
+    );
+    assert_eq!(lyrics.next_change(Duration::from_millis(11_500)), None);
+}
+
+#[test]
+fn test_repeated_stamp_moves_words() {
+    let lyrics = Lyrics::parse("[00:10.00][01:10.00]<00:10.00>啦<00:10.50>\n");
+    assert_eq!(lyrics.lines[1].words[0].start_ms, 70_000);
+    assert_eq!(lyrics.lines[1].words[0].end_ms, 70_500);
+    // `<` 不是時間就照字印
+    assert_eq!(Lyrics::parse("[00:01.00]I <3 U").lines[0].text, "I <3 U");
+}
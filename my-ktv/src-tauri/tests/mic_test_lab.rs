@@ -1,19 +1,21 @@
 use cpal::traits::{DeviceTrait, HostTrait};
+use my_ktv_lib::audio_node::graph::{AudioGraph, NodeId};
 use my_ktv_lib::audio_node::{
-    connect, mic_src::MicSrc, speaker_dest::SpeakerDest, AudioNode, AudioNodeEnum,
+    mic_src::MicSrc, speaker_dest::SpeakerDest, AudioNode, AudioNodeEnum,
 };
 use std::thread;
 use std::time::Duration;
 
 // 輔助函式：快速建立並連接
-fn setup_loopback() -> (AudioNodeEnum, AudioNodeEnum) {
-    let mut mic = AudioNodeEnum::MicSrc(MicSrc::init());
-    let mut spk = AudioNodeEnum::SpeakerDest(SpeakerDest::init());
+fn setup_loopback() -> (AudioGraph, NodeId, NodeId) {
+    let mut graph = AudioGraph::new();
+    let mic = graph.add_node(AudioNodeEnum::MicSrc(MicSrc::init()));
+    let spk = graph.add_node(AudioNodeEnum::SpeakerDest(SpeakerDest::init()));
 
     // 測試連接邏輯
-    connect(&mut mic, &mut spk).expect("連接失敗");
+    graph.connect(mic, spk).expect("連接失敗");
 
-    (mic, spk)
+    (graph, mic, spk)
 }
 
 #[test]
@@ -33,18 +35,25 @@ fn test_all_mic_config() {
 // 1. 純邏輯測試：確認 Producer 所有權有轉移
 // 執行指令: cargo test test_connection_logic
 fn test_connection_logic() {
-    let (mic, spk) = setup_loopback();
+    let (graph, mic, spk) = setup_loopback();
 
     // 驗證狀態
-    match mic {
-        AudioNodeEnum::MicSrc(inner) => {
-            assert!(inner.audio_producer.is_some(), "Mic 應該拿到 Producer");
+    assert!(graph.is_connected(mic, spk));
+    match graph.node(mic) {
+        Some(AudioNodeEnum::MicSrc(inner)) => {
+            assert!(
+                inner
+                    .audio_producer
+                    .as_ref()
+                    .is_some_and(|o| o.contains(spk)),
+                "Mic 應該拿到 Producer"
+            );
         }
         _ => panic!("Wrong type"),
     }
 
-    match spk {
-        AudioNodeEnum::SpeakerDest(inner) => {
+    match graph.node(spk) {
+        Some(AudioNodeEnum::SpeakerDest(inner)) => {
             assert!(inner.audio_producer.is_none(), "Speaker 應該交出 Producer");
         }
         _ => panic!("Wrong type"),
@@ -57,16 +66,16 @@ fn test_connection_logic() {
           // 執行指令: cargo test test_mic_sound -- --nocapture --ignored
 fn test_mic_sound_default() {
     println!("=== 測試開始：預設參數 (請對麥克風說話) ===");
-    let (mut mic, mut spk) = setup_loopback();
+    let (mut graph, mic, spk) = setup_loopback();
 
-    spk.start();
-    mic.start(); // 這裡會印出 MicSrc 的參數 Log
+    graph.start(spk).unwrap();
+    graph.start(mic).unwrap(); // 這裡會印出 MicSrc 的參數 Log
 
     // 讓它跑 5 秒鐘
     thread::sleep(Duration::from_secs(5));
 
-    mic.stop();
-    spk.stop();
+    graph.stop(mic).unwrap();
+    graph.stop(spk).unwrap();
     println!("=== 測試結束 ===");
 }
 
@@ -76,15 +85,15 @@ fn test_mic_sound_default() {
 // 執行指令: cargo test test_mic_stress -- --nocapture --ignored
 fn test_mic_stress() {
     println!("=== 測試開始：壓力測試 (快速開關) ===");
-    let (mut mic, mut spk) = setup_loopback();
+    let (mut graph, mic, spk) = setup_loopback();
 
     for i in 1..=3 {
         println!("Loop #{}", i);
-        spk.start();
-        mic.start();
+        graph.start(spk).unwrap();
+        graph.start(mic).unwrap();
         thread::sleep(Duration::from_millis(500)); // 跑 0.5 秒
-        mic.stop(); // 停止
-        spk.stop();
+        graph.stop(mic).unwrap(); // 停止
+        graph.stop(spk).unwrap();
         thread::sleep(Duration::from_millis(200)); // 休息
     }
     println!("=== 測試結束 ===");
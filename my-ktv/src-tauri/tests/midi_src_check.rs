@@ -0,0 +1,266 @@
+use midly::num::{u15, u28, u4, u7};
+use midly::{Format, Header, MetaMessage, MidiMessage, Smf, Timing, TrackEvent, TrackEventKind};
+use my_ktv_lib::audio_node::graph::AudioGraph;
+use my_ktv_lib::audio_node::midi_src::synth::{load_soundfont, MidiSong, MidiSynth};
+use my_ktv_lib::audio_node::midi_src::MidiSrc;
+use my_ktv_lib::audio_node::mixer::Mixer;
+use my_ktv_lib::audio_node::offline::OfflineRenderer;
+use my_ktv_lib::audio_node::wav_file_dest::WavFileDest;
+use my_ktv_lib::audio_node::{AudioNode, AudioNodeEnum};
+use rodio::Source;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+// 不需要音效卡：自己做一個只有正弦波的 SoundFont，MIDI 合成出來要有聲音、時間要對
+
+const RATE: u32 = 48000;
+const PER_BEAT: u16 = 480;
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("my_ktv_{}_{}", std::process::id(), name))
+}
+
+fn chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut out = id.to_vec();
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(data);
+    out
+}
+
+fn list(kind: &[u8; 4], chunks: &[Vec<u8>]) -> Vec<u8> {
+    let mut data = kind.to_vec();
+    for c in chunks {
+        data.extend_from_slice(c);
+    }
+    chunk(b"LIST", &data)
+}
+
+fn name20(name: &str) -> Vec<u8> {
+    let mut out = name.as_bytes().to_vec();
+    out.resize(20, 0);
+    out
+}
+
+fn words(values: &[u16]) -> Vec<u8> {
+    values.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+/// One preset on every bank and key: a looped 441 Hz sine recorded at A4
+fn write_soundfont(name: &str) -> PathBuf {
+    let period = 100;
+    let mut smpl = Vec::new();
+    for i in 0..period * 20 {
+        let v = (i as f32 / period as f32 * 2.0 * std::f32::consts::PI).sin() * 0.5;
+        smpl.extend_from_slice(&((v * i16::MAX as f32) as i16).to_le_bytes());
+    }
+    // the format wants 46 zero samples after each sample
+    smpl.resize(smpl.len() + 46 * 2, 0);
+
+    let mut phdr = name20("Sine");
+    phdr.extend(words(&[0, 0, 0]));
+    phdr.resize(38, 0);
+    phdr.extend(name20("EOP"));
+    phdr.extend(words(&[0, 0, 1]));
+    phdr.resize(76, 0);
+
+    let mut inst = name20("Sine");
+    inst.extend(words(&[0]));
+    inst.extend(name20("EOI"));
+    inst.extend(words(&[1]));
+
+    let mut shdr = name20("Sine");
+    for v in [
+        0u32,
+        (period * 20) as u32,
+        period as u32,
+        (period * 19) as u32,
+        44100,
+    ] {
+        shdr.extend_from_slice(&v.to_le_bytes());
+    }
+    // A4, no correction, no link, mono
+    shdr.extend([69, 0]);
+    shdr.extend(words(&[0, 1]));
+    shdr.extend(name20("EOS"));
+    shdr.resize(46 * 2, 0);
+
+    let pdta = list(
+        b"pdta",
+        &[
+            chunk(b"phdr", &phdr),
+            chunk(b"pbag", &words(&[0, 0, 1, 0])),
+            chunk(b"pmod", &[0; 10]),
+            // instrument 0, then the terminal
+            chunk(b"pgen", &words(&[41, 0, 0, 0])),
+            chunk(b"inst", &inst),
+            chunk(b"ibag", &words(&[0, 0, 2, 0])),
+            chunk(b"imod", &[0; 10]),
+            // loop the sample, sample 0 (always last), then the terminal
+            chunk(b"igen", &words(&[54, 1, 53, 0, 0, 0])),
+            chunk(b"shdr", &shdr),
+        ],
+    );
+    let info = list(b"INFO", &[chunk(b"ifil", &words(&[2, 1]))]);
+    let sdta = list(b"sdta", &[chunk(b"smpl", &smpl)]);
+
+    let mut body = b"sfbk".to_vec();
+    for part in [info, sdta, pdta] {
+        body.extend(part);
+    }
+    let path = temp_path(&format!("{}.sf2", name));
+    std::fs::write(&path, chunk(b"RIFF", &body)).unwrap();
+    path
+}
+
+/// A4 from beat `from` to beat `to` at 120 bpm, a beat is 500 ms
+fn write_midi(name: &str, from: u32, to: u32) -> PathBuf {
+    let midi = |delta: u32, message| TrackEvent {
+        delta: u28::new(delta * PER_BEAT as u32),
+        kind: TrackEventKind::Midi {
+            channel: u4::new(0),
+            message,
+        },
+    };
+    let mut smf = Smf::new(Header::new(
+        Format::SingleTrack,
+        Timing::Metrical(u15::new(PER_BEAT)),
+    ));
+    smf.tracks = vec![vec![
+        midi(
+            0,
+            MidiMessage::ProgramChange {
+                program: u7::new(0),
+            },
+        ),
+        midi(
+            from,
+            MidiMessage::NoteOn {
+                key: u7::new(69),
+                vel: u7::new(127),
+            },
+        ),
+        midi(
+            to - from,
+            MidiMessage::NoteOff {
+                key: u7::new(69),
+                vel: u7::new(0),
+            },
+        ),
+        TrackEvent {
+            delta: u28::new(0),
+            kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+        },
+    ]];
+    let path = temp_path(&format!("{}.mid", name));
+    smf.save(&path).unwrap();
+    path
+}
+
+fn rms(samples: &[f32]) -> f32 {
+    (samples.iter().map(|s| s * s).sum::<f32>() / samples.len().max(1) as f32).sqrt()
+}
+
+/// Left channel of `secs` seconds from `from`
+fn left(samples: &[f32], from: f32, secs: f32) -> &[f32] {
+    let start = (from * RATE as f32) as usize;
+    let end = ((from + secs) * RATE as f32) as usize;
+    &samples[start.min(samples.len())..end.min(samples.len())]
+}
+
+fn render(synth: MidiSynth) -> Vec<f32> {
+    synth
+        .step_by(2)
+        .map(|s| s as f32 / i16::MAX as f32)
+        .collect()
+}
+
+#[test]
+fn test_synth_plays_the_note() {
+    let soundfont = load_soundfont(&write_soundfont("synth")).unwrap();
+    let song = Arc::new(MidiSong::open(&write_midi("synth", 1, 3)).unwrap());
+    let synth = MidiSynth::new(Arc::clone(&song), &soundfont, RATE).unwrap();
+    assert_eq!((synth.channels(), synth.sample_rate()), (2, RATE));
+    // 最後一個音放開之後留一點尾巴
+    let total = synth.total_duration().unwrap();
+    assert!(total > song.length, "{:?}", total);
+
+    let out = render(synth);
+    assert!(
+        (out.len() as f32 / RATE as f32 - total.as_secs_f32()).abs() < 0.01,
+        "frames: {}",
+        out.len()
+    );
+    // 0.5 秒之前沒聲音，0.5 到 1.5 秒有，放開後漸漸安靜
+    assert!(rms(left(&out, 0.0, 0.45)) < 1e-4);
+    assert!(rms(left(&out, 0.6, 0.8)) > 0.01);
+    assert!(rms(left(&out, 2.2, 0.2)) < rms(left(&out, 0.6, 0.8)) / 10.0);
+}
+
+#[test]
+fn test_synth_seek() {
+    let soundfont = load_soundfont(&write_soundfont("seek")).unwrap();
+    let song = Arc::new(MidiSong::open(&write_midi("seek", 2, 4)).unwrap());
+    let mut synth = MidiSynth::new(song, &soundfont, RATE).unwrap();
+    // 跳到音的中間：那個音按下去的時候被跳過了，不會再響
+    synth.try_seek(Duration::from_millis(1500)).unwrap();
+    let out = render(synth);
+    assert!(rms(&out[..RATE as usize / 4]) < 1e-4);
+    // 跳到音之前：再過 0.5 秒才唱
+    let mut synth = MidiSynth::open(&write_midi("seek_back", 2, 4), &soundfont, RATE).unwrap();
+    synth.try_seek(Duration::from_millis(500)).unwrap();
+    let out = render(synth);
+    assert!(rms(left(&out, 0.0, 0.45)) < 1e-4);
+    assert!(rms(left(&out, 0.6, 0.3)) > 0.01);
+}
+
+#[test]
+fn test_midi_src_into_mixer() {
+    let soundfont = load_soundfont(&write_soundfont("node")).unwrap();
+    let output = temp_path("midi_node.wav");
+    let mut graph = AudioGraph::new();
+    let mixer = graph.add_node(AudioNodeEnum::Mixer(Mixer::with_channels(2)));
+    let mut wav_dest = WavFileDest::init();
+    wav_dest.set_config(output.clone(), RATE, 2);
+    let dest = graph.add_node(AudioNodeEnum::WavFileDest(wav_dest));
+    graph.connect(mixer, dest).unwrap();
+
+    let mut midi_src = MidiSrc::init();
+    midi_src.set_config(write_midi("node", 1, 3), soundfont, RATE, 2);
+    let src = graph.add_node(AudioNodeEnum::MidiSrc(midi_src));
+    graph.connect(src, mixer).unwrap();
+
+    OfflineRenderer::new(graph, dest).unwrap().run().unwrap();
+
+    let mut reader = hound::WavReader::open(&output).unwrap();
+    assert_eq!(reader.spec().sample_rate, RATE);
+    let samples: Vec<f32> = reader.samples::<f32>().map(|s| s.unwrap()).collect();
+    let out: Vec<f32> = samples.chunks(2).map(|f| f[0]).collect();
+    assert!(out.len() as u32 >= RATE * 2, "frames: {}", out.len());
+    assert!(rms(left(&out, 0.0, 0.45)) < 1e-4);
+    assert!(rms(left(&out, 0.6, 0.8)) > 0.01);
+}
+
+#[test]
+fn test_bundled_soundfont() {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("soundfonts/default.sf2");
+    let soundfont = load_soundfont(&path).unwrap();
+    // GM 的 128 個音色加上 bank 128 的鼓組
+    let presets = soundfont.get_presets();
+    for program in 0..128 {
+        assert!(
+            presets
+                .iter()
+                .any(|p| p.get_bank_number() == 0 && p.get_patch_number() == program),
+            "program {} missing",
+            program
+        );
+    }
+    assert!(presets.iter().any(|p| p.get_bank_number() == 128));
+
+    let song = Arc::new(MidiSong::open(&write_midi("bundled", 1, 3)).unwrap());
+    let out = render(MidiSynth::new(song, &soundfont, RATE).unwrap());
+    assert!(rms(left(&out, 0.0, 0.45)) < 1e-4);
+    assert!(rms(left(&out, 0.6, 0.8)) > 0.01);
+    assert!(rms(left(&out, 2.2, 0.2)) < rms(left(&out, 0.6, 0.8)) / 10.0);
+}
@@ -0,0 +1,152 @@
+use my_ktv_lib::audio_node::file_src::FileSrc;
+use my_ktv_lib::audio_node::graph::{AudioGraph, NodeId};
+use my_ktv_lib::audio_node::mixer::{Mixer, MixerInputControl};
+use my_ktv_lib::audio_node::offline::OfflineRenderer;
+use my_ktv_lib::audio_node::wav_file_dest::WavFileDest;
+use my_ktv_lib::audio_node::{AudioNode, AudioNodeEnum};
+use std::path::PathBuf;
+
+// 不需要音效卡：每一軌的 channel strip 設定後離線渲染，檢查左右聲道
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("my_ktv_{}_{}.wav", std::process::id(), name))
+}
+
+fn write_sine(name: &str, amplitude: f32) -> PathBuf {
+    let path = temp_path(name);
+    let spec = hound::WavSpec {
+        channels: 2,
+        sample_rate: 48000,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+    for i in 0..24000 {
+        let t = i as f32 / 48000.0;
+        let v = (t * 440.0 * 2.0 * std::f32::consts::PI).sin() * amplitude;
+        let v = (v * i16::MAX as f32) as i16;
+        writer.write_sample(v).unwrap();
+        writer.write_sample(v).unwrap();
+    }
+    writer.finalize().unwrap();
+    path
+}
+
+/// Render `inputs` through one mixer, `setup` gets the strip of every input before rendering.
+/// Returns (left, right)
+fn render(
+    name: &str,
+    inputs: &[PathBuf],
+    setup: impl Fn(&[MixerInputControl]),
+) -> (Vec<f32>, Vec<f32>) {
+    let output = temp_path(name);
+    let mut graph = AudioGraph::new();
+    let mixer = graph.add_node(AudioNodeEnum::Mixer(Mixer::with_channels(2)));
+    let mut wav_dest = WavFileDest::init();
+    wav_dest.set_config(output.clone(), 48000, 2);
+    let dest = graph.add_node(AudioNodeEnum::WavFileDest(wav_dest));
+    graph.connect(mixer, dest).unwrap();
+
+    let sources: Vec<NodeId> = inputs
+        .iter()
+        .map(|input| {
+            let mut file_src = FileSrc::init();
+            file_src.set_config(input.clone(), 48000, 2);
+            let src = graph.add_node(AudioNodeEnum::FileSrc(file_src));
+            graph.connect(src, mixer).unwrap();
+            src
+        })
+        .collect();
+
+    let controls: Vec<MixerInputControl> =
+        sources.iter().map(|_| MixerInputControl::new()).collect();
+    setup(&controls);
+    if let Some(AudioNodeEnum::Mixer(mixer)) = graph.node_mut(mixer) {
+        for (src, control) in sources.iter().zip(controls.iter()) {
+            mixer.set_input_control(*src, control.clone()).unwrap();
+        }
+    }
+
+    OfflineRenderer::new(graph, dest).unwrap().run().unwrap();
+
+    let mut reader = hound::WavReader::open(&output).unwrap();
+    let samples: Vec<f32> = reader.samples::<f32>().map(|s| s.unwrap()).collect();
+    let left = samples.chunks(2).map(|f| f[0]).collect();
+    let right = samples.chunks(2).map(|f| f[1]).collect();
+    (left, right)
+}
+
+fn peak(samples: &[f32]) -> f32 {
+    samples.iter().fold(0.0f32, |acc, s| acc.max(s.abs()))
+}
+
+#[test]
+fn test_input_gain() {
+    let input = write_sine("strip_gain_in", 0.4);
+    let (left, right) = render("strip_gain_out", &[input], |strips| {
+        strips[0].gain().set_gain_db(-6.0);
+    });
+
+    assert!((peak(&left) - 0.2).abs() < 0.02, "left: {}", peak(&left));
+    assert!((peak(&right) - 0.2).abs() < 0.02, "right: {}", peak(&right));
+}
+
+#[test]
+fn test_input_pan() {
+    let input = write_sine("strip_pan_in", 0.4);
+    let (left, right) = render("strip_pan_out", &[input], |strips| {
+        strips[0].set_pan(-1.0);
+    });
+
+    assert!((peak(&left) - 0.4).abs() < 0.02, "left: {}", peak(&left));
+    assert_eq!(peak(&right), 0.0, "全部往左，右聲道應該沒有聲音");
+}
+
+#[test]
+fn test_input_mute() {
+    let music = write_sine("strip_mute_music", 0.4);
+    let silence = write_sine("strip_mute_silence", 0.0);
+    let (left, right) = render("strip_mute_out", &[music, silence], |strips| {
+        strips[0].gain().set_muted(true);
+    });
+
+    assert_eq!(peak(&left), 0.0);
+    assert_eq!(peak(&right), 0.0);
+}
+
+#[test]
+fn test_input_solo() {
+    let music = write_sine("strip_solo_music", 0.4);
+    let silence = write_sine("strip_solo_silence", 0.0);
+    let (left, _) = render("strip_solo_out", &[music, silence], |strips| {
+        strips[1].set_solo(true);
+    });
+
+    // 有人 solo 時，其他軌淡出後就聽不到
+    assert!(peak(&left[4800..]) < 1e-4, "peak: {}", peak(&left[4800..]));
+}
+
+#[test]
+fn test_strip_survives_reconnect() {
+    let mut graph = AudioGraph::new();
+    let mixer = graph.add_node(AudioNodeEnum::Mixer(Mixer::new()));
+    let mut file_src = FileSrc::init();
+    file_src.set_config(temp_path("unused"), 48000, 2);
+    let src = graph.add_node(AudioNodeEnum::FileSrc(file_src));
+
+    let strip = MixerInputControl::new();
+    strip.gain().set_gain_db(-12.0);
+    strip.set_pan(0.5);
+
+    for _ in 0..2 {
+        graph.connect(src, mixer).unwrap();
+        let Some(AudioNodeEnum::Mixer(inner)) = graph.node_mut(mixer) else {
+            unreachable!()
+        };
+        inner.set_input_control(src, strip.clone()).unwrap();
+        let control = inner.input_control(src).unwrap();
+        assert!((control.gain().gain_db() + 12.0).abs() < 1e-3);
+        assert_eq!(control.pan(), 0.5);
+        graph.disconnect(src, mixer).unwrap();
+    }
+}
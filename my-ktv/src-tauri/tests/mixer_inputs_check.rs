@@ -0,0 +1,112 @@
+use my_ktv_lib::audio_node::backend::AudioBackend;
+use my_ktv_lib::audio_node::fake_audio_wave_src::FakeAudioWaveSRC;
+use my_ktv_lib::audio_node::graph::{AudioGraph, NodeId};
+use my_ktv_lib::audio_node::mixer::Mixer;
+use my_ktv_lib::audio_node::speaker_dest::SpeakerDest;
+use my_ktv_lib::audio_node::virtual_backend::VirtualBackend;
+use my_ktv_lib::audio_node::{AudioNode, AudioNodeEnum};
+use std::sync::Arc;
+
+// 不需要音效卡：mixer 跑在虛擬喇叭的 clock 上時新增、移除輸入
+
+const TICK_FRAMES: usize = 480;
+
+fn peak(samples: &[f32]) -> f32 {
+    samples.iter().fold(0.0f32, |acc, s| acc.max(s.abs()))
+}
+
+fn setup(backend: &Arc<VirtualBackend>) -> (AudioGraph, NodeId, NodeId, NodeId) {
+    let dyn_backend: Arc<dyn AudioBackend> = backend.clone();
+    let mut graph = AudioGraph::new();
+    let src = graph.add_node(AudioNodeEnum::FakeAudioWaveSRC(FakeAudioWaveSRC::init()));
+    let mixer = graph.add_node(AudioNodeEnum::Mixer(Mixer::new()));
+    let spk = graph.add_node(AudioNodeEnum::SpeakerDest(
+        SpeakerDest::with_backend(dyn_backend.as_ref()).unwrap(),
+    ));
+    graph.connect(src, mixer).unwrap();
+    graph.connect(mixer, spk).unwrap();
+    graph.start(spk).unwrap();
+    graph.start(mixer).unwrap();
+    graph.start(src).unwrap();
+    (graph, src, mixer, spk)
+}
+
+fn mixer_mut(graph: &mut AudioGraph, id: NodeId) -> &mut Mixer {
+    match graph.node_mut(id) {
+        Some(AudioNodeEnum::Mixer(mixer)) => mixer,
+        _ => panic!("Wrong type"),
+    }
+}
+
+#[test]
+fn test_add_and_remove_while_running() {
+    let backend = Arc::new(VirtualBackend::manual());
+    let (mut graph, _src, mixer, _spk) = setup(&backend);
+    for _ in 0..5 {
+        backend.tick(TICK_FRAMES);
+    }
+
+    // 跑著的 mixer 直接接上第二個來源
+    let src_2 = graph.add_node(AudioNodeEnum::FakeAudioWaveSRC(FakeAudioWaveSRC::init()));
+    graph.connect(src_2, mixer).unwrap();
+    graph.start(src_2).unwrap();
+    assert_eq!(mixer_mut(&mut graph, mixer).input_count(), 2);
+    for _ in 0..5 {
+        backend.tick(TICK_FRAMES);
+    }
+
+    graph.stop(src_2).unwrap();
+    graph.disconnect(src_2, mixer).unwrap();
+    assert_eq!(mixer_mut(&mut graph, mixer).input_count(), 1);
+    backend.clear_captured();
+    for _ in 0..5 {
+        backend.tick(TICK_FRAMES);
+    }
+
+    // 第一個來源一直都在，拿掉第二個之後（過了 limiter 的 look-ahead）音量回到單一來源
+    let captured = backend.captured();
+    assert_eq!(captured.len(), 5 * TICK_FRAMES * 2);
+    let tail = &captured[TICK_FRAMES * 2..];
+    assert!((peak(tail) - 0.1).abs() < 0.01, "peak: {}", peak(tail));
+}
+
+#[test]
+fn test_abandoned_input_is_pruned() {
+    let backend = Arc::new(VirtualBackend::manual());
+    let (mut graph, _src, mixer, _spk) = setup(&backend);
+
+    // 來源消失但沒有 disconnect：producer 被丟掉
+    let gone = graph.add_node(AudioNodeEnum::FakeAudioWaveSRC(FakeAudioWaveSRC::init()));
+    let (producer, _signal) = mixer_mut(&mut graph, mixer).add_input(gone);
+    drop(producer);
+    assert_eq!(mixer_mut(&mut graph, mixer).input_count(), 2);
+
+    backend.tick(TICK_FRAMES);
+    let inner = mixer_mut(&mut graph, mixer);
+    assert_eq!(inner.input_count(), 1);
+    // 已經被丟掉的輸入再 remove 一次也沒事
+    inner.remove_input(gone);
+    assert_eq!(inner.input_count(), 1);
+}
+
+#[test]
+fn test_commands_survive_stop() {
+    let backend = Arc::new(VirtualBackend::manual());
+    let (mut graph, src, mixer, _spk) = setup(&backend);
+
+    // clock 還沒 tick 就停掉，排隊中的指令不能遺失
+    graph.stop(src).unwrap();
+    graph.disconnect(src, mixer).unwrap();
+    graph.stop(mixer).unwrap();
+    assert_eq!(mixer_mut(&mut graph, mixer).input_count(), 0);
+
+    graph.connect(src, mixer).unwrap();
+    graph.start(mixer).unwrap();
+    graph.start(src).unwrap();
+    for _ in 0..5 {
+        backend.tick(TICK_FRAMES);
+    }
+    let captured = backend.captured();
+    let tail = &captured[TICK_FRAMES * 2..];
+    assert!((peak(tail) - 0.1).abs() < 0.01, "peak: {}", peak(tail));
+}
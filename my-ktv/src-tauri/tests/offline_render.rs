@@ -0,0 +1,115 @@
+use my_ktv_lib::audio_node::offline::OfflineRenderer;
+use std::path::PathBuf;
+
+// 不需要音效卡：產生 WAV 當輸入，離線渲染後讀回來比對
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("my_ktv_{}_{}.wav", std::process::id(), name))
+}
+
+fn write_sine(name: &str, sample_rate: u32, secs: f32, amplitude: f32) -> PathBuf {
+    let path = temp_path(name);
+    let spec = hound::WavSpec {
+        channels: 2,
+        sample_rate,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+    let frames = (sample_rate as f32 * secs) as usize;
+    for i in 0..frames {
+        let t = i as f32 / sample_rate as f32;
+        let v = (t * 440.0 * 2.0 * std::f32::consts::PI).sin() * amplitude;
+        let v = (v * i16::MAX as f32) as i16;
+        writer.write_sample(v).unwrap();
+        writer.write_sample(v).unwrap();
+    }
+    writer.finalize().unwrap();
+    path
+}
+
+fn read_left(path: &PathBuf) -> (u32, Vec<f32>) {
+    let mut reader = hound::WavReader::open(path).unwrap();
+    let spec = reader.spec();
+    let samples: Vec<f32> = reader.samples::<f32>().map(|s| s.unwrap()).collect();
+    let left = samples
+        .chunks(spec.channels as usize)
+        .map(|f| f[0])
+        .collect();
+    (spec.sample_rate, left)
+}
+
+fn peak(samples: &[f32]) -> f32 {
+    samples.iter().fold(0.0f32, |acc, s| acc.max(s.abs()))
+}
+
+fn zero_crossings(samples: &[f32]) -> usize {
+    samples
+        .windows(2)
+        .filter(|w| (w[0] < 0.0) != (w[1] < 0.0))
+        .count()
+}
+
+#[test]
+fn test_resample_golden() {
+    let input = write_sine("resample_in", 44100, 1.0, 0.5);
+    let output = temp_path("resample_out");
+
+    OfflineRenderer::mix_files(&[input], output.clone(), 48000, 2).unwrap();
+
+    let (rate, left) = read_left(&output);
+    assert_eq!(rate, 48000);
+    assert!(
+        (left.len() as i64 - 48000).abs() < 1024,
+        "frames: {}",
+        left.len()
+    );
+    assert!((peak(&left) - 0.5).abs() < 0.02, "peak: {}", peak(&left));
+    // 440Hz 一秒約 880 次過零
+    let crossings = zero_crossings(&left);
+    assert!(
+        (crossings as i64 - 880).abs() < 20,
+        "crossings: {}",
+        crossings
+    );
+}
+
+#[test]
+fn test_mixer_golden() {
+    let input_a = write_sine("mix_a", 48000, 0.5, 0.4);
+    let input_b = write_sine("mix_b", 48000, 0.5, 0.4);
+    let output = temp_path("mix_out");
+
+    OfflineRenderer::mix_files(&[input_a, input_b], output.clone(), 48000, 2).unwrap();
+
+    let (_, left) = read_left(&output);
+    assert!(
+        (left.len() as i64 - 24000).abs() < 1024,
+        "frames: {}",
+        left.len()
+    );
+    // 兩路相同訊號直接相加，振幅加倍
+    assert!((peak(&left) - 0.8).abs() < 0.02, "peak: {}", peak(&left));
+}
+
+#[test]
+fn test_render_is_deterministic() {
+    let input = write_sine("determinism_in", 44100, 0.5, 0.3);
+    let output_a = temp_path("determinism_a");
+    let output_b = temp_path("determinism_b");
+
+    OfflineRenderer::mix_files(std::slice::from_ref(&input), output_a.clone(), 48000, 2).unwrap();
+    OfflineRenderer::mix_files(&[input], output_b.clone(), 48000, 2).unwrap();
+
+    assert_eq!(
+        std::fs::read(output_a).unwrap(),
+        std::fs::read(output_b).unwrap()
+    );
+}
+
+#[test]
+fn test_missing_file_fails() {
+    let output = temp_path("missing_out");
+    let ret = OfflineRenderer::mix_files(&[temp_path("not_exist")], output, 48000, 2);
+    assert!(ret.is_err());
+}
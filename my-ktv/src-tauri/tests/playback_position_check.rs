@@ -0,0 +1,156 @@
+use my_ktv_lib::audio_node::backend::AudioBackend;
+use my_ktv_lib::audio_node::file_src::FileSrc;
+use my_ktv_lib::audio_node::gain::GainNode;
+use my_ktv_lib::audio_node::graph::{AudioGraph, NodeId};
+use my_ktv_lib::audio_node::mixer::Mixer;
+use my_ktv_lib::audio_node::speaker_dest::SpeakerDest;
+use my_ktv_lib::audio_node::virtual_backend::VirtualBackend;
+use my_ktv_lib::audio_node::{AudioNode, AudioNodeEnum};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+// 不需要音效卡：播放位置要對上喇叭實際播出來的，不是解碼到哪裡
+
+const TICK_FRAMES: usize = 480;
+const RATE: u32 = 48000;
+const FILE_FRAMES: u32 = RATE * 2;
+
+/// Two seconds, both channels rise from 0 to 0.5, a sample value tells its time
+fn write_ramp(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("my_ktv_{}_{}.wav", std::process::id(), name));
+    let spec = hound::WavSpec {
+        channels: 2,
+        sample_rate: RATE,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+    for i in 0..FILE_FRAMES {
+        let v = (i as f32 / FILE_FRAMES as f32 * 0.5 * 32768.0) as i16;
+        writer.write_sample(v).unwrap();
+        writer.write_sample(v).unwrap();
+    }
+    writer.finalize().unwrap();
+    path
+}
+
+fn time_of(value: f32) -> f32 {
+    value / 0.5 * FILE_FRAMES as f32 / RATE as f32
+}
+
+fn setup(backend: &Arc<VirtualBackend>, name: &str) -> (AudioGraph, NodeId) {
+    let dyn_backend: Arc<dyn AudioBackend> = backend.clone();
+    let mut graph = AudioGraph::new();
+    let mut file_src = FileSrc::init();
+    file_src.set_config(write_ramp(name), RATE, 2);
+    let src = graph.add_node(AudioNodeEnum::FileSrc(file_src));
+    let gain = graph.add_node(AudioNodeEnum::GainNode(GainNode::init()));
+    let spk = graph.add_node(AudioNodeEnum::SpeakerDest(
+        SpeakerDest::with_backend(dyn_backend.as_ref()).unwrap(),
+    ));
+    graph.connect(src, gain).unwrap();
+    graph.connect(gain, spk).unwrap();
+    graph.start(spk).unwrap();
+    graph.start(gain).unwrap();
+    graph.start(src).unwrap();
+    (graph, src)
+}
+
+fn file_src(graph: &mut AudioGraph, id: NodeId) -> &mut FileSrc {
+    match graph.node_mut(id) {
+        Some(AudioNodeEnum::FileSrc(inner)) => inner,
+        _ => panic!("Wrong type"),
+    }
+}
+
+/// Tick until `frames` audible frames came out, returns the last one heard (left channel)
+fn play(backend: &VirtualBackend, frames: usize) -> f32 {
+    backend.clear_captured();
+    for _ in 0..5000 {
+        let heard: Vec<f32> = backend
+            .captured()
+            .chunks(2)
+            .map(|f| f[0])
+            .filter(|s| *s != 0.0)
+            .collect();
+        if heard.len() >= frames {
+            return *heard.last().unwrap();
+        }
+        backend.tick(TICK_FRAMES);
+        std::thread::sleep(Duration::from_micros(200));
+    }
+    panic!("decoder never caught up");
+}
+
+#[test]
+fn test_position_follows_speaker() {
+    let backend = Arc::new(VirtualBackend::manual());
+    let (mut graph, src) = setup(&backend, "position");
+    let last = play(&backend, 24000);
+    // 解碼器早就跑到前面去了，位置還是停在喇叭播到的地方
+    std::thread::sleep(Duration::from_millis(20));
+
+    let inner = file_src(&mut graph, src);
+    let position = inner.position().as_secs_f32();
+    assert!(
+        (position - time_of(last)).abs() < 0.002,
+        "position {} heard {}",
+        position,
+        time_of(last)
+    );
+    assert_eq!(inner.duration(), Some(Duration::from_secs(2)));
+}
+
+#[test]
+fn test_position_holds_while_paused() {
+    let backend = Arc::new(VirtualBackend::manual());
+    let (mut graph, src) = setup(&backend, "position_pause");
+    let last = play(&backend, 24000);
+
+    file_src(&mut graph, src).pause().unwrap();
+    for _ in 0..10 {
+        backend.tick(TICK_FRAMES);
+    }
+    let position = file_src(&mut graph, src).position().as_secs_f32();
+    assert!(
+        (position - time_of(last)).abs() < 0.002,
+        "position {} heard {}",
+        position,
+        time_of(last)
+    );
+
+    // 暫停時跳到別的地方，位置馬上就是新的
+    file_src(&mut graph, src)
+        .seek(Duration::from_millis(1500))
+        .unwrap();
+    assert_eq!(
+        file_src(&mut graph, src).position(),
+        Duration::from_millis(1500)
+    );
+}
+
+#[test]
+fn test_output_latency_counts_limiter() {
+    let backend = Arc::new(VirtualBackend::manual());
+    let dyn_backend: Arc<dyn AudioBackend> = backend.clone();
+    let mut graph = AudioGraph::new();
+    let mut file_src = FileSrc::init();
+    file_src.set_config(write_ramp("latency"), RATE, 2);
+    let src = graph.add_node(AudioNodeEnum::FileSrc(file_src));
+    let mixer = graph.add_node(AudioNodeEnum::Mixer(Mixer::new()));
+    let spk = graph.add_node(AudioNodeEnum::SpeakerDest(
+        SpeakerDest::with_backend(dyn_backend.as_ref()).unwrap(),
+    ));
+    graph.connect(src, mixer).unwrap();
+    graph.connect(mixer, spk).unwrap();
+
+    // 虛擬喇叭沒有裝置延遲，剩下 limiter 的 look-ahead
+    let lookahead = match graph.node(mixer) {
+        Some(AudioNodeEnum::Mixer(inner)) => inner.latency_frames(),
+        _ => panic!("Wrong type"),
+    };
+    assert!(lookahead > 0);
+    assert_eq!(graph.output_latency_frames(src), lookahead);
+    assert_eq!(graph.output_latency_frames(spk), 0);
+}
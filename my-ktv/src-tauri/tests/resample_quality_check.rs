@@ -0,0 +1,164 @@
+use my_ktv_lib::audio_node::backend::AudioBackend;
+use my_ktv_lib::audio_node::file_src::FileSrc;
+use my_ktv_lib::audio_node::graph::AudioGraph;
+use my_ktv_lib::audio_node::mic_src::MicSrc;
+use my_ktv_lib::audio_node::offline::OfflineRenderer;
+use my_ktv_lib::audio_node::speaker_dest::SpeakerDest;
+use my_ktv_lib::audio_node::virtual_backend::{VirtualBackend, VirtualMicSignal};
+use my_ktv_lib::audio_node::wav_file_dest::WavFileDest;
+use my_ktv_lib::audio_node::{AudioNode, AudioNodeEnum, ResampleQuality};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+// 不需要音效卡：每種品質都要能轉，取樣率一樣時要原封不動
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("my_ktv_{}_{}.wav", std::process::id(), name))
+}
+
+/// One second of a 440Hz stereo sine, returns the path and the 16-bit samples written
+fn write_sine(name: &str, sample_rate: u32) -> (PathBuf, Vec<i16>) {
+    let path = temp_path(name);
+    let spec = hound::WavSpec {
+        channels: 2,
+        sample_rate,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+    let mut written = Vec::new();
+    for i in 0..sample_rate {
+        let t = i as f32 / sample_rate as f32;
+        let v = (t * 440.0 * 2.0 * std::f32::consts::PI).sin() * 0.5;
+        let v = (v * i16::MAX as f32) as i16;
+        // 右聲道反相，才看得出左右有沒有混在一起
+        for s in [v, -v] {
+            writer.write_sample(s).unwrap();
+            written.push(s);
+        }
+    }
+    writer.finalize().unwrap();
+    (path, written)
+}
+
+fn render(name: &str, input: PathBuf, quality: ResampleQuality) -> Vec<f32> {
+    let output = temp_path(name);
+    let mut graph = AudioGraph::new();
+    let mut file_src = FileSrc::init();
+    file_src.set_config(input, 48000, 2);
+    file_src.set_resample_quality(quality);
+    let src = graph.add_node(AudioNodeEnum::FileSrc(file_src));
+    let mut wav_dest = WavFileDest::init();
+    wav_dest.set_config(output.clone(), 48000, 2);
+    let dest = graph.add_node(AudioNodeEnum::WavFileDest(wav_dest));
+    graph.connect(src, dest).unwrap();
+
+    OfflineRenderer::new(graph, dest).unwrap().run().unwrap();
+
+    let mut reader = hound::WavReader::open(&output).unwrap();
+    reader.samples::<f32>().map(|s| s.unwrap()).collect()
+}
+
+fn zero_crossings(samples: &[f32]) -> usize {
+    samples
+        .windows(2)
+        .filter(|w| (w[0] < 0.0) != (w[1] < 0.0))
+        .count()
+}
+
+#[test]
+fn test_same_rate_passes_through() {
+    let (input, written) = write_sine("quality_same_in", 48000);
+    let output = render("quality_same_out", input, ResampleQuality::HighSinc);
+
+    // 沒有濾波器延遲，每個 sample 都一樣
+    assert!(output.len() >= written.len());
+    for (i, (out, src)) in output.iter().zip(written.iter()).enumerate() {
+        let expected = *src as f32 / 32768.0;
+        assert!(
+            (out - expected).abs() < 1e-6,
+            "sample {}: {} != {}",
+            i,
+            out,
+            expected
+        );
+    }
+}
+
+#[test]
+fn test_every_quality_converts() {
+    let (input, _) = write_sine("quality_in", 44100);
+    for quality in [
+        ResampleQuality::Linear,
+        ResampleQuality::FastSinc,
+        ResampleQuality::HighSinc,
+    ] {
+        let output = render("quality_out", input.clone(), quality);
+        let left: Vec<f32> = output.chunks(2).map(|f| f[0]).collect();
+        let right: Vec<f32> = output.chunks(2).map(|f| f[1]).collect();
+
+        // 44.1k 一秒變成大約 48000 個 frame，440Hz 大約 880 次過零
+        assert!(
+            (left.len() as i64 - 48000).abs() < 2048,
+            "{:?}: {} frames",
+            quality,
+            left.len()
+        );
+        let crossings = zero_crossings(&left[4800..43200]) as f32 / 0.8;
+        assert!(
+            (crossings - 880.0).abs() < 10.0,
+            "{:?}: {}",
+            quality,
+            crossings
+        );
+        // 左右反相，加起來要接近零
+        let leak = left[4800..43200]
+            .iter()
+            .zip(right[4800..43200].iter())
+            .fold(0.0f32, |acc, (l, r)| acc.max((l + r).abs()));
+        assert!(leak < 1e-3, "{:?}: leak {}", quality, leak);
+    }
+}
+
+#[test]
+fn test_bypass_keeps_samples() {
+    let (input, written) = write_sine("quality_bypass_in", 44100);
+    let output = render("quality_bypass_out", input, ResampleQuality::Bypass);
+
+    // 不轉換：frame 數量不變（尾巴補零到整塊）
+    assert!(output.len() >= written.len() && output.len() < written.len() + 4096);
+    assert!((output[100] - written[100] as f32 / 32768.0).abs() < 1e-6);
+}
+
+fn mic_delay(low_latency: bool, input_rate: u32) -> usize {
+    let backend = Arc::new(
+        VirtualBackend::manual()
+            .with_input_config(input_rate, 1)
+            .with_mic_signal(VirtualMicSignal::Silence),
+    );
+    let dyn_backend: Arc<dyn AudioBackend> = backend.clone();
+    let mut graph = AudioGraph::new();
+    let mut mic_src = MicSrc::with_backend(Arc::clone(&dyn_backend)).unwrap();
+    mic_src.set_low_latency(low_latency);
+    let mic = graph.add_node(AudioNodeEnum::MicSrc(mic_src));
+    let spk = graph.add_node(AudioNodeEnum::SpeakerDest(
+        SpeakerDest::with_backend(dyn_backend.as_ref()).unwrap(),
+    ));
+    graph.connect(mic, spk).unwrap();
+    graph.start(spk).unwrap();
+    graph.start(mic).unwrap();
+
+    match graph.node(mic) {
+        Some(AudioNodeEnum::MicSrc(inner)) => inner.resample_delay_frames(),
+        _ => panic!("Wrong type"),
+    }
+}
+
+#[test]
+fn test_mic_low_latency_reports_delay() {
+    let normal = mic_delay(false, 44100);
+    let low = mic_delay(true, 44100);
+    assert!(low > 0, "有轉換就一定有延遲");
+    assert!(low < normal, "low latency {} 要比一般 {} 短", low, normal);
+    assert_eq!(mic_delay(true, 48000), 0, "取樣率一樣就沒有濾波器");
+}
@@ -0,0 +1,151 @@
+use my_ktv_lib::song_queue::rotation::{Fifo, RoundRobin, Weighted};
+use my_ktv_lib::song_queue::SongQueue;
+use std::collections::HashMap;
+
+// 不需要音效卡：一個人把歌單塞滿，其他人也要輪得到
+
+fn enqueue_all(queue: &mut SongQueue, songs: &[(&str, &str)]) {
+    for (singer, path) in songs {
+        queue.enqueue(path.to_string(), Some(singer.to_string()));
+    }
+}
+
+/// Play the whole queue, returns the singers in the order they went on air
+fn drain(queue: &mut SongQueue) -> Vec<String> {
+    let mut singers = Vec::new();
+    while let Some(entry) = queue.advance() {
+        singers.push(entry.singer.unwrap_or_default());
+    }
+    singers
+}
+
+/// What the snapshot promises, it has to be what `advance` then does
+fn planned(queue: &SongQueue) -> Vec<String> {
+    queue
+        .snapshot()
+        .upcoming
+        .into_iter()
+        .map(|e| e.singer.unwrap_or_default())
+        .collect()
+}
+
+const HOG: [(&str, &str); 6] = [
+    ("阿明", "1"),
+    ("阿明", "2"),
+    ("阿明", "3"),
+    ("阿明", "4"),
+    ("小美", "5"),
+    ("大雄", "6"),
+];
+
+#[test]
+fn test_fifo_keeps_request_order() {
+    let mut queue = SongQueue::new();
+    queue.set_policy(Box::new(Fifo));
+    enqueue_all(&mut queue, &HOG);
+    assert_eq!(
+        drain(&mut queue),
+        vec!["阿明", "阿明", "阿明", "阿明", "小美", "大雄"]
+    );
+}
+
+#[test]
+fn test_round_robin_interleaves() {
+    let mut queue = SongQueue::new();
+    queue.set_policy(Box::new(RoundRobin));
+    enqueue_all(&mut queue, &HOG);
+
+    let plan = planned(&queue);
+    assert_eq!(plan, vec!["阿明", "小美", "大雄", "阿明", "阿明", "阿明"]);
+    assert_eq!(drain(&mut queue), plan, "實際順序跟顯示的不一樣");
+}
+
+#[test]
+fn test_round_robin_remembers_who_sang() {
+    let mut queue = SongQueue::new();
+    queue.set_policy(Box::new(RoundRobin));
+    enqueue_all(&mut queue, &[("阿明", "1"), ("小美", "2")]);
+    drain(&mut queue);
+
+    // 小美剛唱過，先點也是阿明先唱
+    enqueue_all(&mut queue, &[("小美", "3"), ("阿明", "4")]);
+    assert_eq!(drain(&mut queue), vec!["阿明", "小美"]);
+}
+
+#[test]
+fn test_insert_top_beats_rotation() {
+    let mut queue = SongQueue::new();
+    queue.set_policy(Box::new(RoundRobin));
+    enqueue_all(&mut queue, &HOG);
+    queue.insert_top("壽星".to_string(), Some("阿明".to_string()));
+
+    // 插歌先唱，而且算阿明一輪
+    assert_eq!(
+        planned(&queue),
+        vec!["阿明", "小美", "大雄", "阿明", "阿明", "阿明", "阿明"]
+    );
+    assert_eq!(queue.advance().unwrap().path, "壽星");
+    assert_eq!(queue.advance().unwrap().singer.unwrap(), "小美");
+}
+
+#[test]
+fn test_promote_goes_behind_other_inserts() {
+    let mut queue = SongQueue::new();
+    queue.set_policy(Box::new(RoundRobin));
+    enqueue_all(&mut queue, &HOG);
+    let first = queue.insert_top("插一".to_string(), None);
+    let last = queue.snapshot().upcoming.last().unwrap().clone();
+    queue.promote(last.id).unwrap();
+
+    let upcoming = queue.snapshot().upcoming;
+    assert_eq!(upcoming[0].id, first.id);
+    assert_eq!(upcoming[1].id, last.id);
+    assert!(upcoming[1].priority);
+}
+
+#[test]
+fn test_weighted_shares() {
+    let mut queue = SongQueue::new();
+    let weights = HashMap::from([("阿明".to_string(), 2.0)]);
+    queue.set_policy(Box::new(Weighted::new(weights)));
+    let songs: Vec<(&str, &str)> = (0..6)
+        .map(|_| ("阿明", "a"))
+        .chain((0..3).map(|_| ("小美", "b")))
+        .collect();
+    enqueue_all(&mut queue, &songs);
+
+    // 權重 2 的人一輪唱兩首
+    assert_eq!(
+        drain(&mut queue),
+        vec![
+            "阿明", "小美", "阿明", "阿明", "小美", "阿明", "阿明", "小美", "阿明"
+        ]
+    );
+}
+
+#[test]
+fn test_weighted_late_comer_does_not_catch_up() {
+    let mut queue = SongQueue::new();
+    queue.set_policy(Box::new(Weighted::new(HashMap::new())));
+    enqueue_all(
+        &mut queue,
+        &[("阿明", "1"), ("小美", "2"), ("阿明", "3"), ("小美", "4")],
+    );
+    drain(&mut queue);
+
+    // 大雄晚到，不會一口氣把前面沒唱的都補回來
+    enqueue_all(
+        &mut queue,
+        &[
+            ("大雄", "5"),
+            ("大雄", "6"),
+            ("大雄", "7"),
+            ("阿明", "8"),
+            ("小美", "9"),
+        ],
+    );
+    assert_eq!(
+        drain(&mut queue),
+        vec!["大雄", "阿明", "小美", "大雄", "大雄"]
+    );
+}
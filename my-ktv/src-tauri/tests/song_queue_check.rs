@@ -0,0 +1,76 @@
+use my_ktv_lib::song_queue::SongQueue;
+
+// 不需要音效卡：只看點歌單怎麼排
+
+fn paths(queue: &SongQueue) -> Vec<String> {
+    queue
+        .snapshot()
+        .upcoming
+        .into_iter()
+        .map(|e| e.path)
+        .collect()
+}
+
+#[test]
+fn test_enqueue_and_advance_in_order() {
+    let mut queue = SongQueue::new();
+    queue.enqueue("a".into(), None);
+    queue.enqueue("b".into(), None);
+    assert!(queue.current().is_none());
+
+    assert_eq!(queue.advance().unwrap().path, "a");
+    assert_eq!(queue.current().unwrap().path, "a");
+    assert_eq!(paths(&queue), vec!["b"]);
+
+    assert_eq!(queue.advance().unwrap().path, "b");
+    // 歌單空了，台上也沒有歌
+    assert!(queue.advance().is_none());
+    assert!(queue.current().is_none());
+}
+
+#[test]
+fn test_insert_top_plays_next() {
+    let mut queue = SongQueue::new();
+    queue.enqueue("a".into(), None);
+    queue.advance();
+    queue.enqueue("b".into(), None);
+    queue.insert_top("插歌".into(), None);
+
+    assert_eq!(paths(&queue), vec!["插歌", "b"]);
+    // 插歌不會打斷正在唱的
+    assert_eq!(queue.current().unwrap().path, "a");
+    assert_eq!(queue.advance().unwrap().path, "插歌");
+}
+
+#[test]
+fn test_remove_and_move_by_id() {
+    let mut queue = SongQueue::new();
+    let a = queue.enqueue("a".into(), None);
+    let b = queue.enqueue("b".into(), None);
+    let c = queue.enqueue("c".into(), None);
+    assert_ne!(a.id, b.id);
+
+    queue.move_to(c.id, 0).unwrap();
+    assert_eq!(paths(&queue), vec!["c", "a", "b"]);
+    // 超過尾巴就放最後
+    queue.move_to(c.id, 99).unwrap();
+    assert_eq!(paths(&queue), vec!["a", "b", "c"]);
+
+    assert_eq!(queue.remove(b.id).unwrap(), b);
+    assert_eq!(paths(&queue), vec!["a", "c"]);
+    assert!(queue.remove(b.id).is_err(), "刪過的還找得到");
+    assert!(queue.move_to(b.id, 0).is_err());
+}
+
+#[test]
+fn test_play_now_keeps_queue() {
+    let mut queue = SongQueue::new();
+    queue.enqueue("a".into(), None);
+    let now = queue.play_now("now".into());
+
+    assert_eq!(queue.current(), Some(&now));
+    assert_eq!(paths(&queue), vec!["a"]);
+    assert_eq!(queue.finish_current(), Some(now));
+    assert!(queue.current().is_none());
+    assert_eq!(queue.len(), 1);
+}
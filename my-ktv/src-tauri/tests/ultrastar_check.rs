@@ -0,0 +1,195 @@
+use my_ktv_lib::lyrics::ultrastar::{load_sidecar, NoteKind, UltraStarSong};
+use my_ktv_lib::lyrics::{load_lyrics, load_lyrics_with_notes};
+use std::fs;
+use std::path::PathBuf;
+
+// 不需要音效卡：UltraStar 的 .txt 解成音符跟歌詞，拍子換成 ms 要對
+
+// BPM 150：一拍 60000 / (150 * 4) = 100 ms，GAP 1 秒
+const SOLO: &str = "#TITLE:Hello Song
+#ARTIST:Somebody
+#MP3:Hello Song.mp3
+#BPM:150
+#GAP:1000
+: 0 2 5 Hel
+: 2 2 7 lo
+* 4 4 9  world
+- 10
+F 12 2 0 (hey)
+: 14 2 3 ya
+: 16 4 2 ~
+R 20 2 0  yo
+E
+: 99 1 1 after the end
+";
+
+fn temp_folder(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("my_ktv_{}_{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_notes_and_line_breaks() {
+    let song = UltraStarSong::parse(SOLO).unwrap();
+    assert_eq!(song.title.as_deref(), Some("Hello Song"));
+    assert_eq!(song.audio.as_deref(), Some("Hello Song.mp3"));
+    assert_eq!((song.bpm, song.gap_ms), (150.0, 1000));
+    assert_eq!(song.tracks.len(), 1);
+    assert_eq!(song.tracks[0].singer, None);
+
+    let lines = &song.tracks[0].lines;
+    assert_eq!(lines.len(), 2);
+    assert_eq!((lines[0].start_ms, lines[0].end_ms), (1000, 1800));
+    let notes: Vec<_> = lines[0]
+        .notes
+        .iter()
+        .map(|n| (n.kind, n.start_ms, n.end_ms, n.pitch, n.text.as_str()))
+        .collect();
+    assert_eq!(
+        notes,
+        [
+            (NoteKind::Normal, 1000, 1200, 5, "Hel"),
+            (NoteKind::Normal, 1200, 1400, 7, "lo"),
+            // 多的空白是字的一部分
+            (NoteKind::Golden, 1400, 1800, 9, " world"),
+        ]
+    );
+    let kinds: Vec<_> = lines[1].notes.iter().map(|n| n.kind).collect();
+    assert_eq!(
+        kinds,
+        [
+            NoteKind::Freestyle,
+            NoteKind::Normal,
+            NoteKind::Normal,
+            NoteKind::Rap
+        ]
+    );
+    assert_eq!(lines[1].end_ms, 3200);
+
+    // 歌詞：一個音一個字，~ 只是把前一個字拉長
+    let lyrics = song.lyrics();
+    assert_eq!(lyrics.artist.as_deref(), Some("Somebody"));
+    let texts: Vec<_> = lyrics.lines.iter().map(|l| l.text.as_str()).collect();
+    assert_eq!(texts, ["Hello world", "(hey)ya yo"]);
+    let ya = &lyrics.lines[1].words[1];
+    assert_eq!(
+        (ya.text.as_str(), ya.start_ms, ya.end_ms),
+        ("ya", 2400, 3000)
+    );
+    assert_eq!(lyrics.lines[1].words.len(), 3);
+}
+
+#[test]
+fn test_relative_beats_and_decimal_bpm() {
+    // 相對拍子：換行後從第二個數字重新算；BPM 用逗號當小數點
+    let text = "#BPM:187,5\n#GAP:-200\n#RELATIVE:yes\n\
+                : 0 4 0 one\n- 6 8\n: 0 4 0 two\n- 5\n: 1 2 0 three\n";
+    let song = UltraStarSong::parse(text).unwrap();
+    assert_eq!(song.bpm, 187.5);
+    // 一拍 80 ms
+    let starts: Vec<_> = song.tracks[0].lines.iter().map(|l| l.start_ms).collect();
+    assert_eq!(starts, [0, 440, 920]);
+    assert_eq!(song.tracks[0].lines[0].end_ms, 120);
+
+    assert!(UltraStarSong::parse("#TITLE:x\n: 0 1 0 a\n").is_err());
+    assert!(UltraStarSong::parse("#BPM:100\nno notes here\n").is_err());
+    // 壞掉的音符行略過
+    let song = UltraStarSong::parse("#BPM:100\n: 0 x 0 a\n: 1 1 0 b\n").unwrap();
+    assert_eq!(song.tracks[0].lines[0].notes.len(), 1);
+}
+
+#[test]
+fn test_duet_tracks() {
+    let text = "#BPM:150\n#GAP:0\n#DUETSINGERP1:Ann\n#P2:Bob\n\
+                P1\n: 0 2 0 A\n- 4\n: 10 2 0 A2\n\
+                P2\n: 4 2 0 B\n\
+                P3\n: 20 2 0 Both\nE\n";
+    let song = UltraStarSong::parse(text).unwrap();
+    let singers: Vec<_> = song.tracks.iter().map(|t| t.singer.as_deref()).collect();
+    assert_eq!(singers, [Some("Ann"), Some("Bob")]);
+    let texts = |track: usize| -> Vec<String> {
+        song.tracks[track]
+            .lines
+            .iter()
+            .map(|l| l.notes.iter().map(|n| n.text.as_str()).collect())
+            .collect()
+    };
+    assert_eq!(texts(0), ["A", "A2", "Both"]);
+    assert_eq!(texts(1), ["B", "Both"]);
+
+    // 兩個人一起唱的只出現一次
+    let lyrics = song.lyrics();
+    let lines: Vec<_> = lyrics
+        .lines
+        .iter()
+        .map(|l| (l.time_ms, l.text.as_str()))
+        .collect();
+    assert_eq!(lines, [(0, "A"), (400, "B"), (1000, "A2"), (2000, "Both")]);
+}
+
+#[test]
+fn test_txt_next_to_the_audio() {
+    let dir = temp_folder("ultrastar");
+    let mp3 = dir.join("Hello Song.mp3");
+    fs::write(&mp3, b"not really an mp3").unwrap();
+    assert_eq!(load_sidecar(&mp3).unwrap(), None);
+
+    // UltraStar 的資料夾：.txt 叫別的名字，#MP3 指到音檔
+    fs::write(dir.join("readme.txt"), "not a song").unwrap();
+    fs::write(dir.join("Somebody - Hello Song.txt"), SOLO).unwrap();
+    let song = load_sidecar(&mp3).unwrap().unwrap();
+    assert_eq!(song.tracks[0].lines.len(), 2);
+    let lyrics = load_lyrics(&mp3).unwrap().unwrap();
+    assert_eq!(lyrics.lines[0].text, "Hello world");
+    // 已經讀好的音符直接拿來當歌詞
+    let lyrics = load_lyrics_with_notes(&mp3, Some(&song)).unwrap().unwrap();
+    assert_eq!(lyrics.lines[0].text, "Hello world");
+
+    // 同名的 .txt 優先，.lrc 又比它優先
+    let other = dir.join("Other.ogg");
+    fs::write(&other, b"").unwrap();
+    assert_eq!(load_sidecar(&other).unwrap(), None);
+    fs::write(
+        dir.join("Other.TXT"),
+        SOLO.replace("#TITLE:Hello", "#TITLE:Other"),
+    )
+    .unwrap();
+    let song = load_sidecar(&other).unwrap().unwrap();
+    assert_eq!(song.title.as_deref(), Some("Other Song"));
+    fs::write(dir.join("Other.lrc"), "[00:01.00]從 lrc 來的\n").unwrap();
+    assert_eq!(
+        load_lyrics(&other).unwrap().unwrap().lines[0].text,
+        "從 lrc 來的"
+    );
+    assert_eq!(
+        load_lyrics_with_notes(&other, Some(&song))
+            .unwrap()
+            .unwrap()
+            .lines[0]
+            .text,
+        "從 lrc 來的"
+    );
+}
+
+#[test]
+fn test_txt_search_reads_only_named_headers() {
+    let dir = temp_folder("ultrastar_header");
+    let mp3 = dir.join("Hello Song.mp3");
+    fs::write(&mp3, b"").unwrap();
+
+    // 名字跟音檔無關的 .txt 不去開，就算 #MP3 指到它
+    fs::write(dir.join("Unrelated.txt"), SOLO).unwrap();
+    assert_eq!(load_sidecar(&mp3).unwrap(), None);
+
+    // 有 BOM、#AUDIO 在後面，標頭裡最後一個為準
+    let song = SOLO.replace("#MP3:Hello Song.mp3", "#MP3:old.mp3\n#AUDIO:Hello Song.mp3");
+    fs::write(
+        dir.join("Somebody - Hello Song.txt"),
+        format!("\u{feff}{}", song),
+    )
+    .unwrap();
+    let song = load_sidecar(&mp3).unwrap().unwrap();
+    assert_eq!(song.audio.as_deref(), Some("Hello Song.mp3"));
+}
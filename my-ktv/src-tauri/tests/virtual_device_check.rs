@@ -0,0 +1,117 @@
+use my_ktv_lib::audio_node::backend::{default_backend, AudioBackend, AUDIO_BACKEND_ENV};
+use my_ktv_lib::audio_node::graph::{AudioGraph, NodeId};
+use my_ktv_lib::audio_node::mic_src::MicSrc;
+use my_ktv_lib::audio_node::speaker_dest::SpeakerDest;
+use my_ktv_lib::audio_node::virtual_backend::{VirtualBackend, VirtualMicSignal};
+use my_ktv_lib::audio_node::AudioNodeEnum;
+use std::sync::Arc;
+
+// 不需要音效卡：虛擬麥克風 -> 虛擬喇叭，手動推進時鐘
+
+const TICK_FRAMES: usize = 480;
+
+fn setup_loopback(backend: &Arc<VirtualBackend>) -> (AudioGraph, NodeId, NodeId) {
+    let dyn_backend: Arc<dyn AudioBackend> = backend.clone();
+    let mut graph = AudioGraph::new();
+    let mic = graph.add_node(AudioNodeEnum::MicSrc(
+        MicSrc::with_backend(Arc::clone(&dyn_backend)).unwrap(),
+    ));
+    let spk = graph.add_node(AudioNodeEnum::SpeakerDest(
+        SpeakerDest::with_backend(dyn_backend.as_ref()).unwrap(),
+    ));
+    graph.connect(mic, spk).unwrap();
+    (graph, mic, spk)
+}
+
+fn peak(samples: &[f32]) -> f32 {
+    samples.iter().fold(0.0f32, |acc, s| acc.max(s.abs()))
+}
+
+#[test]
+fn test_virtual_mic_to_speaker() {
+    let backend = Arc::new(
+        VirtualBackend::manual().with_mic_signal(VirtualMicSignal::Sine {
+            frequency: 440.0,
+            amplitude: 0.5,
+        }),
+    );
+    let (mut graph, mic, spk) = setup_loopback(&backend);
+
+    graph.start(spk).unwrap();
+    graph.start(mic).unwrap();
+    for _ in 0..50 {
+        backend.tick(TICK_FRAMES);
+    }
+
+    let captured = backend.captured();
+    assert_eq!(captured.len(), 50 * TICK_FRAMES * 2);
+    // 跳過 resampler 延遲之後應該聽得到麥克風
+    let tail = &captured[captured.len() / 2..];
+    assert!((peak(tail) - 0.5).abs() < 0.05, "peak: {}", peak(tail));
+}
+
+#[test]
+fn test_virtual_mic_plays_wav() {
+    let path = std::env::temp_dir().join(format!("my_ktv_{}_mic.wav", std::process::id()));
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate: 44100,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+    for i in 0..44100 {
+        let t = i as f32 / 44100.0;
+        let v = (t * 440.0 * 2.0 * std::f32::consts::PI).sin() * 0.25;
+        writer.write_sample((v * i16::MAX as f32) as i16).unwrap();
+    }
+    writer.finalize().unwrap();
+
+    let backend = Arc::new(VirtualBackend::manual().with_mic_wav(&path).unwrap());
+    assert_eq!(
+        backend.input_config().unwrap().stream_config.sample_rate,
+        44100
+    );
+    let (mut graph, mic, spk) = setup_loopback(&backend);
+
+    graph.start(spk).unwrap();
+    graph.start(mic).unwrap();
+    for _ in 0..50 {
+        backend.tick(TICK_FRAMES);
+    }
+
+    let captured = backend.captured();
+    let tail = &captured[captured.len() / 2..];
+    assert!((peak(tail) - 0.25).abs() < 0.05, "peak: {}", peak(tail));
+}
+
+#[test]
+fn test_paused_speaker_does_not_advance() {
+    let backend = Arc::new(VirtualBackend::manual());
+    let (mut graph, _mic, spk) = setup_loopback(&backend);
+
+    backend.tick(TICK_FRAMES);
+    assert!(backend.captured().is_empty());
+
+    graph.start(spk).unwrap();
+    backend.tick(TICK_FRAMES);
+    graph.stop(spk).unwrap();
+    backend.tick(TICK_FRAMES);
+
+    let captured = backend.captured();
+    assert_eq!(captured.len(), TICK_FRAMES * 2);
+    assert_eq!(peak(&captured), 0.0, "沒有接任何來源應該是靜音");
+}
+
+#[test]
+fn test_virtual_backend_only_on_request() {
+    // 只有這個測試碰環境變數；沒設的話要嘛是真的音效卡，要嘛回錯誤，不會偷偷換成虛擬的
+    std::env::remove_var(AUDIO_BACKEND_ENV);
+    match default_backend() {
+        Ok(backend) => assert_ne!(backend.name(), "virtual"),
+        Err(e) => assert!(e.contains(AUDIO_BACKEND_ENV), "{}", e),
+    }
+    std::env::set_var(AUDIO_BACKEND_ENV, "virtual");
+    assert_eq!(default_backend().unwrap().name(), "virtual");
+    std::env::remove_var(AUDIO_BACKEND_ENV);
+}